@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use reqwest::header::HeaderMap;
+use tokio::process::Command;
+use crate::atmb::challenge;
+use crate::error::Error;
+
+/// the longest the browser can take to render a page
+const RENDER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Renders the pages through a headless Chrome, only for the pages whose plain requests are blocked by anti-bot.
+///
+/// A browser is started for every page with `--dump-dom`, it's slow but runs the challenge scripts like a visitor.
+pub struct HeadlessBrowser {
+    /// the Chrome or Chromium executable
+    path: PathBuf,
+}
+
+impl HeadlessBrowser {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+        }
+    }
+
+    /// the HTML of the page once the browser has rendered it
+    pub async fn render(&self, url: &str) -> anyhow::Result<String> {
+        let output = Command::new(&self.path)
+            .args(["--headless", "--disable-gpu", &format!("--user-agent={}", super::UA), "--dump-dom", url])
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(RENDER_TIMEOUT, output).await
+            .map_err(|_| anyhow!("the browser cannot render [{}] in {}s", url, RENDER_TIMEOUT.as_secs()))?
+            .map_err(|e| anyhow!("cannot start the browser [{}]: {}", self.path.display(), e))?;
+        if !output.status.success() {
+            bail!("the browser failed to render [{}] ({}): {}", url, output.status, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let html = String::from_utf8_lossy(&output.stdout).into_owned();
+        if let Some(service) = challenge::detect(&HeaderMap::new(), &html) {
+            return Err(Error::Blocked { url: url.to_string(), service }.into());
+        }
+        Ok(html)
+    }
+}
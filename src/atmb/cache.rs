@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// validators returned by the server for a cached page
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheMeta {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// a page that has been stored on disk
+#[derive(Debug)]
+pub struct CachedPage {
+    pub meta: CacheMeta,
+    pub body: String,
+}
+
+/// Disk cache for ATMB pages, keyed by URL.
+///
+/// Every URL is stored as two files: `<key>.html` holds the page body and `<key>.json` holds
+/// the `ETag`/`Last-Modified` validators used for conditional requests.
+pub struct PageCache {
+    dir: PathBuf,
+}
+
+impl PageCache {
+    pub fn new(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        if !dir.exists() {
+            std::fs::create_dir_all(&dir)?;
+        }
+        Ok(
+            Self {
+                dir,
+            }
+        )
+    }
+
+    /// get the cached page of the url, if any
+    pub fn get(&self, url: &str) -> Option<CachedPage> {
+        let (body_path, meta_path) = self.paths(url);
+        let body = std::fs::read_to_string(body_path).ok()?;
+        let meta = std::fs::read_to_string(meta_path).ok()
+            .and_then(|meta| serde_json::from_str(&meta).ok())
+            .unwrap_or_default();
+        Some(CachedPage { meta, body })
+    }
+
+    pub fn put(&self, url: &str, page: &CachedPage) -> anyhow::Result<()> {
+        let (body_path, meta_path) = self.paths(url);
+        std::fs::write(body_path, &page.body)?;
+        std::fs::write(meta_path, serde_json::to_string(&page.meta)?)?;
+        Ok(())
+    }
+
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let key = Self::key(url);
+        (self.dir.join(format!("{key}.html")), self.dir.join(format!("{key}.json")))
+    }
+
+    /// turn the url into a file name, i.e. `https___www.anytimemailbox.com_l_usa`
+    fn key(url: &str) -> String {
+        url.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(PageCache::key("https://www.anytimemailbox.com/l/usa"), "https___www.anytimemailbox.com_l_usa");
+        assert_eq!(
+            PageCache::key("https://www.anytimemailbox.com/s/birmingham-120-19th-street-north"),
+            "https___www.anytimemailbox.com_s_birmingham-120-19th-street-north",
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use reqwest::header::HeaderMap;
+
+/// the challenge pages are small, a bigger page only embeds the scripts of the anti-bot service
+const MAX_CHALLENGE_LEN: usize = 32 * 1024;
+
+/// what the challenge pages of the anti-bot services contain, as `(marker, service)`
+const MARKERS: [(&str, &str); 9] = [
+    ("<title>Just a moment...</title>", "Cloudflare challenge"),
+    ("Attention Required! | Cloudflare", "Cloudflare block"),
+    ("cf-chl-", "Cloudflare challenge"),
+    ("/cdn-cgi/challenge-platform/", "Cloudflare challenge"),
+    ("_Incapsula_Resource", "Incapsula"),
+    ("captcha-delivery.com", "DataDome captcha"),
+    ("px-captcha", "PerimeterX captcha"),
+    ("g-recaptcha", "reCAPTCHA"),
+    ("h-captcha", "hCaptcha"),
+];
+
+/// the anti-bot service whose challenge the response is, `None` if it looks like a real page
+pub fn detect(headers: &HeaderMap, body: &str) -> Option<&'static str> {
+    if headers.get("cf-mitigated").is_some_and(|value| value == "challenge") {
+        return Some("Cloudflare challenge");
+    }
+    if body.len() > MAX_CHALLENGE_LEN {
+        return None;
+    }
+    MARKERS.iter().find(|(marker, _)| body.contains(marker)).map(|&(_, service)| service)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect_challenge() {
+        let headers = HeaderMap::new();
+        let challenge = r#"<html><head><title>Just a moment...</title></head><body><div id="cf-chl-widget"></div></body></html>"#;
+        assert_eq!(detect(&headers, challenge), Some("Cloudflare challenge"));
+        assert_eq!(detect(&headers, r#"<form><div class="g-recaptcha"></div></form>"#), Some("reCAPTCHA"));
+
+        let page = include_str!("../../test_data/https___www.anytimemailbox.com_l_usa_alabama.html");
+        assert_eq!(detect(&headers, page), None);
+        let mut headers = HeaderMap::new();
+        headers.insert("cf-mitigated", "challenge".parse().unwrap());
+        assert_eq!(detect(&headers, ""), Some("Cloudflare challenge"));
+    }
+}
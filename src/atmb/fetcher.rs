@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+/// Gets the pages of ATMB, the crawl is generic over it so it can run on saved pages without network access.
+#[async_trait(?Send)]
+pub trait PageFetcher {
+    /// get the content of a page
+    ///
+    /// * `url_path` - the path of the page, can be either a full URL or a relative path
+    async fn fetch_page(&self, url_path: &str) -> anyhow::Result<String>;
+}
+
+/// Serves the pages from memory, keyed by their path, i.e. `/l/usa/alabama`.
+#[derive(Debug, Default)]
+pub struct FixtureFetcher {
+    pages: HashMap<String, String>,
+}
+
+impl FixtureFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// serve the page at the path or the full URL
+    pub fn with_page(mut self, url_path: &str, html: impl Into<String>) -> Self {
+        self.pages.insert(Self::path(url_path).to_string(), html.into());
+        self
+    }
+
+    fn path(url_path: &str) -> &str {
+        url_path.strip_prefix(super::BASE_URL).unwrap_or(url_path)
+    }
+}
+
+#[async_trait(?Send)]
+impl PageFetcher for FixtureFetcher {
+    async fn fetch_page(&self, url_path: &str) -> anyhow::Result<String> {
+        self.pages.get(Self::path(url_path))
+            .cloned()
+            .ok_or_else(|| anyhow!("no saved page for [{}]", url_path))
+    }
+}
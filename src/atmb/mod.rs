@@ -1,31 +1,124 @@
-use anyhow::bail;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
 use futures::StreamExt;
-use log::info;
-use reqwest::Client;
-use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::{Client, Response, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT};
+use tracing::{debug, info, instrument, warn};
+use crate::atmb::cache::{CacheMeta, CachedPage, PageCache};
 use crate::atmb::model::Mailbox;
-use crate::atmb::page::{CountryPage, LocationDetailPage, StatePage};
+use crate::atmb::page::{CountryPage, LocationDetailPage, LocationIndexPage, Sitemap, StatePage};
+use crate::atmb::session::Session;
+use crate::breaker::{self, CircuitBreaker};
+use crate::concurrency::AdaptiveConcurrency;
+use crate::cassette::Cassette;
+use crate::country::Country;
+use crate::limiter::RateLimiter;
+use crate::metrics::{self, Counter};
+use crate::progress::Progress;
+use crate::shutdown::{self, Interrupted};
+use crate::proxy::ProxyPool;
+use crate::state::{self, UsState};
+use crate::error::Error;
+use crate::utils::{label_timeout, retry_wrapper};
+use crate::provider::{BudgetExhausted, CrawlConfig, MailboxProvider, RequestBudget};
 
-mod page;
+#[cfg(feature = "headless")]
+mod browser;
+mod cache;
+mod challenge;
+mod fetcher;
+mod selector;
+mod session;
 pub mod model;
+pub mod page;
+
+pub use fetcher::{FixtureFetcher, PageFetcher};
 
 const BASE_URL: &str = "https://www.anytimemailbox.com";
 const UA: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+/// sent by the same Firefox as `UA`
+const ACCEPT_HTML: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+const LANGUAGE: &str = "en-US,en;q=0.5";
+
+/// pages of a paginated state page to follow at most, more means the pages likely link back to each other
+const MAX_STATE_PAGES: usize = 50;
+
+/// lists the countries ATMB has locations in
+const LOCATION_INDEX_URL: &str = "/locations";
+
+/// lists the location detail pages, or the sitemaps listing them
+const SITEMAP_URL: &str = "/sitemap.xml";
+
+/// sitemaps to follow at most, an index listing more is unlikely to be ATMB's
+const MAX_SITEMAPS: usize = 20;
+
+/// the pause between the pages of the retry pass is this fraction of the retry delay
+const RETRY_INTERVAL_DIVISOR: u32 = 10;
 
-const US_HOME_PAGE_URL: &str = "/l/usa";
+/// how long the requests are paused once most of them fail
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// where the fetched pages are cached between runs
+const CACHE_DIR: &str = "cache/pages";
 
 /// HTTP client for obtaining information from ATMB
-struct ATMBClient {
+pub struct ATMBClient {
     client: Client,
+    cache: PageCache,
+    /// keeps the requests below the rate limit, to avoid hammering the site
+    limiter: Option<RateLimiter>,
+    /// pauses the requests when ATMB starts failing most of them
+    breaker: CircuitBreaker,
+    /// sends fewer requests at once while they keep failing, at most `--crawl-concurrency`
+    concurrency: AdaptiveConcurrency,
+    /// the cookies and the referer, shared by the proxies
+    session: Session,
+    /// no request is sent once it's used up, see `--max-requests`
+    budget: Option<RequestBudget>,
+    /// proxies the requests are rotated through, `client` is used if not set
+    proxies: Option<ProxyPool>,
+    /// where the responses are recorded to or replayed from, see `--record-http`
+    cassette: Option<Cassette>,
+    /// renders the pages blocked by anti-bot, see `--browser`
+    #[cfg(feature = "headless")]
+    browser: Option<browser::HeadlessBrowser>,
 }
 
 impl ATMBClient {
-    fn new() -> anyhow::Result<Self> {
+    fn new(config: &CrawlConfig) -> anyhow::Result<Self> {
+        #[cfg(not(feature = "headless"))]
+        if config.browser.is_some() {
+            bail!("`--browser` needs the `headless` feature, build with `cargo build --release --features headless`");
+        }
         Ok(
             Self {
-                client: Client::builder()
+                client: config.client_builder()?
                     .default_headers(Self::default_headers())
                     .build()?,
+                cache: PageCache::new(CACHE_DIR)?,
+                limiter: config.rate_limit.map(RateLimiter::new),
+                breaker: CircuitBreaker::new(BREAKER_COOLDOWN),
+                concurrency: AdaptiveConcurrency::new(config.concurrency),
+                session: Session::default(),
+                budget: config.budget.clone(),
+                proxies: match &config.proxy_file {
+                    Some(path) => Some(ProxyPool::load(path, |proxy| {
+                        Ok(
+                            config.with_timeouts(Client::builder())
+                                .proxy(proxy)
+                                .default_headers(Self::default_headers())
+                                .build()?
+                        )
+                    })?),
+                    None => None,
+                },
+                cassette: config.http.as_ref().map(|mode| Cassette::open(mode, "atmb", "html")).transpose()?,
+                #[cfg(feature = "headless")]
+                browser: config.browser.as_ref().map(browser::HeadlessBrowser::new),
             }
         )
     }
@@ -33,139 +126,630 @@ impl ATMBClient {
     fn default_headers() -> HeaderMap {
         let mut map = HeaderMap::new();
         map.insert(USER_AGENT, HeaderValue::from_static(UA));
+        map.insert(ACCEPT, HeaderValue::from_static(ACCEPT_HTML));
+        map.insert(ACCEPT_LANGUAGE, HeaderValue::from_static(LANGUAGE));
         map
     }
 
-    /// get the content of a page
+    /// send the request, through the proxy pool if there's one
     ///
-    /// * `url_path` - the path of the page, can be either a full URL or a relative path
+    /// a proxy that cannot connect or gets blocked is marked unhealthy, and the request is retried
+    /// through another proxy until every proxy has been tried once.
+    async fn send(&self, url: &str, mut headers: HeaderMap) -> anyhow::Result<FetchedPage> {
+        headers.extend(self.session.headers());
+        let Some(proxies) = &self.proxies else {
+            self.wait_for_rate_limit().await;
+            let resp = self.client.get(url).headers(headers).send().await.map_err(label_timeout)?;
+            self.session.update(url, resp.headers());
+            return FetchedPage::read(resp).await;
+        };
+
+        let mut last_error = None;
+        for _ in 0..proxies.len() {
+            let Some((idx, client)) = proxies.next() else {
+                break;
+            };
+            self.wait_for_rate_limit().await;
+            match client.get(url).headers(headers.clone()).send().await {
+                Ok(resp) => {
+                    self.session.update(url, resp.headers());
+                    match FetchedPage::read(resp).await {
+                        Err(e) if is_blocked(&e) => {
+                            proxies.mark_unhealthy(idx);
+                            last_error = Some(e);
+                        }
+                        result => {
+                            proxies.mark_healthy(idx);
+                            return result;
+                        }
+                    }
+                }
+                Err(e) => {
+                    proxies.mark_unhealthy(idx);
+                    last_error = Some(label_timeout(e));
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("no healthy proxy available for [{}]", url)))
+    }
+
+    /// send the request once the circuit breaker and the concurrency limit allow, and report to them how the request went
+    async fn send_guarded(&self, url: &str, headers: HeaderMap) -> anyhow::Result<FetchedPage> {
+        if let Some(budget) = &self.budget {
+            budget.take()?;
+        }
+        self.breaker.acquire().await;
+        let permit = self.concurrency.acquire().await;
+        let result = self.send(url, headers).await;
+        let success = result.as_ref().err().is_none_or(|e| !breaker::is_failure(e));
+        self.breaker.record(success);
+        permit.record(success);
+        result
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
+}
+
+/// A response of ATMB whose body has been read.
+struct FetchedPage {
+    status: StatusCode,
+    meta: CacheMeta,
+    body: String,
+}
+
+impl FetchedPage {
+    /// read the successful and `304 Not Modified` responses, others become an [`Error::Fetch`]
+    ///
+    /// an anti-bot challenge is an [`Error::Blocked`], whatever its status is.
+    async fn read(resp: Response) -> anyhow::Result<Self> {
+        let url = resp.url().to_string();
+        let status = resp.status();
+        let error = (!status.is_success() && status != StatusCode::NOT_MODIFIED).then(|| Error::from_response(&resp));
+        let headers = resp.headers().clone();
+        let header = |name| headers.get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(|value| value.to_string());
+        let meta = CacheMeta {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        let body = resp.text().await.map_err(label_timeout)?;
+        if let Some(service) = challenge::detect(&headers, &body) {
+            return Err(Error::Blocked { url, service }.into());
+        }
+        match error {
+            Some(error) => Err(error.into()),
+            None => Ok(Self { status, meta, body }),
+        }
+    }
+}
+
+/// whether the proxy is blocked by ATMB, rather than the request failed
+fn is_blocked(error: &anyhow::Error) -> bool {
+    matches!(Error::of(error), Some(Error::Blocked { .. }))
+        || matches!(Error::status(error), Some(StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS))
+}
+
+#[async_trait(?Send)]
+impl PageFetcher for ATMBClient {
+    /// the page is served from the cassette when replaying, nothing is sent
+    ///
+    /// the failures not told apart yet become an [`Error::Fetch`], the exhausted budget and the interruption are kept as they are.
+    #[instrument(skip(self))]
     async fn fetch_page(&self, url_path: &str) -> anyhow::Result<String> {
         let url = if url_path.starts_with("http") {
             url_path
         } else {
             &format!("{}{}", BASE_URL, url_path)
         };
-        Ok(
-            self.client
-                .get(url)
-                .send()
-                .await?
-                .text()
-                .await?
-        )
+
+        let result = match &self.cassette {
+            Some(cassette) if cassette.is_replay() => cassette.play(url),
+            Some(cassette) => {
+                let result = self.fetch_live(url).await;
+                if let Err(e) = cassette.record(url, &result) {
+                    warn!("cannot record the response of [{}]: {:?}", url, e);
+                }
+                result
+            }
+            None => self.fetch_live(url).await,
+        };
+        metrics::inc(if result.is_ok() { Counter::PagesFetched } else { Counter::FetchFailures });
+        result.map_err(|e| {
+            if Error::of(&e).is_some() || e.is::<BudgetExhausted>() || e.is::<Interrupted>() {
+                return e;
+            }
+            e.context(Error::Fetch { url: url.to_string(), status: None, retry_after: None })
+        })
     }
 }
 
-pub struct ATMBCrawl {
-    client: ATMBClient,
+impl ATMBClient {
+    /// fetch the page, through the headless browser if it's blocked by anti-bot and `--browser` is given
+    async fn fetch_live(&self, url: &str) -> anyhow::Result<String> {
+        let result = self.fetch_url(url).await;
+        #[cfg(feature = "headless")]
+        if let (Some(browser), Err(e)) = (&self.browser, &result) {
+            if matches!(Error::of(e), Some(Error::Blocked { .. })) {
+                warn!("[{}] is blocked by anti-bot, render it with the headless browser", url);
+                return browser.render(url).await;
+            }
+        }
+        result
+    }
+
+    /// if the page has been cached by a previous run, a conditional request is sent and the
+    /// cached content is reused when the server reports that the page has not been modified.
+    async fn fetch_url(&self, url: &str) -> anyhow::Result<String> {
+        let cached = self.cache.get(url);
+        let mut headers = HeaderMap::new();
+        if let Some(meta) = cached.as_ref().map(|page| &page.meta) {
+            if let Some(etag) = meta.etag.as_deref().and_then(|etag| HeaderValue::from_str(etag).ok()) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = meta.last_modified.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+                headers.insert(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let FetchedPage { status, meta, body } = retry_wrapper(|| self.send_guarded(url, headers.clone())).await?;
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                debug!("[{}] is not modified, use the cached page", url);
+                return Ok(cached.body);
+            }
+        }
+
+        // only pages that can be validated later are worth caching
+        if status.is_success() && (meta.etag.is_some() || meta.last_modified.is_some()) {
+            let page = CachedPage { meta, body };
+            if let Err(e) = self.cache.put(url, &page) {
+                warn!("cannot cache page [{}]: {:?}", url, e);
+            }
+            return Ok(page.body);
+        }
+        Ok(body)
+    }
 }
 
-impl ATMBCrawl {
-    pub fn new() -> anyhow::Result<Self> {
+/// A country listed on ATMB's location index, i.e. `United Kingdom` at `/l/united-kingdom`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountryEntry {
+    pub name: String,
+    /// path of the country page
+    pub path: String,
+    /// `None` if its addresses cannot be parsed and verified yet
+    pub country: Option<Country>,
+}
+
+/// The countries ATMB has locations in, discovered from its location index.
+#[derive(Debug)]
+pub struct CountryRegistry {
+    entries: Vec<CountryEntry>,
+}
+
+impl CountryRegistry {
+    /// fetch the location index
+    pub async fn discover(config: &CrawlConfig) -> anyhow::Result<Self> {
+        Self::fetch(&ATMBClient::new(config)?).await
+    }
+
+    async fn fetch(fetcher: &impl PageFetcher) -> anyhow::Result<Self> {
+        let html = fetcher.fetch_page(LOCATION_INDEX_URL).await?;
+        let index = LocationIndexPage::parse_html(&html).map_err(|e| Error::at_url(e, LOCATION_INDEX_URL))?;
         Ok(
             Self {
-                client: ATMBClient::new()?,
+                entries: index.countries.into_iter()
+                    .map(|(path, name)| CountryEntry {
+                        name: name.to_string(),
+                        path: path.to_string(),
+                        country: name.parse().ok(),
+                    })
+                    .collect(),
             }
         )
     }
 
-    pub async fn fetch(&self) -> anyhow::Result<Vec<Mailbox>> {
-        // we're only interested in US, so hardcode here.
-        let country_html = self.client.fetch_page(US_HOME_PAGE_URL).await?;
-        let country_page = CountryPage::parse_html(&country_html)?;
+    pub fn entries(&self) -> &[CountryEntry] {
+        &self.entries
+    }
 
-        let state_pages = self.fetch_state_pages(&country_page).await?;
-        let total_num = state_pages.iter().map(|sp| sp.len()).sum::<usize>();
+    /// the listed countries that can be crawled
+    pub fn supported(&self) -> Vec<Country> {
+        self.entries.iter().filter_map(|entry| entry.country).collect()
+    }
 
-        let mailboxes = state_pages.into_iter()
-            .filter_map(|sp| match sp.to_mailboxes() {
-                Ok(mailboxes) => Some(mailboxes),
-                Err(e) => {
-                    log::error!("cannot convert state page to mailboxes: {:?}", e);
-                    None
+    /// path of the country page, an error if ATMB doesn't list the country
+    pub fn path(&self, country: Country) -> anyhow::Result<&str> {
+        self.entries.iter()
+            .find(|entry| entry.country == Some(country))
+            .map(|entry| entry.path.as_str())
+            .ok_or_else(|| anyhow!("{} is not found on the location index of ATMB", country))
+    }
+}
+
+/// The crawl finished, but some mailbox's detail cannot be fetched.
+///
+/// It carries what has been fetched so far, so the progress can be saved.
+#[derive(Debug)]
+pub struct IncompleteCrawl {
+    pub fetched: Vec<Mailbox>,
+    pub failed: Vec<Mailbox>,
+    /// the crawl stopped as the `--max-requests` budget is used up, the failed ones are not fetched yet
+    pub exhausted: bool,
+    /// the crawl stopped on Ctrl-C, the failed ones are not fetched yet
+    pub interrupted: bool,
+}
+
+impl Display for IncompleteCrawl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.interrupted {
+            write!(f, "The crawl is interrupted ({} mailbox's detail not fetched yet)", self.failed.len())
+        } else if self.exhausted {
+            write!(f, "The request budget is used up ({} mailbox's detail not fetched yet)", self.failed.len())
+        } else {
+            write!(f, "Some mailbox's detail cannot be fetched ({} failed)", self.failed.len())
+        }
+    }
+}
+
+impl std::error::Error for IncompleteCrawl {}
+
+/// what [`ATMBCrawl::crawl_states`] has got
+struct StateCrawl {
+    fetched: Vec<Mailbox>,
+    /// the ones whose detail page cannot be fetched
+    failed: Vec<Mailbox>,
+    /// how many state pages cannot be fetched, their locations are unknown
+    failed_states: usize,
+}
+
+pub struct ATMBCrawl<F: PageFetcher = ATMBClient> {
+    client: F,
+    /// mailboxes whose detail page has been fetched, keyed by the link
+    fetched: HashMap<String, Mailbox>,
+    /// mailboxes of the previous run, reused if their state page entry is unchanged, keyed by the link
+    previous: HashMap<String, Mailbox>,
+    /// maximum concurrent requests of the detail pages, the state pages use half of it
+    concurrency: usize,
+    /// only the state pages selected via `--states` are fetched
+    states: Option<Vec<UsState>>,
+    /// whose country page the state or province pages are listed on
+    country: Country,
+    /// cross-check against the sitemap, and fetch the locations missing from the state pages
+    sitemap: bool,
+    /// the pause before retrying the failed detail pages, see [`CrawlConfig::retry_delay`]
+    retry_delay: Option<Duration>,
+    /// a detail page failed as the `--max-requests` budget is used up
+    exhausted: Cell<bool>,
+}
+
+impl ATMBCrawl {
+    pub fn new(config: &CrawlConfig) -> anyhow::Result<Self> {
+        Ok(Self::with_fetcher(ATMBClient::new(config)?, config))
+    }
+}
+
+impl<F: PageFetcher> ATMBCrawl<F> {
+    /// crawl the pages got by `fetcher`, i.e. a [`FixtureFetcher`] of the saved pages,
+    /// the rate limit and the proxies of `config` are up to the fetcher
+    pub fn with_fetcher(fetcher: F, config: &CrawlConfig) -> Self {
+        Self {
+            client: fetcher,
+            fetched: HashMap::new(),
+            previous: HashMap::new(),
+            concurrency: config.concurrency.max(1),
+            states: config.states.clone(),
+            country: config.country,
+            sitemap: config.sitemap,
+            retry_delay: config.retry_delay,
+            exhausted: Cell::new(false),
+        }
+    }
+
+    /// skip the detail pages of the mailboxes that have been fetched before
+    pub fn with_fetched(mut self, mailboxes: impl IntoIterator<Item = Mailbox>) -> Self {
+        self.fetched.extend(mailboxes.into_iter().map(|mailbox| (mailbox.link.clone(), mailbox)));
+        self
+    }
+
+    /// skip the detail pages of the mailboxes whose entry on the state page is the same as in the previous run
+    pub fn with_previous(mut self, mailboxes: impl IntoIterator<Item = Mailbox>) -> Self {
+        self.previous.extend(
+            mailboxes.into_iter()
+                .filter(|mailbox| !mailbox.listing.is_empty())
+                .map(|mailbox| (mailbox.link.clone(), mailbox))
+        );
+        self
+    }
+
+    /// visit the detail page of the mailbox, returns it back as the error if the page cannot be fetched
+    async fn fetch_detail(&self, mut mailbox: Mailbox, progress: &Progress) -> Result<Mailbox, Mailbox> {
+        // the rest cannot be fetched anyway, they're left to the next run
+        if self.exhausted.get() || shutdown::is_interrupted() {
+            progress.inc(1);
+            return Err(mailbox);
+        }
+        progress.message(progress.next_position(), format_args!("fetching the detail page of [{}]...", mailbox.name));
+        let result = tokio::select! {
+            result = self.fetch_location_detail_page(&mailbox.link) => result,
+            _ = shutdown::interrupted() => Err(Interrupted.into()),
+        };
+        progress.inc(1);
+        match result {
+            Ok(detail_page) => {
+                detail_page.update_mailbox(&mut mailbox);
+                Ok(mailbox)
+            }
+            Err(err) => {
+                tracing::error!("cannot fetch detail page for [{}]: {:?}", mailbox.name, err);
+                Err(mailbox)
+            }
+        }
+    }
+
+    /// give the failed detail pages another chance, one at a time after a pause
+    ///
+    /// most of the failures are transient, i.e. ATMB throttling the concurrent requests,
+    /// so they likely succeed once the crawl has calmed down.
+    async fn retry_failed(&self, failed: Vec<Mailbox>, delay: Duration) -> (Vec<Mailbox>, Vec<Mailbox>) {
+        info!("[{}] detail pages cannot be fetched, retry them one by one in {}s", failed.len(), delay.as_secs());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown::interrupted() => {}
+        }
+
+        let progress = Progress::new("retried detail pages", failed.len());
+        let mut fetched = Vec::new();
+        let mut still_failed = Vec::new();
+        for (idx, mut mailbox) in failed.into_iter().enumerate() {
+            if shutdown::is_interrupted() {
+                still_failed.push(mailbox);
+                progress.inc(1);
+                continue;
+            }
+            if idx > 0 {
+                tokio::time::sleep(delay / RETRY_INTERVAL_DIVISOR).await;
+            }
+            progress.message(idx + 1, format_args!("retrying the detail page of [{}]...", mailbox.name));
+            match self.fetch_location_detail_page(&mailbox.link).await {
+                Ok(detail_page) => {
+                    detail_page.update_mailbox(&mut mailbox);
+                    fetched.push(mailbox);
                 }
-            })
-            .flatten()
+                Err(err) => {
+                    tracing::error!("cannot fetch detail page for [{}] again: {:?}", mailbox.name, err);
+                    still_failed.push(mailbox);
+                }
+            }
+            progress.inc(1);
+        }
+        progress.finish();
+        info!("[{}] of the failed detail pages are fetched by the retry", fetched.len());
+        (fetched, still_failed)
+    }
+
+    /// fetch the state pages, and the detail pages of their locations as soon as each state page is parsed,
+    /// rather than after every state page is done
+    ///
+    /// the mailboxes fetched before or unchanged since the previous run don't visit the detail page again.
+    async fn crawl_states(&self, country_page: &CountryPage) -> StateCrawl {
+        let states = country_page.states.iter()
+            .filter(|state_html_info| state::is_selected(self.states.as_deref(), state_html_info.name()))
             .collect::<Vec<_>>();
+        let progress = Progress::new("detail pages", 0);
+        let (failed_states, known, unchanged) = (Cell::new(0), Cell::new(0), Cell::new(0));
 
-        if mailboxes.len() != total_num {
-            bail!("Some mailboxes cannot be fetched");
+        let results = futures::stream::iter(states)
+            .map(|state_html_info| async move {
+                debug!("fetching [{}] state page...", state_html_info.name());
+                let state_page = self.fetch_state_page(state_html_info.url()).await
+                    .map(|state_page| state_page.with_state(state_html_info.name()));
+                (state_html_info.name(), state_page)
+            })
+            .buffer_unordered((self.concurrency / 2).max(1))
+            .flat_map(|(name, state_page)| {
+                let mailboxes = state_page.and_then(|state_page| state_page.to_mailboxes(self.country))
+                    .unwrap_or_else(|e| {
+                        tracing::error!("cannot fetch state [{}]: {:?}", name, e);
+                        failed_states.set(failed_states.get() + 1);
+                        Vec::new()
+                    });
+                futures::stream::iter(mailboxes)
+            })
+            .map(|mailbox| {
+                let known = self.fetched.get(&mailbox.link).inspect(|_| known.set(known.get() + 1))
+                    .or_else(|| self.previous.get(&mailbox.link)
+                        .filter(|previous| previous.listing == mailbox.listing)
+                        .inspect(|_| unchanged.set(unchanged.get() + 1)));
+                if known.is_none() {
+                    progress.add_total(1);
+                }
+                let progress = &progress;
+                async move {
+                    match known {
+                        Some(known) => Ok(known.clone()),
+                        None => self.fetch_detail(mailbox, progress).await,
+                    }
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect::<Vec<_>>()
+            .await;
+        progress.finish();
+
+        if known.get() > 0 {
+            info!("[{}] mailboxes have been fetched before, skip their detail pages", known.get());
+        }
+        if unchanged.get() > 0 {
+            info!("[{}] locations are unchanged since the previous run, skip their detail pages", unchanged.get());
         }
+        let (mut fetched, mut failed) = (Vec::with_capacity(results.len()), Vec::new());
+        for result in results {
+            match result {
+                Ok(mailbox) => fetched.push(mailbox),
+                Err(mailbox) => failed.push(mailbox),
+            }
+        }
+        StateCrawl { fetched, failed, failed_states: failed_states.get() }
+    }
 
-        // visit every mailbox detail page to get the address line 2
-        let mailboxes = self.update_street2_for_mailbox(mailboxes).await?;
-        if mailboxes.len() != total_num {
-            bail!("Some mailbox's detail cannot be fetched");
+    /// fetch the state page, and every following page if it's paginated
+    async fn fetch_state_page(&self, url: &str) -> anyhow::Result<StatePage> {
+        let mut state_page = StatePage::parse_html(&self.client.fetch_page(url).await?).map_err(|e| Error::at_url(e, url))?;
+        let mut visited = vec![url.to_string()];
+        while let Some(next_page) = state_page.next_page().map(String::from) {
+            if visited.contains(&next_page) || visited.len() >= MAX_STATE_PAGES {
+                bail!("the pages of [{}] loop or exceed {} pages, the pagination might be changed", url, MAX_STATE_PAGES);
+            }
+            debug!("fetching the next page [{}] of [{}]", next_page, url);
+            state_page.append(StatePage::parse_html(&self.client.fetch_page(&next_page).await?).map_err(|e| Error::at_url(e, &next_page))?);
+            visited.push(next_page);
         }
+        Ok(state_page)
+    }
 
-        Ok(mailboxes)
+    /// the links of every location detail page listed on the sitemap, following the sitemap index
+    async fn fetch_sitemap_links(&self) -> anyhow::Result<HashSet<String>> {
+        let mut pending = vec![SITEMAP_URL.to_string()];
+        let mut visited = HashSet::new();
+        let mut links = HashSet::new();
+        while let Some(url) = pending.pop() {
+            if !visited.insert(url.clone()) {
+                continue;
+            }
+            if visited.len() > MAX_SITEMAPS {
+                bail!("the sitemap lists more than {} sitemaps, its format might be changed", MAX_SITEMAPS);
+            }
+            let sitemap = Sitemap::parse_xml(&self.client.fetch_page(&url).await?)?;
+            pending.extend(sitemap.sitemaps);
+            links.extend(sitemap.urls.iter()
+                .filter(|url| url.contains("/s/"))
+                .map(|url| normalize_link(url)));
+        }
+        Ok(links)
     }
 
-    async fn update_street2_for_mailbox(&self, mailboxes: Vec<Mailbox>) -> anyhow::Result<Vec<Mailbox>> {
-        let total_mailboxes = mailboxes.len();
+    /// compare the crawled mailboxes with the sitemap, and fetch the locations only the sitemap lists
+    ///
+    /// the sitemap lists the locations of every country, the ones under the path of another country
+    /// are dropped before fetching, see [`in_country`]. The other ones, and the ones of the states
+    /// not selected, are fetched and dropped, the cache keeps the following runs cheap.
+    async fn recover_from_sitemap(&self, mailboxes: &[Mailbox], country_path: &str) -> anyhow::Result<Vec<Mailbox>> {
+        let all_links = self.fetch_sitemap_links().await?;
+        let sitemap_links = all_links.iter()
+            .filter(|link| in_country(link, country_path))
+            .cloned()
+            .collect::<HashSet<_>>();
+        let crawled = mailboxes.iter().map(|mailbox| normalize_link(&mailbox.link)).collect::<HashSet<_>>();
+        let missing = sitemap_links.difference(&crawled).cloned().collect::<Vec<_>>();
+        info!(
+            "the sitemap lists [{}] locations of all the countries, [{}] may be in [{}], [{}] of the [{}] crawled are listed, [{}] are not crawled",
+            all_links.len(), sitemap_links.len(), self.country, crawled.intersection(&sitemap_links).count(), crawled.len(), missing.len(),
+        );
 
-        let mailboxes = futures::stream::iter(mailboxes).enumerate().map(|(idx, mut mailbox)| {
+        let progress = Progress::new("sitemap locations", missing.len());
+        let recovered = futures::stream::iter(missing).enumerate().map(|(idx, link)| {
+            let progress = &progress;
             async move {
-                let fut = || async {
-                    info!("[{}/{}] fetching the detail page of [{}]...", idx + 1, total_mailboxes, mailbox.name);
-                    let detail_page = self.fetch_location_detail_page(&mailbox.link).await?;
-                    mailbox.address.line1 = detail_page.street();
-                    Result::<_, anyhow::Error>::Ok(mailbox)
-                };
-                match fut().await {
-                    Ok(mailbox) => Some(mailbox),
-                    Err(err) => {
-                        log::error!("cannot fetch detail page for: {:?}", err);
+                progress.message(idx + 1, format_args!("fetching the detail page of [{}]...", link));
+                let mailbox = self.fetch_location_detail_page(&link).await
+                    .and_then(|detail_page| match detail_page.country() {
+                        Some(country) if country == self.country => detail_page.to_mailbox(link.as_str(), country).map(Some),
+                        _ => Ok(None),
+                    });
+                progress.inc(1);
+                match mailbox {
+                    Ok(mailbox) => mailbox.filter(|mailbox| state::is_selected(self.states.as_deref(), &mailbox.address.state)),
+                    Err(e) => {
+                        warn!("cannot recover the location [{}] from the sitemap: {:?}", link, e);
                         None
                     }
                 }
             }
         })
-            .buffer_unordered(10)
+            .buffer_unordered(self.concurrency)
+            .filter_map(|mailbox| async move { mailbox })
             .collect::<Vec<_>>()
             .await;
+        progress.finish();
 
-        // let mailboxes = mailboxes.into_iter().filter_map(|mailbox| match mailbox {
-        //     Ok(mailbox) => Some(mailbox),
-        //     Err(e) => {
-        //         log::error!("cannot fetch detail page: {:?}", e);
-        //         None
-        //     }
-        // })
-        //     .collect();
-        let mailboxes = mailboxes.into_iter().filter_map(|mailbox| mailbox).collect();
-        Ok(mailboxes)
-    }
-
-    async fn fetch_state_pages(&self, country_page: &CountryPage<'_>) -> anyhow::Result<Vec<StatePage>> {
-        let total_states = country_page.states.len();
-        let state_pages: Vec<anyhow::Result<StatePage>> = futures::stream::iter(&country_page.states).enumerate().map(|(idx, state_html_info)| {
-            info!("[{}/{total_states}] fetching [{}] state page...", idx + 1, state_html_info.name());
-            async move {
-                let state_html = self.client.fetch_page(state_html_info.url()).await?;
-                Ok(StatePage::parse_html(&state_html)?)
-            }
-        })
-            // limit concurrent requests to 5
-            .buffer_unordered(5)
-            .collect()
-            .await;
-
-        if state_pages.iter().filter_map(|state_page| match state_page {
-            Err(e) => {
-                log::error!("cannot fetch state: {:?}", e);
-                Some(())
-            }
-            _ => None
-        })
-            .count() != 0 {
-            bail!("Some states cannot be fetched");
+        if !recovered.is_empty() {
+            info!("[{}] locations missing from the state pages are recovered from the sitemap", recovered.len());
         }
-        Ok(state_pages.into_iter().map(|state_page| state_page.unwrap()).collect())
+        Ok(recovered)
     }
 
+    #[instrument(name = "detail", skip(self))]
     async fn fetch_location_detail_page(&self, mailbox_link: &str) -> anyhow::Result<LocationDetailPage> {
-        let html = self.client.fetch_page(mailbox_link).await?;
-        Ok(LocationDetailPage::parse_html(&html)?)
+        let html = self.client.fetch_page(mailbox_link).await
+            .inspect_err(|e| if e.is::<BudgetExhausted>() {
+                self.exhausted.set(true);
+            })?;
+        LocationDetailPage::parse_html(&html).map_err(|e| Error::at_url(e, mailbox_link))
+    }
+}
+
+/// whether the sitemap link can be a location in the country at `country_path`, i.e. `/l/usa`
+///
+/// only the links under the path of a country, i.e. `/l/canada/...`, are told apart, the country of
+/// the other ones, i.e. `/s/birmingham-120-19th-street-north`, is only known from their detail page.
+fn in_country(link: &str, country_path: &str) -> bool {
+    let path = link.strip_prefix(BASE_URL).unwrap_or(link);
+    if !path.starts_with("/l/") {
+        return true;
+    }
+    path.strip_prefix(country_path.trim_end_matches('/')).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// the full link without the trailing `/`, the sitemap and the state pages may write it differently
+fn normalize_link(link: &str) -> String {
+    let link = link.trim().trim_end_matches('/');
+    if link.starts_with('/') {
+        format!("{}{}", BASE_URL, link)
+    } else {
+        link.to_string()
+    }
+}
+
+#[async_trait(?Send)]
+impl<F: PageFetcher> MailboxProvider for ATMBCrawl<F> {
+    fn name(&self) -> &str {
+        "atmb"
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<Mailbox>> {
+        let registry = CountryRegistry::fetch(&self.client).await?;
+        let country_path = registry.path(self.country)?;
+        let country_html = self.client.fetch_page(country_path).await?;
+        let country_page = CountryPage::parse_html(&country_html).map_err(|e| Error::at_url(e, country_path))?;
+
+        // the detail pages are fetched along with the state pages
+        let StateCrawl { mut fetched, mut failed, failed_states } = self.crawl_states(&country_page).await;
+        if failed_states > 0 && !shutdown::is_interrupted() {
+            bail!("Some states cannot be fetched");
+        }
+        if self.sitemap && !shutdown::is_interrupted() {
+            let crawled = fetched.iter().chain(&failed).cloned().collect::<Vec<_>>();
+            match self.recover_from_sitemap(&crawled, country_path).await {
+                Ok(recovered) => fetched.extend(recovered),
+                Err(e) => warn!("cannot cross-check the locations against the sitemap: {:?}", e),
+            }
+        }
+
+        if let Some(delay) = self.retry_delay.filter(|_| !failed.is_empty() && !self.exhausted.get() && !shutdown::is_interrupted()) {
+            let (retried, still_failed) = self.retry_failed(failed, delay).await;
+            fetched.extend(retried);
+            failed = still_failed;
+        }
+        if !failed.is_empty() {
+            return Err(IncompleteCrawl { fetched, failed, exhausted: self.exhausted.get(), interrupted: shutdown::is_interrupted() }.into());
+        }
+
+        Ok(fetched)
     }
-}
\ No newline at end of file
+}
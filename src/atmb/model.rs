@@ -1,15 +1,119 @@
+use std::hash::{Hash, Hasher};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use crate::country::Country;
+use crate::provider::ProviderKind;
+
+/// the country names an address may end with
+const COUNTRY_SUFFIXES: [(&str, Country); 8] = [
+    (", USA", Country::Us), (", US", Country::Us), (", United States", Country::Us), (", Canada", Country::Ca),
+    (", United Kingdom", Country::Uk), (", UK", Country::Uk), (", Australia", Country::Au), (", AU", Country::Au),
+];
 
 /// basic structure for an address
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Address {
     pub line1: String,
     pub city: String,
+    /// the state, or the province of a Canadian address, can be empty in the UK
     pub state: String,
+    /// the ZIP, or the postal code outside the US, i.e. `M5E 1W7`
     pub zip: String,
     pub zip4: Option<String>,
+    /// addresses saved before the other countries were supported are all in the US
+    #[serde(default)]
+    pub country: Country,
 }
 
 impl Address {
+    /// build the address from the street line and the `City, ST 12345[-6789]` line,
+    /// the country is told by the postal code, i.e. `City, ON M5E 1W7` is a Canadian address
+    pub fn from_lines(line1: impl Into<String>, line2: &str) -> anyhow::Result<Self> {
+        Self::from_lines_in(Country::detect(line2).unwrap_or_default(), line1, line2)
+    }
+
+    /// build the address of the country from the street line and the `City, REGION POSTAL_CODE` line,
+    /// the region is kept as written, and can be left out in the UK, i.e. `London, EC1V 2NX`
+    pub fn from_lines_in(country: Country, line1: impl Into<String>, line2: &str) -> anyhow::Result<Self> {
+        let (rest, zip, zip4) = country.split_postal_code(line2)
+            .ok_or_else(|| anyhow!("Failed to parse zip code from: {}", line2))?;
+        let (city, state) = match rest.split_once(",") {
+            Some((city, state)) => (city.trim(), state.trim()),
+            None => (rest.trim(), ""),
+        };
+        if city.is_empty() {
+            bail!("Failed to parse city from: {}", line2);
+        }
+        if state.is_empty() && country.requires_region() {
+            bail!("Failed to parse state from: {}", line2);
+        }
+        Ok(
+            Self {
+                line1: line1.into(),
+                city: city.to_string(),
+                state: state.to_string(),
+                zip,
+                zip4,
+                country,
+            }
+        )
+    }
+
+    /// parse a one line address, i.e. `156 S Main St, Lumberton, TX 77657` or `1 Yonge St, Toronto, ON M5E 1W7`
+    ///
+    /// the state can be its code or name, and the city can be separated from the state by a space instead of a comma,
+    /// i.e. `156 S Main St, Lumberton TX 77657-1234, USA`. The country is told by the trailing country name if any,
+    /// otherwise by the postal code.
+    pub fn parse(address: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow!("cannot parse [{}], expected i.e. `156 S Main St, Lumberton, TX 77657`", address);
+        let mut rest = address.trim().trim_end_matches(['.', ',']);
+        let mut country = None;
+        for (name, named) in COUNTRY_SUFFIXES {
+            if rest.len() >= name.len() && rest[rest.len() - name.len()..].eq_ignore_ascii_case(name) {
+                rest = &rest[..rest.len() - name.len()];
+                country = Some(named);
+                break;
+            }
+        }
+        let country = country.or_else(|| Country::detect(rest)).ok_or_else(invalid)?;
+        let (rest, zip, zip4) = country.split_postal_code(rest).ok_or_else(invalid)?;
+
+        let mut parts = rest.split(',').map(str::trim).collect::<Vec<_>>();
+        let last = parts.pop().ok_or_else(invalid)?;
+        let (city, state) = match country.region_code(last) {
+            // the state is on its own, the city is the previous part
+            Some(state) => (parts.pop().ok_or_else(invalid)?, state),
+            // `City ST`, the state name may have several words, i.e. `New York New York`
+            None => {
+                let words = last.split_whitespace().collect::<Vec<_>>();
+                let city_and_state = (1..words.len().min(4))
+                    .find_map(|n| {
+                        let state = country.region_code(&words[words.len() - n..].join(" "))?;
+                        let city = last.rsplitn(n + 1, char::is_whitespace).last()?.trim();
+                        Some((city, state))
+                    });
+                match city_and_state {
+                    Some(city_and_state) => city_and_state,
+                    None if !country.requires_region() => (last, ""),
+                    None => return Err(invalid()),
+                }
+            }
+        };
+        if parts.is_empty() || city.is_empty() {
+            return Err(invalid());
+        }
+        Ok(
+            Self {
+                line1: parts.join(", "),
+                city: city.to_string(),
+                state: state.to_string(),
+                zip,
+                zip4,
+                country,
+            }
+        )
+    }
+
     pub fn full_zip(&self) -> String {
         match &self.zip4 {
             Some(zip4) => format!("{}-{}", self.zip, zip4),
@@ -19,10 +123,210 @@ impl Address {
 }
 
 /// Complete ATMB information for a mailbox
-#[derive(Debug, Hash, PartialEq, Eq)]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Mailbox {
+    /// where the mailbox is crawled from
+    #[serde(default)]
+    pub provider: ProviderKind,
     pub name: String,
     pub address: Address,
     pub link: String,
-    pub price: String,
-}
\ No newline at end of file
+    /// `None` if the price is not shown or cannot be parsed
+    pub price: Option<Price>,
+    /// the price as scraped, i.e. `US$9.99/month`, kept for debugging
+    #[serde(default)]
+    pub raw_price: String,
+    /// phone number of the location, if its detail page shows one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+    /// the company running the location, i.e. `Forge`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    /// plans of the location listed on its detail page, ATMB only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plans: Vec<Plan>,
+    /// coordinates of the location, only known if its page has structured data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geo: Option<GeoPoint>,
+    /// the entry of the location on the state page as scraped, i.e. `name|street|city line|price`,
+    /// its detail page is not fetched again with `--incremental` if it's unchanged, empty for the other providers
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub listing: String,
+}
+
+#[cfg(test)]
+impl Mailbox {
+    /// an ATMB mailbox in Dallas with the given link as its name, at `US$9.99/month`, for tests
+    pub fn sample(link: &str) -> Self {
+        Self {
+            provider: ProviderKind::Atmb,
+            name: link.to_string(),
+            address: Address::from_lines("1 Main St", "Dallas, TX 75201").unwrap(),
+            link: link.to_string(),
+            price: Price::parse("US$9.99/month"),
+            raw_price: "US$9.99/month".to_string(),
+            phone: None,
+            operator: None,
+            plans: Vec::new(),
+            geo: None,
+            listing: String::new(),
+        }
+    }
+}
+
+/// A plan of a location, i.e. `2022 Virtual Mail`
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub name: String,
+    /// the prices as scraped, i.e. `US$39.00/month` and `US$399.00/year`
+    pub prices: Vec<String>,
+    /// `(label, allowance)`, i.e. `("Open & Scan", "10 pages / month")`
+    pub features: Vec<(String, String)>,
+}
+
+impl Plan {
+    /// the scraped price of the period, `None` if the plan cannot be paid by it
+    pub fn price_of(&self, period: PricePeriod) -> Option<&str> {
+        self.prices.iter()
+            .find(|price| Price::parse(price).is_some_and(|price| price.period == period))
+            .map(String::as_str)
+    }
+}
+
+/// Coordinates of a location.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// the coordinates are scraped rather than computed, so they're compared bitwise
+impl Eq for GeoPoint {}
+
+impl Hash for GeoPoint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.latitude.to_bits().hash(state);
+        self.longitude.to_bits().hash(state);
+    }
+}
+
+/// billing period of a price
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PricePeriod {
+    Month,
+    Year,
+}
+
+/// Price of the cheapest plan of a mailbox, i.e. `US$9.99/month`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Price {
+    /// in cents, so the prices are compared exactly
+    pub amount: u64,
+    /// ISO 4217 code, i.e. `USD`
+    pub currency: String,
+    pub period: PricePeriod,
+}
+
+impl Price {
+    /// parse the price scraped by the crawlers, `None` if it's empty or in an unknown format
+    pub fn parse(price: &str) -> Option<Self> {
+        let price = price.replace(char::is_whitespace, "");
+        let (amount, period) = match price.split_once('/') {
+            Some((amount, period)) => (amount, period),
+            None => (price.as_str(), "month"),
+        };
+        let period = match period.to_lowercase().as_str() {
+            "month" | "mo" => PricePeriod::Month,
+            "year" | "yr" => PricePeriod::Year,
+            _ => return None,
+        };
+        let digits = amount.find(|c: char| c.is_ascii_digit())?;
+        let currency = match &amount[..digits] {
+            "$" | "US$" | "USD" => "USD",
+            "CA$" | "C$" | "CAD" => "CAD",
+            "A$" | "AU$" | "AUD" => "AUD",
+            "£" | "GBP" => "GBP",
+            "€" | "EUR" => "EUR",
+            _ => return None,
+        };
+        let amount = amount[digits..].replace(',', "");
+        let (dollars, cents) = amount.split_once('.').unwrap_or((&amount, "0"));
+        let cents = match cents.len() {
+            1 => cents.parse::<u64>().ok()? * 10,
+            2 => cents.parse::<u64>().ok()?,
+            _ => return None,
+        };
+        Some(
+            Self {
+                amount: dollars.parse::<u64>().ok()? * 100 + cents,
+                currency: currency.to_string(),
+                period,
+            }
+        )
+    }
+
+    /// the amount in dollars (or euros, pounds...), i.e. `9.99`
+    pub fn decimal_amount(&self) -> f64 {
+        self.amount as f64 / 100.0
+    }
+
+    /// the amount per month in cents, yearly prices are divided by 12
+    pub fn monthly(&self) -> u64 {
+        match self.period {
+            PricePeriod::Month => self.amount,
+            PricePeriod::Year => self.amount.div_ceil(12),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_free_form_address() {
+        let address = Address::parse("156 S Main St, Lumberton, TX 77657").unwrap();
+        assert_eq!(address, Address {
+            line1: "156 S Main St".to_string(),
+            city: "Lumberton".to_string(),
+            state: "TX".to_string(),
+            zip: "77657".to_string(),
+            zip4: None,
+            country: Country::Us,
+        });
+        let address = Address::parse("1 Main St, Ste 200, New York New York 10001-1234, USA").unwrap();
+        assert_eq!(address.line1, "1 Main St, Ste 200");
+        assert_eq!((address.city.as_str(), address.state.as_str()), ("New York", "NY"));
+        let address = Address::parse("1 Main St, Lumberton TX 77657-1234").unwrap();
+        assert_eq!((address.city.as_str(), address.zip4.as_deref()), ("Lumberton", Some("1234")));
+        assert!(Address::parse("Lumberton, TX 77657").is_err());
+        assert!(Address::parse("156 S Main St, Lumberton, TX").is_err());
+
+        let address = Address::parse("1 Yonge St, Toronto, Ontario m5e1w7, Canada").unwrap();
+        assert_eq!((address.state.as_str(), address.zip.as_str(), address.country), ("ON", "M5E 1W7", Country::Ca));
+        let address = Address::from_lines("1 Yonge St", "Toronto, ON M5E 1W7").unwrap();
+        assert_eq!((address.zip.as_str(), address.zip4, address.country), ("M5E 1W7", None, Country::Ca));
+
+        let address = Address::parse("221B Baker St, London NW1 6XE, UK").unwrap();
+        assert_eq!((address.city.as_str(), address.state.as_str(), address.zip.as_str()), ("London", "", "NW1 6XE"));
+        let address = Address::from_lines_in(Country::Uk, "1 City Rd", "London, ec1v2nx").unwrap();
+        assert_eq!((address.city.as_str(), address.zip.as_str()), ("London", "EC1V 2NX"));
+        let address = Address::parse("1 George St, Sydney, New South Wales 2000").unwrap();
+        assert_eq!((address.state.as_str(), address.zip.as_str(), address.country), ("NSW", "2000", Country::Au));
+        assert!(Address::from_lines_in(Country::Au, "1 George St", "Sydney 2000").is_err());
+    }
+
+    #[test]
+    fn test_parse_price() {
+        assert_eq!(
+            Price::parse("US$9.99/month"),
+            Some(Price { amount: 999, currency: "USD".to_string(), period: PricePeriod::Month }),
+        );
+        assert_eq!(Price::parse("US$ 1,199.5 / year").map(|price| (price.amount, price.monthly())), Some((119950, 9996)));
+        assert_eq!(Price::parse("$15").map(|price| price.monthly()), Some(1500));
+        assert_eq!(Price::parse(""), None);
+        assert_eq!(Price::parse("US$9.999/month"), None);
+        assert_eq!(Price::parse("call us"), None);
+    }
+}
@@ -2,55 +2,108 @@ use std::sync::LazyLock;
 use anyhow::{anyhow, bail};
 use regex::Regex;
 use scraper::{Html, Selector};
-use crate::atmb::model::{Address, Mailbox};
-
-static STATE_LIST_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<a class='theme-simple-link' href='(.*?)'>(.*?)</a>"#).unwrap());
+use tracing::warn;
+use crate::atmb::model::{Address, GeoPoint, Mailbox, Plan, Price, PricePeriod};
+use crate::atmb::selector::{snippet, Strategies};
+use crate::country::Country;
+use crate::provider::ProviderKind;
+
+static COUNTRY_LIST_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"<a\s+href="(/l/[a-z0-9-]+)"[^>]*>([^<]+)</a>"#).unwrap());
+
+static STATE_LINK: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("state link", &[r#"a[class="theme-simple-link"]"#, r#"a[class~="theme-simple-link"]"#, r#"[class~="theme-simple-link"] a"#]));
+static LOCATION_CONTAINER: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("location", &[r#"div[class="theme-location-item"]"#, r#"[class~="theme-location-item"]"#]));
+static LOCATION_TITLE: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("title", &[r#"h3[class="t-title"]"#, r#"[class~="t-title"]"#, "h3"]));
+static LOCATION_PRICE: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("price", &[r#"div[class="t-price"]"#, r#"[class~="t-price"]"#]));
+static LOCATION_ADDRESS: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("address", &[r#"div[class="t-addr"]"#, r#"[class~="t-addr"]"#, "address"]));
+static LOCATION_PLAN: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("plan button", &[r#"a[class~="gt-plan"]"#, r#"a[href^="/s/"]"#]));
+/// a link to the next page of a paginated state page, i.e. `<link rel="next">` or WordPress' `<a class="next page-numbers">`
+static NEXT_PAGE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"link[rel="next"], a[rel="next"], a[class~="next"]"#).unwrap());
+static PLAN_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class~="t-plan"]"#).unwrap());
+static PLAN_TITLE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="t-title"]"#).unwrap());
+static PLAN_PRICE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class~="t-price"] td"#).unwrap());
+static PLAN_FEATURE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class~="t-feat"]"#).unwrap());
+static PLAN_FEATURE_LABEL_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="t-label"]"#).unwrap());
+static PLAN_FEATURE_ALLOWANCE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="t-allowance"]"#).unwrap());
+static TITLE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("title").unwrap());
+static TITLE_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"Digital Mailboxes in (.+?)(?: \([^()]*\))? \| Anytime Mailbox").unwrap());
+static SITEMAP_LOC_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<loc>\s*(.*?)\s*</loc>").unwrap());
+static LOCATION_DETAIL: LazyLock<Strategies> = LazyLock::new(|| Strategies::new("address of the detail page", &[r#"div[class="t-sec1"] div[class="t-text"]"#, r#"[class~="t-sec1"] [class~="t-text"]"#]));
+static JSON_LD_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"script[type="application/ld+json"]"#).unwrap());
+static MICRODATA_ADDRESS_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[itemtype$="schema.org/PostalAddress"]"#).unwrap());
+static PHONE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"a[href^="tel:"]"#).unwrap());
+/// i.e. `<div class='t-info'> Mail Center Operator: <br><b>Forge</b>`
+static OPERATOR_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class~="t-info"] b"#).unwrap());
+static DIV_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("div").unwrap());
+
+/// ATMB location index. i.e. https://www.anytimemailbox.com/locations
+///
+/// every page links to the country pages in its menu, so they can be found on any of them.
+#[derive(Debug)]
+pub struct LocationIndexPage<'a> {
+    /// `(path, name)` of the country pages, i.e. `("/l/canada", "Canada")`
+    pub countries: Vec<(&'a str, &'a str)>,
+}
 
-static LOCATION_CONTAINER_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class="theme-location-item"]"#).unwrap());
-static LOCATION_TITLE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"h3[class="t-title"]"#).unwrap());
-static LOCATION_PRICE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class="t-price"]"#).unwrap());
-static LOCATION_ADDRESS_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class="t-addr"]"#).unwrap());
-static LOCATION_PLAN_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"a[class~="gt-plan"]"#).unwrap());
-static LOCATION_DETAIL_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class="t-sec1"] div[class="t-text"]"#).unwrap());
+impl<'a> LocationIndexPage<'a> {
+    pub fn parse_html(html: &'a str) -> anyhow::Result<Self> {
+        let mut countries = Vec::<(&str, &str)>::new();
+        for caps in COUNTRY_LIST_REG.captures_iter(html) {
+            let (_, [path, name]) = caps.extract();
+            if !countries.iter().any(|(known, _)| *known == path) {
+                countries.push((path, name.trim()));
+            }
+        }
+        if countries.is_empty() {
+            bail!("No country found, page structure might be changed");
+        }
+        Ok(
+            Self {
+                countries,
+            }
+        )
+    }
+}
 
 /// ATMB country page. i.e. https://www.anytimemailbox.com/l/usa
 #[derive(Debug)]
-pub struct CountryPage<'a> {
-    pub states: Vec<StateHtmlInfo<'a>>,
+pub struct CountryPage {
+    pub states: Vec<StateHtmlInfo>,
 }
 
 #[derive(Debug)]
-pub struct StateHtmlInfo<'a> {
-    sub_url: &'a str,
-    name: &'a str,
+pub struct StateHtmlInfo {
+    sub_url: String,
+    name: String,
 }
 
-impl StateHtmlInfo<'_> {
+impl StateHtmlInfo {
     pub fn url(&self) -> &str {
-        self.sub_url
+        &self.sub_url
     }
 
     pub fn name(&self) -> &str {
-        self.name
+        &self.name
     }
 }
 
-impl<'a> CountryPage<'a> {
+impl CountryPage {
     /// get state list from the country page
-    pub fn parse_html(html: &'a str) -> anyhow::Result<Self> {
+    pub fn parse_html(html: &str) -> anyhow::Result<Self> {
+        let document = Html::parse_document(html);
         let mut states = Vec::new();
 
-        for caps in STATE_LIST_REG.captures_iter(html) {
-            if caps.len() != 3 {
-                bail!("Unexpected capture length: {}, page structure might be changed", caps.len());
-            }
+        for link in STATE_LINK.all(document.root_element()) {
+            let sub_url = link.value().attr("href")
+                .filter(|href| !href.is_empty())
+                .ok_or_else(|| anyhow!("No state link found - {}", snippet(&link.html())))?;
+            let name = link.text().collect::<String>();
             states.push(StateHtmlInfo {
-                sub_url: caps.get(1).unwrap().as_str(),
-                name: caps.get(2).unwrap().as_str(),
+                sub_url: sub_url.to_string(),
+                name: name.trim().to_string(),
             });
         }
         if states.is_empty() {
-            bail!("No state found, page structure might be changed");
+            return Err(STATE_LINK.not_found(document.root_element()).into());
         }
         Ok(
             Self {
@@ -62,13 +115,38 @@ impl<'a> CountryPage<'a> {
 
 /// ATMB state page. i.e. https://www.anytimemailbox.com/l/usa/alabama
 pub struct StatePage {
+    /// name of the state as listed on the country page, i.e. `Alabama`
+    state: Option<String>,
     locations: Vec<LocationHtmlInfo>,
+    /// URL of the next page if the state page is paginated
+    next_page: Option<String>,
 }
 
 impl StatePage {
     pub fn len(&self) -> usize {
         self.locations.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// URL of the next page, `None` if this is the last one or the state page is not paginated
+    pub fn next_page(&self) -> Option<&str> {
+        self.next_page.as_deref()
+    }
+
+    /// the state the page is listed as on the country page
+    pub fn with_state(mut self, state: impl Into<String>) -> Self {
+        self.state = Some(state.into());
+        self
+    }
+
+    /// append the locations of the next page, and take its link to the page after
+    pub fn append(&mut self, next: StatePage) {
+        self.locations.extend(next.locations);
+        self.next_page = next.next_page;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -87,48 +165,51 @@ impl StatePage {
         let mut locations = Vec::new();
 
         let document = Html::parse_document(html);
-        let location_container = document.select(&LOCATION_CONTAINER_SELECTOR);
+        let location_container = LOCATION_CONTAINER.all(document.root_element());
 
         for location_fragment in location_container {
-            let title = location_fragment.select(&LOCATION_TITLE_SELECTOR).next()
-                .ok_or_else(|| anyhow!("No title found - {}", location_fragment.html()))?
+            let title = LOCATION_TITLE.first(location_fragment)?
                 .text()
                 .collect::<String>();
-            let price = location_fragment.select(&LOCATION_PRICE_SELECTOR).next()
-                .ok_or_else(|| anyhow!("No price found - {}", location_fragment.html()))?
+            let price = LOCATION_PRICE.first(location_fragment)?
                 .text()
                 .collect::<String>();
-            let address = location_fragment.select(&LOCATION_ADDRESS_SELECTOR).next()
-                .ok_or_else(|| anyhow!("No address found - {}", location_fragment.html()))?
+            let address = LOCATION_ADDRESS.first(location_fragment)?
                 .inner_html();
             let (line1, line2) = Self::split_address(&address)
-                .ok_or_else(|| anyhow!("Failed to split address - {}", address))?;
-            let plan_link = location_fragment.select(&LOCATION_PLAN_SELECTOR).next()
-                .ok_or_else(|| anyhow!("No plan button found - {}", location_fragment.html()))?
+                .ok_or_else(|| anyhow!("Failed to split address - {}", snippet(&address)))?;
+            let plan_link = LOCATION_PLAN.first(location_fragment)?
                 .value()
                 .attr("href")
-                .ok_or_else(|| anyhow!("No plan link found - {}", location_fragment.html()))?;
+                .ok_or_else(|| anyhow!("No plan link found - {}", snippet(&location_fragment.html())))?;
 
             let location_link = format!("{}{}", super::BASE_URL, plan_link);
             locations.push(LocationHtmlInfo {
-                name: title,
-                line1: line1.to_string(),
-                line2: line2.to_string(),
+                name: title.trim().to_string(),
+                line1: line1.trim().to_string(),
+                line2: line2.trim().to_string(),
                 price,
                 link: location_link,
             });
         }
 
+        let next_page = document.select(&NEXT_PAGE_SELECTOR)
+            .find_map(|link| link.value().attr("href"))
+            .filter(|href| !href.is_empty() && !href.starts_with('#'))
+            .map(String::from);
         Ok(
             Self {
+                state: None,
                 locations,
+                next_page,
             }
         )
     }
 
-    pub fn to_mailboxes(&self) -> anyhow::Result<Vec<Mailbox>> {
+    /// the addresses are parsed in the format of the country, and checked against the state of the page
+    pub fn to_mailboxes(&self, country: Country) -> anyhow::Result<Vec<Mailbox>> {
         self.locations.iter()
-            .map(|location| location.clone().try_into())
+            .map(|location| location.clone().into_mailbox(country, self.state.as_deref()))
             .collect()
     }
 
@@ -139,38 +220,82 @@ impl StatePage {
 }
 
 impl LocationHtmlInfo {
-    fn parse_city(&self) -> Option<&str> {
-        self.line2.split(",")
-            .next()
+    fn price(&self) -> String {
+        self.price.replace("Starting from", "")
+            .replace(" ", "")
     }
 
-    fn parse_state(&self) -> Option<&str> {
-        self.line2.split(",")
-            .skip(1)
-            .next()
-            .map(|s| s.trim())
-            .and_then(|s| s.split(" ").next())
+    /// * `state` - the state of the page the location is listed on, `None` if unknown
+    fn into_mailbox(self, country: Country, state: Option<&str>) -> anyhow::Result<Mailbox> {
+        let raw_price = self.price();
+        let listing = [self.name.as_str(), &self.line1, &self.line2, &raw_price].join("|");
+        let mut address = Address::from_lines_in(country, self.line1, &self.line2)?;
+        if let Some(state) = state {
+            reconcile_state(&mut address, state, &self.name);
+        }
+        Ok(
+            Mailbox {
+                provider: ProviderKind::Atmb,
+                address,
+                price: Price::parse(&raw_price),
+                raw_price,
+                name: self.name,
+                link: self.link,
+                phone: None,
+                operator: None,
+                plans: Vec::new(),
+                geo: None,
+                listing,
+            }
+        )
     }
+}
 
-    fn parse_zip(&self) -> Option<(&str, Option<&str>)> {
-        fn try_split_zip(zip_str: &str) -> Option<(&str, Option<&str>)> {
-            let mut segments = zip_str.split("-");
-            let zip = segments.next()?;
-            let zip4 = segments.next();
-            Some((zip, zip4))
+/// fill in the state the address lacks from the page it's listed on, and flag the address in another state
+///
+/// the parsed state is kept on a mismatch, as the address is what gets verified. returns whether they match.
+fn reconcile_state(address: &mut Address, listed: &str, name: &str) -> bool {
+    let country = address.country;
+    let Some(listed_code) = country.region_code(listed) else {
+        return true;
+    };
+    if address.state.is_empty() {
+        address.state = listed_code.to_string();
+        return true;
+    }
+    match country.region_code(&address.state) {
+        Some(parsed) if parsed != listed_code => {
+            warn!("[{}] is listed under [{}], but its address is in [{}]", name, listed, address.state);
+            false
         }
-
-        self.line2.split(",")
-            .skip(1)
-            .next()
-            .map(|s| s.trim())
-            .and_then(|s| s.split(" ").skip(1).next())
-            .and_then(|s| try_split_zip(s))
+        _ => true,
     }
+}
 
-    fn price(&self) -> String {
-        self.price.replace("Starting from", "")
-            .replace(" ", "")
+/// ATMB sitemap, i.e. https://www.anytimemailbox.com/sitemap.xml, or an index of the sitemaps
+#[derive(Debug)]
+pub struct Sitemap {
+    /// the URLs of the pages, empty if it's an index
+    pub urls: Vec<String>,
+    /// the URLs of the sitemaps listed by an index
+    pub sitemaps: Vec<String>,
+}
+
+impl Sitemap {
+    pub fn parse_xml(xml: &str) -> anyhow::Result<Self> {
+        let locs = SITEMAP_LOC_REG.captures_iter(xml)
+            .map(|caps| caps[1].replace("&amp;", "&"))
+            .collect::<Vec<_>>();
+        if locs.is_empty() {
+            bail!("No URL found in the sitemap, its format might be changed");
+        }
+        Ok(
+            if xml.contains("<sitemapindex") {
+                Self { urls: Vec::new(), sitemaps: locs }
+            } else {
+                Self { urls: locs, sitemaps: Vec::new() }
+            }
+        )
     }
 }
 
@@ -180,32 +305,240 @@ pub struct LocationDetailPage {
     line1: String,
     /// unit, suite, etc.
     line2: Option<String>,
+    /// city, state, zip
+    city_line: String,
+    /// i.e. `United States`
+    country_line: String,
+    /// name of the location as listed on the state page, i.e. `Birmingham - 19th St`
+    name: Option<String>,
+    /// the cheapest monthly plan, i.e. `US$39.00/month`, empty if there's none
+    price: String,
+    plans: Vec<Plan>,
+    phone: Option<String>,
+    /// the company running the location, i.e. `Forge`
+    operator: Option<String>,
+    geo: Option<GeoPoint>,
+}
+
+/// The schema.org `LocalBusiness` markup of a detail page, as JSON-LD or microdata.
+#[derive(Debug, Default, PartialEq)]
+struct StructuredData {
+    street: Option<String>,
+    city: Option<String>,
+    region: Option<String>,
+    postal_code: Option<String>,
+    /// name or code, i.e. `US`
+    country: Option<String>,
+    phone: Option<String>,
+    geo: Option<GeoPoint>,
+}
+
+impl StructuredData {
+    /// the JSON-LD is preferred, `None` if the page has neither
+    fn parse(document: &Html) -> Option<Self> {
+        document.select(&JSON_LD_SELECTOR)
+            .filter_map(|script| serde_json::from_str::<serde_json::Value>(&script.text().collect::<String>()).ok())
+            .find_map(|value| Self::from_json_ld(&value))
+            .or_else(|| Self::from_microdata(document))
+    }
+
+    /// the first object with an address, it can be nested in a list or a `@graph`
+    fn from_json_ld(value: &serde_json::Value) -> Option<Self> {
+        use serde_json::Value;
+
+        match value {
+            Value::Array(values) => values.iter().find_map(Self::from_json_ld),
+            Value::Object(object) if object.contains_key("@graph") => Self::from_json_ld(&object["@graph"]),
+            Value::Object(object) => {
+                let address = object.get("address").filter(|address| address.is_object())?;
+                let text = |value: &Value, key: &str| match value.get(key)? {
+                    Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+                    Value::Number(n) => Some(n.to_string()),
+                    // i.e. `"addressCountry": { "@type": "Country", "name": "US" }`
+                    Value::Object(object) => object.get("name")?.as_str().map(String::from),
+                    _ => None,
+                };
+                let coordinate = |key: &str| text(object.get("geo")?, key)?.parse::<f64>().ok();
+                Some(
+                    Self {
+                        street: text(address, "streetAddress"),
+                        city: text(address, "addressLocality"),
+                        region: text(address, "addressRegion"),
+                        postal_code: text(address, "postalCode"),
+                        country: text(address, "addressCountry"),
+                        phone: text(value, "telephone"),
+                        geo: coordinate("latitude").zip(coordinate("longitude"))
+                            .map(|(latitude, longitude)| GeoPoint { latitude, longitude }),
+                    }
+                )
+            }
+            _ => None,
+        }
+    }
+
+    fn from_microdata(document: &Html) -> Option<Self> {
+        document.select(&MICRODATA_ADDRESS_SELECTOR).next()?;
+        let prop = |name: &str| {
+            let selector = Selector::parse(&format!(r#"[itemprop="{}"]"#, name)).ok()?;
+            let element = document.select(&selector).next()?;
+            // `<meta itemprop="latitude" content="33.5">` carries the value in `content`
+            let value = match element.value().attr("content") {
+                Some(content) => content.to_string(),
+                None => element.text().collect::<String>(),
+            };
+            Some(value.trim().to_string()).filter(|value| !value.is_empty())
+        };
+        let coordinate = |name: &str| prop(name)?.parse::<f64>().ok();
+        Some(
+            Self {
+                street: prop("streetAddress"),
+                city: prop("addressLocality"),
+                region: prop("addressRegion"),
+                postal_code: prop("postalCode"),
+                country: prop("addressCountry"),
+                phone: prop("telephone"),
+                geo: coordinate("latitude").zip(coordinate("longitude"))
+                    .map(|(latitude, longitude)| GeoPoint { latitude, longitude }),
+            }
+        )
+    }
+
+    /// `city, region postal code` as the pages write it, `None` if the address is incomplete
+    fn city_line(&self) -> Option<String> {
+        let city = self.city.as_ref()?;
+        let postal_code = self.postal_code.as_ref()?;
+        Some(
+            match &self.region {
+                Some(region) => format!("{}, {} {}", city, region, postal_code),
+                None => format!("{} {}", city, postal_code),
+            }
+        )
+    }
 }
 
 impl LocationDetailPage {
     pub fn parse_html(html: &str) -> anyhow::Result<Self> {
         let document = Html::parse_document(html);
-        let address_container = document.select(&LOCATION_DETAIL_SELECTOR).next().unwrap();
-        let div_selector = Selector::parse("div").unwrap();
+        let structured = StructuredData::parse(&document);
+        let (line1, line2, city_line, country_line) = match structured.as_ref()
+            .and_then(|data| Some((data.street.clone()?, data.city_line()?, data.country.clone()?))) {
+            Some((street, city_line, country)) => (street, None, city_line, country),
+            None => Self::parse_address_lines(&document)?,
+        };
+        let name = document.select(&TITLE_SELECTOR).next()
+            .map(|title| title.text().collect::<String>())
+            .and_then(|title| TITLE_REG.captures(&title).map(|caps| caps[1].trim().to_string()));
+        let plans = Self::parse_plans(&document);
+        let prices = plans.iter().flat_map(|plan| plan.prices.iter()).collect::<Vec<_>>();
+        let price = prices.iter()
+            .filter_map(|raw| Price::parse(raw).filter(|price| price.period == PricePeriod::Month).map(|price| (price.amount, raw)))
+            .min()
+            .map(|(_, raw)| raw.to_string())
+            .or_else(|| prices.first().map(|price| price.to_string()))
+            .unwrap_or_default();
+        let (phone, geo) = match structured {
+            Some(data) => (data.phone, data.geo),
+            None => (None, None),
+        };
+        // a `tel:` link is the phone number if the structured data has none
+        let phone = phone.or_else(|| document.select(&PHONE_SELECTOR)
+            .filter_map(|link| link.value().attr("href")?.strip_prefix("tel:"))
+            .map(|phone| phone.trim().to_string())
+            .find(|phone| !phone.is_empty()));
+        let operator = document.select(&OPERATOR_SELECTOR).next()
+            .map(|operator| operator.text().collect::<String>().trim().to_string())
+            .filter(|operator| !operator.is_empty());
+        Ok(
+            Self {
+                line1,
+                line2,
+                city_line,
+                country_line,
+                name,
+                price,
+                plans,
+                phone,
+                operator,
+                geo,
+            }
+        )
+    }
+
+    /// the plans with their prices and features, the first text of a price cell is the price, i.e. `US$ 39.00 / month`
+    fn parse_plans(document: &Html) -> Vec<Plan> {
+        let text = |element: scraper::ElementRef| element.text().collect::<String>().trim().to_string();
+        document.select(&PLAN_SELECTOR)
+            .map(|plan| Plan {
+                name: plan.select(&PLAN_TITLE_SELECTOR).next().map(text).unwrap_or_default(),
+                prices: plan.select(&PLAN_PRICE_SELECTOR)
+                    .filter_map(|td| td.text().next())
+                    .map(|price| price.replace(" ", ""))
+                    .filter(|price| !price.is_empty())
+                    .collect(),
+                features: plan.select(&PLAN_FEATURE_SELECTOR)
+                    .filter_map(|feature| Some((
+                        text(feature.select(&PLAN_FEATURE_LABEL_SELECTOR).next()?),
+                        text(feature.select(&PLAN_FEATURE_ALLOWANCE_SELECTOR).next()?),
+                    )))
+                    .collect(),
+            })
+            .collect()
+    }
 
-        let lines = address_container.select(&div_selector)
-            .map(|div| div.text().collect::<String>())
+    /// the address by counting the divs, when the page has no structured data,
+    /// returns the street, the unit or suite, city/state/zip, and the country
+    fn parse_address_lines(document: &Html) -> anyhow::Result<(String, Option<String>, String, String)> {
+        let address_container = LOCATION_DETAIL.first(document.root_element())?;
+
+        let lines = address_container.select(&DIV_SELECTOR)
+            .map(|div| div.text().collect::<String>().trim().to_string())
             .filter(|s| !s.is_empty())
             .collect::<Vec<_>>();
 
-        let line2 = match lines.len() {
-            // no line2
-            4 => None,
-            // one-line line2
-            5 => Some(lines[2].to_string()),
-            // two-line line2
-            6 => Some(format!("{} {}", lines[2], lines[3])),
-            _ => bail!("Unexpected address line count: {}, page structure might be changed: {:?}", lines.len(), lines),
-        };
+        // the lines are the name placeholder, the street, the unit or suite if any, city/state/zip, and the country
+        if lines.len() < 4 {
+            bail!(
+                "Unexpected address line count: {}, page structure might be changed: {:?} - {}",
+                lines.len(), lines, snippet(&address_container.html()),
+            );
+        }
+        if lines.len() > 6 {
+            warn!("the address has {} lines, more than ever seen, the layout of ATMB might be changed: {:?}", lines.len(), lines);
+        }
+        let line2 = Some(lines[2..lines.len() - 2].join(" ")).filter(|line2| !line2.is_empty());
+        Ok((lines[1].clone(), line2, lines[lines.len() - 2].clone(), lines[lines.len() - 1].clone()))
+    }
+
+    /// fill the mailbox crawled from the state page with what only the detail page has
+    pub fn update_mailbox(&self, mailbox: &mut Mailbox) {
+        mailbox.address.line1 = self.street();
+        mailbox.phone.clone_from(&self.phone);
+        mailbox.operator.clone_from(&self.operator);
+        mailbox.plans.clone_from(&self.plans);
+        mailbox.geo = self.geo;
+    }
+
+    /// the country the location is in, `None` if it's not supported
+    pub fn country(&self) -> Option<Country> {
+        self.country_line.parse().ok()
+    }
+
+    /// build the mailbox from the detail page alone, for the locations missing from the state pages
+    pub fn to_mailbox(&self, link: impl Into<String>, country: Country) -> anyhow::Result<Mailbox> {
+        let link = link.into();
         Ok(
-            Self {
-                line1: lines[1].clone(),
-                line2,
+            Mailbox {
+                provider: ProviderKind::Atmb,
+                address: Address::from_lines_in(country, self.street(), &self.city_line)?,
+                name: self.name.clone().unwrap_or_else(|| link.clone()),
+                link,
+                price: Price::parse(&self.price),
+                raw_price: self.price.clone(),
+                phone: self.phone.clone(),
+                operator: self.operator.clone(),
+                plans: self.plans.clone(),
+                geo: self.geo,
+                listing: String::new(),
             }
         )
     }
@@ -220,44 +553,22 @@ impl LocationDetailPage {
     }
 }
 
-impl TryInto<Address> for LocationHtmlInfo {
-    type Error = anyhow::Error;
-
-    fn try_into(self) -> Result<Address, Self::Error> {
-        let (zip, zip4) = self.parse_zip().ok_or_else(|| anyhow!("Failed to parse zip code from: {}", self.line2))?;
-        Ok(
-            Address {
-                city: self.parse_city().ok_or_else(|| anyhow!("Failed to parse city from: {}", self.line2))?.to_string(),
-                state: self.parse_state().ok_or_else(|| anyhow!("Failed to parse state from: {}", self.line2))?.to_string(),
-                zip: zip.to_owned(),
-                zip4: zip4.map(|s| s.to_owned()),
-                line1: self.line1,
-            }
-        )
-    }
-}
-
 impl TryInto<Mailbox> for LocationHtmlInfo {
     type Error = anyhow::Error;
 
     fn try_into(self) -> Result<Mailbox, Self::Error> {
-        Ok(
-            Mailbox {
-                address: self.clone().try_into()?,
-                price: self.price(),
-                name: self.name,
-                link: self.link,
-            }
-        )
+        self.into_mailbox(Country::Us, None)
     }
 }
 
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     const COUNTRY_PAGE_HTML: &str = include_str!("../../test_data/https___www.anytimemailbox.com_l_usa.html");
     const STATE_PAGE_HTML: &str = include_str!("../../test_data/https___www.anytimemailbox.com_l_usa_alabama.html");
+    const PAGINATED_STATE_PAGE_HTML: &str = include_str!("../../test_data/state_page_paginated.html");
     const LOCATION_PAGE_HTML: &str = include_str!("../../test_data/https___www.anytimemailbox.com_s_birmingham-120-19th-street-north.html");
 
     fn new_location_info() -> LocationHtmlInfo {
@@ -277,16 +588,63 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_parse_sitemap() {
+        let index = Sitemap::parse_xml(r#"<sitemapindex><sitemap><loc>https://www.anytimemailbox.com/locations-sitemap.xml</loc></sitemap></sitemapindex>"#).unwrap();
+        assert_eq!(index.sitemaps, ["https://www.anytimemailbox.com/locations-sitemap.xml"]);
+        let sitemap = Sitemap::parse_xml("<urlset><url><loc> https://www.anytimemailbox.com/s/a?x=1&amp;y=2 </loc></url></urlset>").unwrap();
+        assert_eq!(sitemap.urls, ["https://www.anytimemailbox.com/s/a?x=1&y=2"]);
+        assert!(sitemap.sitemaps.is_empty());
+    }
+
+    #[test]
+    fn test_parse_location_index() {
+        let index = LocationIndexPage::parse_html(COUNTRY_PAGE_HTML).unwrap();
+        assert!(index.countries.contains(&("/l/usa", "United States")));
+        assert!(index.countries.contains(&("/l/canada", "Canada")));
+        assert!(index.countries.iter().all(|(path, _)| !path.starts_with("/l/usa/")));
+    }
+
     #[test]
     fn test_parse_country_page() {
         let country_page = CountryPage::parse_html(COUNTRY_PAGE_HTML).unwrap();
         assert_eq!(country_page.states.len(), 50);
     }
 
+    #[test]
+    fn test_parse_country_page_quotes() {
+        let html = r#"<ul>
+            <li><a class='theme-simple-link' href='/l/usa/alabama'>Alabama</a></li>
+            <li><a href="/l/usa/new-york" class="theme-simple-link">New York</a></li>
+            <li><a class="theme-simple-link bold" href="/l/usa/texas"> Texas </a></li>
+        </ul>"#;
+        let country_page = CountryPage::parse_html(html).unwrap();
+        let states = country_page.states.iter().map(|state| (state.url(), state.name())).collect::<Vec<_>>();
+        assert_eq!(states, [("/l/usa/alabama", "Alabama"), ("/l/usa/new-york", "New York")]);
+
+        // the exact class no longer matches, the fallback still does
+        let html = r#"<a class="theme-simple-link bold" href="/l/usa/texas"> Texas </a>"#;
+        let country_page = CountryPage::parse_html(html).unwrap();
+        assert_eq!(country_page.states[0].name(), "Texas");
+        assert!(CountryPage::parse_html("<a href='/l/usa/texas'>Texas</a>").is_err());
+    }
+
     #[test]
     fn test_parse_location_list() {
         let state_page = StatePage::parse_html(STATE_PAGE_HTML).unwrap();
         assert_eq!(state_page.locations.len(), 10);
+        assert_eq!(state_page.next_page(), None);
+    }
+
+    #[test]
+    fn test_parse_paginated_state_page() {
+        let mut state_page = StatePage::parse_html(PAGINATED_STATE_PAGE_HTML).unwrap();
+        assert_eq!(state_page.len(), 2);
+        assert_eq!(state_page.next_page(), Some("https://www.anytimemailbox.com/l/usa/texas/page/2"));
+
+        state_page.append(StatePage::parse_html(STATE_PAGE_HTML).unwrap());
+        assert_eq!(state_page.len(), 12);
+        assert_eq!(state_page.next_page(), None);
     }
 
     #[test]
@@ -299,7 +657,7 @@ mod test {
         assert_eq!(mailbox.address.state, "ST");
         assert_eq!(mailbox.address.zip, "12345");
         assert!(mailbox.address.zip4.is_none());
-        assert_eq!(mailbox.price, "US$9.99/month");
+        assert_eq!(mailbox.raw_price, "US$9.99/month");
 
         let location = new_location_info_with_zip4();
         let mailbox: Mailbox = location.try_into().unwrap();
@@ -309,7 +667,22 @@ mod test {
         assert_eq!(mailbox.address.state, "ST");
         assert_eq!(mailbox.address.zip, "12345");
         assert_eq!(mailbox.address.zip4, Some("6789".to_string()));
-        assert_eq!(mailbox.price, "US$9.99/month");
+        assert_eq!(mailbox.raw_price, "US$9.99/month");
+    }
+
+    #[test]
+    fn test_reconcile_state() {
+        let mut address = Address::from_lines("123 Main St", "City, TX 12345").unwrap();
+        assert!(reconcile_state(&mut address, "Texas", "Test"));
+        assert!(!reconcile_state(&mut address, "Alabama", "Test"));
+        assert_eq!(address.state, "TX");
+
+        let mut address = Address::from_lines_in(Country::Uk, "1 High St", "London SW1A 1AA").unwrap();
+        assert!(reconcile_state(&mut address, "England", "Test"));
+        assert_eq!(address.state, "England");
+
+        let state_page = StatePage::parse_html(STATE_PAGE_HTML).unwrap().with_state("Alabama");
+        assert!(state_page.to_mailboxes(Country::Us).unwrap().iter().all(|mailbox| mailbox.address.state == "AL"));
     }
 
     #[test]
@@ -320,10 +693,79 @@ mod test {
         assert_eq!(line2, "City With WhiteSpace, ST 12345");
     }
 
+    #[test]
+    fn test_parse_changed_layout() {
+        // another class is added, the fallback selectors still find the address
+        let html = r#"<div class="t-sec1 wide"><div class="t-text"><div>YOUR NAME</div><div>1 Main St</div><div>Dallas, TX 75201</div><div>United States</div></div></div>"#;
+        let location_detail = LocationDetailPage::parse_html(html).unwrap();
+        assert_eq!(location_detail.street(), "1 Main St");
+        assert_eq!(location_detail.country(), Some(Country::Us));
+
+        let err = LocationDetailPage::parse_html("<div class='t-sec2'>moved</div>").err().unwrap().to_string();
+        assert!(err.contains(r#"div[class="t-sec1"] div[class="t-text"]"#), "{}", err);
+        assert!(err.contains("moved"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_structured_data() {
+        // the structured data is preferred over the divs
+        let html = r#"<script type="application/ld+json">{"@context": "https://schema.org", "@graph": [
+            {"@type": "WebPage", "name": "Dallas"},
+            {"@type": "LocalBusiness", "telephone": "+1 214-555-0100",
+             "address": {"@type": "PostalAddress", "streetAddress": "1 Main St Ste 100", "addressLocality": "Dallas",
+                         "addressRegion": "TX", "postalCode": "75201", "addressCountry": {"@type": "Country", "name": "US"}},
+             "geo": {"@type": "GeoCoordinates", "latitude": 32.78, "longitude": "-96.8"}}
+        ]}</script>
+        <div class="t-sec1"><div class="t-text"><div>YOUR NAME</div><div>1 Old St</div><div>Dallas, TX 75201</div><div>United States</div></div></div>"#;
+        let location_detail = LocationDetailPage::parse_html(html).unwrap();
+        assert_eq!(location_detail.street(), "1 Main St Ste 100");
+        assert_eq!(location_detail.country(), Some(Country::Us));
+        let mailbox = location_detail.to_mailbox("/s/dallas", Country::Us).unwrap();
+        assert_eq!(mailbox.address.city, "Dallas");
+        assert_eq!(mailbox.phone.as_deref(), Some("+1 214-555-0100"));
+        assert_eq!(mailbox.geo, Some(GeoPoint { latitude: 32.78, longitude: -96.8 }));
+
+        let html = r#"<div itemscope itemtype="https://schema.org/LocalBusiness">
+            <span itemprop="telephone">(205) 555-0100</span>
+            <div itemprop="address" itemscope itemtype="https://schema.org/PostalAddress">
+                <span itemprop="streetAddress">120 19th Street North</span>
+                <span itemprop="addressLocality">Birmingham</span>, <span itemprop="addressRegion">AL</span>
+                <span itemprop="postalCode">35203</span> <meta itemprop="addressCountry" content="US">
+            </div>
+            <meta itemprop="latitude" content="33.5186"><meta itemprop="longitude" content="-86.8104">
+        </div>"#;
+        let data = StructuredData::from_microdata(&Html::parse_document(html)).unwrap();
+        assert_eq!(data.city_line().as_deref(), Some("Birmingham, AL 35203"));
+        assert_eq!(data.phone.as_deref(), Some("(205) 555-0100"));
+        assert_eq!(data.geo, Some(GeoPoint { latitude: 33.5186, longitude: -86.8104 }));
+
+        // the fixture has none, the divs are counted
+        assert_eq!(StructuredData::parse(&Html::parse_document(LOCATION_PAGE_HTML)), None);
+    }
+
     #[test]
     fn test_parse_location_detail_page() {
         let location_detail = LocationDetailPage::parse_html(LOCATION_PAGE_HTML).unwrap();
         assert_eq!(location_detail.line1, "120 19th Street North");
         assert_eq!(location_detail.line2, Some("Suite MAILBOX".to_string()));
+        assert_eq!(location_detail.country(), Some(Country::Us));
+        let mailbox = location_detail.to_mailbox("/s/birmingham-120-19th-street-north", Country::Us).unwrap();
+        assert_eq!(mailbox.name, "Birmingham - 19th St");
+        assert_eq!(mailbox.address.full_zip(), "35203");
+        assert_eq!(mailbox.raw_price, "US$39.00/month");
+        assert_eq!(mailbox.operator.as_deref(), Some("Forge"));
+        assert_eq!(mailbox.plans.len(), 1);
+        let plan = &mailbox.plans[0];
+        assert_eq!(plan.name, "2022 Virtual Mail");
+        assert_eq!(plan.price_of(PricePeriod::Year), Some("US$399.00/year"));
+        assert_eq!(plan.features[1], ("Open & Scan".to_string(), "10 pages / month".to_string()));
+        assert_eq!(plan.features.len(), 4);
+        assert_eq!(mailbox.phone, None);
+
+        let html = r#"<div class="t-sec1"><div class="t-text"><div>YOUR NAME</div><div>1 Main St</div><div>Dallas, TX 75201</div><div>United States</div></div></div>
+            <div class='t-info'> Mail Center Operator: <br><b> Main Street Mail </b></div><a href="tel:+1-214-555-0100">Call us</a>"#;
+        let location_detail = LocationDetailPage::parse_html(html).unwrap();
+        assert_eq!(location_detail.phone.as_deref(), Some("+1-214-555-0100"));
+        assert_eq!(location_detail.operator.as_deref(), Some("Main Street Mail"));
     }
 }
\ No newline at end of file
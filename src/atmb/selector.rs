@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use scraper::{ElementRef, Selector};
+use tracing::warn;
+use crate::error::Error;
+
+/// characters of the HTML quoted in the errors
+const SNIPPET_LEN: usize = 300;
+
+/// A part of the page located by several selectors, tried in order.
+///
+/// The first selector matches the current layout of ATMB, the others are looser ones that still work
+/// if the markup is tweaked, i.e. another class is added. Once a fallback is used the layout has changed,
+/// a warning is logged so the selectors can be updated before the fallbacks stop working too.
+pub struct Strategies {
+    what: &'static str,
+    selectors: Vec<(&'static str, Selector)>,
+    /// the layout change is only warned once
+    warned: AtomicBool,
+}
+
+impl Strategies {
+    /// * `what` - the part of the page, i.e. `title`
+    /// * `selectors` - CSS selectors of the part, the current layout first
+    pub fn new(what: &'static str, selectors: &[&'static str]) -> Self {
+        Self {
+            what,
+            selectors: selectors.iter()
+                .map(|&css| (css, Selector::parse(css).unwrap_or_else(|e| panic!("invalid selector `{}`: {:?}", css, e))))
+                .collect(),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// the first element matched under `fragment`, an [`Error::Parse`] if there's none
+    pub fn first<'a>(&self, fragment: ElementRef<'a>) -> anyhow::Result<ElementRef<'a>> {
+        match self.all(fragment).into_iter().next() {
+            Some(element) => Ok(element),
+            None => Err(self.not_found(fragment).into()),
+        }
+    }
+
+    /// the part is not found under `fragment`, the URL is filled in by the crawler
+    pub fn not_found(&self, fragment: ElementRef) -> Error {
+        Error::Parse {
+            url: String::new(),
+            what: self.what,
+            selector: self.names(),
+            snippet: snippet(&fragment.html()),
+        }
+    }
+
+    /// every element matched under `fragment` by the first selector that matches any, empty if none does
+    pub fn all<'a>(&self, fragment: ElementRef<'a>) -> Vec<ElementRef<'a>> {
+        for (idx, (css, selector)) in self.selectors.iter().enumerate() {
+            let elements = fragment.select(selector).collect::<Vec<_>>();
+            if elements.is_empty() {
+                continue;
+            }
+            if idx > 0 && !self.warned.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "the layout of ATMB might be changed, the {} is not found by `{}` but by the fallback `{}`",
+                    self.what, self.selectors[0].0, css,
+                );
+            }
+            return elements;
+        }
+        Vec::new()
+    }
+
+    /// i.e. `` `h3[class="t-title"]`, `h3` ``
+    fn names(&self) -> String {
+        self.selectors.iter().map(|(css, _)| format!("`{}`", css)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// the beginning of the HTML, with the whitespaces collapsed
+pub fn snippet(html: &str) -> String {
+    let html = html.split_whitespace().collect::<Vec<_>>().join(" ");
+    match html.char_indices().nth(SNIPPET_LEN) {
+        Some((idx, _)) => format!("{}...", &html[..idx]),
+        None => html,
+    }
+}
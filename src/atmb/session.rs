@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use reqwest::header::{HeaderMap, HeaderValue, COOKIE, REFERER, SET_COOKIE};
+
+/// Cookies and the referer of the requests to ATMB, so they look like a visitor browsing the site.
+///
+/// Only ATMB is requested, so the cookies are kept by their name, their domain and path are ignored.
+#[derive(Default)]
+pub struct Session {
+    cookies: RefCell<BTreeMap<String, String>>,
+    /// the page visited last, sent as the referer of the next request
+    last_url: RefCell<Option<String>>,
+}
+
+impl Session {
+    /// the `Cookie` and `Referer` headers of the next request
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let cookies = self.cookies.borrow().iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if let Some(cookies) = HeaderValue::from_str(&cookies).ok().filter(|_| !cookies.is_empty()) {
+            headers.insert(COOKIE, cookies);
+        }
+        if let Some(referer) = self.last_url.borrow().as_deref().and_then(|url| HeaderValue::from_str(url).ok()) {
+            headers.insert(REFERER, referer);
+        }
+        headers
+    }
+
+    /// keep the cookies set by the response of `url`, the expired ones are removed
+    pub fn update(&self, url: &str, headers: &HeaderMap) {
+        let mut cookies = self.cookies.borrow_mut();
+        for set_cookie in headers.get_all(SET_COOKIE).iter().filter_map(|value| value.to_str().ok()) {
+            let mut attributes = set_cookie.split(';').map(str::trim);
+            let Some((name, value)) = attributes.next().and_then(|pair| pair.split_once('=')) else {
+                continue;
+            };
+            let expired = value.is_empty() || attributes.any(|attribute| attribute.eq_ignore_ascii_case("max-age=0"));
+            if expired {
+                cookies.remove(name);
+            } else {
+                cookies.insert(name.to_string(), value.to_string());
+            }
+        }
+        *self.last_url.borrow_mut() = Some(url.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_cookies() {
+        let session = Session::default();
+        assert!(session.headers().is_empty());
+
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "ASP.NET_SessionId=abc; path=/; HttpOnly".parse().unwrap());
+        headers.append(SET_COOKIE, "lang=en; Max-Age=3600".parse().unwrap());
+        session.update("https://www.anytimemailbox.com/l/usa", &headers);
+        let sent = session.headers();
+        assert_eq!(sent[COOKIE], "ASP.NET_SessionId=abc; lang=en");
+        assert_eq!(sent[REFERER], "https://www.anytimemailbox.com/l/usa");
+
+        let mut headers = HeaderMap::new();
+        headers.append(SET_COOKIE, "lang=; Max-Age=0".parse().unwrap());
+        session.update("https://www.anytimemailbox.com/l/usa/alabama", &headers);
+        assert_eq!(session.headers()[COOKIE], "ASP.NET_SessionId=abc");
+    }
+}
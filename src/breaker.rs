@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::Duration;
+use reqwest::StatusCode;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use crate::error::Error;
+
+/// the latest requests the failure rate is measured over
+const WINDOW: usize = 20;
+/// the circuit opens when more than this fraction of the window failed
+const MAX_FAILURE_RATE: f64 = 0.5;
+/// successful requests in a row needed to close the circuit again
+const PROBES: usize = 5;
+/// how often a waiting request checks whether the probe has finished
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Pauses all the requests to a host for a while once most of them fail.
+///
+/// When more than half of the last 20 requests failed, i.e. the host is throttling us, the circuit opens
+/// and every request waits for the cooldown. Then the requests are sent one at a time, a few successful
+/// ones in a row close the circuit, a failed one opens it again.
+pub struct CircuitBreaker {
+    cooldown: Duration,
+    state: RefCell<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    /// whether the latest requests succeeded, the oldest first
+    outcomes: VecDeque<bool>,
+    circuit: Circuit,
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Circuit {
+    #[default]
+    Closed,
+    /// every request waits until then
+    Open { until: Instant },
+    /// the requests are sent one at a time
+    HalfOpen { successes: usize, probing: bool },
+}
+
+impl CircuitBreaker {
+    /// * `cooldown` - how long the requests are paused once the circuit opens
+    pub fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            state: RefCell::new(BreakerState::default()),
+        }
+    }
+
+    /// wait until a request is allowed, every allowed request must be followed by [`CircuitBreaker::record`]
+    pub async fn acquire(&self) {
+        while let Some(wait) = self.try_acquire() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// let the request go if the circuit allows, otherwise returns how long to wait before asking again
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut state = self.state.borrow_mut();
+        match state.circuit {
+            Circuit::Closed => None,
+            Circuit::Open { until } => {
+                let now = Instant::now();
+                if now < until {
+                    return Some(until - now);
+                }
+                info!("the cooldown is over, resume the requests one at a time");
+                state.circuit = Circuit::HalfOpen { successes: 0, probing: true };
+                None
+            }
+            Circuit::HalfOpen { probing: true, .. } => Some(PROBE_POLL_INTERVAL),
+            Circuit::HalfOpen { ref mut probing, .. } => {
+                *probing = true;
+                None
+            }
+        }
+    }
+
+    /// report whether the allowed request succeeded
+    pub fn record(&self, success: bool) {
+        let mut state = self.state.borrow_mut();
+        match state.circuit {
+            Circuit::Closed => {
+                state.outcomes.push_back(success);
+                if state.outcomes.len() > WINDOW {
+                    state.outcomes.pop_front();
+                }
+                let failures = state.outcomes.iter().filter(|success| !**success).count();
+                if state.outcomes.len() == WINDOW && failures as f64 > WINDOW as f64 * MAX_FAILURE_RATE {
+                    warn!(
+                        "[{}] of the last [{}] requests failed, pause the requests for {}s",
+                        failures, WINDOW, self.cooldown.as_secs(),
+                    );
+                    state.outcomes.clear();
+                    state.circuit = Circuit::Open { until: Instant::now() + self.cooldown };
+                }
+            }
+            // the requests sent before the circuit opened
+            Circuit::Open { .. } => {}
+            Circuit::HalfOpen { successes, .. } if success && successes + 1 >= PROBES => {
+                info!("[{}] requests in a row succeeded, resume the requests", PROBES);
+                state.circuit = Circuit::Closed;
+            }
+            Circuit::HalfOpen { successes, .. } if success => {
+                state.circuit = Circuit::HalfOpen { successes: successes + 1, probing: false };
+            }
+            Circuit::HalfOpen { .. } => {
+                warn!("the request failed again after the cooldown, pause the requests for {}s", self.cooldown.as_secs());
+                state.circuit = Circuit::Open { until: Instant::now() + self.cooldown };
+            }
+        }
+    }
+}
+
+/// whether the error means the host is unhealthy or throttling us, a `404 Not Found` doesn't
+pub fn is_failure(error: &anyhow::Error) -> bool {
+    match Error::status(error) {
+        Some(status) => status.is_server_error() || matches!(status, StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_open_and_close() {
+        let breaker = CircuitBreaker::new(Duration::ZERO);
+        (0..9).for_each(|_| breaker.record(true));
+        (0..10).for_each(|_| breaker.record(false));
+        assert_eq!(breaker.state.borrow().circuit, Circuit::Closed);
+        breaker.record(false);
+        assert!(matches!(breaker.state.borrow().circuit, Circuit::Open { .. }));
+
+        // the cooldown is over, only one request is let through at a time
+        assert_eq!(breaker.try_acquire(), None);
+        assert_eq!(breaker.try_acquire(), Some(PROBE_POLL_INTERVAL));
+        breaker.record(false);
+        assert!(matches!(breaker.state.borrow().circuit, Circuit::Open { .. }));
+        for _ in 0..PROBES {
+            assert_eq!(breaker.try_acquire(), None);
+            breaker.record(true);
+        }
+        assert_eq!(breaker.state.borrow().circuit, Circuit::Closed);
+    }
+}
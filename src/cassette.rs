@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+use anyhow::{anyhow, bail};
+
+/// longest file name of a recorded response, the longer keys are truncated and suffixed with their hash
+const MAX_NAME_LEN: usize = 120;
+
+/// whether the HTTP responses are recorded or replayed, selected via `--record-http` or `--replay-http`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpMode {
+    /// send the requests, and save the responses under the directory
+    Record(PathBuf),
+    /// serve the responses saved under the directory, nothing is sent
+    Replay(PathBuf),
+}
+
+/// The responses of a service saved on disk, one file per request.
+///
+/// A response is saved as `<dir>/<service>/<key>.<extension>`, a request that failed as `<key>.error`
+/// with the error message, so a run with intermittent failures can be replayed with the same failures.
+#[derive(Debug)]
+pub struct Cassette {
+    dir: PathBuf,
+    extension: &'static str,
+    replay: bool,
+}
+
+impl Cassette {
+    /// * `service` - the subdirectory of the responses, i.e. `atmb`
+    /// * `extension` - of the response files, i.e. `html`
+    pub fn open(mode: &HttpMode, service: &str, extension: &'static str) -> anyhow::Result<Self> {
+        let (dir, replay) = match mode {
+            HttpMode::Record(dir) => (dir.join(service), false),
+            HttpMode::Replay(dir) => (dir.join(service), true),
+        };
+        if replay && !dir.is_dir() {
+            bail!("no {} responses recorded in [{}], record them with `--record-http` first", service, dir.display());
+        }
+        if !replay {
+            std::fs::create_dir_all(&dir)?;
+        }
+        Ok(
+            Self {
+                dir,
+                extension,
+                replay,
+            }
+        )
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.replay
+    }
+
+    /// the response recorded for the request, or the error it failed with
+    pub fn play(&self, key: &str) -> anyhow::Result<String> {
+        let (body_path, error_path) = self.paths(key);
+        if let Ok(body) = std::fs::read_to_string(body_path) {
+            return Ok(body);
+        }
+        match std::fs::read_to_string(error_path) {
+            Ok(error) => Err(anyhow!("{} (replayed)", error)),
+            Err(_) => Err(anyhow!("no response of [{}] recorded in [{}]", key, self.dir.display())),
+        }
+    }
+
+    /// save the response of the request, or the error it failed with, replacing the one recorded before
+    pub fn record(&self, key: &str, result: &anyhow::Result<String>) -> anyhow::Result<()> {
+        let (body_path, error_path) = self.paths(key);
+        let (path, stale, content) = match result {
+            Ok(body) => (body_path, error_path, body.clone()),
+            Err(e) => (error_path, body_path, format!("{:#}", e)),
+        };
+        std::fs::write(path, content)?;
+        if stale.exists() {
+            std::fs::remove_file(stale)?;
+        }
+        Ok(())
+    }
+
+    fn paths(&self, key: &str) -> (PathBuf, PathBuf) {
+        let name = file_name(key);
+        (self.dir.join(format!("{}.{}", name, self.extension)), self.dir.join(format!("{}.error", name)))
+    }
+}
+
+/// turn the key into a file name, i.e. `https___www.anytimemailbox.com_l_usa`
+fn file_name(key: &str) -> String {
+    let name = key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect::<String>();
+    if name.len() <= MAX_NAME_LEN {
+        return name;
+    }
+    // FNV-1a, the names must stay the same across builds so the cassettes can be shared
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3));
+    format!("{}_{:016x}", &name[..MAX_NAME_LEN], hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("https://www.anytimemailbox.com/l/usa"), "https___www.anytimemailbox.com_l_usa");
+        let long = "1".repeat(200);
+        let name = file_name(&long);
+        assert_eq!(name.len(), MAX_NAME_LEN + 17);
+        assert_ne!(name, file_name(&"1".repeat(201)));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::atmb::model::Mailbox;
+use crate::provider::ProviderKind;
+use crate::record::stable_id;
+use crate::snapshot::Snapshot;
+use crate::verify::model::AdditionalInfo;
+
+/// A change of a location since the previous run, appended to `result/changes.csv`.
+///
+/// The rows are never rewritten, so the file tells when the price, CMRA or RDI of a location has changed,
+/// i.e. why the RDI of this run differs from a live lookup made some time ago.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    /// RFC 3339 timestamp of the run the change is found in
+    pub time: String,
+    /// see [`stable_id`]
+    pub id: String,
+    pub provider: ProviderKind,
+    pub name: String,
+    pub link: String,
+    pub change: ChangeKind,
+    /// empty for a delisting's new value
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    #[serde(rename = "price")]
+    Price,
+    #[serde(rename = "CMRA")]
+    Cmra,
+    #[serde(rename = "rdi")]
+    Rdi,
+    /// the location is not listed by its provider anymore
+    #[serde(rename = "delisted")]
+    Delisted,
+}
+
+impl ChangeRecord {
+    fn new(mailbox: &Mailbox, time: &str, change: ChangeKind, old: String, new: String) -> Self {
+        Self {
+            time: time.to_string(),
+            id: stable_id(mailbox.provider, &mailbox.link),
+            provider: mailbox.provider,
+            name: mailbox.name.clone(),
+            link: mailbox.link.clone(),
+            change,
+            old,
+            new,
+        }
+    }
+}
+
+/// the price, CMRA and RDI changes of the verified mailboxes since they are saved in the snapshot
+///
+/// * `time` - RFC 3339 timestamp of the run
+pub fn changes<'a>(previous: &Snapshot, verified: impl IntoIterator<Item = (&'a Mailbox, &'a AdditionalInfo)>, time: &str) -> Vec<ChangeRecord> {
+    let mut changes = Vec::new();
+    for (mailbox, info) in verified {
+        let Some((old_mailbox, old_info)) = previous.get(&mailbox.link) else {
+            continue;
+        };
+        let fields = [
+            (ChangeKind::Price, old_mailbox.raw_price.clone(), mailbox.raw_price.clone()),
+            (ChangeKind::Cmra, format!("{:?}", old_info.cmra), format!("{:?}", info.cmra)),
+            (ChangeKind::Rdi, format!("{:?}", old_info.rdi), format!("{:?}", info.rdi)),
+        ];
+        changes.extend(
+            fields.into_iter()
+                .filter(|(_, old, new)| old != new)
+                .map(|(change, old, new)| ChangeRecord::new(mailbox, time, change, old, new))
+        );
+    }
+    changes.sort_by(|c1, c2| c1.id.cmp(&c2.id));
+    changes
+}
+
+/// the mailboxes of the snapshot that are not `listed` anymore, among the ones `crawled` by this run
+pub fn delisted(previous: &Snapshot, listed: &HashSet<&str>, crawled: impl Fn(&Mailbox) -> bool, time: &str) -> Vec<ChangeRecord> {
+    let mut delisted = previous.mailboxes()
+        .filter(|mailbox| crawled(mailbox) && !listed.contains(mailbox.link.as_str()))
+        .map(|mailbox| ChangeRecord::new(mailbox, time, ChangeKind::Delisted, mailbox.raw_price.clone(), String::new()))
+        .collect::<Vec<_>>();
+    delisted.sort_by(|c1, c2| c1.id.cmp(&c2.id));
+    delisted
+}
+
+/// append the changes to the CSV file, the header is written only if the file is new
+pub fn append(changes: &[ChangeRecord], path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let is_new = std::fs::metadata(path).map_or(true, |metadata| metadata.len() == 0);
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut wtr = csv::WriterBuilder::new().has_headers(is_new).from_writer(file);
+    for change in changes {
+        wtr.serialize(change)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::atmb::model::Price;
+    use crate::verify::model::Rdi;
+
+    #[test]
+    fn test_changes() {
+        let info = AdditionalInfo::sample();
+        let (a, b, c) = (Mailbox::sample("a"), Mailbox::sample("b"), Mailbox::sample("c"));
+        let mut previous = Snapshot::default();
+        previous.extend([(&a, &info), (&b, &info), (&c, &info)]);
+
+        let a_changed = Mailbox { price: Price::parse("US$12.99/month"), raw_price: "US$12.99/month".to_string(), ..a.clone() };
+        let b_info = AdditionalInfo { rdi: Rdi::Commercial, ..info.clone() };
+        let changes = changes(&previous, [(&a_changed, &info), (&b, &b_info)], "2024-07-08T06:00:00Z");
+        let mut kinds = changes.iter().map(|change| (change.link.as_str(), change.change, change.new.as_str())).collect::<Vec<_>>();
+        kinds.sort_by_key(|(link, _, _)| *link);
+        assert_eq!(kinds, [("a", ChangeKind::Price, "US$12.99/month"), ("b", ChangeKind::Rdi, "Commercial")]);
+
+        let listed = HashSet::from(["a", "b"]);
+        let delisted = delisted(&previous, &listed, |_| true, "2024-07-08T06:00:00Z");
+        assert_eq!(delisted.iter().map(|change| change.link.as_str()).collect::<Vec<_>>(), ["c"]);
+        assert!(super::delisted(&previous, &listed, |mailbox| mailbox.link != "c", "").is_empty());
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use crate::atmb::model::Mailbox;
+use crate::verify::model::AdditionalInfo;
+
+/// Intermediate state of a run, persisted so that an interrupted run can be resumed with `--resume`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// whether all the mailboxes have been crawled
+    pub crawled: bool,
+    /// mailboxes whose detail page has been fetched
+    pub mailboxes: Vec<Mailbox>,
+    /// verification results keyed by the mailbox link
+    pub verified: HashMap<String, AdditionalInfo>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// load the checkpoint from `path`, or start from an empty one if it doesn't exist
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            warn!("no checkpoint found at [{}], starting from scratch", path.display());
+            return Ok(Self::new(path));
+        }
+        let mut checkpoint: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        checkpoint.path = path.to_path_buf();
+        info!("resuming from checkpoint: [{}] mailboxes fetched, [{}] verified",
+            checkpoint.mailboxes.len(), checkpoint.verified.len());
+        Ok(checkpoint)
+    }
+
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(&self.path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// the run has completed, the checkpoint is no longer needed
+    pub fn remove(self) -> anyhow::Result<()> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,579 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use getopts::Matches;
+use tracing::info;
+use atmb_us_physical::atmb::model::Address;
+use atmb_us_physical::cassette::HttpMode;
+use atmb_us_physical::country::Country;
+use atmb_us_physical::dedupe::DuplicatePolicy;
+use atmb_us_physical::provider::{CrawlConfig, DEFAULT_CONNECT_TIMEOUT, DEFAULT_CRAWL_CONCURRENCY, DEFAULT_RATE_LIMIT, DEFAULT_READ_TIMEOUT, DEFAULT_RETRY_DELAY, ProviderKind, RequestBudget};
+use atmb_us_physical::notify::EmailConfig;
+use atmb_us_physical::notify::webhook::WebhookFormat;
+use atmb_us_physical::plan::QuotaPlan;
+use atmb_us_physical::record::{OutputFormat, SortKey};
+use atmb_us_physical::schedule::Schedule;
+use atmb_us_physical::sqlite::Sink;
+use atmb_us_physical::verify::{self, SmartyCredential, VerifierKind, VerifyConfig};
+use atmb_us_physical::verify::model::RdiFilter;
+use crate::config::{self, ConfigValue};
+
+/// loaded if exists and `--config` is not given
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const DEFAULT_CACHE_TTL_DAYS: u64 = 30;
+const DEFAULT_VERIFY_CONCURRENCY: usize = 10;
+const DEFAULT_LISTEN: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8080);
+
+/// what to do, selected by the free arguments
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// crawl, verify and save the results
+    Run,
+    /// compare an older result CSV with a newer one, the newer one defaults to the latest result
+    Diff {
+        old: PathBuf,
+        new: Option<PathBuf>,
+    },
+    /// verify the mailboxes of a previous run without crawling, defaults to the raw results of `--skip-verify`
+    Verify {
+        input: Option<PathBuf>,
+    },
+    /// check a single address, i.e. `check "156 S Main St, Lumberton, TX 77657"`
+    Check {
+        address: Address,
+    },
+    /// render the residential locations of a result CSV on an HTML map
+    Report {
+        input: Option<PathBuf>,
+        output: Option<PathBuf>,
+    },
+    /// list the countries ATMB has locations in
+    Countries,
+    /// serve the database of `--sink sqlite` over HTTP, defaults to `result/mailboxes.db`
+    Serve {
+        input: Option<PathBuf>,
+    },
+}
+
+/// runtime options parsed from the command line
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    /// continue from the checkpoint left by an interrupted run
+    pub resume: bool,
+    /// write the successful records even if some detail pages cannot be fetched
+    pub allow_partial: bool,
+    /// skip the detail pages and the lookups of the locations unchanged since the previous run
+    pub incremental: bool,
+    /// keep running, and run on the schedule instead of once
+    pub watch: Option<Schedule>,
+    /// stop the run like Ctrl-C once it has taken this long
+    pub timeout: Option<Duration>,
+    /// POST the new non-CMRA residential locations found by a diff to the URL
+    pub webhook: Option<String>,
+    pub webhook_format: WebhookFormat,
+    /// email the differences and the result CSV after a diff
+    pub email: Option<EmailConfig>,
+    /// post the differences to the Telegram chat, as `(bot token, chat id)`
+    pub telegram: Option<(String, String)>,
+    /// the mailbox providers to crawl
+    pub providers: Vec<ProviderKind>,
+    /// the countries to crawl one after another, every country ATMB lists if `None`
+    pub countries: Option<Vec<Country>>,
+    /// concurrency and rate limit of the crawlers, the country is the first selected one
+    pub crawl: CrawlConfig,
+    /// maximum concurrent lookups of the verifiers
+    pub verify_concurrency: usize,
+    /// only crawl the mailboxes, the verification is skipped
+    pub skip_verify: bool,
+    /// the service used to verify the addresses
+    pub verifier: VerifierKind,
+    /// where the records are written besides the result files
+    pub sink: Option<Sink>,
+    /// the other verifiers of `--verifier consensus:...`, each of them is asked too
+    pub consensus: Vec<VerifierKind>,
+    /// the second verifier to query for the doubtful results
+    pub double_check: Option<VerifierKind>,
+    /// the verifier filling in the RDI and the coordinates the first one hasn't reported
+    pub enrich: Option<VerifierKind>,
+    /// what to do if the lookups left are not enough
+    pub quota_plan: QuotaPlan,
+    /// check the city, state and ZIP with the ZIP Code API before verifying the addresses
+    pub zip_precheck: bool,
+    /// cache TTL and credentials of the verifiers
+    pub verify: VerifyConfig,
+    /// replace the scraped address with the one standardized by the verifier
+    pub use_verified_address: bool,
+    /// maximum monthly price in cents, the mailboxes without a known price are dropped too
+    pub max_price: Option<u64>,
+    /// write the CMRA mailboxes too, with the `CMRA` column telling them apart
+    pub include_cmra: bool,
+    /// RDI of the mailboxes to write
+    pub rdi: RdiFilter,
+    /// which of the listings at the same address to write
+    pub duplicates: DuplicatePolicy,
+    /// order of the records in the result files
+    pub sort_by: SortKey,
+    /// sort the records in descending order
+    pub desc: bool,
+    /// formats of the result files
+    pub formats: Vec<OutputFormat>,
+    /// keep a copy of the results under `result/history/<date>/`
+    pub archive: bool,
+    /// write the statistics of the run to `result/summary.json`
+    pub summary: bool,
+    /// list the addresses whose candidates disagree on the CMRA or RDI in `result/ambiguous.csv`
+    pub ambiguous_report: bool,
+    /// add the new non-CMRA locations of the run to `result/feed.xml`
+    pub feed: bool,
+    /// where `serve` listens, and `/metrics` of `--watch`
+    pub listen: SocketAddr,
+    /// serve `/metrics` on `listen` while watching
+    pub metrics: bool,
+}
+
+impl Options {
+    /// parse options from the process arguments, print the usage and exit if `--help` is given
+    pub fn from_env() -> anyhow::Result<Self> {
+        let args = std::env::args().collect::<Vec<_>>();
+        let program = args.first().map(|s| s.as_str()).unwrap_or(env!("CARGO_PKG_NAME"));
+        let opts = Self::definitions();
+        let matches = opts.parse(&args[1..])
+            .map_err(|e| anyhow!("{}\n\n{}", e, opts.short_usage(program)))?;
+
+        if matches.opt_present("help") {
+            let brief = format!("Usage: {program} [options]\n       {program} diff OLD_CSV [NEW_CSV]\n       {program} report [CSV] [HTML]\n       {program} verify [CSV|JSON]\n       {program} check \"STREET, CITY, ST ZIP\"\n       {program} countries\n       {program} serve [DB]");
+            print!("{}", opts.usage(&brief));
+            std::process::exit(0);
+        }
+
+        let config_file = matches.opt_str("config").map(PathBuf::from)
+            .or_else(|| Path::new(DEFAULT_CONFIG_FILE).exists().then(|| PathBuf::from(DEFAULT_CONFIG_FILE)));
+        let Some(config_file) = config_file else {
+            return Self::from_matches(&matches);
+        };
+        info!("loading options from [{}]", config_file.display());
+        let config_args = Self::config_args(&opts, &matches, config::load(&config_file)?)
+            .map_err(|e| anyhow!("invalid config file [{}]: {}", config_file.display(), e))?;
+        let matches = opts.parse(config_args.iter().chain(&args[1..]))?;
+        Self::from_matches(&matches)
+    }
+
+    /// turn the entries of the config file into command line arguments,
+    /// the options given on the command line take precedence and are left out
+    fn config_args(opts: &getopts::Options, matches: &Matches, entries: Vec<(String, ConfigValue)>) -> anyhow::Result<Vec<String>> {
+        let mut args = Vec::new();
+        for (key, value) in entries {
+            if key == "config" || key == "help" {
+                bail!("`{}` cannot be set in the config file", key);
+            }
+            let arg = match value {
+                ConfigValue::Bool(true) => format!("--{key}"),
+                ConfigValue::Bool(false) => continue,
+                ConfigValue::Scalar(value) => format!("--{key}={value}"),
+                // the commas inside an item are escaped, so it's not split by `parse_list`
+                ConfigValue::List(items) => format!(
+                    "--{key}={}",
+                    items.iter().map(|item| item.replace('\\', "\\\\").replace(',', "\\,")).collect::<Vec<_>>().join(","),
+                ),
+            };
+            // make sure the option exists before looking it up in the command line matches
+            opts.parse([&arg])?;
+            if !matches.opt_present(&key) {
+                args.push(arg);
+            }
+        }
+        Ok(args)
+    }
+
+    fn definitions() -> getopts::Options {
+        let mut opts = getopts::Options::new();
+        opts.optflag("h", "help", "print this help menu");
+        opts.optopt("", "config", "read the options from FILE, the command line options take precedence (default: config.toml if exists)", "FILE");
+        opts.optflag("", "resume", "continue from the checkpoint of an interrupted run");
+        opts.optopt("", "timeout", "stop the run like Ctrl-C if it takes longer than DURATION, i.e. 45m or 1h30m, exits with 124", "DURATION");
+        opts.optopt("", "watch", "keep running and run on the cron-style schedule in UTC, i.e. \"0 6 * * 1\" for 06:00 every Monday, every run is kept in result/runs/<time>/", "SCHEDULE");
+        opts.optopt("", "webhook", "POST the new non-CMRA residential locations to URL after a diff, i.e. between two runs of `--watch`", "URL");
+        opts.optopt("", "webhook-format", "format of the webhook payload: json (default), slack, or discord", "FORMAT");
+        opts.optopt("", "smtp-server", "email the differences and the result CSV after a diff via the SMTP server, the password of `--smtp-user` is read from SMTP_PASSWORD (needs the `email` feature)", "HOST:PORT");
+        opts.optopt("", "smtp-user", "the login of `--smtp-server`", "USER");
+        opts.optopt("", "email-from", "the sender of the email", "ADDRESS");
+        opts.optopt("", "email-to", "comma separated recipients of the email", "ADDRESSES");
+        opts.optopt("", "telegram-chat", "post the summary and the new locations to the Telegram chat after a diff, via the bot of `--telegram-token`", "CHAT_ID");
+        opts.optopt("", "telegram-token", "token of the Telegram bot (default: TELEGRAM_BOT_TOKEN)", "TOKEN");
+        opts.optflag("", "incremental", "only fetch the detail pages and verify the locations new or changed since the previous run");
+        opts.optopt("", "provider", "comma separated mailbox providers to crawl: atmb (default), ipostal1, postscan, or all", "NAMES");
+        opts.optopt("", "country", "comma separated countries of the ATMB locations: us (default), ca, uk, au, or all, the others are written to result/mailboxes_<country>.<ext>", "COUNTRIES");
+        opts.optopt("", "states", "comma separated states to crawl and write, by code or name, i.e. TX,FL (default: all)", "STATES");
+        opts.optflag("", "sitemap", "cross-check the ATMB locations against its sitemap, and fetch the ones missing from the state pages");
+        opts.optopt("", "crawl-concurrency", "maximum concurrent page requests, state pages use half of it (default: 10)", "N");
+        opts.optopt("", "rate-limit", "maximum ATMB requests per second, 0 for unlimited (default: 5)", "N");
+        opts.optopt("", "connect-timeout", "give up a page request if it cannot connect in SECS seconds (default: 10)", "SECS");
+        opts.optopt("", "read-timeout", "give up a page request if nothing is received for SECS seconds (default: 30)", "SECS");
+        opts.optopt("", "max-requests", "send at most N requests to ATMB, then write what has been crawled and save the progress for `--resume`", "N");
+        opts.optopt("", "retry-delay", "retry the failed detail pages one by one after SECS seconds, 0 to not retry (default: 30)", "SECS");
+        opts.optopt("", "browser", "render the ATMB pages blocked by anti-bot with the headless Chrome at PATH, needs the `headless` feature", "PATH");
+        opts.optopt("", "proxy", "proxy of the crawlers, i.e. http://127.0.0.1:8080 (default: HTTP_PROXY/HTTPS_PROXY)", "URL");
+        opts.optopt("", "proxy-file", "rotate the ATMB requests through the proxies listed in FILE, one per line", "FILE");
+        opts.optopt("", "verify-concurrency", "maximum concurrent address lookups (default: 10)", "N");
+        opts.optflag("", "skip-verify", "only crawl, write the unverified mailboxes to result/raw_mailboxes.<ext>");
+        opts.optopt("", "sink", "also write the records to: sqlite (result/mailboxes.db)", "NAME");
+        opts.optopt("", "verifier", "the service used to verify the addresses: smarty (default), usps, melissa, geocodio (no CMRA), international (outside the US), or consensus:smarty,usps to ask all of them", "NAME");
+        opts.optopt("", "credentials-file", "read the Smarty credentials from FILE, one AUTH_ID=AUTH_TOKEN[:LOOKUPS] per line, LOOKUPS is the monthly cap (default: 1000) or unlimited (default: CREDENTIALS env var)", "FILE");
+        opts.optopt("", "smarty-match", "how Smarty matches the addresses: enhanced (default), strict, or invalid", "STRATEGY");
+        opts.optopt("", "smarty-license", "the license of the Smarty plan (default: us-core-cloud)", "LICENSE");
+        opts.optopt("", "smarty-key", "authenticate to Smarty with the website key instead of the secret keys of CREDENTIALS, optionally followed by :LOOKUPS", "KEY");
+        opts.optopt("", "smarty-referer", "the Referer sent with `--smarty-key`, one of the hosts the key is configured for", "HOST");
+        opts.optopt("", "melissa-license", "the license key of `--verifier melissa` (default: MELISSA_LICENSE env var)", "KEY");
+        opts.optopt("", "geocodio-key", "the API key of Geocodio (default: GEOCODIO_API_KEY env var)", "KEY");
+        opts.optopt("", "enrich", "fill in the unknown RDI and the missing coordinates with a second verifier, i.e. geocodio", "NAME");
+        opts.optopt("", "double-check", "query a second verifier for the results with unknown RDI or changed since cached", "NAME");
+        opts.optopt("", "quota-plan", "if fewer lookups are left than needed: warn (default), prompt, or prioritize the new locations", "PLAN");
+        opts.optflag("", "zip-precheck", "check the city, state and ZIP with Smarty's ZIP Code API first, the ones that disagree are not verified");
+        opts.optopt("", "cache-ttl", "re-verify the cached results older than DAYS days (default: 30)", "DAYS");
+        opts.optflag("", "use-verified-address", "write the address standardized by the verifier instead of the scraped one");
+        opts.optopt("", "max-price", "only verify and write the mailboxes at most PRICE per month, i.e. 15 or 9.99", "PRICE");
+        opts.optflag("", "include-cmra", "write the CMRA mailboxes too, the CMRA column tells them apart");
+        opts.optopt("", "rdi", "only write the mailboxes of the RDI: residential, commercial, or any (default)", "RDI");
+        opts.optopt("", "duplicates", "which of the listings at the same address to write: all (default), or cheapest, they're listed in result/duplicates.csv", "POLICY");
+        opts.optopt("", "sort-by", "order of the records: price (default), state, zip, name, or id", "KEY");
+        opts.optflag("", "desc", "sort the records in descending order");
+        opts.optopt("", "format", "comma separated result formats: csv (default), json, jsonl, geojson, xlsx, html, markdown", "FORMATS");
+        opts.optflag("", "split-by-state", "put a table under a heading for every state in the markdown result");
+        opts.optflag("", "archive", "keep a copy of the results under result/history/<date>/");
+        opts.optflag("", "summary", "write the statistics of the run to result/summary.json");
+        opts.optflag("", "ambiguous-report", "list the addresses whose candidates disagree on the CMRA or RDI in result/ambiguous.csv");
+        opts.optopt("", "listen", "the address `serve` and `--metrics` listen on (default: 127.0.0.1:8080)", "ADDR");
+        opts.optflag("", "metrics", "serve the Prometheus metrics at /metrics of `--listen` while `--watch` is running (needs the `server` feature)");
+        opts.optflag("", "feed", "add the non-CMRA locations new since the previous run to the Atom feed result/feed.xml");
+        opts.optopt("", "record-http", "save the ATMB pages and the Smarty lookups under DIR, to be replayed with `--replay-http`", "DIR");
+        opts.optopt("", "replay-http", "serve the ATMB pages and the Smarty lookups recorded under DIR, nothing is sent", "DIR");
+        opts.optflag("", "allow-partial", "write the successful records even if some detail pages cannot be fetched");
+        opts
+    }
+
+    fn from_matches(matches: &Matches) -> anyhow::Result<Self> {
+        let verifiers = verifiers(matches)?;
+        let countries = match matches.opt_str("country") {
+            Some(countries) if countries.eq_ignore_ascii_case("all") => None,
+            Some(countries) => Some(parse_list(&countries)?),
+            None => Some(vec![Country::Us]),
+        };
+        let mut options = Self {
+            command: Self::command(&matches.free)?,
+            resume: matches.opt_present("resume"),
+            allow_partial: matches.opt_present("allow-partial"),
+            incremental: matches.opt_present("incremental"),
+            watch: matches.opt_get("watch")?,
+            timeout: matches.opt_str("timeout")
+                .map(|timeout| humantime::parse_duration(&timeout)
+                    .map_err(|e| anyhow!("invalid `--timeout` [{}], expected i.e. 45m or 1h30m: {}", timeout, e)))
+                .transpose()?,
+            webhook: matches.opt_str("webhook"),
+            webhook_format: matches.opt_get("webhook-format")?.unwrap_or_default(),
+            email: match matches.opt_str("smtp-server") {
+                Some(server) => Some(EmailConfig {
+                    server,
+                    from: matches.opt_str("email-from").ok_or_else(|| anyhow!("`--email-from` must be given with `--smtp-server`"))?,
+                    to: matches.opt_str("email-to")
+                        .map(|to| split_list(&to).iter().map(|to| to.trim().to_string()).filter(|to| !to.is_empty()).collect::<Vec<_>>())
+                        .filter(|to| !to.is_empty())
+                        .ok_or_else(|| anyhow!("`--email-to` must be given with `--smtp-server`"))?,
+                    user: matches.opt_str("smtp-user"),
+                }),
+                None => None,
+            },
+            telegram: match matches.opt_str("telegram-chat") {
+                Some(chat_id) => {
+                    let token = matches.opt_str("telegram-token")
+                        .or_else(|| std::env::var("TELEGRAM_BOT_TOKEN").ok())
+                        .ok_or_else(|| anyhow!("`--telegram-token` or `TELEGRAM_BOT_TOKEN` must be given with `--telegram-chat`"))?;
+                    Some((token, chat_id))
+                }
+                None => None,
+            },
+            providers: match matches.opt_str("provider") {
+                Some(providers) if providers.eq_ignore_ascii_case("all") => ProviderKind::ALL.to_vec(),
+                Some(providers) => parse_list(&providers)?,
+                None => vec![ProviderKind::Atmb],
+            },
+            crawl: CrawlConfig {
+                concurrency: matches.opt_get_default("crawl-concurrency", DEFAULT_CRAWL_CONCURRENCY)?,
+                rate_limit: match matches.opt_get_default("rate-limit", DEFAULT_RATE_LIMIT)? {
+                    rate if rate < 0.0 || rate.is_nan() => bail!("`--rate-limit` cannot be negative"),
+                    0.0 => None,
+                    rate => Some(rate),
+                },
+                connect_timeout: timeout(matches, "connect-timeout", DEFAULT_CONNECT_TIMEOUT)?,
+                read_timeout: timeout(matches, "read-timeout", DEFAULT_READ_TIMEOUT)?,
+                proxy: matches.opt_str("proxy"),
+                proxy_file: matches.opt_str("proxy-file").map(PathBuf::from),
+                states: matches.opt_str("states").map(|states| parse_list(&states)).transpose()?,
+                country: countries.as_ref().and_then(|countries| countries.first().copied()).unwrap_or_default(),
+                sitemap: matches.opt_present("sitemap"),
+                retry_delay: match matches.opt_get_default("retry-delay", DEFAULT_RETRY_DELAY.as_secs())? {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs)),
+                },
+                http: http_mode(matches)?,
+                browser: matches.opt_str("browser").map(PathBuf::from),
+                budget: match matches.opt_get::<usize>("max-requests")? {
+                    Some(0) => bail!("`--max-requests` must be at least 1"),
+                    max => max.map(RequestBudget::new),
+                },
+            },
+            countries,
+            verify_concurrency: matches.opt_get_default("verify-concurrency", DEFAULT_VERIFY_CONCURRENCY)?,
+            skip_verify: matches.opt_present("skip-verify"),
+            sink: matches.opt_get("sink")?,
+            verifier: verifiers.0,
+            consensus: verifiers.1,
+            double_check: matches.opt_get("double-check")?,
+            enrich: matches.opt_get("enrich")?,
+            quota_plan: matches.opt_get("quota-plan")?.unwrap_or_default(),
+            zip_precheck: matches.opt_present("zip-precheck"),
+            verify: VerifyConfig {
+                cache_ttl: Duration::from_secs(matches.opt_get_default("cache-ttl", DEFAULT_CACHE_TTL_DAYS)?.saturating_mul(24 * 3600)),
+                credentials_file: matches.opt_str("credentials-file").map(PathBuf::from),
+                http: http_mode(matches)?,
+                smarty_match: matches.opt_get("smarty-match")?.unwrap_or_default(),
+                smarty_license: matches.opt_str("smarty-license").unwrap_or_else(|| verify::DEFAULT_SMARTY_LICENSE.to_string()),
+                smarty_website_key: match (matches.opt_str("smarty-key"), matches.opt_str("smarty-referer")) {
+                    (Some(key), Some(referer)) => {
+                        let (key, monthly_lookups) = verify::split_monthly_lookups(&key)?;
+                        Some(SmartyCredential::WebsiteKey { key: key.to_string(), referer, monthly_lookups })
+                    }
+                    (Some(_), None) => bail!("`--smarty-key` requires `--smarty-referer`, one of the hosts the key is configured for"),
+                    (None, Some(_)) => bail!("`--smarty-referer` can only be used with `--smarty-key`"),
+                    (None, None) => None,
+                },
+                melissa_license: matches.opt_str("melissa-license"),
+                geocodio_key: matches.opt_str("geocodio-key"),
+            },
+            use_verified_address: matches.opt_present("use-verified-address"),
+            max_price: match matches.opt_get::<f64>("max-price")? {
+                Some(price) if price < 0.0 || price.is_nan() => bail!("`--max-price` cannot be negative"),
+                Some(price) => Some((price * 100.0).round() as u64),
+                None => None,
+            },
+            include_cmra: matches.opt_present("include-cmra"),
+            rdi: matches.opt_get("rdi")?.unwrap_or_default(),
+            duplicates: matches.opt_get("duplicates")?.unwrap_or_default(),
+            sort_by: matches.opt_get("sort-by")?.unwrap_or_default(),
+            desc: matches.opt_present("desc"),
+            formats: match matches.opt_str("format") {
+                Some(formats) => parse_list(&formats)?
+                    .into_iter()
+                    .map(|format| match format {
+                        OutputFormat::Markdown { .. } => OutputFormat::Markdown { by_state: matches.opt_present("split-by-state") },
+                        format => format,
+                    })
+                    .collect(),
+                None => vec![OutputFormat::Csv],
+            },
+            archive: matches.opt_present("archive"),
+            summary: matches.opt_present("summary"),
+            ambiguous_report: matches.opt_present("ambiguous-report"),
+            feed: matches.opt_present("feed"),
+            listen: matches.opt_get("listen")?.unwrap_or(DEFAULT_LISTEN),
+            metrics: matches.opt_present("metrics"),
+        };
+        if options.crawl.concurrency == 0 || options.verify_concurrency == 0 {
+            bail!("the concurrency must be at least 1");
+        }
+        if options.crawl.proxy.is_some() && options.crawl.proxy_file.is_some() {
+            bail!("`--proxy` and `--proxy-file` cannot be used together");
+        }
+        if options.webhook.is_none() && matches.opt_present("webhook-format") {
+            bail!("`--webhook-format` can only be used with `--webhook`");
+        }
+        #[cfg(not(feature = "email"))]
+        if options.email.is_some() {
+            bail!("`--smtp-server` needs the `email` feature, build with `cargo build --release --features email`");
+        }
+        if options.metrics && options.watch.is_none() {
+            bail!("`--metrics` can only be used with `--watch`, `serve` always serves them");
+        }
+        if options.timeout.is_some() && options.watch.is_some() {
+            bail!("`--timeout` stops the whole process, it cannot be used with `--watch`");
+        }
+        if options.timeout.is_some() && !matches!(options.command, Command::Run | Command::Verify { .. }) {
+            bail!("`--timeout` can only be used to crawl or with the `verify` subcommand");
+        }
+        if options.watch.is_some() && !matches!(options.command, Command::Run) {
+            bail!("`--watch` can only be used to crawl, not with a subcommand");
+        }
+        if options.double_check == Some(options.verifier) {
+            bail!("the verifier of `--double-check` must be different from `--verifier`");
+        }
+        if (options.double_check.is_some() || options.enrich.is_some()) && !options.consensus.is_empty() {
+            bail!("`--double-check` and `--enrich` cannot be used with `--verifier consensus:...`");
+        }
+        if options.enrich == Some(options.verifier) {
+            bail!("the verifier of `--enrich` must be different from `--verifier`");
+        }
+        if options.verifier == VerifierKind::Geocodio && !options.include_cmra {
+            bail!("geocodio doesn't report CMRA, use it with `--include-cmra`, or as `--enrich geocodio` of another verifier");
+        }
+        if options.double_check == Some(VerifierKind::Geocodio) {
+            bail!("geocodio doesn't report CMRA to double-check, use `--enrich geocodio` instead");
+        }
+        options.check_country(matches)?;
+        Ok(options)
+    }
+
+    /// the mailboxes outside the US are only crawled from ATMB, and have neither CMRA nor RDI to filter by,
+    /// they are always verified with the international verifier
+    fn check_country(&mut self, matches: &Matches) -> anyhow::Result<()> {
+        let (has_us, has_others) = match &self.countries {
+            Some(countries) => (countries.contains(&Country::Us), countries.iter().any(|country| *country != Country::Us)),
+            None => (true, true),
+        };
+        if matches!(self.command, Command::Verify { .. }) && self.countries.as_ref().is_none_or(|countries| countries.len() > 1) {
+            bail!("the mailboxes of one country can be verified at a time");
+        }
+        if has_us && [Some(self.verifier), self.double_check, self.enrich].contains(&Some(VerifierKind::International)) {
+            bail!("the international verifier cannot verify the US addresses");
+        }
+        if !has_others {
+            return Ok(());
+        }
+        if self.providers != [ProviderKind::Atmb] {
+            bail!("only ATMB has locations outside the US, crawl the other providers separately");
+        }
+        if self.crawl.states.is_some() {
+            bail!("`--states` can only be used in the US");
+        }
+        if self.rdi != RdiFilter::Any || self.double_check.is_some() || self.enrich.is_some() || !self.consensus.is_empty() {
+            bail!("`--rdi`, `--double-check`, `--enrich` and `--verifier consensus:...` can only be used in the US, RDI and CMRA are unknown elsewhere");
+        }
+        if !has_us && matches.opt_present("verifier") && self.verifier != VerifierKind::International {
+            bail!("the addresses outside the US can only be verified with the international verifier");
+        }
+        if self.crawl.country != Country::Us {
+            self.verifier = VerifierKind::International;
+        }
+        Ok(())
+    }
+
+    fn command(free: &[String]) -> anyhow::Result<Command> {
+        let Some((name, args)) = free.split_first() else {
+            return Ok(Command::Run);
+        };
+        match (name.as_str(), args) {
+            ("diff", [old]) => Ok(Command::Diff { old: old.into(), new: None }),
+            ("diff", [old, new]) => Ok(Command::Diff { old: old.into(), new: Some(new.into()) }),
+            ("diff", _) => bail!("usage: diff OLD_CSV [NEW_CSV]"),
+            ("report", []) => Ok(Command::Report { input: None, output: None }),
+            ("report", [input]) => Ok(Command::Report { input: Some(input.into()), output: None }),
+            ("report", [input, output]) => Ok(Command::Report { input: Some(input.into()), output: Some(output.into()) }),
+            ("report", _) => bail!("usage: report [CSV] [HTML]"),
+            ("verify", []) => Ok(Command::Verify { input: None }),
+            ("verify", [input]) => Ok(Command::Verify { input: Some(input.into()) }),
+            ("verify", _) => bail!("usage: verify [CSV|JSON]"),
+            ("check", [address]) => Ok(Command::Check { address: Address::parse(address)? }),
+            ("check", [line1, line2]) => Ok(Command::Check { address: Address::from_lines(line1.as_str(), line2)? }),
+            ("check", _) => bail!("usage: check \"STREET, CITY, ST ZIP\""),
+            ("countries", []) => Ok(Command::Countries),
+            ("serve", []) => Ok(Command::Serve { input: None }),
+            ("serve", [input]) => Ok(Command::Serve { input: Some(input.into()) }),
+            ("serve", _) => bail!("usage: serve [DB]"),
+            ("countries", _) => bail!("usage: countries"),
+            _ => bail!("unknown command: {}", name),
+        }
+    }
+}
+
+/// a timeout given in seconds, it must be positive
+fn timeout(matches: &Matches, name: &str, default: Duration) -> anyhow::Result<Duration> {
+    match matches.opt_get::<u64>(name)? {
+        Some(0) => bail!("`--{}` must be at least 1 second", name),
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Ok(default),
+    }
+}
+
+/// the verifier of `--verifier` and the others of `consensus:`, at least two different ones that report CMRA
+fn verifiers(matches: &Matches) -> anyhow::Result<(VerifierKind, Vec<VerifierKind>)> {
+    let Some(value) = matches.opt_str("verifier") else {
+        return Ok((VerifierKind::default(), Vec::new()));
+    };
+    let Some(names) = value.strip_prefix("consensus:") else {
+        return Ok((value.parse()?, Vec::new()));
+    };
+    let kinds = names.split(',')
+        .map(|name| name.trim().parse())
+        .collect::<anyhow::Result<Vec<VerifierKind>>>()?;
+    if kinds.len() < 2 {
+        bail!("`--verifier consensus:` needs at least two verifiers, i.e. `consensus:smarty,usps`");
+    }
+    if let Some(kind) = kinds.iter().find(|kind| matches!(kind, VerifierKind::Geocodio | VerifierKind::International)) {
+        bail!("[{:?}] doesn't report CMRA to vote on", kind);
+    }
+    if kinds.iter().enumerate().any(|(idx, kind)| kinds[..idx].contains(kind)) {
+        bail!("the verifiers of `--verifier consensus:` must be different");
+    }
+    Ok((kinds[0], kinds[1..].to_vec()))
+}
+
+/// `--record-http` or `--replay-http`, they cannot be used together
+fn http_mode(matches: &Matches) -> anyhow::Result<Option<HttpMode>> {
+    match (matches.opt_str("record-http"), matches.opt_str("replay-http")) {
+        (Some(_), Some(_)) => bail!("`--record-http` and `--replay-http` cannot be used together"),
+        (Some(dir), None) => Ok(Some(HttpMode::Record(dir.into()))),
+        (None, Some(dir)) => Ok(Some(HttpMode::Replay(dir.into()))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// split a comma separated list, `\,` is a comma inside an item and `\\` a backslash
+fn split_list(s: &str) -> Vec<String> {
+    let (mut items, mut item, mut chars) = (Vec::new(), String::new(), s.chars().peekable());
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some(',' | '\\')) => item.extend(chars.next()),
+            ',' => items.push(std::mem::take(&mut item)),
+            c => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+}
+
+/// parse a comma separated list, duplicated items are ignored
+fn parse_list<T: FromStr<Err = anyhow::Error> + PartialEq>(s: &str) -> anyhow::Result<Vec<T>> {
+    let mut items = Vec::new();
+    for item in split_list(s).iter().map(|item| item.trim()).filter(|item| !item.is_empty()) {
+        let item = item.parse()?;
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+    if items.is_empty() {
+        return Err(anyhow!("empty list: {}", s));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_list_with_commas() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("config.toml");
+        std::fs::write(&config_file, "email-to = [\"a,b\", \"c\"]\nstates = [\"TX\", \"FL\"]\n").unwrap();
+
+        let opts = Options::definitions();
+        let matches = opts.parse(Vec::<String>::new()).unwrap();
+        let args = Options::config_args(&opts, &matches, config::load(&config_file).unwrap()).unwrap();
+        let matches = opts.parse(&args).unwrap();
+        assert_eq!(split_list(&matches.opt_str("email-to").unwrap()), ["a,b", "c"]);
+        assert_eq!(split_list(r"a\,b,c\\,d\e"), ["a,b", "c\\", "d\\e"]);
+        let states = Options::from_matches(&matches).unwrap().crawl.states.unwrap();
+        assert_eq!(states.iter().map(|state| state.code).collect::<Vec<_>>(), ["TX", "FL"]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// failed requests in a row that halve the concurrency
+const FAILURES_TO_LOWER: usize = 3;
+/// successful requests in a row that raise the concurrency by one
+const SUCCESSES_TO_RAISE: usize = 10;
+/// how often a waiting request checks whether a slot is free
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Limits the concurrent requests to a host, adapting the limit to how they go.
+///
+/// It starts at the maximum, i.e. `--crawl-concurrency`. A few failed requests in a row halve the limit,
+/// down to one request at a time, and every run of successful ones raises it by one until it's back at the maximum.
+/// So a throttling host sees fewer requests at once, without waiting for most of them to fail as the
+/// [`CircuitBreaker`](crate::breaker::CircuitBreaker) does.
+pub struct AdaptiveConcurrency {
+    max: usize,
+    state: RefCell<State>,
+}
+
+struct State {
+    limit: usize,
+    in_flight: usize,
+    failures: usize,
+    successes: usize,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            max,
+            state: RefCell::new(State { limit: max, in_flight: 0, failures: 0, successes: 0 }),
+        }
+    }
+
+    /// wait for a free slot, it's released when the permit is dropped
+    pub async fn acquire(&self) -> Permit<'_> {
+        while !self.try_acquire() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Permit { concurrency: self }
+    }
+
+    fn try_acquire(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+        if state.in_flight >= state.limit {
+            return false;
+        }
+        state.in_flight += 1;
+        true
+    }
+
+    fn record(&self, success: bool) {
+        let mut state = self.state.borrow_mut();
+        if success {
+            state.failures = 0;
+            state.successes += 1;
+            if state.successes >= SUCCESSES_TO_RAISE && state.limit < self.max {
+                state.successes = 0;
+                state.limit += 1;
+                info!("[{}] requests in a row succeeded, raise the concurrency to [{}]", SUCCESSES_TO_RAISE, state.limit);
+            }
+        } else {
+            state.successes = 0;
+            state.failures += 1;
+            if state.failures >= FAILURES_TO_LOWER && state.limit > 1 {
+                state.failures = 0;
+                state.limit = (state.limit / 2).max(1);
+                warn!("[{}] requests in a row failed, lower the concurrency to [{}]", FAILURES_TO_LOWER, state.limit);
+            }
+        }
+    }
+}
+
+/// A slot of [`AdaptiveConcurrency`], report how the request went with [`Permit::record`]
+pub struct Permit<'a> {
+    concurrency: &'a AdaptiveConcurrency,
+}
+
+impl Permit<'_> {
+    /// report whether the request succeeded, and release the slot
+    pub fn record(self, success: bool) {
+        self.concurrency.record(success);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.concurrency.state.borrow_mut().in_flight -= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lower_and_raise() {
+        let concurrency = AdaptiveConcurrency::new(8);
+        for _ in 0..FAILURES_TO_LOWER * 2 {
+            concurrency.record(false);
+        }
+        assert_eq!(concurrency.state.borrow().limit, 2);
+        // a success in between resets the failures
+        concurrency.record(false);
+        concurrency.record(true);
+        concurrency.record(false);
+        assert_eq!(concurrency.state.borrow().limit, 2);
+
+        for _ in 0..SUCCESSES_TO_RAISE * 10 {
+            concurrency.record(true);
+        }
+        assert_eq!(concurrency.state.borrow().limit, 8);
+
+        let concurrency = AdaptiveConcurrency::new(1);
+        assert!(concurrency.try_acquire());
+        assert!(!concurrency.try_acquire());
+    }
+}
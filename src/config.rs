@@ -0,0 +1,134 @@
+use std::path::Path;
+use anyhow::{anyhow, bail};
+
+/// A value of the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    Bool(bool),
+    /// strings and numbers, numbers are kept as written
+    Scalar(String),
+    List(Vec<String>),
+}
+
+/// Load the options from a config file.
+///
+/// Only a flat subset of TOML is supported, every line is a `key = value` pair where the key is the name
+/// of a command line option, i.e. `verify-concurrency = 5` or `provider = ["atmb", "ipostal1"]`.
+/// Comments start with `#`, and `[section]` headers are ignored so the options can be grouped.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Vec<(String, ConfigValue)>> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("cannot read config file [{}]: {}", path.display(), e))?;
+    parse(&content).map_err(|e| anyhow!("invalid config file [{}]: {}", path.display(), e))
+}
+
+fn parse(content: &str) -> anyhow::Result<Vec<(String, ConfigValue)>> {
+    let mut entries = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() || (line.starts_with('[') && line.ends_with(']')) {
+            continue;
+        }
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected `key = value`", idx + 1))?;
+        let key = key.trim().replace('_', "-");
+        if key.is_empty() {
+            bail!("line {}: the key cannot be empty", idx + 1);
+        }
+        let value = parse_value(value.trim()).map_err(|e| anyhow!("line {}: {}", idx + 1, e))?;
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// drop the comment, `#` inside a quoted string is kept
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (idx, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> anyhow::Result<ConfigValue> {
+    match value {
+        "true" => return Ok(ConfigValue::Bool(true)),
+        "false" => return Ok(ConfigValue::Bool(false)),
+        _ => {}
+    }
+    if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        let items = split_items(items)
+            .into_iter()
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(parse_scalar)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        return Ok(ConfigValue::List(items));
+    }
+    parse_scalar(value).map(ConfigValue::Scalar)
+}
+
+/// split the items of a list, `,` inside a quoted string is kept
+fn split_items(items: &str) -> Vec<&str> {
+    let (mut quote, mut start, mut split) = (None, 0, Vec::new());
+    for (idx, c) in items.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (',', None) => {
+                split.push(&items[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&items[start..]);
+    split
+}
+
+fn parse_scalar(value: &str) -> anyhow::Result<String> {
+    let unquoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')));
+    match unquoted {
+        Some(s) => Ok(s.to_string()),
+        None if value.parse::<f64>().is_ok() => Ok(value.to_string()),
+        None => bail!("unsupported value `{}`, strings must be quoted", value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let content = r#"
+            # scheduled run
+            [crawl]
+            provider = ["atmb", "ipostal1"]  # two providers
+            rate_limit = 2.5
+            proxy = "http://127.0.0.1:8080/#anchor"
+
+            [verify]
+            verifier = 'usps'
+            archive = true
+        "#;
+        assert_eq!(parse(content).unwrap(), vec![
+            ("provider".to_string(), ConfigValue::List(vec!["atmb".to_string(), "ipostal1".to_string()])),
+            ("rate-limit".to_string(), ConfigValue::Scalar("2.5".to_string())),
+            ("proxy".to_string(), ConfigValue::Scalar("http://127.0.0.1:8080/#anchor".to_string())),
+            ("verifier".to_string(), ConfigValue::Scalar("usps".to_string())),
+            ("archive".to_string(), ConfigValue::Bool(true)),
+        ]);
+        assert_eq!(parse(r#"states = ["Washington, D.C.", 'a,b', "TX"]"#).unwrap(), vec![
+            ("states".to_string(), ConfigValue::List(vec!["Washington, D.C.".to_string(), "a,b".to_string(), "TX".to_string()])),
+        ]);
+        assert!(parse("verifier = usps").is_err());
+        assert!(parse("verifier").is_err());
+    }
+}
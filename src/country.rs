@@ -0,0 +1,123 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::LazyLock;
+use anyhow::anyhow;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::state::{self, UsState};
+
+// the postal codes at the end of an address, i.e. `77657-1234`, `M5E 1W7`, `SW1A 2AA` and `2000`,
+// the ZIP+4 may be written without the `-`, i.e. `77657 1234` or `776571234`
+static US_ZIP_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|\s)(\d{5})(?:[-\s]?(\d{4}))?$").unwrap());
+static CA_POSTAL_CODE_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(?:^|\s)([A-Z]\d[A-Z])\s?(\d[A-Z]\d)$").unwrap());
+static UK_POSTCODE_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(?:^|\s)([A-Z]{1,2}\d[A-Z\d]?)\s?(\d[A-Z]{2})$").unwrap());
+static AU_POSTCODE_REG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?:^|\s)(\d{4})$").unwrap());
+
+/// countries whose addresses can be parsed and verified, selected via `--country`
+///
+/// ATMB lists a few more, see [`crate::atmb::CountryRegistry`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Country {
+    #[default]
+    Us,
+    Ca,
+    Uk,
+    Au,
+}
+
+impl Country {
+    pub const ALL: [Country; 4] = [Country::Us, Country::Ca, Country::Uk, Country::Au];
+
+    /// ISO 3166 code, i.e. `US`, the UK is `GB`
+    pub fn code(self) -> &'static str {
+        match self {
+            Country::Us => "US",
+            Country::Ca => "CA",
+            Country::Uk => "GB",
+            Country::Au => "AU",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Country::Us => "United States",
+            Country::Ca => "Canada",
+            Country::Uk => "United Kingdom",
+            Country::Au => "Australia",
+        }
+    }
+
+    /// the country whose postal code the address ends with
+    pub fn detect(address: &str) -> Option<Country> {
+        Self::ALL.into_iter().find(|country| country.split_postal_code(address).is_some())
+    }
+
+    /// split the postal code off the end of the address, i.e. `Toronto, ON M5E 1W7` into `Toronto, ON` and `M5E 1W7`
+    ///
+    /// the postal code is normalized with a space in the middle, the ZIP+4 of a US ZIP is returned on its own.
+    pub fn split_postal_code(self, address: &str) -> Option<(&str, String, Option<String>)> {
+        let address = address.trim_end();
+        let reg = match self {
+            Country::Us => &US_ZIP_REG,
+            Country::Ca => &CA_POSTAL_CODE_REG,
+            Country::Uk => &UK_POSTCODE_REG,
+            Country::Au => &AU_POSTCODE_REG,
+        };
+        let caps = reg.captures(address)?;
+        let rest = address[..caps.get(0)?.start()].trim_end();
+        let group = |idx: usize| caps.get(idx).map(|m| m.as_str().to_uppercase());
+        match self {
+            Country::Us => Some((rest, group(1)?, group(2))),
+            Country::Ca | Country::Uk => Some((rest, format!("{} {}", group(1)?, group(2)?), None)),
+            Country::Au => Some((rest, group(1)?, None)),
+        }
+    }
+
+    /// code of the state or province, given by its code or name, `None` if it's unknown
+    ///
+    /// the UK has no such level in its addresses, the nations are accepted though, i.e. `England`.
+    pub fn region_code(self, region: &str) -> Option<&'static str> {
+        match self {
+            Country::Us => region.parse::<UsState>().ok().map(|state| state.code),
+            Country::Ca => state::province_code(region),
+            Country::Uk => state::uk_nation(region),
+            Country::Au => state::au_state_code(region),
+        }
+    }
+
+    /// whether the addresses must have a state or province
+    pub fn requires_region(self) -> bool {
+        self != Country::Uk
+    }
+
+    /// appended to the names of the result files, the US ones keep their original names
+    pub fn file_suffix(self) -> &'static str {
+        match self {
+            Country::Us => "",
+            Country::Ca => "_ca",
+            Country::Uk => "_uk",
+            Country::Au => "_au",
+        }
+    }
+}
+
+impl FromStr for Country {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "us" | "usa" | "united states" => Ok(Country::Us),
+            "ca" | "canada" => Ok(Country::Ca),
+            "uk" | "gb" | "united kingdom" => Ok(Country::Uk),
+            "au" | "australia" => Ok(Country::Au),
+            _ => Err(anyhow!("unknown country: {}, expected one of: us, ca, uk, au", s)),
+        }
+    }
+}
+
+impl Display for Country {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
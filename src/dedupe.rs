@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use anyhow::anyhow;
+use serde::Serialize;
+use crate::atmb::model::Mailbox;
+use crate::verify::model::{AdditionalInfo, YesOrNo};
+
+/// which of the listings at the same address to keep, selected via `--duplicates`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// keep every listing, they're only reported
+    #[default]
+    All,
+    /// keep the cheapest listing by the monthly price
+    Cheapest,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "all" => Ok(DuplicatePolicy::All),
+            "cheapest" => Ok(DuplicatePolicy::Cheapest),
+            _ => Err(anyhow!("unknown duplicate policy: {}, expected one of: all, cheapest", s)),
+        }
+    }
+}
+
+/// A listing sharing its address with another one, written to `result/duplicates.csv`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateRecord {
+    /// the normalized street and ZIP the listings share
+    pub street: String,
+    pub zip: String,
+    pub name: String,
+    pub price: String,
+    pub link: String,
+    /// whether the listing is kept in the results
+    pub kept: YesOrNo,
+}
+
+/// group the verified mailboxes by their address, returns the mailboxes to keep and the duplicates found
+///
+/// the address is the standardized one if the verifier has found a match, so the listings written
+/// differently, i.e. `Suite 100` and `Ste 100`, are still found to be the same place.
+pub fn dedupe(mailboxes: Vec<(Mailbox, AdditionalInfo)>, policy: DuplicatePolicy) -> (Vec<(Mailbox, AdditionalInfo)>, Vec<DuplicateRecord>) {
+    let mut groups = BTreeMap::<(String, String), Vec<(Mailbox, AdditionalInfo)>>::new();
+    for (mailbox, info) in mailboxes {
+        groups.entry(address_key(&mailbox, &info)).or_default().push((mailbox, info));
+    }
+
+    let mut kept = Vec::new();
+    let mut duplicates = Vec::new();
+    for ((street, zip), mut group) in groups {
+        if group.len() == 1 {
+            kept.extend(group);
+            continue;
+        }
+        // the cheapest goes first, the ones without a known price go last, the link breaks the ties
+        group.sort_by_key(|(mailbox, _)| (
+            mailbox.price.as_ref().map_or(u64::MAX, |price| price.monthly()),
+            mailbox.link.clone(),
+        ));
+        for (idx, (mailbox, info)) in group.into_iter().enumerate() {
+            let keep = policy == DuplicatePolicy::All || idx == 0;
+            duplicates.push(DuplicateRecord {
+                street: street.clone(),
+                zip: zip.clone(),
+                name: mailbox.name.clone(),
+                price: mailbox.raw_price.clone(),
+                link: mailbox.link.clone(),
+                kept: if keep { YesOrNo::Y } else { YesOrNo::N },
+            });
+            if keep {
+                kept.push((mailbox, info));
+            }
+        }
+    }
+    (kept, duplicates)
+}
+
+/// the upper case street without the punctuations, and the ZIP or postal code
+fn address_key(mailbox: &Mailbox, info: &AdditionalInfo) -> (String, String) {
+    let (street, zip) = match &info.verified {
+        Some(verified) if !verified.street.is_empty() => (verified.street.as_str(), verified.zip.as_str()),
+        _ => (mailbox.address.line1.as_str(), mailbox.address.zip.as_str()),
+    };
+    let street = street.chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_uppercase() } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (street, zip.trim().to_uppercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::atmb::model::{Address, Price};
+
+    #[test]
+    fn test_dedupe() {
+        let at = |street| Address::from_lines(street, "Dallas, TX 75201").unwrap();
+        let mailboxes = vec![
+            (Mailbox { address: at("1 Main St, Suite 100"), price: Price::parse("US$19.99/month"), ..Mailbox::sample("a") }, AdditionalInfo::sample()),
+            (Mailbox { address: at("1 MAIN ST SUITE 100"), ..Mailbox::sample("b") }, AdditionalInfo::sample()),
+            (Mailbox { address: at("2 Main St"), ..Mailbox::sample("c") }, AdditionalInfo::sample()),
+        ];
+        let (kept, duplicates) = dedupe(mailboxes.clone(), DuplicatePolicy::All);
+        assert_eq!(kept.len(), 3);
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[0].street, "1 MAIN ST SUITE 100");
+
+        let (kept, duplicates) = dedupe(mailboxes, DuplicatePolicy::Cheapest);
+        let mut names = kept.iter().map(|(mailbox, _)| mailbox.name.as_str()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["b", "c"]);
+        assert_eq!(duplicates.iter().map(|duplicate| duplicate.kept.clone()).collect::<Vec<_>>(), [YesOrNo::Y, YesOrNo::N]);
+    }
+}
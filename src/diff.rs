@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use crate::record::Record;
+
+/// Differences between the records of two runs, matched by the stable ID of the location
+#[derive(Debug)]
+pub struct Diff<'a> {
+    pub added: Vec<&'a Record>,
+    pub removed: Vec<&'a Record>,
+    pub changed: Vec<Change<'a>>,
+}
+
+/// a location that exists in both runs, but some of its fields are different
+#[derive(Debug)]
+pub struct Change<'a> {
+    pub new: &'a Record,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl<'a> Diff<'a> {
+    pub fn between(old: &'a [Record], new: &'a [Record]) -> Self {
+        let old_by_id = old.iter().map(|record| (record.id.as_str(), record)).collect::<HashMap<_, _>>();
+        let new_by_id = new.iter().map(|record| (record.id.as_str(), record)).collect::<HashMap<_, _>>();
+
+        let mut added = new.iter()
+            .filter(|record| !old_by_id.contains_key(record.id.as_str()))
+            .collect::<Vec<_>>();
+        let mut removed = old.iter()
+            .filter(|record| !new_by_id.contains_key(record.id.as_str()))
+            .collect::<Vec<_>>();
+        let mut changed = new.iter()
+            .filter_map(|new| {
+                let old = old_by_id.get(new.id.as_str())?;
+                let fields = Self::compare(old, new);
+                (!fields.is_empty()).then_some(Change { new, fields })
+            })
+            .collect::<Vec<_>>();
+
+        added.sort_by(|r1, r2| r1.link.cmp(&r2.link));
+        removed.sort_by(|r1, r2| r1.link.cmp(&r2.link));
+        changed.sort_by(|c1, c2| c1.new.link.cmp(&c2.new.link));
+        Self {
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn compare(old: &Record, new: &Record) -> Vec<FieldChange> {
+        let fields = [
+            ("name", old.name.clone(), new.name.clone()),
+            ("address", address(old), address(new)),
+            ("price", old.price.clone(), new.price.clone()),
+            ("CMRA", format!("{:?}", old.cmra), format!("{:?}", new.cmra)),
+            ("rdi", format!("{:?}", old.rdi), format!("{:?}", new.rdi)),
+        ];
+        fields.into_iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(field, old, new)| FieldChange { field, old, new })
+            .collect()
+    }
+}
+
+pub(crate) fn address(record: &Record) -> String {
+    // results written before the zip4 column was added have the full ZIP in the zip column
+    let zip = match record.zip4.as_str() {
+        "" => record.zip.clone(),
+        zip4 => format!("{}-{}", record.zip, zip4),
+    };
+    format!("{}, {}, {} {}", record.street, record.city, record.state, zip)
+}
+
+/// human readable report, one location per line
+impl Display for Diff<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "added: {}, removed: {}, changed: {}", self.added.len(), self.removed.len(), self.changed.len())?;
+        for record in &self.added {
+            writeln!(f, "+ [{}] {} | {} | {} | {}", record.provider, record.name, address(record), record.price, record.link)?;
+        }
+        for record in &self.removed {
+            writeln!(f, "- [{}] {} | {} | {} | {}", record.provider, record.name, address(record), record.price, record.link)?;
+        }
+        for change in &self.changed {
+            let fields = change.fields.iter()
+                .map(|field| format!("{}: {} -> {}", field.field, field.old, field.new))
+                .collect::<Vec<_>>()
+                .join("; ");
+            writeln!(f, "~ [{}] {} | {} | {}", change.new.provider, change.new.name, fields, change.new.link)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::verify::model::YesOrNo;
+
+    fn new_record(link: &str) -> Record {
+        Record::sample(link)
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![new_record("a"), new_record("b"), new_record("c")];
+        let new = vec![
+            new_record("a"),
+            Record { price: "US$19.99/month".to_string(), cmra: YesOrNo::Y, ..new_record("b") },
+            new_record("d"),
+        ];
+        let diff = Diff::between(&old, &new);
+        assert_eq!(diff.added.iter().map(|r| r.link.as_str()).collect::<Vec<_>>(), vec!["d"]);
+        assert_eq!(diff.removed.iter().map(|r| r.link.as_str()).collect::<Vec<_>>(), vec!["c"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].fields, vec![
+            FieldChange { field: "price", old: "US$9.99/month".to_string(), new: "US$19.99/month".to_string() },
+            FieldChange { field: "CMRA", old: "N".to_string(), new: "Y".to_string() },
+        ]);
+        assert!(Diff::between(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use crate::utils::parse_retry_after;
+
+/// The failures the callers can tell apart, carried inside the [`anyhow::Error`]s.
+///
+/// find it with [`Error::of`], the CLI exits with [`Error::exit_code`] so the schedulers can branch on it.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// the request failed, `status` is the unsuccessful status the server responded with
+    #[error("{}", fetch_message(url, *status))]
+    Fetch {
+        url: String,
+        status: Option<StatusCode>,
+        /// delay requested by the `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+    /// a part of the page cannot be found by any of its selectors, the layout is likely changed
+    #[error("No {what} found by any of the selectors {selector}{}, the layout might be changed - {snippet}", at_url(url))]
+    Parse {
+        /// filled in once the page is known, see [`Error::at_url`]
+        url: String,
+        /// the part of the page, i.e. `title`
+        what: &'static str,
+        selector: String,
+        snippet: String,
+    },
+    /// the verifier cannot look up the address
+    #[error("cannot verify [{address}]")]
+    Verify { address: String },
+    /// the verifier has no lookups left
+    #[error("the lookups of [{verifier}] are used up")]
+    QuotaExceeded { verifier: &'static str },
+    /// the server responded with an anti-bot challenge instead of the page
+    #[error(
+        "[{url}] is blocked by anti-bot ({service}), lower `--rate-limit` and `--crawl-concurrency`, or use `--proxy-file`, and try again later"
+    )]
+    Blocked {
+        url: String,
+        /// the anti-bot service, i.e. `Cloudflare challenge`
+        service: &'static str,
+    },
+}
+
+impl Error {
+    /// the unsuccessful response as an [`Error::Fetch`]
+    pub fn from_response(resp: &Response) -> Self {
+        Error::Fetch {
+            url: resp.url().to_string(),
+            status: Some(resp.status()),
+            retry_after: resp.headers().get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+        }
+    }
+
+    /// the error carried by `error`, the outermost one if there are several
+    pub fn of(error: &anyhow::Error) -> Option<&Self> {
+        error.downcast_ref::<Self>()
+    }
+
+    /// the status the server responded with, if it's an [`Error::Fetch`]
+    pub fn status(error: &anyhow::Error) -> Option<StatusCode> {
+        match Self::of(error) {
+            Some(Error::Fetch { status, .. }) => *status,
+            _ => None,
+        }
+    }
+
+    /// fill in the URL of the page that cannot be parsed, the other errors are returned as they are
+    pub fn at_url(mut error: anyhow::Error, url: &str) -> anyhow::Error {
+        if let Some(Error::Parse { url: page, .. }) = error.downcast_mut::<Self>() {
+            if page.is_empty() {
+                *page = url.to_string();
+            }
+        }
+        error
+    }
+
+    /// the exit code of the CLI, listed in the README
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Fetch { .. } => 3,
+            Error::Parse { .. } => 4,
+            Error::Verify { .. } => 5,
+            Error::QuotaExceeded { .. } => 6,
+            Error::Blocked { .. } => 7,
+        }
+    }
+}
+
+fn fetch_message(url: &str, status: Option<StatusCode>) -> String {
+    match status {
+        Some(status) => format!("[{}] responded with [{}]", url, status),
+        None => format!("cannot fetch [{}]", url),
+    }
+}
+
+fn at_url(url: &str) -> String {
+    match url {
+        "" => String::new(),
+        url => format!(" in [{}]", url),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_kinds() {
+        let parse = || anyhow::Error::new(Error::Parse {
+            url: String::new(),
+            what: "title",
+            selector: "`h1`".to_string(),
+            snippet: "<div></div>".to_string(),
+        });
+        let error = Error::at_url(parse(), "https://example.com/s/a");
+        assert!(matches!(Error::of(&error), Some(Error::Parse { url, .. }) if url == "https://example.com/s/a"));
+        assert_eq!(
+            error.to_string(),
+            "No title found by any of the selectors `h1` in [https://example.com/s/a], the layout might be changed - <div></div>",
+        );
+        assert!(!parse().to_string().contains(" in ["));
+
+        // the kind is still found under a context
+        let error = anyhow::Error::new(Error::QuotaExceeded { verifier: "smarty" }).context("the verification is cancelled");
+        assert_eq!(Error::of(&error).map(Error::exit_code), Some(6));
+        assert!(Error::of(&anyhow::anyhow!("cannot parse the page")).is_none());
+
+        let error = anyhow::Error::new(Error::Fetch { url: "https://example.com".to_string(), status: Some(StatusCode::NOT_FOUND), retry_after: None });
+        assert_eq!(Error::status(&error), Some(StatusCode::NOT_FOUND));
+        assert_eq!(error.to_string(), "[https://example.com] responded with [404 Not Found]");
+    }
+}
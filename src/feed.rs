@@ -0,0 +1,107 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::diff;
+use crate::record::Record;
+use crate::verify::model::YesOrNo;
+use crate::xlsx::escape;
+
+/// the feed keeps the latest entries only
+const MAX_ENTRIES: usize = 200;
+const FEED_ID: &str = "urn:atmb-us-physical:new-locations";
+
+/// An Atom feed of the newly added non-CMRA locations, written to `result/feed.xml` with `--feed`.
+///
+/// The entries are kept in a JSON file between the runs, as the feed itself is only written.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Feed {
+    /// the newest first
+    entries: Vec<FeedEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FeedEntry {
+    /// the link of the location, also the id of the entry
+    link: String,
+    title: String,
+    summary: String,
+    /// RFC 3339 timestamp of the run the location was added in
+    updated: String,
+}
+
+impl Feed {
+    /// load the entries of the previous runs, no entries if there is none
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// add the non-CMRA locations among the added ones, a location added again replaces its old entry
+    ///
+    /// * `updated` - RFC 3339 timestamp of the run
+    pub fn add(&mut self, added: &[&Record], updated: &str) -> usize {
+        let new_entries = added.iter()
+            .filter(|record| record.cmra == YesOrNo::N)
+            .map(|record| FeedEntry {
+                link: record.link.clone(),
+                title: format!("{}, {} {}", record.name, record.city, record.state),
+                summary: format!("{} | {} | {:?}", diff::address(record), record.price, record.rdi),
+                updated: updated.to_string(),
+            })
+            .collect::<Vec<_>>();
+        let count = new_entries.len();
+        self.entries.retain(|entry| !new_entries.iter().any(|new| new.link == entry.link));
+        self.entries.splice(0..0, new_entries);
+        self.entries.truncate(MAX_ENTRIES);
+        count
+    }
+
+    /// the Atom XML of the feed
+    pub fn render(&self) -> String {
+        let updated = self.entries.first().map(|entry| entry.updated.as_str()).unwrap_or("1970-01-01T00:00:00Z");
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+            <title>New non-CMRA mailboxes</title>\n<id>{}</id>\n<updated>{}</updated>\n",
+            FEED_ID, updated,
+        );
+        for entry in &self.entries {
+            xml.push_str(&format!(
+                "<entry>\n<title>{}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<updated>{}</updated>\n<summary>{}</summary>\n</entry>\n",
+                escape(&entry.title), escape(&entry.link), escape(&entry.link), entry.updated, escape(&entry.summary),
+            ));
+        }
+        xml.push_str("</feed>\n");
+        xml
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_feed_entries() {
+        let mut feed = Feed::default();
+        let a = Record { name: "A & B".to_string(), ..Record::sample("a") };
+        let cmra = Record { cmra: YesOrNo::Y, ..Record::sample("b") };
+        assert_eq!(feed.add(&[&a, &cmra], "2024-07-01T06:00:00Z"), 1);
+        assert_eq!(feed.add(&[&Record::sample("c"), &a], "2024-07-08T06:00:00Z"), 2);
+        assert_eq!(feed.entries.iter().map(|entry| entry.link.as_str()).collect::<Vec<_>>(), vec!["c", "a"]);
+
+        let xml = feed.render();
+        assert!(xml.contains("<updated>2024-07-08T06:00:00Z</updated>"));
+        assert!(xml.contains("<title>A &amp; B, "));
+        assert_eq!(xml.matches("<entry>").count(), 2);
+    }
+}
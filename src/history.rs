@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// where the results of every run are archived
+const HISTORY_DIR: &str = "result/history";
+const INDEX_FILE: &str = "index.csv";
+/// the directory holding a copy of the latest archived results
+const LATEST_DIR: &str = "latest";
+/// where the results of every run of `--watch` are kept
+const RUNS_DIR: &str = "result/runs";
+
+/// one row of `result/history/index.csv`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// UTC date of the run, i.e. `2024-07-01`, also the name of its archive directory
+    pub date: String,
+    /// RFC 3339 timestamp of the moment the results were written
+    pub finished_at: String,
+    /// number of verified mailboxes
+    pub total: usize,
+    /// number of non-CMRA mailboxes written to the results
+    pub non_cmra: usize,
+}
+
+impl RunSummary {
+    pub fn new(total: usize, non_cmra: usize) -> Self {
+        let finished_at = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        Self {
+            date: finished_at[..10].to_string(),
+            finished_at,
+            total,
+            non_cmra,
+        }
+    }
+}
+
+/// Copy the result files to `result/history/<date>/` and `result/history/latest/`,
+/// and record the run in the index. A rerun on the same day replaces the archive of that day.
+pub fn archive(result_files: &[PathBuf], summary: RunSummary) -> anyhow::Result<()> {
+    let history_dir = Path::new(HISTORY_DIR);
+    for dir in [history_dir.join(&summary.date), history_dir.join(LATEST_DIR)] {
+        std::fs::create_dir_all(&dir)?;
+        for file in result_files {
+            if let Some(name) = file.file_name() {
+                std::fs::copy(file, dir.join(name))?;
+            }
+        }
+    }
+
+    let index_path = history_dir.join(INDEX_FILE);
+    let mut runs = load_index(&index_path)?;
+    runs.retain(|run| run.date != summary.date);
+    runs.push(summary);
+    runs.sort_by(|r1, r2| r1.date.cmp(&r2.date));
+
+    let mut wtr = csv::Writer::from_path(&index_path)?;
+    for run in &runs {
+        wtr.serialize(run)?;
+    }
+    wtr.flush()?;
+    info!("results archived to [{}], [{}] runs in history", history_dir.display(), runs.len());
+    Ok(())
+}
+
+/// load the summaries of all archived runs
+pub fn load_index(path: impl AsRef<Path>) -> anyhow::Result<Vec<RunSummary>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(
+        csv::Reader::from_path(path)?
+            .deserialize()
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Copy the result files to `result/runs/<time>/` for `--watch`, i.e. `result/runs/2024-07-01T06-00-00Z/`,
+/// returns the directory. Unlike [`archive`], every run is kept.
+pub fn save_run(result_files: &[PathBuf]) -> anyhow::Result<PathBuf> {
+    let finished_at = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let dir = Path::new(RUNS_DIR).join(finished_at.replace(':', "-"));
+    std::fs::create_dir_all(&dir)?;
+    for file in result_files {
+        if let Some(name) = file.file_name() {
+            std::fs::copy(file, dir.join(name))?;
+        }
+    }
+    info!("results of the run saved to [{}]", dir.display());
+    Ok(dir)
+}
+
+/// the directory of the latest run saved by [`save_run`]
+pub fn latest_run() -> anyhow::Result<Option<PathBuf>> {
+    let runs_dir = Path::new(RUNS_DIR);
+    if !runs_dir.exists() {
+        return Ok(None);
+    }
+    let mut runs = std::fs::read_dir(runs_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    runs.retain(|path| path.is_dir());
+    // the timestamps sort in time order
+    runs.sort();
+    Ok(runs.pop())
+}
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use tracing::info;
+use crate::atmb::model::Mailbox;
+use crate::ipostal1::page::LocationListPage;
+use crate::metrics::{self, Counter};
+use crate::provider::{CrawlConfig, MailboxProvider};
+use crate::state::{self, UsState};
+
+mod page;
+
+const BASE_URL: &str = "https://ipostal1.com";
+const UA: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+const US_LOCATIONS_URL: &str = "/virtual-address/usa";
+
+/// Crawler of the iPostal1 US locations.
+///
+/// Unlike ATMB, all the locations are listed on a single page with their full address,
+/// so there's no need to visit the detail pages.
+pub struct IPostal1Crawl {
+    client: Client,
+    /// the locations of the other states are dropped from the list
+    states: Option<Vec<UsState>>,
+}
+
+impl IPostal1Crawl {
+    pub fn new(config: &CrawlConfig) -> anyhow::Result<Self> {
+        Ok(
+            Self {
+                client: config.client_builder()?
+                    .default_headers(Self::default_headers())
+                    .build()?,
+                states: config.states.clone(),
+            }
+        )
+    }
+
+    fn default_headers() -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.insert(USER_AGENT, HeaderValue::from_static(UA));
+        map
+    }
+}
+
+#[async_trait(?Send)]
+impl MailboxProvider for IPostal1Crawl {
+    fn name(&self) -> &str {
+        "ipostal1"
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<Mailbox>> {
+        info!("fetching iPostal1 location list...");
+        let html = async {
+            Ok::<_, anyhow::Error>(
+                self.client.get(format!("{}{}", BASE_URL, US_LOCATIONS_URL)).send().await?.error_for_status()?.text().await?
+            )
+        }
+            .await;
+        metrics::inc(if html.is_ok() { Counter::PagesFetched } else { Counter::FetchFailures });
+        let html = html?;
+        let page = LocationListPage::parse_html(&html)?;
+        info!("got [{}] iPostal1 locations", page.len());
+        let mut mailboxes = page.to_mailboxes()?;
+        mailboxes.retain(|mailbox| state::is_selected(self.states.as_deref(), &mailbox.address.state));
+        Ok(mailboxes)
+    }
+}
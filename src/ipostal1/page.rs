@@ -0,0 +1,153 @@
+use std::sync::LazyLock;
+use anyhow::{anyhow, bail};
+use scraper::{Html, Selector};
+use crate::atmb::model::{Address, Mailbox, Price};
+use crate::provider::ProviderKind;
+
+static LOCATION_CONTAINER_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class~="location-item"]"#).unwrap());
+static LOCATION_NAME_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="location-name"]"#).unwrap());
+static LOCATION_ADDRESS_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="location-address"]"#).unwrap());
+static LOCATION_PRICE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="location-price"]"#).unwrap());
+static LOCATION_LINK_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"a[href]"#).unwrap());
+
+/// iPostal1 location list page. i.e. https://ipostal1.com/virtual-address/usa
+pub struct LocationListPage {
+    locations: Vec<LocationHtmlInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct LocationHtmlInfo {
+    name: String,
+    /// street address
+    line1: String,
+    /// city, state, zip
+    line2: String,
+    price: String,
+    link: String,
+}
+
+impl LocationListPage {
+    pub fn parse_html(html: &str) -> anyhow::Result<Self> {
+        let mut locations = Vec::new();
+
+        let document = Html::parse_document(html);
+        for location_fragment in document.select(&LOCATION_CONTAINER_SELECTOR) {
+            let name = location_fragment.select(&LOCATION_NAME_SELECTOR).next()
+                .ok_or_else(|| anyhow!("No name found - {}", location_fragment.html()))?
+                .text()
+                .collect::<String>();
+            // the address is rendered as `street<br>city, state zip`
+            let lines = location_fragment.select(&LOCATION_ADDRESS_SELECTOR).next()
+                .ok_or_else(|| anyhow!("No address found - {}", location_fragment.html()))?
+                .text()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>();
+            let [line1, line2] = lines.as_slice() else {
+                bail!("Unexpected address line count: {}, page structure might be changed: {:?}", lines.len(), lines);
+            };
+            let price = location_fragment.select(&LOCATION_PRICE_SELECTOR).next()
+                .ok_or_else(|| anyhow!("No price found - {}", location_fragment.html()))?
+                .text()
+                .collect::<String>();
+            let link = location_fragment.select(&LOCATION_LINK_SELECTOR).next()
+                .and_then(|a| a.value().attr("href"))
+                .ok_or_else(|| anyhow!("No location link found - {}", location_fragment.html()))?;
+
+            locations.push(LocationHtmlInfo {
+                name: name.trim().to_string(),
+                line1: line1.clone(),
+                line2: line2.clone(),
+                price,
+                link: if link.starts_with("http") { link.to_string() } else { format!("{}{}", super::BASE_URL, link) },
+            });
+        }
+
+        if locations.is_empty() {
+            bail!("No location found, page structure might be changed");
+        }
+        Ok(
+            Self {
+                locations,
+            }
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn to_mailboxes(&self) -> anyhow::Result<Vec<Mailbox>> {
+        self.locations.iter()
+            .map(|location| location.clone().try_into())
+            .collect()
+    }
+}
+
+impl LocationHtmlInfo {
+    /// i.e. `$9.99 /mo` -> `US$9.99/month`
+    fn price(&self) -> String {
+        let price = self.price.trim()
+            .trim_start_matches("From")
+            .replace(' ', "");
+        let price = match price.strip_suffix("/mo") {
+            Some(amount) => format!("{}/month", amount),
+            None => price,
+        };
+        if price.starts_with('$') {
+            format!("US{}", price)
+        } else {
+            price
+        }
+    }
+}
+
+impl TryInto<Mailbox> for LocationHtmlInfo {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<Mailbox, Self::Error> {
+        let raw_price = self.price();
+        Ok(
+            Mailbox {
+                provider: ProviderKind::IPostal1,
+                address: Address::from_lines(self.line1.clone(), &self.line2)?,
+                price: Price::parse(&raw_price),
+                raw_price,
+                name: self.name,
+                link: self.link,
+                phone: None,
+                operator: None,
+                plans: Vec::new(),
+                geo: None,
+                listing: String::new(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOCATION_LIST_HTML: &str = r#"
+        <div class="location-item">
+            <h3 class="location-name">Austin - Congress Ave</h3>
+            <p class="location-address">1100 Congress Ave<br>Austin, TX 78701</p>
+            <span class="location-price">From $9.99 /mo</span>
+            <a href="/virtual-address/usa/tx/austin-1100-congress-ave">Select</a>
+        </div>
+    "#;
+
+    #[test]
+    fn test_parse_location_list() {
+        let page = LocationListPage::parse_html(LOCATION_LIST_HTML).unwrap();
+        let mailboxes = page.to_mailboxes().unwrap();
+        assert_eq!(mailboxes.len(), 1);
+        assert_eq!(mailboxes[0].name, "Austin - Congress Ave");
+        assert_eq!(mailboxes[0].address.line1, "1100 Congress Ave");
+        assert_eq!(mailboxes[0].address.state, "TX");
+        assert_eq!(mailboxes[0].address.zip, "78701");
+        assert_eq!(mailboxes[0].raw_price, "US$9.99/month");
+        assert_eq!(mailboxes[0].link, "https://ipostal1.com/virtual-address/usa/tx/austin-1100-congress-ave");
+    }
+}
@@ -0,0 +1,41 @@
+//! Crawl the mailboxes of ATMB, iPostal1 and PostScan Mail, and tell the non-CMRA ones apart.
+//! The ATMB locations in Canada, the UK and Australia can be crawled too, they are only checked for deliverability.
+//!
+//! The binary is a thin CLI on top of this crate, the crawlers ([`provider::MailboxProvider`]),
+//! the verifiers ([`verify::AddressVerifier`]) and the [`record::Record`]s can be used on their own.
+
+pub mod atmb;
+mod breaker;
+pub mod cassette;
+pub mod changelog;
+pub mod checkpoint;
+mod concurrency;
+pub mod country;
+pub mod dedupe;
+pub mod diff;
+pub mod error;
+pub mod feed;
+pub mod history;
+pub mod ipostal1;
+mod limiter;
+pub mod metrics;
+pub mod normalize;
+pub mod plan;
+pub mod notify;
+pub mod postscan;
+pub mod progress;
+pub mod provider;
+mod proxy;
+pub mod record;
+pub mod report;
+pub mod schedule;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shutdown;
+pub mod snapshot;
+pub mod sqlite;
+pub mod state;
+pub mod stats;
+mod utils;
+pub mod verify;
+mod xlsx;
@@ -0,0 +1,70 @@
+use std::cell::RefCell;
+use std::time::Duration;
+use rand::Rng;
+use tokio::time::Instant;
+
+/// Token bucket limiting the rate of the requests to a host.
+///
+/// The bucket holds at most `rate` tokens and refills `rate` tokens per second, every request takes one.
+/// A random delay of up to half the refill interval is added when a request has to wait,
+/// so the waiting requests don't fire in lockstep.
+pub struct RateLimiter {
+    /// tokens refilled per second
+    rate: f64,
+    bucket: RefCell<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// * `rate` - maximum requests per second, must be positive
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            bucket: RefCell::new(Bucket {
+                tokens: rate.max(1.0),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// wait until a request is allowed
+    pub async fn acquire(&self) {
+        while let Some(wait) = self.try_acquire() {
+            let jitter = rand::thread_rng().gen_range(0.0..=0.5) / self.rate;
+            tokio::time::sleep(wait + Duration::from_secs_f64(jitter)).await;
+        }
+    }
+
+    /// take a token if there's one, otherwise returns how long to wait for the next token
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut bucket = self.bucket.borrow_mut();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_wait() {
+        let limiter = RateLimiter::new(2.0);
+        assert!(limiter.try_acquire().is_none());
+        assert!(limiter.try_acquire().is_none());
+        let wait = limiter.try_acquire().unwrap();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(500));
+    }
+}
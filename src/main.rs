@@ -1,100 +1,1062 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+use anyhow::bail;
 use futures::StreamExt;
-use log::{error, info};
-use crate::atmb::ATMBCrawl;
-use crate::atmb::model::Mailbox;
-use crate::record::Record;
-use crate::smarty::{AdditionalInfo, SmartyClientProxy};
+use serde::Serialize;
+use tracing::{error, info, info_span, instrument, warn, Instrument};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use atmb_us_physical::atmb::{CountryRegistry, IncompleteCrawl};
+use atmb_us_physical::atmb::model::{Address, Mailbox};
+use atmb_us_physical::changelog::{self, ChangeKind, ChangeRecord};
+use atmb_us_physical::checkpoint::Checkpoint;
+use atmb_us_physical::country::Country;
+use atmb_us_physical::dedupe::{self, DuplicateRecord};
+use atmb_us_physical::diff::Diff;
+use atmb_us_physical::error::Error;
+use atmb_us_physical::feed::Feed;
+use atmb_us_physical::history::{self, RunSummary};
+use atmb_us_physical::metrics::{self, Counter};
+use atmb_us_physical::normalize::{self, ParseError};
+use atmb_us_physical::plan::{self, QuotaPlan};
+use atmb_us_physical::notify::Notifier;
+use atmb_us_physical::notify::telegram::Telegram;
+use atmb_us_physical::notify::webhook::Webhook;
+use atmb_us_physical::progress::Progress;
+use atmb_us_physical::record::{AmbiguousRecord, CsvSink, InternationalRecord, OutputFormat, PlanRecord, RawRecord, Record, SortFields};
+use atmb_us_physical::report;
+use atmb_us_physical::schedule::Schedule;
+use atmb_us_physical::shutdown::{self, Interrupted};
+use atmb_us_physical::snapshot::Snapshot;
+use atmb_us_physical::sqlite::{Sink, SqliteSink};
+use atmb_us_physical::stats::RunStats;
+use atmb_us_physical::verify::{self, AddressVerifier, VerifierKind};
+use atmb_us_physical::verify::cache::CachedVerifier;
+use atmb_us_physical::verify::model::{AdditionalInfo, Rdi, Vote, YesOrNo};
+use crate::cli::{Command, Options};
 
-mod atmb;
-mod record;
-mod smarty;
+mod cli;
+mod config;
 
+/// the records are kept in `result/mailboxes.db` with `--sink sqlite`
+const SQLITE_FILE: &str = "result/mailboxes.db";
+// the files of a country other than the US are suffixed with its code, i.e. `result/mailboxes_ca.csv`
+const CHECKPOINT_FILE_STEM: &str = "cache/checkpoint";
+const VERIFY_CHECKPOINT_FILE_STEM: &str = "cache/verify_checkpoint";
+const FAILED_FILE_STEM: &str = "result/failed";
+/// the result is saved as `result/mailboxes.<ext>`
+const RESULT_FILE_STEM: &str = "result/mailboxes";
+const REPORT_FILE: &str = "result/map.html";
+/// the crawled mailboxes are saved as `result/raw_mailboxes.<ext>` with `--skip-verify`
+const RAW_FILE_STEM: &str = "result/raw_mailboxes";
+/// the plans of the ATMB locations are saved as `result/plans.csv`
+const PLANS_FILE_STEM: &str = "result/plans";
+/// the mailboxes whose address is malformed are saved as `result/parse_errors.csv`
+const PARSE_ERRORS_FILE_STEM: &str = "result/parse_errors";
+/// the listings sharing an address are saved as `result/duplicates.csv`
+const DUPLICATES_FILE_STEM: &str = "result/duplicates";
+/// the records are appended to `result/verifying.csv` as they're verified, it's removed once the results are written
+const VERIFYING_FILE_STEM: &str = "result/verifying";
+/// the records completed before Ctrl-C are written as `result/partial-<time>.csv`
+const PARTIAL_FILE_STEM: &str = "result/partial";
+/// the addresses whose candidates disagree are saved as `result/ambiguous.csv` with `--ambiguous-report`
+const AMBIGUOUS_FILE_STEM: &str = "result/ambiguous";
+/// written as `result/summary.json` with `--summary`
+const SUMMARY_FILE_STEM: &str = "result/summary";
+/// the Atom feed of the new locations written with `--feed`
+const FEED_FILE: &str = "result/feed.xml";
+/// the entries of the feed, kept between the runs
+const FEED_ENTRIES_FILE: &str = "cache/feed.json";
+/// the price, CMRA and RDI changes and the delistings found by every run are appended to `result/changes.csv`
+const CHANGES_FILE_STEM: &str = "result/changes";
+/// the verified mailboxes of the previous runs, for `--incremental`
+const SNAPSHOT_FILE_STEM: &str = "cache/snapshot";
+
+/// the file of the crawled country, i.e. `result/mailboxes_ca.csv`
+fn country_file(stem: &str, extension: &str, options: &Options) -> PathBuf {
+    PathBuf::from(format!("{}{}.{}", stem, options.crawl.country.file_suffix(), extension))
+}
+
+/// log to stderr at `info` unless `RUST_LOG` is set, the spans are also exported with the `otlp` feature
 fn init_logger() {
-    match std::env::var("RUST_LOG") {
-        Err(_) => unsafe { std::env::set_var("RUST_LOG", "info") },
-        _ => {}
-    }
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_ansi(std::io::stderr().is_terminal()));
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp_layer());
+    registry.init();
+}
+
+/// the layer exporting the spans to the collector at `OTEL_EXPORTER_OTLP_ENDPOINT`, none if it's not set
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>() -> Option<impl tracing_subscriber::Layer<S>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider;
 
-    env_logger::init();
+    std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT")?;
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+        .inspect_err(|e| eprintln!("cannot export the spans: {}", e))
+        .ok()?;
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    opentelemetry::global::set_tracer_provider(provider);
+    Some(tracing_opentelemetry::layer().with_tracer(tracer))
 }
 
 #[tokio::main]
 async fn main() {
     init_logger();
 
-    match run().await {
+    let result = match Options::from_env() {
+        Ok(options) => {
+            // the long ones save what has been done on Ctrl-C
+            if matches!(options.command, Command::Run | Command::Verify { .. }) {
+                shutdown::listen();
+                if let Some(timeout) = options.timeout {
+                    shutdown::deadline(timeout);
+                }
+            }
+            match &options.command {
+                Command::Run => match options.watch.clone() {
+                    Some(schedule) => watch(options, &schedule).await,
+                    None => run(options).await.map(|_| ()),
+                },
+                Command::Diff { old, new } => diff(old, new.as_deref(), &options).await,
+                Command::Report { input, output } => report(input.as_deref(), output.as_deref()),
+                Command::Verify { input } => verify(input.as_deref(), &options).await,
+                Command::Check { address } => check(address, &options).await,
+                Command::Countries => countries(&options).await,
+                Command::Serve { input } => serve(input.as_deref(), &options).await,
+            }
+        }
+        Err(e) => Err(e),
+    };
+    let code = match &result {
+        Ok(()) => 0,
+        Err(e) if e.is::<Interrupted>() => {
+            warn!("{}, rerun with `--resume` to continue", e);
+            // the exit code of `timeout(1)`, so the schedulers tell it apart from Ctrl-C
+            if shutdown::timed_out().is_some() { 124 } else { 130 }
+        }
         Err(e) => {
-            log::error!("Error: {:?}", e);
-            std::process::exit(1);
+            error!("Error: {:?}", e);
+            Error::of(e).map_or(1, Error::exit_code)
+        }
+    };
+    // send the spans left in the batch
+    #[cfg(feature = "otlp")]
+    opentelemetry::global::shutdown_tracer_provider();
+    if code != 0 {
+        std::process::exit(code);
+    }
+}
+
+/// run on the `--watch` schedule until the process is stopped, and serve `/metrics` with `--metrics`
+async fn watch(options: Options, schedule: &Schedule) -> anyhow::Result<()> {
+    if !options.metrics {
+        return watch_runs(options, schedule).await;
+    }
+    #[cfg(feature = "server")]
+    {
+        let server = atmb_us_physical::server::Server::new(SQLITE_FILE);
+        let listen = options.listen;
+        tokio::select! {
+            result = watch_runs(options, schedule) => result,
+            result = server.serve(listen) => result,
         }
-        _ => {}
     }
+    #[cfg(not(feature = "server"))]
+    bail!("`--metrics` needs the `server` feature, build with `cargo build --release --features server`");
 }
 
-async fn run() -> anyhow::Result<()> {
-    let atmb = ATMBCrawl::new()?;
-    let mailboxes = atmb.fetch().await?;
+/// every run is copied to `result/runs/<time>/` with the differences from the run before in `diff.txt`
+///
+/// a failed run is logged, and the next one is tried on schedule
+async fn watch_runs(options: Options, schedule: &Schedule) -> anyhow::Result<()> {
+    let mut previous = history::latest_run()?;
+    loop {
+        let now = SystemTime::now();
+        let Some(next) = schedule.next_after(now) else {
+            bail!("the schedule `{}` never fires", schedule);
+        };
+        info!("waiting for the next run at [{}]...", humantime::format_rfc3339_seconds(next));
+        tokio::select! {
+            _ = tokio::time::sleep(next.duration_since(now).unwrap_or_default()) => {}
+            _ = shutdown::interrupted() => {
+                info!("interrupted, stop watching");
+                return Ok(());
+            }
+        }
+
+        let mut run_options = options.clone();
+        run_options.crawl.budget = options.crawl.budget.as_ref().map(|budget| budget.renewed());
+        match run(run_options).await {
+            Ok(result_files) => {
+                let dir = history::save_run(&result_files)?;
+                if let Some(previous) = &previous {
+                    diff_runs(previous, &dir, &options).await?;
+                }
+                previous = Some(dir);
+            }
+            Err(e) if e.is::<Interrupted>() => return Err(e),
+            Err(e) => error!("the scheduled run failed, try again at the next one: {:?}", e),
+        }
+    }
+}
+
+/// write the differences of the US results between two runs of `--watch` to `diff.txt` of the newer one,
+/// and notify the subscribers
+async fn diff_runs(old_dir: &Path, new_dir: &Path, options: &Options) -> anyhow::Result<()> {
+    let name = format!("{}.csv", RESULT_FILE_STEM.rsplit('/').next().unwrap_or(RESULT_FILE_STEM));
+    let (old_file, new_file) = (old_dir.join(&name), new_dir.join(&name));
+    if !old_file.exists() || !new_file.exists() {
+        return Ok(());
+    }
+    let old_records = Record::load_csv(&old_file)?;
+    let new_records = Record::load_csv(&new_file)?;
+    let diff = Diff::between(&old_records, &new_records);
+    notify(&diff, &new_file, options).await;
+    if diff.is_empty() {
+        info!("no differences from the previous run in [{}]", old_dir.display());
+        return Ok(());
+    }
+    let diff_file = new_dir.join("diff.txt");
+    std::fs::write(&diff_file, diff.to_string())?;
+    info!("the differences from the previous run have been saved to [{}]", diff_file.display());
+    Ok(())
+}
+
+/// crawl, verify and save the mailboxes of every selected country, one after another
+///
+/// returns the written result files
+async fn run(options: Options) -> anyhow::Result<Vec<PathBuf>> {
+    let countries = match &options.countries {
+        Some(countries) => countries.clone(),
+        None => {
+            let countries = CountryRegistry::discover(&options.crawl).await?.supported();
+            info!("found [{}] countries to crawl on ATMB", countries.len());
+            countries
+        }
+    };
+    let mut result_files = Vec::new();
+    for country in countries {
+        if options.crawl.budget.as_ref().is_some_and(|budget| budget.is_exhausted()) {
+            warn!("the request budget is used up, [{}] and the following countries are not crawled", country);
+            break;
+        }
+        let mut options = options.clone();
+        options.crawl.country = country;
+        if country != Country::Us {
+            options.verifier = VerifierKind::International;
+        }
+        info!("begin to crawl the mailboxes in [{}]...", country);
+        result_files.extend(run_country(options).await?);
+    }
+    Ok(result_files)
+}
+
+/// crawl, verify and save the mailboxes of the country in `options.crawl`, returns the written result files
+#[instrument(skip_all, fields(country = %options.crawl.country))]
+async fn run_country(options: Options) -> anyhow::Result<Vec<PathBuf>> {
+    let checkpoint_file = country_file(CHECKPOINT_FILE_STEM, "json", &options);
+    let mut checkpoint = if options.resume {
+        Checkpoint::load(checkpoint_file)?
+    } else {
+        Checkpoint::new(checkpoint_file)
+    };
+
+    let snapshot = if options.incremental {
+        let snapshot = Snapshot::load(country_file(SNAPSHOT_FILE_STEM, "json", &options))?;
+        if snapshot.is_empty() {
+            warn!("no previous run found, every location is crawled and verified");
+        } else {
+            info!("loaded [{}] mailboxes of the previous runs", snapshot.len());
+        }
+        snapshot
+    } else {
+        Snapshot::default()
+    };
+
+    let mut stats = RunStats::default();
+    if !checkpoint.crawled {
+        shutdown::enter_stage("crawl");
+        let start = Instant::now();
+        stats.fetch_failures = crawl(&options, &snapshot, &mut checkpoint).await?;
+        stats.finish_stage("crawl", start);
+    }
+    let mut mailboxes = checkpoint.mailboxes.clone();
+    // a resumed checkpoint may contain the mailboxes of other states
+    mailboxes.retain(|mailbox| is_selected(mailbox, &options));
+    stats.record_mailboxes(&mailboxes);
+    shutdown::enter_stage("address check");
+    let mailboxes = check_addresses(mailboxes, &options, &mut stats).await?;
+    // the unchanged mailboxes are not looked up again
+    for mailbox in &mailboxes {
+        if let Some(info) = snapshot.unchanged(mailbox) {
+            checkpoint.verified.entry(mailbox.link.clone()).or_insert_with(|| info.clone());
+        }
+    }
+    save_plans(&mailboxes, &options)?;
 
     info!("finished fetching, got [{}] mailboxes in total", mailboxes.len());
+    if options.skip_verify {
+        shutdown::enter_stage(shutdown::SAVE_STAGE);
+        let start = Instant::now();
+        let raw_files = save_raw(&mailboxes, &options)?;
+        stats.finish_stage("save", start);
+        if checkpoint.crawled {
+            checkpoint.remove()?;
+        }
+        print_stats(&stats, &options)?;
+        return Ok(raw_files);
+    }
+    let result_files = verify_and_save(mailboxes, &options, &mut checkpoint, &mut stats).await?;
+    // keep the checkpoint of a partial run, so the failed mailboxes can be retried with `--resume`
+    if checkpoint.crawled {
+        checkpoint.remove()?;
+    }
+    print_stats(&stats, &options)?;
+    Ok(result_files)
+}
+
+/// whether the mailbox is in the selected country and states and within the `--max-price`,
+/// the others are dropped before the verification to save the lookups
+fn is_selected(mailbox: &Mailbox, options: &Options) -> bool {
+    mailbox.address.country == options.crawl.country
+        && options.crawl.includes_state(&mailbox.address.state)
+        && options.max_price.is_none_or(|max_price| {
+            mailbox.price.as_ref().is_some_and(|price| price.monthly() <= max_price)
+        })
+}
+
+/// verify the mailboxes of a previous run without crawling again
+async fn verify(input: Option<&Path>, options: &Options) -> anyhow::Result<()> {
+    let default_input = country_file(RAW_FILE_STEM, "csv", options);
+    let input = input.unwrap_or(&default_input);
+    let mut mailboxes = RawRecord::load_mailboxes(input)?;
+    info!("loaded [{}] mailboxes from [{}]", mailboxes.len(), input.display());
+    mailboxes.retain(|mailbox| is_selected(mailbox, options));
+    let mut stats = RunStats::default();
+    stats.record_mailboxes(&mailboxes);
+    let mailboxes = check_addresses(mailboxes, options, &mut stats).await?;
+
+    // a separate checkpoint, so the one of an interrupted crawl is left untouched
+    let checkpoint_file = country_file(VERIFY_CHECKPOINT_FILE_STEM, "json", options);
+    let mut checkpoint = if options.resume {
+        Checkpoint::load(checkpoint_file)?
+    } else {
+        Checkpoint::new(checkpoint_file)
+    };
+    verify_and_save(mailboxes, options, &mut checkpoint, &mut stats).await?;
+    checkpoint.remove()?;
+    print_stats(&stats, options)
+}
+
+/// verify the mailboxes, and save the non-CMRA ones, or all of them with `--include-cmra`
+///
+/// the mailboxes outside the US are all saved, as their CMRA is unknown, returns the written result files
+async fn verify_and_save(mailboxes: Vec<Mailbox>, options: &Options, checkpoint: &mut Checkpoint, stats: &mut RunStats) -> anyhow::Result<Vec<PathBuf>> {
     info!("begin to inquire mailbox address info...");
 
-    let mailboxes_info = inquire_mailboxes_info(mailboxes).await?;
-    // filter out CMRA and addresses
-    let records = mailboxes_info.into_iter().filter_map(|(mailbox, info)| {
-        if info.is_cmra() {
-            None
+    shutdown::enter_stage("verification");
+    let start = Instant::now();
+    let snapshot_file = country_file(SNAPSHOT_FILE_STEM, "json", options);
+    let mut snapshot = Snapshot::load(&snapshot_file)?;
+    let mailboxes_info = inquire_mailboxes_info(mailboxes, options, &snapshot, checkpoint, stats).await?;
+    stats.finish_stage("verification", start);
+    let changes = find_changes(&snapshot, &mailboxes_info, options, checkpoint);
+    snapshot.remove(changes.iter().filter(|change| change.change == ChangeKind::Delisted).map(|change| change.link.as_str()));
+    snapshot.extend(&mailboxes_info);
+    snapshot.save(&snapshot_file)?;
+    save_changes(&changes, options)?;
+    if shutdown::is_interrupted() {
+        // the verified ones are in the checkpoint, and written with their CMRA and RDI to filter by
+        let mailboxes_info = mailboxes_info.into_iter();
+        if options.crawl.country == Country::Us {
+            let records = mailboxes_info.map(|(mailbox, info)| Record::from_mailbox_and_info(mailbox, info, options.use_verified_address));
+            save_partial(&records.collect::<Vec<_>>(), options)?;
         } else {
-            Some(Record::from_mailbox_and_info(mailbox, info))
+            let records = mailboxes_info.map(|(mailbox, info)| InternationalRecord::from_mailbox_and_info(mailbox, info, options.use_verified_address));
+            save_partial(&records.collect::<Vec<_>>(), options)?;
         }
-    })
-        .collect::<Vec<_>>();
+        remove_verifying(options)?;
+        bail!(Interrupted);
+    }
+
+    shutdown::enter_stage(shutdown::SAVE_STAGE);
+    let start = Instant::now();
+    let _save = info_span!("save").entered();
+    for (mailbox, info) in &mailboxes_info {
+        stats.record_info(mailbox, info);
+    }
+    if options.ambiguous_report {
+        save_ambiguous(&mailboxes_info, options)?;
+    }
+    let (mailboxes_info, duplicates) = dedupe::dedupe(mailboxes_info.into_iter().collect(), options.duplicates);
+    stats.duplicates = duplicates.len() - duplicates.iter().map(|duplicate| (&duplicate.street, &duplicate.zip)).collect::<HashSet<_>>().len();
+    save_duplicates(&duplicates, options)?;
+
+    let (written, result_files) = if options.crawl.country == Country::Us {
+        // filter out CMRA unless `--include-cmra` is set, and the addresses of other RDI,
+        // as most of the verifiers of `--verifier consensus:...` agree
+        let records = mailboxes_info.into_iter().filter_map(|(mailbox, info)| {
+            let (cmra, rdi) = info.decided();
+            if (cmra == YesOrNo::Y && !options.include_cmra) || !options.rdi.matches(&rdi) {
+                None
+            } else {
+                Some(Record::from_mailbox_and_info(mailbox, info, options.use_verified_address))
+            }
+        })
+            .collect::<Vec<_>>();
+        if options.sink == Some(Sink::Sqlite) {
+            save_sqlite(&records, SQLITE_FILE)?;
+        }
+        let written = records.iter().filter(|record| *record.consensus_cmra.as_ref().unwrap_or(&record.cmra) == YesOrNo::N).count();
+        if options.feed {
+            update_feed(&records, options)?;
+        }
+        (written, save_records(records, options)?)
+    } else {
+        let records = mailboxes_info.into_iter()
+            .map(|(mailbox, info)| InternationalRecord::from_mailbox_and_info(mailbox, info, options.use_verified_address))
+            .collect::<Vec<_>>();
+        (records.len(), save_records(records, options)?)
+    };
+    remove_verifying(options)?;
+    if options.archive {
+        history::archive(&result_files, RunSummary::new(stats.verified, written))?;
+    }
+    stats.finish_stage("save", start);
+    Ok(result_files)
+}
+
+/// the changes since the mailboxes were saved in the snapshot, the delistings only if the crawl of this run is complete
+fn find_changes(previous: &Snapshot, mailboxes_info: &HashMap<Mailbox, AdditionalInfo>, options: &Options, checkpoint: &Checkpoint) -> Vec<ChangeRecord> {
+    let time = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let mut changes = changelog::changes(previous, mailboxes_info, &time);
+    // the `verify` subcommand and the crawls with failed pages don't see every listing
+    if checkpoint.crawled {
+        let listed = checkpoint.mailboxes.iter().map(|mailbox| mailbox.link.as_str()).collect::<HashSet<_>>();
+        let crawled = |mailbox: &Mailbox| {
+            mailbox.address.country == options.crawl.country
+                && options.crawl.includes_state(&mailbox.address.state)
+                && options.providers.contains(&mailbox.provider)
+        };
+        changes.extend(changelog::delisted(previous, &listed, crawled, &time));
+    }
+    changes
+}
+
+/// append the changes to `result/changes.csv`
+fn save_changes(changes: &[ChangeRecord], options: &Options) -> anyhow::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let out_file = country_file(CHANGES_FILE_STEM, "csv", options);
+    info!("[{}] changes since the previous run have been appended to [{}]", changes.len(), out_file.display());
+    changelog::append(changes, out_file)
+}
 
-    let out_file = "result/mailboxes.csv";
-    info!("saving records to [{}]", out_file);
-    save_records(records, out_file)?;
+/// add the locations new since the results of the previous run to `result/feed.xml`
+fn update_feed(records: &[Record], options: &Options) -> anyhow::Result<()> {
+    let previous_file = country_file(RESULT_FILE_STEM, "csv", options);
+    // the previous CSV results may not be there if only the other formats were written
+    let previous = if previous_file.exists() { Record::load_csv(&previous_file)? } else { Vec::new() };
+    let diff = Diff::between(&previous, records);
+    let mut feed = Feed::load(FEED_ENTRIES_FILE)?;
+    let added = feed.add(&diff.added, &humantime::format_rfc3339_seconds(SystemTime::now()).to_string());
+    feed.save(FEED_ENTRIES_FILE)?;
+    if let Some(parent) = Path::new(FEED_FILE).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(FEED_FILE, feed.render())?;
+    info!("[{}] new locations have been added to the feed [{}]", added, FEED_FILE);
     Ok(())
 }
 
-async fn inquire_mailboxes_info(mailboxes: Vec<Mailbox>) -> anyhow::Result<HashMap<Mailbox, AdditionalInfo>> {
-    let client = SmartyClientProxy::new()?;
+/// print the statistics of the run, and write them to `result/summary.json` with `--summary`
+fn print_stats(stats: &RunStats, options: &Options) -> anyhow::Result<()> {
+    print!("{}", stats.table());
+    if options.summary {
+        let summary_file = country_file(SUMMARY_FILE_STEM, "json", options);
+        stats.save(&summary_file)?;
+        info!("run summary has been saved to [{}]", summary_file.display());
+    }
+    Ok(())
+}
 
-    let total = mailboxes.len();
-    let mailboxes_info = futures::stream::iter(mailboxes.into_iter()).enumerate().map(|(idx, mailbox)| {
+/// print the countries listed on ATMB, and whether they can be crawled
+async fn countries(options: &Options) -> anyhow::Result<()> {
+    let registry = CountryRegistry::discover(&options.crawl).await?;
+    for entry in registry.entries() {
+        match entry.country {
+            Some(country) => println!("{:<20} {:<24} --country {}", entry.name, entry.path, country.code().to_lowercase()),
+            None => println!("{:<20} {:<24} not supported yet", entry.name, entry.path),
+        }
+    }
+    Ok(())
+}
+
+/// check a single address, and print what the verifiers report
+async fn check(address: &Address, options: &Options) -> anyhow::Result<()> {
+    info!("checking [{}, {}, {} {}]...", address.line1, address.city, address.state, address.full_zip());
+    let mut info = verify::check_address(address, options.verifier, &options.verify).await?;
+    if !options.consensus.is_empty() {
+        let mut votes = vec![vote(options.verifier, &info)];
+        for &other in &options.consensus {
+            votes.push(vote(other, &verify::check_address(address, other, &options.verify).await?));
+        }
+        info.votes = votes;
+    }
+    // the international verifier is the only one outside the US
+    if let Some(second) = options.double_check.filter(|_| address.country == Country::Us) {
+        info.double_check = Some(Box::new(verify::check_address(address, second, &options.verify).await?));
+    }
+    if let Some(enricher) = options.enrich.filter(|_| address.country == Country::Us && info.needs_enrichment()) {
+        info.enrich(&verify::check_address(address, enricher, &options.verify).await?);
+    }
+    println!("{}", serde_json::to_string_pretty(&info)?);
+    Ok(())
+}
+
+/// the notifiers selected via `--webhook`, `--smtp-server` and `--telegram-chat`
+fn notifiers(options: &Options) -> anyhow::Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers = Vec::<Box<dyn Notifier>>::new();
+    if let Some(url) = &options.webhook {
+        notifiers.push(Box::new(Webhook::new(url, options.webhook_format)?));
+    }
+    if let Some((token, chat_id)) = &options.telegram {
+        notifiers.push(Box::new(Telegram::new(token, chat_id)?));
+    }
+    #[cfg(feature = "email")]
+    if let Some(email) = &options.email {
+        notifiers.push(Box::new(atmb_us_physical::notify::email::Email::new(email.clone())?));
+    }
+    Ok(notifiers)
+}
+
+/// tell every notifier about the differences, a failed one doesn't stop the others or the run
+async fn notify(diff: &Diff<'_>, result_file: &Path, options: &Options) {
+    let notifiers = match notifiers(options) {
+        Ok(notifiers) => notifiers,
+        Err(e) => {
+            error!("cannot set up the notifiers: {:?}", e);
+            return;
+        }
+    };
+    for notifier in notifiers {
+        if let Err(e) = notifier.notify(diff, result_file).await {
+            error!("cannot notify via [{}]: {:?}", notifier.name(), e);
+        }
+    }
+}
+
+/// serve the results of `--sink sqlite` over HTTP until the process is stopped
+async fn serve(input: Option<&Path>, options: &Options) -> anyhow::Result<()> {
+    let default_input = PathBuf::from(SQLITE_FILE);
+    let input = input.unwrap_or(&default_input);
+    #[cfg(feature = "server")]
+    {
+        let mut server = atmb_us_physical::server::Server::new(input);
+        match options.verifier.build(&options.verify) {
+            Ok(verifier) => server = server.with_verifier(verifier),
+            Err(e) => warn!("`POST /verify` is disabled as the verifier cannot be set up: {:#}", e),
+        }
+        server.serve(options.listen).await
+    }
+    #[cfg(not(feature = "server"))]
+    bail!(
+        "serving [{}] on [{}] needs the `server` feature, build with `cargo build --release --features server`",
+        input.display(), options.listen,
+    );
+}
+
+/// print the differences between two result CSV files, and notify the subscribers
+async fn diff(old: &Path, new: Option<&Path>, options: &Options) -> anyhow::Result<()> {
+    let default_new = PathBuf::from(format!("{}.csv", RESULT_FILE_STEM));
+    let new = new.unwrap_or(&default_new);
+    let old_records = Record::load_csv(old)?;
+    let new_records = Record::load_csv(new)?;
+    let diff = Diff::between(&old_records, &new_records);
+    if diff.is_empty() {
+        println!("no differences found");
+    } else {
+        print!("{}", diff);
+        notify(&diff, new, options).await;
+    }
+    Ok(())
+}
+
+/// render the residential locations of a result CSV on an HTML map
+fn report(input: Option<&Path>, output: Option<&Path>) -> anyhow::Result<()> {
+    let default_input = PathBuf::from(format!("{}.csv", RESULT_FILE_STEM));
+    let input = input.unwrap_or(&default_input);
+    let output = output.unwrap_or(Path::new(REPORT_FILE));
+    let records = Record::load_csv(input)?;
+    let html = report::render(&records)?;
+    if let Some(parent) = output.parent() {
+        if !parent.exists() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::write(output, html)?;
+    info!("map report has been saved to [{}]", output.display());
+    Ok(())
+}
+
+/// crawl the mailboxes of the selected providers, the progress is saved to the checkpoint
+///
+/// returns the number of mailboxes that cannot be fetched
+#[instrument(skip_all)]
+async fn crawl(options: &Options, snapshot: &Snapshot, checkpoint: &mut Checkpoint) -> anyhow::Result<usize> {
+    let fetched = std::mem::take(&mut checkpoint.mailboxes);
+    let previous = snapshot.mailboxes().cloned().collect::<Vec<_>>();
+    let mut mailboxes = Vec::new();
+    let mut failed = Vec::new();
+
+    for kind in &options.providers {
+        if shutdown::is_interrupted() {
+            return Err(save_interrupted_crawl(mailboxes, checkpoint, options));
+        }
+        let provider = kind.build(&fetched, &previous, &options.crawl)?;
+        info!("begin to crawl [{}] mailboxes...", provider.name());
+        match provider.fetch().await {
+            Ok(provider_mailboxes) => mailboxes.extend(provider_mailboxes),
+            Err(e) => {
+                let Some(incomplete) = e.downcast_ref::<IncompleteCrawl>() else {
+                    return Err(e);
+                };
+                mailboxes.extend(incomplete.fetched.iter().cloned());
+                if incomplete.interrupted {
+                    return Err(save_interrupted_crawl(mailboxes, checkpoint, options));
+                }
+                // the fetched ones are written when the budget is used up, the rest are left to `--resume`
+                if !options.allow_partial && !incomplete.exhausted {
+                    checkpoint.mailboxes = mailboxes;
+                    checkpoint.save()?;
+                    info!("progress has been saved, rerun with `--resume` to continue");
+                    return Err(e);
+                }
+                if incomplete.exhausted {
+                    warn!("{}, continue with the fetched ones", incomplete);
+                } else {
+                    warn!("{}, continue with the fetched ones as `--allow-partial` is set", incomplete);
+                }
+                failed.extend(incomplete.failed.iter().cloned());
+            }
+        }
+    }
+
+    checkpoint.mailboxes = mailboxes;
+    checkpoint.crawled = failed.is_empty();
+    checkpoint.save()?;
+    if !failed.is_empty() {
+        info!("progress has been saved, rerun with `--resume` to retry the failed ones");
+        let failed_file = country_file(FAILED_FILE_STEM, "csv", options);
+        info!("saving failed mailboxes to [{}]", failed_file.display());
+        save_failed(&failed, failed_file)?;
+    }
+    Ok(failed.len())
+}
+
+/// save the mailboxes crawled before Ctrl-C to the checkpoint and `result/partial-<time>.csv`
+fn save_interrupted_crawl(mailboxes: Vec<Mailbox>, checkpoint: &mut Checkpoint, options: &Options) -> anyhow::Error {
+    let records = mailboxes.iter().map(RawRecord::from_mailbox).collect::<Vec<_>>();
+    checkpoint.mailboxes = mailboxes;
+    let saved = checkpoint.save().and_then(|_| save_partial(&records, options));
+    match saved {
+        Ok(_) => Interrupted.into(),
+        Err(e) => e.context(Interrupted),
+    }
+}
+
+/// write the records completed before Ctrl-C to `result/partial-<time>.csv`
+fn save_partial<T: Serialize>(records: &[T], options: &Options) -> anyhow::Result<()> {
+    let time = humantime::format_rfc3339_seconds(SystemTime::now()).to_string().replace(':', "-");
+    let out_file = country_file(&format!("{}-{}", PARTIAL_FILE_STEM, time), "csv", options);
+    info!("saving [{}] records completed before the interruption to [{}]", records.len(), out_file.display());
+    OutputFormat::Csv.write(records, out_file)
+}
+
+/// inquire the address info of the mailboxes, the ones verified in the checkpoint are reused
+///
+/// * `previous` - the mailboxes of the previous runs, the ones left out by `--quota-plan prioritize` keep their results
+#[instrument(name = "verify", skip_all, fields(mailboxes = mailboxes.len()))]
+async fn inquire_mailboxes_info(mailboxes: Vec<Mailbox>, options: &Options, previous: &Snapshot, checkpoint: &mut Checkpoint, stats: &mut RunStats) -> anyhow::Result<HashMap<Mailbox, AdditionalInfo>> {
+    let (verified, mailboxes): (Vec<_>, Vec<_>) = mailboxes.into_iter()
+        .partition(|mailbox| checkpoint.verified.contains_key(&mailbox.link));
+    if !verified.is_empty() {
+        info!("[{}] mailboxes have been verified before, skip them", verified.len());
+    }
+
+    let client = options.verifier.build(&options.verify)?;
+    let (mailboxes, skipped) = plan_lookups(mailboxes, &client, previous, options)?;
+
+    let progress = Progress::new("verification", mailboxes.len());
+    let sink = RefCell::new(CsvSink::create(country_file(VERIFYING_FILE_STEM, "csv", options))?);
+    // the records of other countries are not written to the database
+    let staging = match options.sink {
+        Some(Sink::Sqlite) if options.crawl.country == Country::Us => Some(SqliteSink::open(SQLITE_FILE)?),
+        _ => None,
+    };
+    let staging = RefCell::new(staging);
+    let batch_size = client.batch_size().max(1);
+    let mailboxes_info = futures::stream::iter(mailboxes.chunks(batch_size)).enumerate().map(|(idx, batch)| {
         let client = &client;
+        let progress = &progress;
         async move {
-            info!("[{}/{total}] fetching mailbox address info for [{}]", idx + 1, mailbox.name);
+            let more = match batch.len() {
+                1 => String::new(),
+                len => format!(" and [{}] more", len - 1),
+            };
+            progress.message(idx * batch_size + batch.len(), format_args!("fetching mailbox address info for [{}]{}", batch[0].name, more));
 
-            let address = &mailbox.address;
-            let additional_info = match client.inquire_address(address.clone()).await {
-                Ok(info) => info,
+            let addresses = batch.iter().map(|mailbox| mailbox.address.clone()).collect::<Vec<_>>();
+            let infos = client.inquire_batch(&addresses)
+                .instrument(info_span!("lookup", addresses = addresses.len()))
+                .await;
+            progress.inc(batch.len());
+            batch.iter().zip(infos).filter_map(|(mailbox, info)| match info {
+                Ok(info) => Some((mailbox.clone(), info)),
                 Err(e) => {
                     error!("cannot inquire address info for [{}]: {:?}", mailbox.name, e);
-                    return None;
+                    None
                 }
-            };
-            Some((mailbox, additional_info))
+            })
+                .collect::<Vec<_>>()
         }
     })
-        .buffer_unordered(10)
+        .buffer_unordered(options.verify_concurrency)
+        .inspect(|verified| append_verifying(&mut sink.borrow_mut(), staging.borrow_mut().as_mut(), verified, options))
+        // the lookups in flight are cancelled, the finished ones are kept
+        .take_until(shutdown::interrupted())
         .collect::<Vec<_>>()
         .await;
+    progress.finish();
+
+    client.save_cache()?;
+    for (credential, count) in client.usage() {
+        metrics::add_lookups(&credential, count as u64);
+        stats.lookups.insert(credential, count);
+    }
+
+    let mut mailboxes_info = mailboxes_info.into_iter().flatten().collect::<HashMap<_, _>>();
+    if !options.consensus.is_empty() && !shutdown::is_interrupted() {
+        ask_consensus(&mut mailboxes_info, options.verifier, options).await?;
+    }
+    if let Some(second) = options.double_check.filter(|_| !shutdown::is_interrupted()) {
+        double_check(&mut mailboxes_info, &client, second, options).await?;
+    }
+    if let Some(enricher) = options.enrich.filter(|_| !shutdown::is_interrupted()) {
+        enrich(&mut mailboxes_info, enricher, options).await?;
+    }
+    checkpoint.verified.extend(mailboxes_info.iter().map(|(mailbox, info)| (mailbox.link.clone(), info.clone())));
+    checkpoint.save()?;
 
-    Ok(mailboxes_info.into_iter().filter_map(|info| info).collect::<HashMap<_, _>>())
+    mailboxes_info.extend(verified.into_iter().map(|mailbox| {
+        let info = checkpoint.verified[&mailbox.link].clone();
+        (mailbox, info)
+    }));
+    mailboxes_info.extend(skipped.into_iter().filter_map(|mailbox| {
+        let info = previous.get(&mailbox.link)?.1.clone();
+        Some((mailbox, info))
+    }));
+    Ok(mailboxes_info)
 }
 
-/// write result to CSV file
-fn save_records(mut records: Vec<Record>, save_path: impl AsRef<Path>) -> anyhow::Result<()> {
-    records.sort_by(|r1, r2| (&r1.cmra, &r1.rdi).cmp(&(&r2.cmra, &r2.rdi)));
-    if let Some(parent) = save_path.as_ref().parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)?;
+/// append the verified mailboxes to `result/verifying.csv`, and to the `verifying` table of `--sink sqlite`,
+/// a failure only loses the crash protection
+fn append_verifying(sink: &mut CsvSink, staging: Option<&mut SqliteSink>, verified: &[(Mailbox, AdditionalInfo)], options: &Options) {
+    let verified = verified.iter().cloned();
+    let appended = if options.crawl.country == Country::Us {
+        let records = verified.map(|(mailbox, info)| Record::from_mailbox_and_info(mailbox, info, options.use_verified_address)).collect::<Vec<_>>();
+        if let Some(staging) = staging {
+            if let Err(e) = staging.stage(&records) {
+                warn!("cannot stage the verified mailboxes in [{}]: {:?}", SQLITE_FILE, e);
+            }
         }
+        sink.append(records)
+    } else {
+        sink.append(verified.map(|(mailbox, info)| InternationalRecord::from_mailbox_and_info(mailbox, info, options.use_verified_address)))
+    };
+    if let Err(e) = appended {
+        warn!("cannot append the verified mailboxes to [{}]: {:?}", sink.path().display(), e);
     }
-    let mut wtr = csv::Writer::from_path(save_path)?;
-    for record in &records {
-        wtr.serialize(record)?;
+}
+
+/// the file the verified mailboxes are appended to, not needed once the results or the partial ones are written
+fn remove_verifying(options: &Options) -> anyhow::Result<()> {
+    let path = country_file(VERIFYING_FILE_STEM, "csv", options);
+    if path.exists() {
+        std::fs::remove_file(path)?;
     }
     Ok(())
 }
+
+/// check the lookups needed against the ones left, returns the mailboxes to verify and the ones skipped for `--quota-plan`
+fn plan_lookups(mailboxes: Vec<Mailbox>, client: &CachedVerifier, previous: &Snapshot, options: &Options) -> anyhow::Result<(Vec<Mailbox>, Vec<Mailbox>)> {
+    let Some(remaining) = client.remaining_quota() else {
+        return Ok((mailboxes, Vec::new()));
+    };
+    let (pending, mut cached): (Vec<_>, Vec<_>) = mailboxes.into_iter()
+        .partition(|mailbox| client.pending([&mailbox.address]) > 0);
+    if remaining >= pending.len() {
+        cached.extend(pending);
+        return Ok((cached, Vec::new()));
+    }
+    match options.quota_plan {
+        QuotaPlan::Warn => {
+            warn!("[{}] addresses need to be verified, but only [{}] lookups are left, the rest will fail", pending.len(), remaining);
+            cached.extend(pending);
+            Ok((cached, Vec::new()))
+        }
+        QuotaPlan::Prompt => {
+            eprint!("[{}] addresses need to be verified, but only [{}] lookups are left, the rest will fail. Continue? [y/N] ", pending.len(), remaining);
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                return Err(anyhow::Error::new(Error::QuotaExceeded { verifier: client.name() })
+                    .context("the verification is cancelled, as the lookups left are not enough"));
+            }
+            cached.extend(pending);
+            Ok((cached, Vec::new()))
+        }
+        QuotaPlan::Prioritize => {
+            let (kept, skipped) = plan::prioritize(pending, previous, remaining);
+            let reused = skipped.iter().filter(|mailbox| previous.get(&mailbox.link).is_some()).count();
+            warn!(
+                "only [{}] lookups are left, [{}] addresses are not verified this time, [{}] of them keep the results of the previous runs",
+                remaining, skipped.len(), reused,
+            );
+            cached.extend(kept);
+            Ok((cached, skipped))
+        }
+    }
+}
+
+/// query the second verifier for the mailboxes whose result is doubtful,
+/// that is, the RDI is unknown or the result differs from the cached one
+async fn double_check(mailboxes_info: &mut HashMap<Mailbox, AdditionalInfo>, primary: &CachedVerifier, second: VerifierKind, options: &Options) -> anyhow::Result<()> {
+    let doubtful = mailboxes_info.iter_mut()
+        .filter(|(mailbox, info)| info.rdi == Rdi::Unknown || primary.has_changed(&mailbox.address))
+        .collect::<Vec<_>>();
+    if doubtful.is_empty() {
+        return Ok(());
+    }
+    info!("double-checking [{}] mailboxes with [{:?}]...", doubtful.len(), second);
+
+    let client = second.build(&options.verify)?;
+    futures::stream::iter(doubtful)
+        .for_each_concurrent(options.verify_concurrency, |(mailbox, info)| {
+            let client = &client;
+            async move {
+                match client.inquire(&mailbox.address).await {
+                    Ok(second_info) => info.double_check = Some(Box::new(second_info)),
+                    Err(e) => error!("cannot double-check address info for [{}]: {:?}", mailbox.name, e),
+                }
+            }
+        })
+        .await;
+    client.save_cache()
+}
+
+/// ask the other verifiers of `--verifier consensus:...` too, the mailboxes any of them fails for only have the votes of the others
+async fn ask_consensus(mailboxes_info: &mut HashMap<Mailbox, AdditionalInfo>, primary: VerifierKind, options: &Options) -> anyhow::Result<()> {
+    for info in mailboxes_info.values_mut() {
+        info.votes = vec![vote(primary, info)];
+    }
+    for &other in &options.consensus {
+        info!("asking [{:?}] for the consensus of [{}] mailboxes...", other, mailboxes_info.len());
+        let client = other.build(&options.verify)?;
+        futures::stream::iter(mailboxes_info.iter_mut())
+            .for_each_concurrent(options.verify_concurrency, |(mailbox, info)| {
+                let client = &client;
+                async move {
+                    match client.inquire(&mailbox.address).await {
+                        Ok(other_info) => info.votes.push(vote(other, &other_info)),
+                        Err(e) => error!("cannot inquire address info for [{}] with [{:?}]: {:?}", mailbox.name, other, e),
+                    }
+                }
+            })
+            .await;
+        client.save_cache()?;
+        for (credential, count) in client.usage() {
+            metrics::add_lookups(&credential, count as u64);
+        }
+    }
+    Ok(())
+}
+
+fn vote(kind: VerifierKind, info: &AdditionalInfo) -> Vote {
+    Vote {
+        verifier: kind.name().to_string(),
+        cmra: info.cmra.clone(),
+        rdi: info.rdi.clone(),
+    }
+}
+
+/// fill in the unknown RDI and the missing coordinates with the enricher of `--enrich`,
+/// a failed lookup keeps the result of the primary verifier
+async fn enrich(mailboxes_info: &mut HashMap<Mailbox, AdditionalInfo>, enricher: VerifierKind, options: &Options) -> anyhow::Result<()> {
+    let incomplete = mailboxes_info.iter_mut()
+        .filter(|(_, info)| info.needs_enrichment())
+        .collect::<Vec<_>>();
+    if incomplete.is_empty() {
+        return Ok(());
+    }
+    info!("enriching [{}] mailboxes with [{:?}]...", incomplete.len(), enricher);
+
+    let client = enricher.build(&options.verify)?;
+    futures::stream::iter(incomplete)
+        .for_each_concurrent(options.verify_concurrency, |(mailbox, info)| {
+            let client = &client;
+            async move {
+                match client.inquire(&mailbox.address).await {
+                    Ok(enrichment) => info.enrich(&enrichment),
+                    Err(e) => error!("cannot enrich address info for [{}]: {:?}", mailbox.name, e),
+                }
+            }
+        })
+        .await;
+    client.save_cache()
+}
+
+/// write result to `result/mailboxes.<ext>` in every selected format, returns the written files
+fn save_records<T: Serialize + SortFields>(mut records: Vec<T>, options: &Options) -> anyhow::Result<Vec<PathBuf>> {
+    options.sort_by.sort(&mut records, options.desc);
+    metrics::add(Counter::RecordsWritten, records.len() as u64);
+    let formats = &options.formats;
+    let mut out_files = Vec::with_capacity(formats.len());
+    for format in formats {
+        let out_file = country_file(RESULT_FILE_STEM, format.extension(), options);
+        info!("saving records to [{}]", out_file.display());
+        format.write(&records, &out_file)?;
+        out_files.push(out_file);
+    }
+    Ok(out_files)
+}
+
+/// insert the records into the SQLite database, or update the ones of the previous runs
+fn save_sqlite(records: &[Record], save_path: impl AsRef<Path>) -> anyhow::Result<()> {
+    info!("saving records to [{}]", save_path.as_ref().display());
+    SqliteSink::open(save_path)?.write(records)?;
+    Ok(())
+}
+
+/// write the crawled mailboxes without verification to `result/raw_mailboxes.<ext>`, returns the written files
+fn save_raw(mailboxes: &[Mailbox], options: &Options) -> anyhow::Result<Vec<PathBuf>> {
+    let records = mailboxes.iter().map(RawRecord::from_mailbox).collect::<Vec<_>>();
+    let mut out_files = Vec::with_capacity(options.formats.len());
+    for format in &options.formats {
+        let out_file = country_file(RAW_FILE_STEM, format.extension(), options);
+        info!("saving unverified mailboxes to [{}]", out_file.display());
+        format.write(&records, &out_file)?;
+        out_files.push(out_file);
+    }
+    Ok(out_files)
+}
+
+/// write the listings sharing an address to `result/duplicates.csv`, the one of a previous run is removed if there are none
+fn save_duplicates(duplicates: &[DuplicateRecord], options: &Options) -> anyhow::Result<()> {
+    let out_file = country_file(DUPLICATES_FILE_STEM, "csv", options);
+    if duplicates.is_empty() {
+        if out_file.exists() {
+            std::fs::remove_file(&out_file)?;
+        }
+        return Ok(());
+    }
+    info!("[{}] listings share their address with another one, see [{}]", duplicates.len(), out_file.display());
+    OutputFormat::Csv.write(duplicates, out_file)
+}
+
+/// normalize the addresses, the malformed ones are dropped and written to `result/parse_errors.csv`
+///
+/// with `--zip-precheck`, the ones whose city, state and ZIP disagree are dropped as well
+async fn check_addresses(mailboxes: Vec<Mailbox>, options: &Options, stats: &mut RunStats) -> anyhow::Result<Vec<Mailbox>> {
+    let (mut mailboxes, mut parse_errors) = normalize::check_mailboxes(mailboxes);
+    if options.zip_precheck && !options.skip_verify && options.crawl.country == Country::Us {
+        match precheck_zips(&mailboxes, options).await {
+            Ok(problems) => {
+                let mut problems = problems.into_iter();
+                mailboxes.retain(|mailbox| match problems.next().flatten() {
+                    Some(problem) => {
+                        parse_errors.push(ParseError::new(mailbox, "zip", problem));
+                        false
+                    }
+                    None => true,
+                });
+            }
+            Err(e) => warn!("cannot check the ZIP codes, verify all the addresses: {:?}", e),
+        }
+    }
+    stats.parse_errors = parse_errors.iter().map(|error| &error.link).collect::<HashSet<_>>().len();
+    save_parse_errors(&parse_errors, options)?;
+    Ok(mailboxes)
+}
+
+/// the problem of every mailbox reported by the ZIP Code API, `None` if its city, state and ZIP agree
+async fn precheck_zips(mailboxes: &[Mailbox], options: &Options) -> anyhow::Result<Vec<Option<String>>> {
+    let mut checker = options.verify.zip_checker()?;
+    let addresses = mailboxes.iter().map(|mailbox| &mailbox.address).collect::<Vec<_>>();
+    let problems = checker.check(&addresses).await;
+    checker.save()?;
+    problems
+}
+
+/// write the malformed addresses to `result/parse_errors.csv`, the one of a previous run is removed if there are none
+fn save_parse_errors(errors: &[ParseError], options: &Options) -> anyhow::Result<()> {
+    let out_file = country_file(PARSE_ERRORS_FILE_STEM, "csv", options);
+    if errors.is_empty() {
+        if out_file.exists() {
+            std::fs::remove_file(&out_file)?;
+        }
+        return Ok(());
+    }
+    warn!("[{}] addresses are malformed and will not be verified, see [{}]", errors.len(), out_file.display());
+    OutputFormat::Csv.write(errors, out_file)
+}
+
+/// write the plans of the mailboxes to `result/plans.csv`, nothing is written if none has plans
+fn save_plans(mailboxes: &[Mailbox], options: &Options) -> anyhow::Result<()> {
+    let records = mailboxes.iter().flat_map(PlanRecord::from_mailbox).collect::<Vec<_>>();
+    if records.is_empty() {
+        return Ok(());
+    }
+    let out_file = country_file(PLANS_FILE_STEM, "csv", options);
+    info!("saving [{}] plans to [{}]", records.len(), out_file.display());
+    OutputFormat::Csv.write(&records, out_file)
+}
+
+/// write the candidates of the addresses they disagree on to `result/ambiguous.csv`, the one of a previous run is removed if there are none
+fn save_ambiguous(mailboxes_info: &HashMap<Mailbox, AdditionalInfo>, options: &Options) -> anyhow::Result<()> {
+    let mut records = mailboxes_info.iter()
+        .flat_map(|(mailbox, info)| AmbiguousRecord::from_mailbox_and_info(mailbox, info))
+        .collect::<Vec<_>>();
+    let out_file = country_file(AMBIGUOUS_FILE_STEM, "csv", options);
+    if records.is_empty() {
+        if out_file.exists() {
+            std::fs::remove_file(&out_file)?;
+        }
+        return Ok(());
+    }
+    // the candidates of an address stay together
+    records.sort_by(|r1, r2| r1.id.cmp(&r2.id));
+    let addresses = records.iter().map(|record| &record.id).collect::<HashSet<_>>().len();
+    warn!("the candidates of [{}] addresses disagree on the CMRA or RDI, see [{}]", addresses, out_file.display());
+    OutputFormat::Csv.write(&records, out_file)
+}
+
+/// write the mailboxes whose detail cannot be fetched to CSV file, for manual retry
+fn save_failed(mailboxes: &[Mailbox], save_path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let records = mailboxes.iter().map(RawRecord::from_mailbox).collect::<Vec<_>>();
+    OutputFormat::Csv.write(&records, save_path)
+}
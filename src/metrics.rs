@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of the process, served at `/metrics` in the text format of Prometheus.
+///
+/// They're kept for the life of the process, so the runs of `--watch` add up.
+struct Metrics {
+    counters: [AtomicU64; Counter::ALL.len()],
+    /// lookups of the verifiers, keyed by the credential
+    lookups: Mutex<BTreeMap<String, u64>>,
+}
+
+static METRICS: Metrics = Metrics {
+    counters: [const { AtomicU64::new(0) }; Counter::ALL.len()],
+    lookups: Mutex::new(BTreeMap::new()),
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    /// pages fetched from the mailbox providers
+    PagesFetched,
+    /// pages that cannot be fetched even after the retries
+    FetchFailures,
+    /// requests retried after a failure
+    Retries,
+    /// lookups answered by the cache of the verifiers
+    CacheHits,
+    /// records written to the results
+    RecordsWritten,
+}
+
+impl Counter {
+    const ALL: [Counter; 5] = [
+        Counter::PagesFetched,
+        Counter::FetchFailures,
+        Counter::Retries,
+        Counter::CacheHits,
+        Counter::RecordsWritten,
+    ];
+
+    /// the metric name and its help text
+    fn describe(self) -> (&'static str, &'static str) {
+        match self {
+            Counter::PagesFetched => ("atmb_pages_fetched_total", "Pages fetched from the mailbox providers."),
+            Counter::FetchFailures => ("atmb_fetch_failures_total", "Pages that cannot be fetched after the retries."),
+            Counter::Retries => ("atmb_retries_total", "Requests retried after a failure."),
+            Counter::CacheHits => ("atmb_verify_cache_hits_total", "Lookups answered by the cache of the verifiers."),
+            Counter::RecordsWritten => ("atmb_records_written_total", "Records written to the results."),
+        }
+    }
+}
+
+pub fn inc(counter: Counter) {
+    add(counter, 1);
+}
+
+pub fn add(counter: Counter, n: u64) {
+    METRICS.counters[counter as usize].fetch_add(n, Ordering::Relaxed);
+}
+
+/// count the lookups consumed by the credential
+pub fn add_lookups(credential: &str, n: u64) {
+    let mut lookups = METRICS.lookups.lock().unwrap_or_else(|e| e.into_inner());
+    *lookups.entry(credential.to_string()).or_default() += n;
+}
+
+/// all the metrics in the text format of Prometheus
+pub fn render() -> String {
+    let mut text = String::new();
+    for counter in Counter::ALL {
+        let (name, help) = counter.describe();
+        let value = METRICS.counters[counter as usize].load(Ordering::Relaxed);
+        let _ = write!(text, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n");
+    }
+    text.push_str("# HELP atmb_lookups_total Lookups of the verifiers by the credential.\n# TYPE atmb_lookups_total counter\n");
+    for (credential, count) in METRICS.lookups.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        let credential = credential.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        let _ = writeln!(text, "atmb_lookups_total{{credential=\"{}\"}} {}", credential, count);
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        add(Counter::RecordsWritten, 3);
+        add_lookups("key \"1\"", 2);
+        let text = render();
+        assert!(text.contains("# TYPE atmb_records_written_total counter\natmb_records_written_total "));
+        assert!(text.contains("atmb_lookups_total{credential=\"key \\\"1\\\"\"} "));
+    }
+}
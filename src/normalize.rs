@@ -0,0 +1,143 @@
+use serde::Serialize;
+use crate::atmb::model::{Address, Mailbox};
+use crate::country::Country;
+use crate::provider::ProviderKind;
+
+/// A parsed address that doesn't look like a US address, written to `result/parse_errors.csv`.
+///
+/// The mailbox is not verified, so the malformed listings don't waste the lookups.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParseError {
+    pub provider: ProviderKind,
+    pub name: String,
+    pub street: String,
+    pub city: String,
+    pub state: String,
+    pub zip: String,
+    pub link: String,
+    /// the field that is malformed, i.e. `zip`
+    pub field: &'static str,
+    pub problem: String,
+}
+
+/// normalize the addresses of the mailboxes and validate them, returns the valid mailboxes and the errors,
+/// a mailbox with several malformed fields has an error for each
+pub fn check_mailboxes(mailboxes: Vec<Mailbox>) -> (Vec<Mailbox>, Vec<ParseError>) {
+    let mut valid = Vec::with_capacity(mailboxes.len());
+    let mut errors = Vec::new();
+    for mut mailbox in mailboxes {
+        normalize(&mut mailbox.address);
+        let problems = validate(&mailbox.address);
+        if problems.is_empty() {
+            valid.push(mailbox);
+            continue;
+        }
+        errors.extend(problems.into_iter().map(|(field, problem)| ParseError::new(&mailbox, field, problem)));
+    }
+    (valid, errors)
+}
+
+impl ParseError {
+    pub fn new(mailbox: &Mailbox, field: &'static str, problem: String) -> Self {
+        Self {
+            provider: mailbox.provider,
+            name: mailbox.name.clone(),
+            street: mailbox.address.line1.clone(),
+            city: mailbox.address.city.clone(),
+            state: mailbox.address.state.clone(),
+            zip: mailbox.address.full_zip(),
+            link: mailbox.link.clone(),
+            field,
+            problem,
+        }
+    }
+}
+
+/// clean up the text fields of a US address, the addresses of the other countries are left as is
+///
+/// the typographic characters are replaced with their ASCII ones, i.e. `’` with `'`, the invisible ones are dropped,
+/// the state name is replaced with its code, and a ZIP+4 written without the `-` is split.
+pub fn normalize(address: &mut Address) {
+    if address.country != Country::Us {
+        return;
+    }
+    address.line1 = normalize_text(&address.line1);
+    address.city = normalize_text(&address.city);
+    address.state = normalize_text(&address.state);
+    if let Some(code) = Country::Us.region_code(&address.state) {
+        address.state = code.to_string();
+    }
+    address.zip = normalize_text(&address.zip);
+    if address.zip4.is_none() && address.zip.len() == 9 && address.zip.bytes().all(|b| b.is_ascii_digit()) {
+        let (zip, zip4) = address.zip.split_at(5);
+        (address.zip, address.zip4) = (zip.to_string(), Some(zip4.to_string()));
+    }
+    address.zip4 = address.zip4.as_deref().map(normalize_text).filter(|zip4| !zip4.is_empty());
+}
+
+/// the problems of a normalized US address as `(field, problem)`, empty if it looks right
+pub fn validate(address: &Address) -> Vec<(&'static str, String)> {
+    if address.country != Country::Us {
+        return Vec::new();
+    }
+    let mut problems = Vec::new();
+    for (field, value) in [("street", &address.line1), ("city", &address.city)] {
+        if value.is_empty() {
+            problems.push((field, "is empty".to_string()));
+        } else if let Some(c) = value.chars().find(|c| !c.is_ascii() && !c.is_alphabetic()) {
+            // letters like the `ñ` of `Cañon City` are fine
+            problems.push((field, format!("has the stray character `{}` (U+{:04X})", c, c as u32)));
+        }
+    }
+    if Country::Us.region_code(&address.state).is_none() {
+        problems.push(("state", format!("`{}` is not a US state", address.state)));
+    }
+    if address.zip.len() != 5 || !address.zip.bytes().all(|b| b.is_ascii_digit()) {
+        problems.push(("zip", format!("`{}` is not a 5 digit ZIP", address.zip)));
+    }
+    if let Some(zip4) = address.zip4.as_ref().filter(|zip4| zip4.len() != 4 || !zip4.bytes().all(|b| b.is_ascii_digit())) {
+        problems.push(("zip4", format!("`{}` is not a 4 digit ZIP+4", zip4)));
+    }
+    problems
+}
+
+/// replace the typographic spaces, quotes and dashes with ASCII ones, drop the invisible characters,
+/// and collapse the whitespaces
+fn normalize_text(text: &str) -> String {
+    let text = text.chars()
+        .filter(|c| !matches!(c, '\u{200B}'..='\u{200D}' | '\u{FEFF}' | '\u{00AD}'))
+        .map(|c| match c {
+            '\u{2018}' | '\u{2019}' | '\u{02BC}' => '\'',
+            '\u{201C}' | '\u{201D}' => '"',
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+            c if c.is_whitespace() => ' ',
+            c => c,
+        })
+        .collect::<String>();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_address() {
+        let mut address = Address::from_lines("120\u{a0}19th Street\u{200b} North", "Bessemer\u{2019}s, Alabama 35203").unwrap();
+        address.zip = "352033712".to_string();
+        normalize(&mut address);
+        assert_eq!(address.line1, "120 19th Street North");
+        assert_eq!(address.city, "Bessemer's");
+        assert_eq!(address.state, "AL");
+        assert_eq!(address.full_zip(), "35203-3712");
+        assert!(validate(&address).is_empty());
+
+        address.city = "Cañon City".to_string();
+        assert!(validate(&address).is_empty());
+        address.city = "Ca\u{fffd}on City".to_string();
+        address.state = "XX".to_string();
+        address.zip4 = Some("37".to_string());
+        let fields = validate(&address).into_iter().map(|(field, _)| field).collect::<Vec<_>>();
+        assert_eq!(fields, ["city", "state", "zip4"]);
+    }
+}
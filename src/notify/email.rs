@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use rand::Rng;
+use tracing::info;
+use crate::diff::Diff;
+use crate::notify::{self, EmailConfig, Notifier};
+
+/// the longest a conversation with the SMTP server can take
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// the port connected via TLS from the start, the others are upgraded with `STARTTLS`
+const IMPLICIT_TLS_PORT: u16 = 465;
+
+/// Sends the differences and the result CSV as attachment to `--email-to` after every diff.
+///
+/// The envelope is made of the bare addresses, the display names are only kept in the headers.
+pub struct Email {
+    config: EmailConfig,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    host: String,
+    port: u16,
+    password: Option<String>,
+}
+
+impl Email {
+    pub fn new(config: EmailConfig) -> anyhow::Result<Self> {
+        let (host, port) = match config.server.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), port.parse().map_err(|_| anyhow!("invalid port of the SMTP server [{}]", config.server))?),
+            None => (config.server.clone(), 587),
+        };
+        let from = config.from.parse::<Mailbox>()
+            .map_err(|e| anyhow!("invalid `--email-from` [{}]: {}", config.from, e))?;
+        let to = config.to.iter()
+            .map(|to| to.parse::<Mailbox>().map_err(|e| anyhow!("invalid `--email-to` [{}]: {}", to, e)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let password = match &config.user {
+            Some(_) => Some(std::env::var("SMTP_PASSWORD").map_err(|_| anyhow!("`SMTP_PASSWORD` environment variable must be set to log in as `--smtp-user`"))?),
+            None => None,
+        };
+        Ok(
+            Self {
+                config,
+                from,
+                to,
+                host,
+                port,
+                password,
+            }
+        )
+    }
+
+    /// the transport to the SMTP server, over TLS with the credentials of `--smtp-user` if given
+    fn transport(&self) -> anyhow::Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let builder = if self.port == IMPLICIT_TLS_PORT {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?
+        };
+        let mut builder = builder.port(self.port).timeout(Some(TIMEOUT));
+        if let (Some(user), Some(password)) = (&self.config.user, &self.password) {
+            builder = builder.credentials(Credentials::new(user.clone(), password.clone()));
+        }
+        Ok(builder.build())
+    }
+
+    /// the email of the differences, with the result CSV attached
+    fn message(&self, diff: &Diff<'_>, result_file: &Path) -> anyhow::Result<Message> {
+        let subject = match notify::new_locations(diff).len() {
+            0 => "no new non-CMRA residential locations".to_string(),
+            new => format!("{} new non-CMRA residential locations", new),
+        };
+        let body = if diff.is_empty() { "no differences found\n".to_string() } else { diff.to_string() };
+        let name = result_file.file_name().and_then(|name| name.to_str()).unwrap_or("mailboxes.csv");
+        self.compose(&subject, &body, name, std::fs::read(result_file)?, SystemTime::now())
+    }
+
+    /// the message with the `Date` and `Message-ID` headers the relays expect
+    fn compose(&self, subject: &str, body: &str, name: &str, attachment: Vec<u8>, now: SystemTime) -> anyhow::Result<Message> {
+        let message_id = format!(
+            "<{}.{:016x}@{}>",
+            now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis(), rand::thread_rng().gen::<u64>(), self.from.email.domain(),
+        );
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .date(now)
+            .message_id(Some(message_id))
+            .subject(format!("[atmb] {}", subject));
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let message = builder.multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain(body.to_string()))
+                .singlepart(Attachment::new(name.to_string()).body(attachment, ContentType::parse("text/csv")?)),
+        )?;
+        Ok(message)
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    async fn notify(&self, diff: &Diff<'_>, result_file: &Path) -> anyhow::Result<()> {
+        let message = self.message(diff, result_file)?;
+        tokio::time::timeout(TIMEOUT, self.transport()?.send(message)).await
+            .map_err(|_| anyhow!("the SMTP server [{}] doesn't respond in {}s", self.config.server, TIMEOUT.as_secs()))?
+            .map_err(|e| anyhow!("cannot send the email via [{}]: {}", self.config.server, e))?;
+        info!("the differences have been emailed to [{}]", self.config.to.join(", "));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lettre::transport::stub::AsyncStubTransport;
+    use super::*;
+
+    fn email() -> Email {
+        Email::new(EmailConfig {
+            server: "smtp.example.com:587".to_string(),
+            from: "ATMB <atmb@example.com>".to_string(),
+            to: vec!["Ops Team <a@example.com>".to_string(), "b@example.com".to_string()],
+            user: None,
+        })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send_message() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_791_969_020);
+        let attachment = "id,name\n".repeat(20);
+        let message = email().compose("1 new location", "added: Test\n.hidden\n", "mailboxes.csv", attachment.into_bytes(), now).unwrap();
+
+        let transport = AsyncStubTransport::new_ok();
+        transport.send(message).await.unwrap();
+        let (envelope, message) = transport.messages().await.pop().unwrap();
+        // `MAIL FROM` and `RCPT TO` take the bare addresses
+        assert_eq!(envelope.from().map(|from| from.to_string()).as_deref(), Some("atmb@example.com"));
+        assert_eq!(envelope.to().iter().map(|to| to.to_string()).collect::<Vec<_>>(), ["a@example.com", "b@example.com"]);
+
+        assert!(message.contains("From: ATMB <atmb@example.com>\r\n"));
+        assert!(message.contains("To: \"Ops Team\" <a@example.com>, b@example.com\r\n"));
+        assert!(message.contains("Date: Wed, 14 Oct 2026 09:10:20 +0000\r\n"));
+        assert!(message.contains("Subject: [atmb] 1 new location\r\n"));
+        let message_id = message.lines().find_map(|line| line.strip_prefix("Message-ID: ")).unwrap();
+        assert!(message_id.starts_with("<1791969020000.") && message_id.ends_with("@example.com>"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"mailboxes.csv\"\r\n"));
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let config = EmailConfig {
+            server: "smtp.example.com".to_string(),
+            from: "atmb".to_string(),
+            to: vec!["b@example.com".to_string()],
+            user: None,
+        };
+        assert!(Email::new(config).is_err());
+    }
+}
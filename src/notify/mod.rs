@@ -0,0 +1,81 @@
+use std::path::Path;
+use async_trait::async_trait;
+use crate::diff::{self, Diff};
+use crate::record::Record;
+use crate::verify::model::{Rdi, YesOrNo};
+
+#[cfg(feature = "email")]
+pub mod email;
+pub mod telegram;
+pub mod webhook;
+
+/// A channel the subscribers learn about the new locations from, notified after the diff of two runs.
+#[async_trait(?Send)]
+pub trait Notifier {
+    /// short name of the channel, i.e. `webhook`
+    fn name(&self) -> &str;
+
+    /// tell about the differences from the previous run
+    ///
+    /// * `result_file` - the result CSV of the newer run
+    async fn notify(&self, diff: &Diff<'_>, result_file: &Path) -> anyhow::Result<()>;
+}
+
+/// the SMTP server and the recipients of the email of the differences, selected via `--smtp-server`
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    /// `host:port`, connected via TLS if the port is 465, otherwise upgraded with `STARTTLS`
+    pub server: String,
+    pub from: String,
+    pub to: Vec<String>,
+    /// the login, its password is read from the `SMTP_PASSWORD` environment variable
+    pub user: Option<String>,
+}
+
+/// the newly added locations that are non-CMRA and residential, the ones the subscribers are after
+pub fn new_locations<'a>(diff: &Diff<'a>) -> Vec<&'a Record> {
+    diff.added.iter()
+        .filter(|record| record.cmra == YesOrNo::N && record.rdi == Rdi::Residential)
+        .copied()
+        .collect()
+}
+
+/// human readable list of the locations within `max_len` bytes, the ones that don't fit are counted at the end
+pub fn text(locations: &[&Record], max_len: usize) -> String {
+    let mut text = format!("{} new non-CMRA residential locations:", locations.len());
+    for (idx, record) in locations.iter().enumerate() {
+        let line = format!("\n• {} | {} | {} | {}", record.name, diff::address(record), record.price, record.link);
+        let more = format!("\n… and {} more", locations.len() - idx);
+        if text.len() + line.len() + more.len() > max_len {
+            text.push_str(&more);
+            break;
+        }
+        text.push_str(&line);
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_locations_text() {
+        let old = vec![Record::sample("a")];
+        let new = vec![
+            Record::sample("a"),
+            Record::sample("b"),
+            Record { cmra: YesOrNo::Y, ..Record::sample("c") },
+            Record::sample("d"),
+        ];
+        let diff = Diff::between(&old, &new);
+        let locations = new_locations(&diff);
+        assert_eq!(locations.iter().map(|r| r.link.as_str()).collect::<Vec<_>>(), vec!["b", "d"]);
+
+        let full = text(&locations, usize::MAX);
+        assert_eq!(full.lines().count(), 3);
+        let short = text(&locations, full.len() - 1);
+        assert!(short.len() < full.len());
+        assert!(short.ends_with("… and 1 more"));
+    }
+}
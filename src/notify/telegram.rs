@@ -0,0 +1,67 @@
+use std::path::Path;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use tracing::info;
+use crate::diff::Diff;
+use crate::notify::{self, Notifier};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// the longest message Telegram accepts
+const MAX_LEN: usize = 4096;
+
+/// Posts the summary of the differences and the new locations to a Telegram chat through a bot.
+pub struct Telegram {
+    client: Client,
+    token: String,
+    chat_id: String,
+}
+
+impl Telegram {
+    /// * `token` - of the bot, given by @BotFather
+    /// * `chat_id` - of the chat the bot is a member of, or `@channel` of a public channel
+    pub fn new(token: &str, chat_id: &str) -> anyhow::Result<Self> {
+        Ok(
+            Self {
+                client: Client::builder().timeout(TIMEOUT).build()?,
+                token: token.to_string(),
+                chat_id: chat_id.to_string(),
+            }
+        )
+    }
+
+    fn text(diff: &Diff<'_>) -> String {
+        let summary = format!("added: {}, removed: {}, changed: {}\n", diff.added.len(), diff.removed.len(), diff.changed.len());
+        let locations = notify::new_locations(diff);
+        if locations.is_empty() {
+            return summary + "no new non-CMRA residential locations";
+        }
+        let text = notify::text(&locations, MAX_LEN - summary.len());
+        summary + &text
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Telegram {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    async fn notify(&self, diff: &Diff<'_>, _result_file: &Path) -> anyhow::Result<()> {
+        self.client.post(format!("https://api.telegram.org/bot{}/sendMessage", self.token))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": Self::text(diff),
+                "disable_web_page_preview": true,
+            }))
+            .send()
+            .await
+            // the token is part of the URL, keep it out of the logs
+            .map_err(|e| e.without_url())?
+            .error_for_status()
+            .map_err(|e| e.without_url())?;
+        info!("the differences have been posted to the Telegram chat [{}]", self.chat_id);
+        Ok(())
+    }
+}
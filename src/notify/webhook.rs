@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use tracing::info;
+use crate::diff::Diff;
+use crate::notify::{self, Notifier};
+use crate::record::Record;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// the longest message Slack shows in full
+const SLACK_MAX_LEN: usize = 3000;
+/// the longest message Discord accepts
+const DISCORD_MAX_LEN: usize = 2000;
+
+/// how the payload of the webhook is formatted, selected via `--webhook-format`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// `{"locations": [...]}`, every location with the columns of the result CSV
+    #[default]
+    Json,
+    /// `{"text": "..."}` of an incoming webhook of Slack
+    Slack,
+    /// `{"content": "..."}` of a webhook of Discord
+    Discord,
+}
+
+impl FromStr for WebhookFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(WebhookFormat::Json),
+            "slack" => Ok(WebhookFormat::Slack),
+            "discord" => Ok(WebhookFormat::Discord),
+            _ => Err(anyhow!("unknown webhook format: {}, expected one of: json, slack, discord", s)),
+        }
+    }
+}
+
+/// POST the new non-CMRA residential locations to the URL given via `--webhook`
+pub struct Webhook {
+    client: Client,
+    url: String,
+    format: WebhookFormat,
+}
+
+impl Webhook {
+    pub fn new(url: &str, format: WebhookFormat) -> anyhow::Result<Self> {
+        Ok(
+            Self {
+                client: Client::builder().timeout(TIMEOUT).build()?,
+                url: url.to_string(),
+                format,
+            }
+        )
+    }
+
+    fn payload(&self, locations: &[&Record]) -> serde_json::Value {
+        match self.format {
+            WebhookFormat::Json => json!({ "locations": locations }),
+            WebhookFormat::Slack => json!({ "text": notify::text(locations, SLACK_MAX_LEN) }),
+            WebhookFormat::Discord => json!({ "content": notify::text(locations, DISCORD_MAX_LEN) }),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    /// nothing is posted if there are no new locations
+    async fn notify(&self, diff: &Diff<'_>, _result_file: &Path) -> anyhow::Result<()> {
+        let locations = notify::new_locations(diff);
+        if locations.is_empty() {
+            return Ok(());
+        }
+        self.client.post(&self.url)
+            .json(&self.payload(&locations))
+            .send()
+            .await?
+            .error_for_status()?;
+        info!("[{}] new locations have been posted to the webhook", locations.len());
+        Ok(())
+    }
+}
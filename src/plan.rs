@@ -0,0 +1,69 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use crate::atmb::model::Mailbox;
+use crate::snapshot::Snapshot;
+
+/// What to do if fewer lookups are left than the addresses to verify, selected via `--quota-plan`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaPlan {
+    /// verify all of them, the lookups beyond the quota fail
+    #[default]
+    Warn,
+    /// ask whether to continue, the run stops if not
+    Prompt,
+    /// only verify as many as the quota allows, see [`prioritize`]
+    Prioritize,
+}
+
+impl FromStr for QuotaPlan {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(QuotaPlan::Warn),
+            "prompt" => Ok(QuotaPlan::Prompt),
+            "prioritize" => Ok(QuotaPlan::Prioritize),
+            _ => Err(anyhow!("unknown quota plan: {}, expected one of: warn, prompt, prioritize", s)),
+        }
+    }
+}
+
+/// split the mailboxes into the `budget` most wanted ones and the rest
+///
+/// the locations new since the previous runs go first, then the ones changed since, then the others,
+/// the cheapest first within each of them.
+pub fn prioritize(mut mailboxes: Vec<Mailbox>, previous: &Snapshot, budget: usize) -> (Vec<Mailbox>, Vec<Mailbox>) {
+    let priority = |mailbox: &Mailbox| match previous.get(&mailbox.link) {
+        None => 0,
+        Some((previous, _)) if previous != mailbox => 1,
+        Some(_) => 2,
+    };
+    mailboxes.sort_by_cached_key(|mailbox| (
+        priority(mailbox),
+        mailbox.price.as_ref().map_or(u64::MAX, |price| price.monthly()),
+        mailbox.link.clone(),
+    ));
+    let rest = mailboxes.split_off(budget.min(mailboxes.len()));
+    (mailboxes, rest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::atmb::model::Price;
+    use crate::verify::model::AdditionalInfo;
+
+    #[test]
+    fn test_prioritize() {
+        let info = AdditionalInfo::sample();
+        let (unchanged, changed) = (Mailbox { price: Price::parse("US$5.00/month"), ..Mailbox::sample("unchanged") }, Mailbox::sample("changed"));
+        let mut previous = Snapshot::default();
+        previous.extend([(&unchanged, &info), (&Mailbox { price: Price::parse("US$12.99/month"), ..changed.clone() }, &info)]);
+
+        let mailboxes = vec![unchanged, changed, Mailbox { price: Price::parse("US$19.99/month"), ..Mailbox::sample("new") }, Mailbox::sample("new-cheap")];
+        let (kept, rest) = prioritize(mailboxes, &previous, 3);
+        let links = |mailboxes: &[Mailbox]| mailboxes.iter().map(|mailbox| mailbox.link.clone()).collect::<Vec<_>>();
+        assert_eq!(links(&kept), ["new-cheap", "new", "changed"]);
+        assert_eq!(links(&rest), ["unchanged"]);
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::bail;
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use crate::atmb::model::Mailbox;
+use crate::metrics::{self, Counter};
+use crate::postscan::page::{LocationListPage, StateListPage};
+use crate::progress::Progress;
+use crate::provider::{CrawlConfig, MailboxProvider};
+use crate::state::{self, UsState};
+
+mod page;
+
+const BASE_URL: &str = "https://www.postscanmail.com";
+const UA: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+const US_LOCATIONS_URL: &str = "/locations";
+
+/// Crawler of the PostScan Mail US locations.
+///
+/// The locations are grouped by state, every state page lists the full address of its locations.
+pub struct PostScanCrawl {
+    client: Client,
+    /// maximum concurrent requests, the state pages use half of it like ATMB
+    concurrency: usize,
+    /// only the state pages selected via `--states` are fetched
+    states: Option<Vec<UsState>>,
+}
+
+impl PostScanCrawl {
+    pub fn new(config: &CrawlConfig) -> anyhow::Result<Self> {
+        Ok(
+            Self {
+                client: config.client_builder()?
+                    .default_headers(Self::default_headers())
+                    .build()?,
+                concurrency: config.concurrency.max(1),
+                states: config.states.clone(),
+            }
+        )
+    }
+
+    fn default_headers() -> HeaderMap {
+        let mut map = HeaderMap::new();
+        map.insert(USER_AGENT, HeaderValue::from_static(UA));
+        map
+    }
+
+    async fn fetch_page(&self, url_path: &str) -> anyhow::Result<String> {
+        let url = if url_path.starts_with("http") {
+            url_path
+        } else {
+            &format!("{}{}", BASE_URL, url_path)
+        };
+        let result = async {
+            Ok(self.client.get(url).send().await?.error_for_status()?.text().await?)
+        }
+            .await;
+        metrics::inc(if result.is_ok() { Counter::PagesFetched } else { Counter::FetchFailures });
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl MailboxProvider for PostScanCrawl {
+    fn name(&self) -> &str {
+        "postscan"
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<Mailbox>> {
+        let html = self.fetch_page(US_LOCATIONS_URL).await?;
+        let state_list = StateListPage::parse_html(&html)?;
+        let states = state_list.states.iter()
+            .filter(|state| state::is_selected(self.states.as_deref(), &state.name))
+            .collect::<Vec<_>>();
+
+        let progress = Progress::new("PostScan state pages", states.len());
+        let results = futures::stream::iter(states).enumerate().map(|(idx, state)| {
+            progress.message(idx + 1, format_args!("fetching [{}] PostScan state page...", state.name));
+            let progress = &progress;
+            async move {
+                let html = self.fetch_page(&state.url).await;
+                progress.inc(1);
+                LocationListPage::parse_html(&html?)?.to_mailboxes()
+            }
+        })
+            .buffer_unordered((self.concurrency / 2).max(1))
+            .collect::<Vec<anyhow::Result<Vec<Mailbox>>>>()
+            .await;
+        progress.finish();
+
+        let mut mailboxes = Vec::new();
+        let mut failed = 0;
+        for result in results {
+            match result {
+                Ok(state_mailboxes) => mailboxes.extend(state_mailboxes),
+                Err(e) => {
+                    tracing::error!("cannot fetch PostScan state: {:?}", e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed != 0 {
+            bail!("{} PostScan states cannot be fetched", failed);
+        }
+        Ok(mailboxes)
+    }
+}
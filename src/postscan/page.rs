@@ -0,0 +1,187 @@
+use std::sync::LazyLock;
+use anyhow::{anyhow, bail};
+use scraper::{Html, Selector};
+use crate::atmb::model::{Address, Mailbox, Price};
+use crate::provider::ProviderKind;
+
+static STATE_LINK_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"ul[class~="state-list"] a[href]"#).unwrap());
+
+static LOCATION_CONTAINER_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"div[class~="location-card"]"#).unwrap());
+static LOCATION_NAME_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="location-title"]"#).unwrap());
+static LOCATION_STREET_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="street-address"]"#).unwrap());
+static LOCATION_CITY_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="city-state-zip"]"#).unwrap());
+static LOCATION_PRICE_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"[class~="location-price"]"#).unwrap());
+static LOCATION_LINK_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse(r#"a[class~="location-link"]"#).unwrap());
+
+/// PostScan Mail location directory. i.e. https://www.postscanmail.com/locations
+pub struct StateListPage {
+    pub states: Vec<StateLink>,
+}
+
+pub struct StateLink {
+    pub name: String,
+    pub url: String,
+}
+
+impl StateListPage {
+    pub fn parse_html(html: &str) -> anyhow::Result<Self> {
+        let document = Html::parse_document(html);
+        let states = document.select(&STATE_LINK_SELECTOR)
+            .filter_map(|a| Some(StateLink {
+                name: a.text().collect::<String>().trim().to_string(),
+                url: a.value().attr("href")?.to_string(),
+            }))
+            .collect::<Vec<_>>();
+        if states.is_empty() {
+            bail!("No state found, page structure might be changed");
+        }
+        Ok(
+            Self {
+                states,
+            }
+        )
+    }
+}
+
+/// PostScan Mail state page. i.e. https://www.postscanmail.com/locations/texas
+pub struct LocationListPage {
+    locations: Vec<LocationHtmlInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct LocationHtmlInfo {
+    name: String,
+    /// street address
+    line1: String,
+    /// city, state, zip
+    line2: String,
+    /// not every location shows its price on the list
+    price: Option<String>,
+    link: String,
+}
+
+impl LocationListPage {
+    pub fn parse_html(html: &str) -> anyhow::Result<Self> {
+        let mut locations = Vec::new();
+
+        let document = Html::parse_document(html);
+        for location_fragment in document.select(&LOCATION_CONTAINER_SELECTOR) {
+            let text_of = |selector: &Selector, what: &str| {
+                location_fragment.select(selector).next()
+                    .map(|element| element.text().collect::<String>().trim().to_string())
+                    .ok_or_else(|| anyhow!("No {} found - {}", what, location_fragment.html()))
+            };
+            let link = location_fragment.select(&LOCATION_LINK_SELECTOR).next()
+                .and_then(|a| a.value().attr("href"))
+                .ok_or_else(|| anyhow!("No location link found - {}", location_fragment.html()))?;
+
+            locations.push(LocationHtmlInfo {
+                name: text_of(&LOCATION_NAME_SELECTOR, "name")?,
+                line1: text_of(&LOCATION_STREET_SELECTOR, "street")?,
+                line2: text_of(&LOCATION_CITY_SELECTOR, "city")?,
+                price: text_of(&LOCATION_PRICE_SELECTOR, "price").ok(),
+                link: if link.starts_with("http") { link.to_string() } else { format!("{}{}", super::BASE_URL, link) },
+            });
+        }
+
+        Ok(
+            Self {
+                locations,
+            }
+        )
+    }
+
+    pub fn to_mailboxes(&self) -> anyhow::Result<Vec<Mailbox>> {
+        self.locations.iter()
+            .map(|location| location.clone().try_into())
+            .collect()
+    }
+}
+
+impl LocationHtmlInfo {
+    /// i.e. `Plans from $15.00/month` -> `US$15.00/month`
+    fn price(&self) -> String {
+        let Some(price) = &self.price else {
+            return String::new();
+        };
+        let price = price.replace(' ', "");
+        match price.find('$') {
+            Some(idx) => format!("US{}", &price[idx..]),
+            None => price,
+        }
+    }
+}
+
+impl TryInto<Mailbox> for LocationHtmlInfo {
+    type Error = anyhow::Error;
+
+    fn try_into(self) -> Result<Mailbox, Self::Error> {
+        let raw_price = self.price();
+        Ok(
+            Mailbox {
+                provider: ProviderKind::PostScan,
+                address: Address::from_lines(self.line1.clone(), &self.line2)?,
+                price: Price::parse(&raw_price),
+                raw_price,
+                name: self.name,
+                link: self.link,
+                phone: None,
+                operator: None,
+                plans: Vec::new(),
+                geo: None,
+                listing: String::new(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STATE_LIST_HTML: &str = r#"
+        <ul class="state-list">
+            <li><a href="/locations/alabama">Alabama</a></li>
+            <li><a href="/locations/texas">Texas</a></li>
+        </ul>
+    "#;
+
+    const LOCATION_LIST_HTML: &str = r#"
+        <div class="location-card">
+            <h4 class="location-title">Dallas - Main St</h4>
+            <div class="street-address">1401 Main St</div>
+            <div class="city-state-zip">Dallas, TX 75202-4006</div>
+            <div class="location-price">Plans from $15.00 / month</div>
+            <a class="location-link" href="/locations/texas/dallas-1401-main-st">View</a>
+        </div>
+        <div class="location-card">
+            <h4 class="location-title">Houston - Smith St</h4>
+            <div class="street-address">1000 Smith St</div>
+            <div class="city-state-zip">Houston, TX 77002</div>
+            <a class="location-link" href="/locations/texas/houston-1000-smith-st">View</a>
+        </div>
+    "#;
+
+    #[test]
+    fn test_parse_state_list() {
+        let page = StateListPage::parse_html(STATE_LIST_HTML).unwrap();
+        assert_eq!(page.states.len(), 2);
+        assert_eq!(page.states[1].name, "Texas");
+        assert_eq!(page.states[1].url, "/locations/texas");
+    }
+
+    #[test]
+    fn test_parse_location_list() {
+        let page = LocationListPage::parse_html(LOCATION_LIST_HTML).unwrap();
+        let mailboxes = page.to_mailboxes().unwrap();
+        assert_eq!(mailboxes.len(), 2);
+        assert_eq!(mailboxes[0].provider, ProviderKind::PostScan);
+        assert_eq!(mailboxes[0].address.city, "Dallas");
+        assert_eq!(mailboxes[0].address.zip4, Some("4006".to_string()));
+        assert_eq!(mailboxes[0].raw_price, "US$15.00/month");
+        assert_eq!(mailboxes[0].price.as_ref().map(|price| price.amount), Some(1500));
+        assert_eq!(mailboxes[1].raw_price, "");
+        assert!(mailboxes[1].price.is_none());
+        assert_eq!(mailboxes[1].link, "https://www.postscanmail.com/locations/texas/houston-1000-smith-st");
+    }
+}
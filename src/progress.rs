@@ -0,0 +1,117 @@
+use std::cell::Cell;
+use std::fmt::Display;
+use std::io::{IsTerminal, Write};
+use tracing::info;
+
+/// width of the bar, in characters
+const BAR_WIDTH: usize = 30;
+
+/// Progress of a stage, drawn as a bar on the terminal.
+///
+/// When stderr is not a terminal, i.e. the output is redirected to a file, the bar is not drawn
+/// and every item is logged as `[idx/total] message` instead.
+pub struct Progress {
+    label: &'static str,
+    total: Cell<usize>,
+    done: Cell<usize>,
+    bar: bool,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, total: usize) -> Self {
+        let progress = Self {
+            label,
+            total: Cell::new(total),
+            done: Cell::new(0),
+            bar: std::io::stderr().is_terminal(),
+        };
+        progress.draw();
+        progress
+    }
+
+    /// describe the item being worked on, only logged if the bar is not drawn
+    ///
+    /// * `position` - position of the item, starts from 1
+    pub fn message(&self, position: usize, message: impl Display) {
+        if !self.bar {
+            info!("[{}/{}] {}", position, self.total.get(), message);
+        }
+    }
+
+    /// the position of the next item, starts from 1
+    pub fn next_position(&self) -> usize {
+        self.done.get() + 1
+    }
+
+    /// `n` more items are done
+    pub fn inc(&self, n: usize) {
+        self.done.set((self.done.get() + n).min(self.total.get()));
+        self.draw();
+    }
+
+    /// `n` more items are found to work on, for the stages whose total is only known as they go
+    pub fn add_total(&self, n: usize) {
+        self.total.set(self.total.get() + n);
+        self.draw();
+    }
+
+    pub fn finish(self) {
+        if self.bar {
+            eprintln!();
+        }
+    }
+
+    fn draw(&self) {
+        if self.bar {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K{}", render_bar(self.label, self.done.get(), self.total.get()));
+            let _ = stderr.flush();
+        }
+    }
+}
+
+/// i.e. `detail pages [=========>           ] 320/1000`
+fn render_bar(label: &str, done: usize, total: usize) -> String {
+    let filled = (done * BAR_WIDTH).checked_div(total).unwrap_or(BAR_WIDTH);
+    let bar = match filled {
+        0 => " ".repeat(BAR_WIDTH),
+        filled if filled >= BAR_WIDTH => "=".repeat(BAR_WIDTH),
+        filled => format!("{}>{}", "=".repeat(filled - 1), " ".repeat(BAR_WIDTH - filled)),
+    };
+    format!("{label} [{bar}] {done}/{total}")
+}
+
+/// render the counts as a table, i.e.
+///
+/// ```text
+/// +-----------+------+
+/// | mailboxes | 1734 |
+/// +-----------+------+
+/// ```
+pub fn summary_table(rows: &[(&str, usize)]) -> String {
+    let name_width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+    let count_width = rows.iter().map(|(_, count)| count.to_string().len()).max().unwrap_or_default();
+    let border = format!("+-{}-+-{}-+\n", "-".repeat(name_width), "-".repeat(count_width));
+    let mut table = border.clone();
+    for (name, count) in rows {
+        table.push_str(&format!("| {name:<name_width$} | {count:>count_width$} |\n"));
+    }
+    table.push_str(&border);
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render_bar("states", 0, 10), format!("states [{}] 0/10", " ".repeat(30)));
+        assert_eq!(render_bar("states", 5, 10), format!("states [{}>{}] 5/10", "=".repeat(14), " ".repeat(15)));
+        assert_eq!(render_bar("states", 0, 0), format!("states [{}] 0/0", "=".repeat(30)));
+        assert_eq!(
+            summary_table(&[("mailboxes", 1734), ("non-CMRA", 12)]),
+            "+-----------+------+\n| mailboxes | 1734 |\n| non-CMRA  |   12 |\n+-----------+------+\n",
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use reqwest::{Client, ClientBuilder, Proxy};
+use serde::{Deserialize, Serialize};
+use crate::atmb::ATMBCrawl;
+use crate::atmb::model::Mailbox;
+use crate::cassette::HttpMode;
+use crate::country::Country;
+use crate::ipostal1::IPostal1Crawl;
+use crate::postscan::PostScanCrawl;
+use crate::state::{self, UsState};
+
+/// maximum concurrent requests of a crawler, see `--crawl-concurrency`
+pub const DEFAULT_CRAWL_CONCURRENCY: usize = 10;
+/// maximum requests per second to a host, see `--rate-limit`
+pub const DEFAULT_RATE_LIMIT: f64 = 5.0;
+/// how long to wait for a connection, see `--connect-timeout`
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// how long to wait for the response to make progress, see `--read-timeout`
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// how long to calm down before retrying the failed detail pages, see `--retry-delay`
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// settings shared by the crawlers
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+    /// maximum concurrent requests, iPostal1 has a single page so it's not limited
+    pub concurrency: usize,
+    /// maximum requests per second, `None` means unlimited
+    pub rate_limit: Option<f64>,
+    /// a request is given up if it cannot connect in time
+    pub connect_timeout: Duration,
+    /// a request is given up if nothing is read for this long, so a hung connection doesn't stall the crawl
+    pub read_timeout: Duration,
+    /// proxy for all the requests, i.e. `http://127.0.0.1:8080`, `HTTP_PROXY` and friends are used if not set
+    pub proxy: Option<String>,
+    /// file listing the proxies that the ATMB requests are rotated through, cannot be used with `proxy`
+    pub proxy_file: Option<PathBuf>,
+    /// only crawl the mailboxes in these states, all the states if `None`
+    pub states: Option<Vec<UsState>>,
+    /// the country of the ATMB locations, the other providers only have US locations
+    pub country: Country,
+    /// cross-check the crawled locations against the sitemap, and fetch the ones missing from the state pages
+    pub sitemap: bool,
+    /// the detail pages failed are retried one by one after this delay, they're not retried if `None`
+    pub retry_delay: Option<Duration>,
+    /// record the ATMB responses, or replay the recorded ones
+    pub http: Option<HttpMode>,
+    /// the headless Chrome rendering the ATMB pages blocked by anti-bot, needs the `headless` feature
+    pub browser: Option<PathBuf>,
+    /// the ATMB requests allowed in the run, unlimited if `None`
+    pub budget: Option<RequestBudget>,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CRAWL_CONCURRENCY,
+            rate_limit: Some(DEFAULT_RATE_LIMIT),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            proxy: None,
+            proxy_file: None,
+            states: None,
+            country: Country::Us,
+            sitemap: false,
+            retry_delay: Some(DEFAULT_RETRY_DELAY),
+            http: None,
+            browser: None,
+            budget: None,
+        }
+    }
+}
+
+impl CrawlConfig {
+    /// HTTP client builder with the proxy and the timeouts applied
+    pub fn client_builder(&self) -> anyhow::Result<ClientBuilder> {
+        let builder = self.with_timeouts(Client::builder());
+        Ok(
+            match &self.proxy {
+                Some(proxy) => builder.proxy(
+                    Proxy::all(proxy).map_err(|e| anyhow!("invalid proxy [{}]: {}", proxy, e))?
+                ),
+                None => builder,
+            }
+        )
+    }
+
+    /// apply the connect and read timeouts
+    pub fn with_timeouts(&self, builder: ClientBuilder) -> ClientBuilder {
+        builder
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout)
+    }
+
+    /// whether the state, given by its code or name, is selected via `--states`
+    pub fn includes_state(&self, state: &str) -> bool {
+        state::is_selected(self.states.as_deref(), state)
+    }
+}
+
+/// The requests allowed in a run, selected via `--max-requests`.
+///
+/// The clones share the count, so the countries crawled one after another share the budget.
+#[derive(Debug, Clone)]
+pub struct RequestBudget {
+    max: usize,
+    used: Arc<AtomicUsize>,
+}
+
+impl RequestBudget {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// take a request from the budget, an [`BudgetExhausted`] error if it's used up
+    pub fn take(&self) -> anyhow::Result<()> {
+        match self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| (used < self.max).then_some(used + 1)) {
+            Ok(_) => Ok(()),
+            Err(_) => bail!(BudgetExhausted { max: self.max }),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used.load(Ordering::Acquire) >= self.max
+    }
+
+    /// a budget of the same size that is not shared with this one, for the next run of `--watch`
+    pub fn renewed(&self) -> Self {
+        Self::new(self.max)
+    }
+}
+
+/// No request can be sent as the `--max-requests` budget is used up.
+#[derive(Debug)]
+pub struct BudgetExhausted {
+    pub max: usize,
+}
+
+impl Display for BudgetExhausted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the budget of [{}] requests is used up", self.max)
+    }
+}
+
+impl std::error::Error for BudgetExhausted {}
+
+/// A mailbox service whose locations can be crawled.
+///
+/// New providers only need to implement this trait and register themselves in [`ProviderKind`].
+#[async_trait(?Send)]
+pub trait MailboxProvider {
+    /// short name of the provider, i.e. `atmb`
+    fn name(&self) -> &str;
+
+    /// crawl all the US mailboxes of the provider, every mailbox is tagged with the provider
+    async fn fetch(&self) -> anyhow::Result<Vec<Mailbox>>;
+}
+
+/// mailbox providers selectable via `--provider`, also recorded on every mailbox as its source
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Atmb,
+    IPostal1,
+    PostScan,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 3] = [ProviderKind::Atmb, ProviderKind::IPostal1, ProviderKind::PostScan];
+
+    /// build the crawler of the provider
+    ///
+    /// * `fetched` - mailboxes fetched by an interrupted run, the crawler skips them if it can
+    /// * `previous` - mailboxes of the previous run with `--incremental`, the unchanged ones are skipped if it can
+    pub fn build(self, fetched: &[Mailbox], previous: &[Mailbox], config: &CrawlConfig) -> anyhow::Result<Box<dyn MailboxProvider>> {
+        Ok(
+            match self {
+                ProviderKind::Atmb => Box::new(
+                    ATMBCrawl::new(config)?
+                        .with_fetched(fetched.iter().cloned())
+                        .with_previous(previous.iter().cloned())
+                ),
+                ProviderKind::IPostal1 => Box::new(IPostal1Crawl::new(config)?),
+                ProviderKind::PostScan => Box::new(PostScanCrawl::new(config)?),
+            }
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProviderKind::Atmb => "atmb",
+            ProviderKind::IPostal1 => "ipostal1",
+            ProviderKind::PostScan => "postscan",
+        }
+    }
+}
+
+impl FromStr for ProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "atmb" => Ok(ProviderKind::Atmb),
+            "ipostal1" => Ok(ProviderKind::IPostal1),
+            "postscan" => Ok(ProviderKind::PostScan),
+            _ => Err(anyhow!("unknown provider: {}, expected one of: atmb, ipostal1, postscan, all", s)),
+        }
+    }
+}
+
+impl Display for ProviderKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shared_request_budget() {
+        let budget = RequestBudget::new(2);
+        let shared = budget.clone();
+        assert!(budget.take().is_ok());
+        assert!(shared.take().is_ok());
+        assert!(budget.is_exhausted());
+        assert!(shared.take().unwrap_err().is::<BudgetExhausted>());
+    }
+}
@@ -0,0 +1,114 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use reqwest::{Client, Proxy};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// how long a failing proxy is left out before it's tried again
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// a proxy of the pool with the client sending requests through it
+struct PoolEntry {
+    url: String,
+    client: Client,
+    /// the proxy is skipped until then after a failure
+    unhealthy_until: Cell<Option<Instant>>,
+}
+
+/// Pool of proxies that the requests are rotated through.
+///
+/// A proxy that fails is marked unhealthy and skipped for a while, so the retries go through a different one.
+pub struct ProxyPool {
+    entries: Vec<PoolEntry>,
+    next: Cell<usize>,
+}
+
+impl ProxyPool {
+    /// load the proxies from a file, one URL per line, empty lines and lines starting with `#` are ignored
+    ///
+    /// * `build` - builds the client of every proxy, so the caller can apply its own settings
+    pub fn load(path: impl AsRef<Path>, build: impl Fn(Proxy) -> anyhow::Result<Client>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("cannot read proxy file [{}]: {}", path.display(), e))?;
+        Self::parse(&content, build)
+    }
+
+    fn parse(content: &str, build: impl Fn(Proxy) -> anyhow::Result<Client>) -> anyhow::Result<Self> {
+        let entries = content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|url| {
+                let proxy = Proxy::all(url).map_err(|e| anyhow!("invalid proxy [{}]: {}", url, e))?;
+                Ok(
+                    PoolEntry {
+                        url: url.to_string(),
+                        client: build(proxy)?,
+                        unhealthy_until: Cell::new(None),
+                    }
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if entries.is_empty() {
+            bail!("no proxy found in the proxy file");
+        }
+        Ok(
+            Self {
+                entries,
+                next: Cell::new(0),
+            }
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// the next healthy proxy in turn, returns its index and client
+    pub fn next(&self) -> Option<(usize, &Client)> {
+        let now = Instant::now();
+        let start = self.next.get();
+        (0..self.entries.len())
+            .map(|offset| (start + offset) % self.entries.len())
+            .find(|&idx| self.entries[idx].unhealthy_until.get().is_none_or(|until| until <= now))
+            .map(|idx| {
+                self.next.set(idx + 1);
+                (idx, &self.entries[idx].client)
+            })
+    }
+
+    pub fn mark_unhealthy(&self, idx: usize) {
+        let entry = &self.entries[idx];
+        if entry.unhealthy_until.get().is_none() {
+            warn!("proxy [{}] is failing, skip it for {}s", entry.url, UNHEALTHY_COOLDOWN.as_secs());
+        }
+        entry.unhealthy_until.set(Some(Instant::now() + UNHEALTHY_COOLDOWN));
+    }
+
+    pub fn mark_healthy(&self, idx: usize) {
+        self.entries[idx].unhealthy_until.set(None);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rotate_healthy_proxies() {
+        let content = "# proxies\nhttp://127.0.0.1:8001\n\nhttp://127.0.0.1:8002\nhttp://127.0.0.1:8003\n";
+        let pool = ProxyPool::parse(content, |proxy| Ok(Client::builder().proxy(proxy).build()?)).unwrap();
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool.next().map(|(idx, _)| idx), Some(0));
+        pool.mark_unhealthy(1);
+        assert_eq!(pool.next().map(|(idx, _)| idx), Some(2));
+        assert_eq!(pool.next().map(|(idx, _)| idx), Some(0));
+        pool.mark_unhealthy(0);
+        pool.mark_unhealthy(2);
+        assert!(pool.next().is_none());
+        pool.mark_healthy(1);
+        assert_eq!(pool.next().map(|(idx, _)| idx), Some(1));
+    }
+}
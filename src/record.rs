@@ -1,34 +1,1049 @@
-use serde::Serialize;
-use crate::atmb::model::Mailbox;
-use crate::smarty::{AdditionalInfo, Rdi, YesOrNo};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use crate::atmb::model::{Address, GeoPoint, Mailbox, Price, PricePeriod};
+use crate::country::Country;
+use crate::provider::ProviderKind;
+use crate::report;
+use crate::state::UsState;
+use crate::verify::model::{AdditionalInfo, Rdi, YesOrNo};
+use crate::xlsx;
 
 /// The final struct that will be used to store the data
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Record {
+    /// see [`stable_id`], filled in when results written before the column was added are loaded
+    #[serde(default)]
+    pub id: String,
+    /// results written before the provider column was added are all from ATMB
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub name: String,
+    pub street: String,
+    pub city: String,
+    pub state: String,
+    /// verified ZIP if any, otherwise the scraped one
+    pub zip: String,
+    #[serde(default)]
+    pub zip4: String,
+    #[serde(default)]
+    pub county: String,
+    #[serde(default)]
+    pub county_fips: String,
+    /// the price as scraped, i.e. `US$9.99/month`
+    pub price: String,
+    /// i.e. `9.99`, empty if the price cannot be parsed
+    #[serde(default)]
+    pub price_amount: Option<f64>,
+    #[serde(default)]
+    pub price_currency: String,
+    #[serde(default)]
+    pub price_period: Option<PricePeriod>,
+    pub link: String,
+    /// phone number of the location, empty if its page doesn't show one
+    #[serde(default)]
+    pub phone: String,
+    /// the company running the location
+    #[serde(default)]
+    pub operator: String,
+    #[serde(default)]
+    pub latitude: Option<f64>,
+    #[serde(default)]
+    pub longitude: Option<f64>,
+    pub rdi: Rdi,
+    #[serde(rename = "CMRA")]
+    pub cmra: YesOrNo,
+    #[serde(default)]
+    pub dpv_match_code: String,
+    #[serde(default)]
+    pub dpv_footnotes: String,
+    #[serde(default)]
+    pub active: Option<YesOrNo>,
+    #[serde(default)]
+    pub vacant: Option<YesOrNo>,
+    /// whether USPS doesn't deliver to the address, see [`AdditionalInfo::no_stat`]
+    #[serde(default)]
+    pub no_stat: Option<YesOrNo>,
+    #[serde(default)]
+    pub enhanced_match: String,
+    /// whether a unit number is needed for the address to be deliverable, see [`AdditionalInfo::missing_secondary`]
+    #[serde(default)]
+    pub missing_secondary: Option<YesOrNo>,
+    /// RDI reported by the second verifier, see `--double-check`
+    #[serde(default)]
+    pub double_check_rdi: Option<Rdi>,
+    /// CMRA reported by the second verifier, see `--double-check`
+    #[serde(default, rename = "double_check_CMRA")]
+    pub double_check_cmra: Option<YesOrNo>,
+    /// what every verifier has reported with `--verifier consensus:...`, empty for the ones not asked
+    #[serde(default)]
+    pub smarty_rdi: Option<Rdi>,
+    #[serde(default, rename = "smarty_CMRA")]
+    pub smarty_cmra: Option<YesOrNo>,
+    #[serde(default)]
+    pub usps_rdi: Option<Rdi>,
+    #[serde(default, rename = "usps_CMRA")]
+    pub usps_cmra: Option<YesOrNo>,
+    #[serde(default)]
+    pub melissa_rdi: Option<Rdi>,
+    #[serde(default, rename = "melissa_CMRA")]
+    pub melissa_cmra: Option<YesOrNo>,
+    /// what most of them agree on, see [`AdditionalInfo::consensus`]
+    #[serde(default)]
+    pub consensus_rdi: Option<Rdi>,
+    #[serde(default, rename = "consensus_CMRA")]
+    pub consensus_cmra: Option<YesOrNo>,
+    /// whether they disagree on the CMRA or the RDI
+    #[serde(default)]
+    pub disagreement: Option<YesOrNo>,
+    /// how many candidates the verifier has matched, 0 in the results written before the column was added
+    #[serde(default)]
+    pub candidates: usize,
+    /// when the CMRA and RDI were queried, i.e. `2024-06-01T08:00:00Z`, the cached results keep the time they were queried
+    #[serde(default)]
+    pub verified_at: String,
+    /// the verifier that has reported the CMRA and RDI, i.e. `smarty`
+    #[serde(default)]
+    pub verifier: String,
+}
+
+impl Record {
+    /// build the record, the street, city and state are replaced with the standardized ones
+    /// if `use_verified_address` is set and the verifier has found a match
+    pub fn from_mailbox_and_info(mailbox: Mailbox, info: AdditionalInfo, use_verified_address: bool) -> Self {
+        let (street, city, state) = match &info.verified {
+            Some(verified) if use_verified_address && !verified.street.is_empty() => (
+                verified.street.clone(),
+                verified.city.clone(),
+                verified.state.clone(),
+            ),
+            _ => (mailbox.address.line1, mailbox.address.city, mailbox.address.state),
+        };
+        let (latitude, longitude) = coordinates(&info, mailbox.geo);
+        let vote = |verifier: &str| info.votes.iter().find(|vote| vote.verifier == verifier);
+        let (smarty, usps, melissa) = (vote("smarty").cloned(), vote("usps").cloned(), vote("melissa").cloned());
+        let consensus = info.consensus();
+        let (zip, zip4, county, county_fips) = match &info.verified {
+            Some(verified) => (
+                verified.zip.clone(),
+                verified.zip4.clone(),
+                verified.county_name.clone(),
+                verified.county_fips.clone(),
+            ),
+            None => (
+                mailbox.address.zip,
+                mailbox.address.zip4.unwrap_or_default(),
+                String::new(),
+                String::new(),
+            ),
+        };
+        Self {
+            id: stable_id(mailbox.provider, &mailbox.link),
+            provider: mailbox.provider,
+            zip,
+            zip4,
+            county,
+            county_fips,
+            name: mailbox.name,
+            street,
+            city,
+            state,
+            price_amount: mailbox.price.as_ref().map(Price::decimal_amount),
+            price_currency: mailbox.price.as_ref().map(|price| price.currency.clone()).unwrap_or_default(),
+            price_period: mailbox.price.map(|price| price.period),
+            price: mailbox.raw_price,
+            link: mailbox.link,
+            phone: mailbox.phone.unwrap_or_default(),
+            operator: mailbox.operator.unwrap_or_default(),
+            latitude,
+            longitude,
+            double_check_rdi: info.double_check.as_ref().map(|second| second.rdi.clone()),
+            double_check_cmra: info.double_check.as_ref().map(|second| second.cmra.clone()),
+            smarty_rdi: smarty.as_ref().map(|vote| vote.rdi.clone()),
+            smarty_cmra: smarty.map(|vote| vote.cmra),
+            usps_rdi: usps.as_ref().map(|vote| vote.rdi.clone()),
+            usps_cmra: usps.map(|vote| vote.cmra),
+            melissa_rdi: melissa.as_ref().map(|vote| vote.rdi.clone()),
+            melissa_cmra: melissa.map(|vote| vote.cmra),
+            disagreement: consensus.as_ref().map(|consensus| if consensus.disagreement { YesOrNo::Y } else { YesOrNo::N }),
+            consensus_rdi: consensus.as_ref().map(|consensus| consensus.rdi.clone()),
+            consensus_cmra: consensus.map(|consensus| consensus.cmra),
+            candidates: info.candidate_count(),
+            verified_at: format_timestamp(info.verified_at),
+            verifier: info.verifier.clone(),
+            missing_secondary: info.missing_secondary(),
+            rdi: info.rdi,
+            cmra: info.cmra,
+            dpv_match_code: info.dpv_match_code,
+            dpv_footnotes: info.dpv_footnotes,
+            active: info.active,
+            vacant: info.vacant,
+            no_stat: info.no_stat,
+            enhanced_match: info.enhanced_match,
+        }
+    }
+
+    /// load the records from a CSV file written by a previous run
+    pub fn load_csv(path: impl AsRef<Path>) -> anyhow::Result<Vec<Self>> {
+        let path = path.as_ref();
+        let mut rdr = csv::Reader::from_path(path)
+            .map_err(|e| anyhow!("cannot open [{}]: {}", path.display(), e))?;
+        rdr.deserialize::<Self>()
+            .map(|record| record
+                .map(|mut record| {
+                    if record.id.is_empty() {
+                        record.id = stable_id(record.provider, &record.link);
+                    }
+                    record
+                })
+                .map_err(|e| anyhow!("cannot parse [{}]: {}", path.display(), e)))
+            .collect()
+    }
+}
+
+/// ISO-8601 of the seconds since the UNIX epoch, empty if it's unknown
+fn format_timestamp(secs: u64) -> String {
+    match secs {
+        0 => String::new(),
+        secs => humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(secs)).to_string(),
+    }
+}
+
+/// The ID of a location that stays the same between runs even if its name or price changes,
+/// 16 hex digits of the FNV-1a hash of the provider and the link.
+pub fn stable_id(provider: ProviderKind, link: &str) -> String {
+    let hash = format!("{}:{}", provider.name(), link).bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+impl Record {
+    /// a residential record with the given link, for tests
+    pub fn sample(link: &str) -> Self {
+        Self {
+            id: stable_id(ProviderKind::Atmb, link),
+            provider: ProviderKind::Atmb,
+            name: "Test".to_string(),
+            street: "123 Main St".to_string(),
+            city: "City".to_string(),
+            state: "ST".to_string(),
+            zip: "12345".to_string(),
+            zip4: "6789".to_string(),
+            county: "Jefferson".to_string(),
+            county_fips: "01073".to_string(),
+            price: "US$9.99/month".to_string(),
+            price_amount: Some(9.99),
+            price_currency: "USD".to_string(),
+            price_period: Some(PricePeriod::Month),
+            link: link.to_string(),
+            phone: String::new(),
+            operator: String::new(),
+            rdi: Rdi::Residential,
+            latitude: Some(33.5186),
+            longitude: Some(-86.8104),
+            cmra: YesOrNo::N,
+            dpv_match_code: "Y".to_string(),
+            dpv_footnotes: "AABB".to_string(),
+            active: Some(YesOrNo::Y),
+            vacant: Some(YesOrNo::N),
+            no_stat: Some(YesOrNo::N),
+            enhanced_match: String::new(),
+            missing_secondary: Some(YesOrNo::N),
+            double_check_rdi: None,
+            double_check_cmra: None,
+            smarty_rdi: None,
+            smarty_cmra: None,
+            usps_rdi: None,
+            usps_cmra: None,
+            melissa_rdi: None,
+            melissa_cmra: None,
+            consensus_rdi: None,
+            consensus_cmra: None,
+            disagreement: None,
+            candidates: 1,
+            verified_at: "2024-06-01T08:00:00Z".to_string(),
+            verifier: "smarty".to_string(),
+        }
+    }
+}
+
+/// The final struct of a mailbox outside the US.
+///
+/// The international verifiers only tell whether the address is deliverable, so there are no CMRA
+/// and RDI columns. The province and the postal code are kept in the `state` and `zip` columns,
+/// so the result files are laid out like the US ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InternationalRecord {
+    /// see [`stable_id`]
+    pub id: String,
+    pub provider: ProviderKind,
+    pub name: String,
+    pub street: String,
+    pub city: String,
+    /// the province or region
+    pub state: String,
+    /// verified postal code if any, otherwise the scraped one
+    pub zip: String,
+    pub country: Country,
+    pub price: String,
+    pub price_amount: Option<f64>,
+    pub price_currency: String,
+    pub price_period: Option<PricePeriod>,
+    pub link: String,
+    pub phone: String,
+    pub operator: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// `Verified`, `Partial`, `Ambiguous` or `None`
+    pub verification_status: String,
+    pub address_precision: String,
+}
+
+impl InternationalRecord {
+    /// build the record, see [`Record::from_mailbox_and_info`] for `use_verified_address`
+    pub fn from_mailbox_and_info(mailbox: Mailbox, info: AdditionalInfo, use_verified_address: bool) -> Self {
+        let (latitude, longitude) = coordinates(&info, mailbox.geo);
+        let verified = info.verified.unwrap_or_default();
+        let (street, city, state) = if use_verified_address && !verified.street.is_empty() {
+            (verified.street, verified.city, verified.state)
+        } else {
+            (mailbox.address.line1, mailbox.address.city, mailbox.address.state)
+        };
+        let status = info.international.unwrap_or_default();
+        Self {
+            id: stable_id(mailbox.provider, &mailbox.link),
+            provider: mailbox.provider,
+            name: mailbox.name,
+            street,
+            city,
+            state,
+            zip: if verified.zip.is_empty() { mailbox.address.zip } else { verified.zip },
+            country: mailbox.address.country,
+            price_amount: mailbox.price.as_ref().map(Price::decimal_amount),
+            price_currency: mailbox.price.as_ref().map(|price| price.currency.clone()).unwrap_or_default(),
+            price_period: mailbox.price.map(|price| price.period),
+            price: mailbox.raw_price,
+            link: mailbox.link,
+            phone: mailbox.phone.unwrap_or_default(),
+            operator: mailbox.operator.unwrap_or_default(),
+            latitude,
+            longitude,
+            verification_status: status.verification_status,
+            address_precision: status.address_precision,
+        }
+    }
+}
+
+/// the coordinates of the verifier, or the ones on the page of the location if the verifier has none
+fn coordinates(info: &AdditionalInfo, geo: Option<GeoPoint>) -> (Option<f64>, Option<f64>) {
+    match (info.latitude, info.longitude, geo) {
+        (Some(latitude), Some(longitude), _) => (Some(latitude), Some(longitude)),
+        (_, _, Some(geo)) => (Some(geo.latitude), Some(geo.longitude)),
+        (latitude, longitude, None) => (latitude, longitude),
+    }
+}
+
+/// A crawled mailbox without verification info, written to `failed.csv` for the mailboxes whose
+/// detail page cannot be fetched, and to the raw results with `--skip-verify`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawRecord {
+    #[serde(default)]
+    provider: ProviderKind,
     name: String,
     street: String,
     city: String,
     state: String,
+    /// the full ZIP, i.e. `35203-3712`, unless `zip4` is given
     zip: String,
+    /// result files have the ZIP+4 in its own column
+    #[serde(default, skip_serializing)]
+    zip4: String,
+    /// files written before the Canadian mailboxes were supported are all in the US
+    #[serde(default)]
+    country: Country,
+    /// the price as scraped, the parsed columns are only written for reference
     price: String,
+    #[serde(default, skip_deserializing)]
+    price_amount: Option<f64>,
+    #[serde(default, skip_deserializing)]
+    price_currency: String,
+    #[serde(default, skip_deserializing)]
+    price_period: Option<PricePeriod>,
     link: String,
+    #[serde(default)]
+    phone: String,
+    #[serde(default)]
+    operator: String,
+}
+
+impl RawRecord {
+    pub fn from_mailbox(mailbox: &Mailbox) -> Self {
+        Self {
+            provider: mailbox.provider,
+            name: mailbox.name.clone(),
+            street: mailbox.address.line1.clone(),
+            city: mailbox.address.city.clone(),
+            state: mailbox.address.state.clone(),
+            zip: mailbox.address.full_zip(),
+            zip4: String::new(),
+            country: mailbox.address.country,
+            price: mailbox.raw_price.clone(),
+            price_amount: mailbox.price.as_ref().map(Price::decimal_amount),
+            price_currency: mailbox.price.as_ref().map(|price| price.currency.clone()).unwrap_or_default(),
+            price_period: mailbox.price.as_ref().map(|price| price.period),
+            link: mailbox.link.clone(),
+            phone: mailbox.phone.clone().unwrap_or_default(),
+            operator: mailbox.operator.clone().unwrap_or_default(),
+        }
+    }
+
+    pub fn into_mailbox(self) -> Mailbox {
+        let (zip, zip4) = match self.zip.split_once('-').filter(|_| self.country == Country::Us) {
+            Some((zip, zip4)) => (zip.to_string(), Some(zip4.to_string())),
+            None => (self.zip, Some(self.zip4).filter(|zip4| !zip4.is_empty())),
+        };
+        Mailbox {
+            provider: self.provider,
+            name: self.name,
+            address: Address {
+                line1: self.street,
+                city: self.city,
+                state: self.state,
+                zip,
+                zip4,
+                country: self.country,
+            },
+            link: self.link,
+            price: Price::parse(&self.price),
+            raw_price: self.price,
+            phone: Some(self.phone).filter(|phone| !phone.is_empty()),
+            operator: Some(self.operator).filter(|operator| !operator.is_empty()),
+            plans: Vec::new(),
+            geo: None,
+            listing: String::new(),
+        }
+    }
+
+    /// load the mailboxes from a file written by a previous run
+    ///
+    /// a CSV file can be any of the raw results, `failed.csv` or the final results,
+    /// a JSON file can be the raw results or the checkpoint.
+    pub fn load_mailboxes(path: impl AsRef<Path>) -> anyhow::Result<Vec<Mailbox>> {
+        let path = path.as_ref();
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        if !is_json {
+            let mut rdr = csv::Reader::from_path(path)
+                .map_err(|e| anyhow!("cannot open [{}]: {}", path.display(), e))?;
+            return rdr.deserialize::<Self>()
+                .map(|record| record
+                    .map(Self::into_mailbox)
+                    .map_err(|e| anyhow!("cannot parse [{}]: {}", path.display(), e)))
+                .collect();
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("cannot open [{}]: {}", path.display(), e))?;
+        let mut value: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("cannot parse [{}]: {}", path.display(), e))?;
+        let mailboxes = match value.get_mut("mailboxes") {
+            Some(mailboxes) => serde_json::from_value(mailboxes.take()),
+            None => serde_json::from_value::<Vec<Self>>(value)
+                .map(|records| records.into_iter().map(Self::into_mailbox).collect()),
+        };
+        mailboxes.map_err(|e| anyhow!("cannot parse [{}]: {}", path.display(), e))
+    }
+}
+
+/// A plan of a location, written to `result/plans.csv`, joined with the results by the link
+#[derive(Debug, Serialize)]
+pub struct PlanRecord {
+    pub link: String,
+    /// name of the location
+    pub location: String,
+    pub plan: String,
+    pub monthly_price: String,
+    pub yearly_price: String,
+    /// i.e. `Incoming Mail: 60 / month; Recipients: 5`
+    pub features: String,
+}
+
+impl PlanRecord {
+    /// a record for every plan of the mailbox
+    pub fn from_mailbox(mailbox: &Mailbox) -> Vec<Self> {
+        mailbox.plans.iter()
+            .map(|plan| Self {
+                link: mailbox.link.clone(),
+                location: mailbox.name.clone(),
+                plan: plan.name.clone(),
+                monthly_price: plan.price_of(PricePeriod::Month).unwrap_or_default().to_string(),
+                yearly_price: plan.price_of(PricePeriod::Year).unwrap_or_default().to_string(),
+                features: plan.features.iter()
+                    .map(|(label, allowance)| format!("{}: {}", label, allowance))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            })
+            .collect()
+    }
+}
+
+/// A candidate of an address whose candidates disagree on the CMRA or the RDI, written to `result/ambiguous.csv`
+/// with `--ambiguous-report`
+#[derive(Debug, Serialize)]
+pub struct AmbiguousRecord {
+    pub id: String,
+    pub name: String,
+    pub link: String,
+    /// the address as scraped
+    pub address: String,
+    /// the candidate as standardized by the verifier
+    pub street: String,
+    pub zip: String,
+    pub dpv_match_code: String,
     pub rdi: Rdi,
     #[serde(rename = "CMRA")]
     pub cmra: YesOrNo,
+    /// whether the candidate is the one in the results
+    pub picked: YesOrNo,
 }
 
-impl Record {
-    pub fn from_mailbox_and_info(mailbox: Mailbox, info: AdditionalInfo) -> Self {
-        Self {
-            zip: mailbox.address.full_zip(),
-            name: mailbox.name,
-            street: mailbox.address.line1,
-            city: mailbox.address.city,
-            state: mailbox.address.state,
-            price: mailbox.price,
-            link: mailbox.link,
-            rdi: info.rdi,
-            cmra: info.cmra,
+impl AmbiguousRecord {
+    /// a record for every candidate if they disagree, none otherwise
+    pub fn from_mailbox_and_info(mailbox: &Mailbox, info: &AdditionalInfo) -> Vec<Self> {
+        if !info.is_ambiguous() {
+            return Vec::new();
         }
+        let picked_street = info.verified.as_ref().map(|verified| verified.street.as_str());
+        info.candidates.iter()
+            .map(|candidate| Self {
+                id: stable_id(mailbox.provider, &mailbox.link),
+                name: mailbox.name.clone(),
+                link: mailbox.link.clone(),
+                address: format!("{}, {}, {} {}", mailbox.address.line1, mailbox.address.city, mailbox.address.state, mailbox.address.full_zip()),
+                street: candidate.street.clone(),
+                zip: candidate.zip.clone(),
+                dpv_match_code: candidate.dpv_match_code.clone(),
+                rdi: candidate.rdi.clone(),
+                cmra: candidate.cmra.clone(),
+                picked: if Some(candidate.street.as_str()) == picked_street
+                    && candidate.dpv_match_code == info.dpv_match_code && candidate.rdi == info.rdi && candidate.cmra == info.cmra {
+                    YesOrNo::Y
+                } else {
+                    YesOrNo::N
+                },
+            })
+            .collect()
+    }
+}
+
+/// order of the records in the result files, selectable via `--sort-by`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// monthly price, the records without a known price go last
+    #[default]
+    Price,
+    /// state, then city
+    State,
+    Zip,
+    Name,
+    /// the stable ID, the order doesn't change with the prices or names between runs
+    Id,
+}
+
+/// the columns the records are sorted by, see [`SortKey`]
+pub trait SortFields {
+    /// the price as scraped
+    fn price(&self) -> &str;
+    fn state(&self) -> &str;
+    fn city(&self) -> &str;
+    /// the ZIP and ZIP+4, or the postal code
+    fn zip(&self) -> (&str, &str);
+    fn name(&self) -> &str;
+    /// see [`stable_id`]
+    fn id(&self) -> &str;
+}
+
+impl SortFields for Record {
+    fn price(&self) -> &str {
+        &self.price
+    }
+
+    fn state(&self) -> &str {
+        &self.state
+    }
+
+    fn city(&self) -> &str {
+        &self.city
+    }
+
+    fn zip(&self) -> (&str, &str) {
+        (&self.zip, &self.zip4)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl SortFields for InternationalRecord {
+    fn price(&self) -> &str {
+        &self.price
+    }
+
+    fn state(&self) -> &str {
+        &self.state
+    }
+
+    fn city(&self) -> &str {
+        &self.city
+    }
+
+    fn zip(&self) -> (&str, &str) {
+        (&self.zip, "")
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl SortKey {
+    /// sort the records, ties are broken by the stable ID so the order is stable between runs
+    ///
+    /// * `desc` - sort in descending order, the records without a known price still go last
+    pub fn sort<T: SortFields>(self, records: &mut [T], desc: bool) {
+        let directed = |ordering: Ordering| if desc { ordering.reverse() } else { ordering };
+        records.sort_by(|r1, r2| {
+            let ordering = match self {
+                SortKey::Price => {
+                    let monthly_price = |record: &T| Price::parse(record.price()).map(|price| price.monthly());
+                    match (monthly_price(r1), monthly_price(r2)) {
+                        (Some(p1), Some(p2)) => directed(p1.cmp(&p2)),
+                        (p1, p2) => p2.is_some().cmp(&p1.is_some()),
+                    }
+                }
+                SortKey::State => directed((r1.state(), r1.city()).cmp(&(r2.state(), r2.city()))),
+                SortKey::Zip => directed(r1.zip().cmp(&r2.zip())),
+                SortKey::Name => directed(r1.name().cmp(r2.name())),
+                SortKey::Id => directed(r1.id().cmp(r2.id())),
+            };
+            ordering.then_with(|| r1.id().cmp(r2.id()))
+        });
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "price" => Ok(SortKey::Price),
+            "state" => Ok(SortKey::State),
+            "zip" => Ok(SortKey::Zip),
+            "name" => Ok(SortKey::Name),
+            "id" => Ok(SortKey::Id),
+            _ => Err(anyhow!("unknown sort key: {}, expected one of: price, state, zip, name, id", s)),
+        }
+    }
+}
+
+/// Appends the records to a CSV file as they're completed, flushed every time,
+/// so what has been done survives a crash of the run before the results are written.
+pub struct CsvSink {
+    path: PathBuf,
+    writer: csv::Writer<File>,
+}
+
+impl CsvSink {
+    /// create the file, replacing the one left by a previous run
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(
+            Self {
+                path: path.to_path_buf(),
+                writer: csv::Writer::from_path(path)?,
+            }
+        )
+    }
+
+    pub fn append<T: Serialize>(&mut self, records: impl IntoIterator<Item = T>) -> anyhow::Result<()> {
+        for record in records {
+            self.writer.serialize(record)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// file formats the records can be written in, selectable via `--format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Csv,
+    /// a pretty printed JSON array
+    Json,
+    /// newline-delimited JSON, one record per line
+    Jsonl,
+    /// a GeoJSON FeatureCollection, records without coordinates are left out
+    GeoJson,
+    /// an Excel workbook with a frozen header, auto filters and the rows colored by RDI
+    Xlsx,
+    /// a self-contained HTML page with a searchable and sortable table
+    Html,
+    /// a GitHub flavored Markdown table of the main columns
+    Markdown {
+        /// a table under a heading for every state, see `--split-by-state`
+        by_state: bool,
+    },
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::GeoJson => "geojson",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Html => "html",
+            OutputFormat::Markdown { .. } => "md",
+        }
+    }
+
+    /// write the records to the file, the parent directory is created if needed
+    pub fn write<T: Serialize>(&self, records: &[T], save_path: impl AsRef<Path>) -> anyhow::Result<()> {
+        if let Some(parent) = save_path.as_ref().parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        match self {
+            OutputFormat::Csv => {
+                let mut wtr = csv::Writer::from_path(save_path)?;
+                for record in records {
+                    wtr.serialize(record)?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Json => {
+                let mut wtr = BufWriter::new(File::create(save_path)?);
+                serde_json::to_writer_pretty(&mut wtr, records)?;
+                writeln!(wtr)?;
+                wtr.flush()?;
+            }
+            OutputFormat::Jsonl => {
+                let mut wtr = BufWriter::new(File::create(save_path)?);
+                for record in records {
+                    serde_json::to_writer(&mut wtr, record)?;
+                    writeln!(wtr)?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::GeoJson => {
+                let features = records.iter()
+                    .map(geojson_feature)
+                    .collect::<anyhow::Result<Vec<_>>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                let collection = serde_json::json!({
+                    "type": "FeatureCollection",
+                    "features": features,
+                });
+                let mut wtr = BufWriter::new(File::create(save_path)?);
+                serde_json::to_writer_pretty(&mut wtr, &collection)?;
+                writeln!(wtr)?;
+                wtr.flush()?;
+            }
+            OutputFormat::Xlsx => {
+                let (header, rows) = table_rows(records)?;
+                xlsx::write(&header, &rows, save_path)?;
+            }
+            OutputFormat::Html => {
+                let (header, rows) = table_rows(records)?;
+                std::fs::write(save_path, report::render_table(&header, &rows)?)?;
+            }
+            OutputFormat::Markdown { by_state } => {
+                let (header, rows) = table_rows(records)?;
+                std::fs::write(save_path, markdown(&header, &rows, *by_state))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the header and the rows of the records as they are written to CSV,
+/// so the other tabular formats share the same columns
+fn table_rows<T: Serialize>(records: &[T]) -> anyhow::Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for record in records {
+        wtr.serialize(record)?;
+    }
+    let csv = wtr.into_inner().map_err(|e| anyhow!("cannot serialize the records: {}", e))?;
+    let mut rdr = csv::Reader::from_reader(csv.as_slice());
+    let header = rdr.headers()?.iter().map(String::from).collect();
+    let rows = rdr.records()
+        .map(|row| row.map(|row| row.iter().map(String::from).collect()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((header, rows))
+}
+
+/// columns of the Markdown table, the others are too much to read
+const MARKDOWN_COLUMNS: [&str; 9] = ["provider", "name", "street", "city", "state", "zip", "price", "rdi", "CMRA"];
+
+/// render the rows as Markdown tables, the links are put on the names
+fn markdown(header: &[String], rows: &[Vec<String>], by_state: bool) -> String {
+    let columns = MARKDOWN_COLUMNS.iter()
+        .filter_map(|column| header.iter().position(|name| name == column))
+        .collect::<Vec<_>>();
+    let name_column = header.iter().position(|name| name == "name");
+    let link_column = header.iter().position(|name| name == "link");
+    let cell = |row: &[String], idx: usize| {
+        let value = row[idx].replace('|', "\\|");
+        match link_column.map(|link| &row[link]) {
+            Some(link) if Some(idx) == name_column && !link.is_empty() => format!("[{}]({})", value, link),
+            _ => value,
+        }
+    };
+    let table = |rows: &[&Vec<String>]| {
+        let mut table = format!("| {} |\n", columns.iter().map(|&idx| header[idx].as_str()).collect::<Vec<_>>().join(" | "));
+        table.push_str(&format!("|{}\n", " --- |".repeat(columns.len())));
+        for row in rows {
+            table.push_str(&format!("| {} |\n", columns.iter().map(|&idx| cell(row, idx)).collect::<Vec<_>>().join(" | ")));
+        }
+        table
+    };
+
+    let state_column = header.iter().position(|name| name == "state");
+    let (true, Some(state_column)) = (by_state, state_column) else {
+        return table(&rows.iter().collect::<Vec<_>>());
+    };
+    let mut states = BTreeMap::<&str, Vec<&Vec<String>>>::new();
+    for row in rows {
+        states.entry(&row[state_column]).or_default().push(row);
+    }
+    states.into_iter()
+        .map(|(state, rows)| {
+            let heading = match state.parse::<UsState>() {
+                Ok(state) => format!("{} ({})", state.name, state.code),
+                Err(_) => state.to_string(),
+            };
+            format!("## {}\n\n{}", heading, table(&rows))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// fields of the record kept as the feature properties
+const GEOJSON_PROPERTIES: [&str; 7] = ["id", "provider", "name", "price", "CMRA", "rdi", "link"];
+
+/// turn the record into a GeoJSON point feature, `None` if it has no coordinates
+fn geojson_feature<T: Serialize>(record: &T) -> anyhow::Result<Option<serde_json::Value>> {
+    let serde_json::Value::Object(mut fields) = serde_json::to_value(record)? else {
+        bail!("record is not serialized as an object");
+    };
+    let (Some(latitude), Some(longitude)) = (
+        fields.get("latitude").and_then(|v| v.as_f64()),
+        fields.get("longitude").and_then(|v| v.as_f64()),
+    ) else {
+        return Ok(None);
+    };
+    fields.retain(|key, _| GEOJSON_PROPERTIES.contains(&key.as_str()));
+    Ok(
+        Some(serde_json::json!({
+            "type": "Feature",
+            // GeoJSON positions are longitude first
+            "geometry": { "type": "Point", "coordinates": [longitude, latitude] },
+            "properties": fields,
+        }))
+    )
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            "geojson" => Ok(OutputFormat::GeoJson),
+            "xlsx" | "excel" => Ok(OutputFormat::Xlsx),
+            "html" => Ok(OutputFormat::Html),
+            "markdown" | "md" => Ok(OutputFormat::Markdown { by_state: false }),
+            _ => Err(anyhow!("unknown format: {}, expected one of: csv, json, jsonl, geojson, xlsx, html, markdown", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_raw_record_zip() {
+        let csv = "provider,name,street,city,state,zip,zip4,county,price,link\n\
+            atmb,A,120 19th St N,Birmingham,AL,35203,3712,Jefferson,US$9.99/month,https://example.com/a\n\
+            atmb,B,1 Main St,Austin,TX,78701-1234,,,US$9.99/month,https://example.com/b\n";
+        let mut rdr = csv::Reader::from_reader(csv.as_bytes());
+        let mailboxes = rdr.deserialize::<RawRecord>()
+            .map(|record| record.unwrap().into_mailbox())
+            .collect::<Vec<_>>();
+        assert_eq!(mailboxes[0].address.full_zip(), "35203-3712");
+        assert_eq!(mailboxes[1].address.full_zip(), "78701-1234");
+        assert_eq!(mailboxes[1].address.zip, "78701");
+    }
+
+    #[test]
+    fn test_sort_by_price() {
+        let mut records = vec![
+            Record { price: "US$19.99/month".to_string(), ..Record::sample("a") },
+            Record { price: String::new(), ..Record::sample("b") },
+            Record { price: "US$9.99/month".to_string(), ..Record::sample("c") },
+            Record { price: "US$96/year".to_string(), ..Record::sample("d") },
+        ];
+        let links = |records: &[Record]| records.iter().map(|record| record.link.clone()).collect::<Vec<_>>();
+        SortKey::Price.sort(&mut records, false);
+        assert_eq!(links(&records), ["d", "c", "a", "b"]);
+        SortKey::Price.sort(&mut records, true);
+        assert_eq!(links(&records), ["a", "c", "d", "b"]);
+    }
+
+    #[test]
+    fn test_stable_id() {
+        let id = stable_id(ProviderKind::Atmb, "https://example.com/a");
+        assert_eq!(id.len(), 16);
+        assert_eq!(id, stable_id(ProviderKind::Atmb, "https://example.com/a"));
+        assert_ne!(id, stable_id(ProviderKind::PostScan, "https://example.com/a"));
+
+        // the results written before the column was added get the same ID
+        let csv = "provider,name,street,city,state,zip,price,link,rdi,CMRA\n\
+            atmb,A,1 Main St,Austin,TX,78701,US$9.99/month,https://example.com/a,Residential,N\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mailboxes.csv");
+        std::fs::write(&path, csv).unwrap();
+        let records = Record::load_csv(&path).unwrap();
+        assert_eq!(records[0].id, id);
+    }
+
+    #[test]
+    fn test_consensus_columns() {
+        let mut info: AdditionalInfo = serde_json::from_str(r#"{"cmra": "N", "rdi": "Residential"}"#).unwrap();
+        info.votes = serde_json::from_str(r#"[
+            {"verifier": "smarty", "cmra": "N", "rdi": "Residential"},
+            {"verifier": "usps", "cmra": "Y", "rdi": "Commercial"},
+            {"verifier": "melissa", "cmra": "N", "rdi": "Residential"}
+        ]"#).unwrap();
+        let csv = "provider,name,street,city,state,zip,price,link\n\
+            atmb,A,1 Main St,Austin,TX,78701,US$9.99/month,https://example.com/a\n";
+        let mailbox = csv::Reader::from_reader(csv.as_bytes()).deserialize::<RawRecord>().next().unwrap().unwrap().into_mailbox();
+        let record = Record::from_mailbox_and_info(mailbox, info, false);
+        assert_eq!((record.usps_cmra, record.usps_rdi), (Some(YesOrNo::Y), Some(Rdi::Commercial)));
+        assert_eq!((record.consensus_cmra, record.consensus_rdi), (Some(YesOrNo::N), Some(Rdi::Residential)));
+        assert_eq!(record.disagreement, Some(YesOrNo::Y));
+        assert_eq!(record.verified_at, "");
+        assert_eq!(format_timestamp(1717228800), "2024-06-01T08:00:00Z");
+    }
+
+    #[test]
+    fn test_csv_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mailboxes.csv");
+        let mut sink = CsvSink::create(&path).unwrap();
+        sink.append([Record::sample("https://example.com/a")]).unwrap();
+        // readable before the sink is closed
+        assert_eq!(Record::load_csv(&path).unwrap().len(), 1);
+        sink.append([Record::sample("https://example.com/b"), Record::sample("https://example.com/c")]).unwrap();
+        drop(sink);
+        let links = Record::load_csv(&path).unwrap().into_iter().map(|record| record.link).collect::<Vec<_>>();
+        assert_eq!(links, ["https://example.com/a", "https://example.com/b", "https://example.com/c"]);
+    }
+
+    #[test]
+    fn test_markdown() {
+        let records = [
+            Record { name: "A|B".to_string(), state: "TX".to_string(), ..Record::sample("https://example.com/a") },
+            Record { state: "AL".to_string(), ..Record::sample("https://example.com/b") },
+        ];
+        let (header, rows) = table_rows(&records).unwrap();
+        let markdown = markdown(&header, &rows, true);
+        assert!(markdown.starts_with("## Alabama (AL)\n\n| provider | name | street | city | state | zip | price | rdi | CMRA |\n"));
+        assert!(markdown.contains("\n## Texas (TX)\n"));
+        assert!(markdown.contains("| atmb | [A\\|B](https://example.com/a) | 123 Main St |"));
+    }
+
+    #[test]
+    fn test_geojson_feature() {
+        let record = serde_json::json!({
+            "name": "Birmingham", "street": "120 19th Street North", "price": "US$9.99/month",
+            "link": "/s/birmingham", "latitude": 33.5186, "longitude": -86.8104, "rdi": "Commercial", "CMRA": "N",
+        });
+        let feature = geojson_feature(&record).unwrap().unwrap();
+        assert_eq!(feature["geometry"]["coordinates"], serde_json::json!([-86.8104, 33.5186]));
+        assert_eq!(feature["properties"]["CMRA"], "N");
+        assert!(feature["properties"].get("street").is_none());
+
+        let record = serde_json::json!({ "name": "Birmingham", "latitude": null, "longitude": null });
+        assert!(geojson_feature(&record).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_international_record() {
+        use crate::verify::model::{InternationalStatus, VerifiedAddress};
+
+        let mailbox = Mailbox {
+            address: Address::from_lines_in(Country::Ca, "1 Yonge St", "Toronto, ON M5E1W7").unwrap(),
+            price: Price::parse("CA$ 19.99/month"),
+            raw_price: "CA$ 19.99/month".to_string(),
+            ..Mailbox::sample("/s/toronto")
+        };
+        let info = AdditionalInfo {
+            international: Some(InternationalStatus {
+                verification_status: "Verified".to_string(),
+                address_precision: "Premise".to_string(),
+            }),
+            verified: Some(VerifiedAddress {
+                street: "1 Yonge Street".to_string(),
+                city: "Toronto".to_string(),
+                state: "Ontario".to_string(),
+                zip: "M5E 1W7".to_string(),
+                ..Default::default()
+            }),
+            ..AdditionalInfo::sample()
+        };
+
+        let record = InternationalRecord::from_mailbox_and_info(mailbox.clone(), info.clone(), true);
+        assert_eq!((record.street.as_str(), record.state.as_str(), record.zip.as_str()), ("1 Yonge Street", "Ontario", "M5E 1W7"));
+        assert_eq!(record.country, Country::Ca);
+        assert_eq!((record.price_amount, record.price_currency.as_str()), (Some(19.99), "CAD"));
+        assert_eq!((record.verification_status.as_str(), record.address_precision.as_str()), ("Verified", "Premise"));
+
+        // the scraped address is kept, the verified postal code is still preferred
+        let record = InternationalRecord::from_mailbox_and_info(mailbox.clone(), info, false);
+        assert_eq!((record.street.as_str(), record.state.as_str(), record.zip.as_str()), ("1 Yonge St", "ON", "M5E 1W7"));
+
+        let record = InternationalRecord::from_mailbox_and_info(mailbox, AdditionalInfo::sample(), true);
+        assert_eq!((record.street.as_str(), record.zip.as_str()), ("1 Yonge St", "M5E 1W7"));
+        assert!(record.verification_status.is_empty());
     }
 }
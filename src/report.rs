@@ -0,0 +1,182 @@
+use crate::record::Record;
+use crate::verify::model::{Rdi, YesOrNo};
+
+/// placeholder in the template replaced with the locations
+const DATA_PLACEHOLDER: &str = "/*LOCATIONS*/[]";
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Non-CMRA residential mailboxes</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>html, body, #map { height: 100%; margin: 0; }</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+const locations = /*LOCATIONS*/[];
+const map = L.map('map').setView([39.8, -98.6], 4);
+L.tileLayer('https://{s}.tile.openstreetmap.org/{z}/{x}/{y}.png', {
+    maxZoom: 19,
+    attribution: '&copy; OpenStreetMap contributors',
+}).addTo(map);
+const escape = (s) => String(s).replace(/[&<>"']/g, (c) => ({'&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;'}[c]));
+for (const location of locations) {
+    L.marker([location.latitude, location.longitude])
+        .bindPopup(`<b>${escape(location.name)}</b><br>${escape(location.address)}<br>${escape(location.price)}<br><a href="${escape(location.link)}" target="_blank">Sign up</a>`)
+        .addTo(map);
+}
+</script>
+</body>
+</html>
+"#;
+
+/// placeholder in the table template replaced with the columns and rows
+const TABLE_PLACEHOLDER: &str = "/*TABLE*/{}";
+
+const TABLE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Mailboxes</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+input { width: 100%; max-width: 30em; padding: 0.4em; margin-bottom: 0.5em; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { border: 1px solid #ddd; padding: 0.3em 0.5em; white-space: nowrap; }
+th { background: #f3f3f3; cursor: pointer; position: sticky; top: 0; }
+th.asc::after { content: " \25B2"; }
+th.desc::after { content: " \25BC"; }
+tr:nth-child(even) td { background: #fafafa; }
+</style>
+</head>
+<body>
+<input id="search" type="search" placeholder="Search, i.e. TX residential" autofocus>
+<p id="count"></p>
+<table><thead><tr id="header"></tr></thead><tbody id="rows"></tbody></table>
+<script>
+const table = /*TABLE*/{};
+const header = document.getElementById('header');
+const body = document.getElementById('rows');
+const linkColumn = table.columns.indexOf('link');
+let sorted = { column: -1, desc: false };
+
+table.columns.forEach((column, idx) => {
+    const th = document.createElement('th');
+    th.textContent = column;
+    th.onclick = () => {
+        sorted = { column: idx, desc: sorted.column === idx && !sorted.desc };
+        const compare = (a, b) => {
+            const [x, y] = [a[idx], b[idx]];
+            const numeric = x !== '' && y !== '' && !isNaN(x) && !isNaN(y);
+            return numeric ? x - y : x.localeCompare(y);
+        };
+        table.rows.sort((a, b) => sorted.desc ? compare(b, a) : compare(a, b));
+        for (const other of header.children) other.className = '';
+        th.className = sorted.desc ? 'desc' : 'asc';
+        render();
+    };
+    header.appendChild(th);
+});
+
+function render() {
+    // every word must appear in the row
+    const words = document.getElementById('search').value.toLowerCase().split(/\s+/).filter((word) => word);
+    const rows = table.rows.filter((row) => {
+        const text = row.join(' ').toLowerCase();
+        return words.every((word) => text.includes(word));
+    });
+    body.replaceChildren(...rows.map((row) => {
+        const tr = document.createElement('tr');
+        row.forEach((value, idx) => {
+            const td = document.createElement('td');
+            if (idx === linkColumn && value) {
+                const a = document.createElement('a');
+                a.href = value;
+                a.target = '_blank';
+                a.textContent = 'link';
+                td.appendChild(a);
+            } else {
+                td.textContent = value;
+            }
+            tr.appendChild(td);
+        });
+        return tr;
+    }));
+    document.getElementById('count').textContent = `${rows.length} of ${table.rows.length} mailboxes`;
+}
+
+document.getElementById('search').oninput = render;
+render();
+</script>
+</body>
+</html>
+"#;
+
+/// Render a self-contained HTML page of the rows, searchable and sortable by clicking the headers.
+///
+/// Unlike the map, nothing is loaded from the network, the rows are embedded as JSON.
+pub fn render_table(header: &[String], rows: &[Vec<String>]) -> anyhow::Result<String> {
+    let table = serde_json::json!({
+        "columns": header,
+        "rows": rows,
+    });
+    let data = serde_json::to_string(&table)?.replace("</", "<\\/");
+    Ok(TABLE_TEMPLATE.replacen(TABLE_PLACEHOLDER, &data, 1))
+}
+
+/// Render a self-contained HTML map of the non-CMRA residential locations.
+///
+/// The locations are embedded as JSON, only Leaflet and the map tiles are loaded from the network.
+/// Records without coordinates are left out.
+pub fn render(records: &[Record]) -> anyhow::Result<String> {
+    let locations = records.iter()
+        .filter(|record| record.cmra == YesOrNo::N && record.rdi == Rdi::Residential)
+        .filter_map(|record| {
+            Some(serde_json::json!({
+                "name": record.name,
+                "address": format!("{}, {}, {} {}", record.street, record.city, record.state, record.zip),
+                "price": record.price,
+                "link": record.link,
+                "latitude": record.latitude?,
+                "longitude": record.longitude?,
+            }))
+        })
+        .collect::<Vec<_>>();
+    // `</script>` inside a string would end the script block
+    let data = serde_json::to_string(&locations)?.replace("</", "<\\/");
+    Ok(TEMPLATE.replacen(DATA_PLACEHOLDER, &data, 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let records = vec![
+            Record { name: "Home</script>".to_string(), ..Record::sample("https://example.com/a") },
+            Record { rdi: Rdi::Commercial, ..Record::sample("https://example.com/b") },
+            Record { latitude: None, longitude: None, ..Record::sample("https://example.com/c") },
+        ];
+        let html = render(&records).unwrap();
+        assert!(html.contains("https://example.com/a"));
+        assert!(!html.contains("https://example.com/b"));
+        assert!(!html.contains("https://example.com/c"));
+        assert!(html.contains(r#""name":"Home<\/script>""#));
+        assert!(!html.contains(DATA_PLACEHOLDER));
+    }
+
+    #[test]
+    fn test_render_table() {
+        let header = vec!["name".to_string(), "link".to_string()];
+        let rows = vec![vec!["Home</script>".to_string(), "https://example.com/a".to_string()]];
+        let html = render_table(&header, &rows).unwrap();
+        assert!(html.contains(r#"const table = {"columns":["name","link"],"rows":[["Home<\/script>","https://example.com/a"]]};"#));
+        assert!(!TABLE_TEMPLATE.chars().any(|c| c.is_control() && !c.is_ascii_whitespace()));
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail};
+
+/// how far ahead the next run is looked for, a schedule like `0 0 30 2 *` never fires
+const MAX_LOOKAHEAD_DAYS: u64 = 4 * 366;
+
+/// A cron-style schedule of `--watch`, i.e. `0 6 * * 1` for 06:00 every Monday, in UTC.
+///
+/// The five fields are the minute, hour, day of month, month and day of week (0 or 7 is Sunday),
+/// each one is `*`, a number, a range `1-5`, a list `1,15`, a step `*/15` or `1-30/2`.
+/// Like cron, a day matches either field if both the day of month and the day of week are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    /// the allowed values of every field as bits
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// whether the day of month or the day of week is `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// the first time after `time` the schedule fires, `None` if it never does
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let start = time.duration_since(UNIX_EPOCH).ok()?.as_secs() / 60 + 1;
+        let mut minute = start;
+        while minute < start + MAX_LOOKAHEAD_DAYS * 24 * 60 {
+            let days = minute / (24 * 60);
+            if !self.matches_day(days) {
+                minute = (days + 1) * 24 * 60;
+                continue;
+            }
+            let hour = minute / 60 % 24;
+            if !has(self.hours, hour) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if has(self.minutes, minute % 60) {
+                return Some(UNIX_EPOCH + Duration::from_secs(minute * 60));
+            }
+            minute += 1;
+        }
+        None
+    }
+
+    /// * `days` - since the unix epoch
+    fn matches_day(&self, days: u64) -> bool {
+        let (month, day) = month_and_day(days);
+        if !has(self.months, month) {
+            return false;
+        }
+        // 1970-01-01 is a Thursday
+        let weekday = (days + 4) % 7;
+        let day_matches = has(self.days, day);
+        let weekday_matches = has(self.weekdays, weekday);
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            bail!("invalid schedule `{}`, expected 5 fields: minute hour day-of-month month day-of-week", s);
+        };
+        let field = |field: &str, name: &str, min: u64, max: u64| {
+            parse_field(field, min, max).map_err(|e| anyhow!("invalid {} of the schedule `{}`: {}", name, s, e))
+        };
+        let mut weekdays_bits = field(weekdays, "day of week", 0, 7)?;
+        // 7 is Sunday as well
+        if has(weekdays_bits, 7) {
+            weekdays_bits |= 1;
+        }
+        Ok(
+            Self {
+                expression: fields.join(" "),
+                minutes: field(minutes, "minute", 0, 59)?,
+                hours: field(hours, "hour", 0, 23)?,
+                days: field(days, "day of month", 1, 31)?,
+                months: field(months, "month", 1, 12)?,
+                weekdays: weekdays_bits,
+                any_day: days == "*",
+                any_weekday: weekdays == "*",
+            }
+        )
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn has(bits: u64, value: u64) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// the allowed values of a field as bits, i.e. `1-5`, `*/15` or `0,30`
+fn parse_field(field: &str, min: u64, max: u64) -> anyhow::Result<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().map_err(|_| anyhow!("invalid step `{}`", step))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("the step cannot be 0");
+        }
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => {
+                let parse = |value: &str| value.parse::<u64>().map_err(|_| anyhow!("invalid value `{}`", value));
+                match range.split_once('-') {
+                    Some((from, to)) => (parse(from)?, parse(to)?),
+                    // `5/10` is every 10 from 5
+                    None if step > 1 => (parse(range)?, max),
+                    None => (parse(range)?, parse(range)?),
+                }
+            }
+        };
+        if from < min || to > max || from > to {
+            bail!("`{}` is out of {}-{}", part, min, max);
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// the month and the day of month of the days since the unix epoch
+fn month_and_day(days: u64) -> (u64, u64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    (if month < 10 { month + 3 } else { month - 9 }, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(rfc3339: &str) -> SystemTime {
+        humantime::parse_rfc3339(rfc3339).unwrap()
+    }
+
+    #[test]
+    fn test_next_run() {
+        // 2024-07-01 is a Monday
+        let weekly = "0 6 * * 1".parse::<Schedule>().unwrap();
+        assert_eq!(weekly.next_after(time("2024-06-30T12:00:00Z")), Some(time("2024-07-01T06:00:00Z")));
+        assert_eq!(weekly.next_after(time("2024-07-01T06:00:00Z")), Some(time("2024-07-08T06:00:00Z")));
+
+        let leap_day = "30 */12 29 2 *".parse::<Schedule>().unwrap();
+        assert_eq!(leap_day.next_after(time("2024-03-01T00:00:00Z")), Some(time("2028-02-29T00:30:00Z")));
+        assert_eq!("0 0 30 2 *".parse::<Schedule>().unwrap().next_after(time("2024-01-01T00:00:00Z")), None);
+
+        // either the 1st or a Sunday
+        let either = "0 0 1 * 7".parse::<Schedule>().unwrap();
+        assert_eq!(either.next_after(time("2024-07-01T00:00:00Z")), Some(time("2024-07-07T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        assert!("0 6 * *".parse::<Schedule>().is_err());
+        assert!("60 6 * * 1".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert!("0 6 5-1 * *".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::{anyhow, bail};
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, RawQuery, State};
+use axum::extract::rejection::BytesRejection;
+use axum::http::{HeaderValue, StatusCode};
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+use crate::atmb::model::{Address, Price};
+use crate::country::Country;
+use crate::metrics;
+use crate::record::Record;
+use crate::sqlite::SqliteSink;
+use crate::verify::AddressVerifier;
+use crate::verify::cache::CachedVerifier;
+use crate::verify::model::{RdiFilter, YesOrNo};
+
+/// the longest body of a request
+const MAX_BODY_LEN: usize = 64 * 1024;
+/// the response header telling how many lookups are left, if the verifier knows
+const REMAINING_QUOTA_HEADER: &str = "x-remaining-quota";
+/// the `POST /verify` requests waiting for the verifier
+const PENDING_LOOKUPS: usize = 16;
+
+/// The HTTP API of the `serve` subcommand, over the results of the latest run.
+///
+/// The results are read from the database of `--sink sqlite` on every request,
+/// so the ones written by `--watch` are served as soon as they're written.
+pub struct Server {
+    /// the database of `--sink sqlite`, i.e. `result/mailboxes.db`
+    results: PathBuf,
+    /// answers `POST /verify`, the lookups are cached like the ones of a run
+    verifier: Option<CachedVerifier>,
+}
+
+/// what the handlers share, the verifier itself stays with [`Server::serve`] as it's not `Send`
+#[derive(Clone)]
+struct AppState {
+    results: Arc<PathBuf>,
+    lookups: mpsc::Sender<Lookup>,
+}
+
+/// a `POST /verify` handed to the verifier, which replies with the response
+struct Lookup {
+    address: Address,
+    reply: oneshot::Sender<Response>,
+}
+
+impl Server {
+    pub fn new(results: impl Into<PathBuf>) -> Self {
+        Self {
+            results: results.into(),
+            verifier: None,
+        }
+    }
+
+    /// verify the addresses of `POST /verify` with the verifier
+    pub fn with_verifier(mut self, verifier: CachedVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// serve the requests until the process is stopped
+    pub async fn serve(self, addr: SocketAddr) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr).await
+            .map_err(|e| anyhow!("cannot listen on [{}]: {}", addr, e))?;
+        info!("serving [{}] on http://{}", self.results.display(), addr);
+        let (lookups, pending) = mpsc::channel(PENDING_LOOKUPS);
+        let app = router(AppState { results: Arc::new(self.results), lookups });
+        tokio::select! {
+            result = axum::serve(listener, app).into_future() => Ok(result?),
+            _ = answer_lookups(self.verifier, pending) => Ok(()),
+        }
+    }
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/mailboxes", get(mailboxes).fallback(|| async { error(StatusCode::METHOD_NOT_ALLOWED, "only GET is allowed") }))
+        .route("/verify", post(verify).fallback(|| async { error(StatusCode::METHOD_NOT_ALLOWED, "only POST is allowed") }))
+        .route("/metrics", get(|| async { ([(CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render()).into_response() }))
+        .fallback(|uri: axum::http::Uri| async move { error(StatusCode::NOT_FOUND, &format!("no such endpoint: {}", uri.path())) })
+        .layer(DefaultBodyLimit::max(MAX_BODY_LEN))
+        .with_state(state)
+}
+
+/// `GET /mailboxes?state=TX,FL&rdi=residential&max_price=9.99&cmra=n`
+async fn mailboxes(State(state): State<AppState>, RawQuery(query): RawQuery) -> Response {
+    let filter = match MailboxFilter::parse(query.as_deref().unwrap_or_default()) {
+        Ok(filter) => filter,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+    };
+    let records = match SqliteSink::open_read_only(state.results.as_path()).and_then(|sink| sink.latest::<Record>()) {
+        Ok(records) => records,
+        Err(e) => {
+            error!("cannot read the results [{}]: {:?}", state.results.display(), e);
+            return error(StatusCode::SERVICE_UNAVAILABLE, "the results are not available yet, they're written by a run with `--sink sqlite`");
+        }
+    };
+    let records = records.iter().filter(|record| filter.matches(record)).collect::<Vec<_>>();
+    json_response(StatusCode::OK, &records)
+}
+
+/// `POST /verify` of `{"address": "156 S Main St, Lumberton, TX 77657"}`,
+/// or `{"line1": "156 S Main St", "line2": "Lumberton, TX 77657"}`
+async fn verify(State(state): State<AppState>, body: Result<Bytes, BytesRejection>) -> Response {
+    let address = match body.map_err(|e| anyhow!("cannot read the request: {}", e)).and_then(|body| parse_verify_request(&body)) {
+        Ok(address) => address,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("{:#}", e)),
+    };
+    let (reply, response) = oneshot::channel();
+    if state.lookups.send(Lookup { address, reply }).await.is_err() {
+        return error(StatusCode::SERVICE_UNAVAILABLE, "the verifier has stopped");
+    }
+    response.await.unwrap_or_else(|_| error(StatusCode::SERVICE_UNAVAILABLE, "the verifier has stopped"))
+}
+
+fn parse_verify_request(body: &[u8]) -> anyhow::Result<Address> {
+    let address = match serde_json::from_slice::<VerifyRequest>(body)? {
+        VerifyRequest::Address { address } => Address::parse(&address)?,
+        VerifyRequest::Lines { line1, line2 } => Address::from_lines(line1, &line2)?,
+    };
+    if address.country != Country::Us {
+        bail!("only the addresses in the US can be verified");
+    }
+    Ok(address)
+}
+
+/// verify the addresses of `POST /verify` one by one, until the server is stopped
+async fn answer_lookups(verifier: Option<CachedVerifier>, mut pending: mpsc::Receiver<Lookup>) {
+    while let Some(Lookup { address, reply }) = pending.recv().await {
+        let response = match &verifier {
+            Some(verifier) => lookup(verifier, &address).await,
+            None => error(StatusCode::SERVICE_UNAVAILABLE, "no verifier is configured"),
+        };
+        // the client may have gone
+        let _ = reply.send(response);
+    }
+}
+
+async fn lookup(verifier: &CachedVerifier, address: &Address) -> Response {
+    if verifier.remaining_quota() == Some(0) && verifier.pending([address]) > 0 {
+        return error(StatusCode::TOO_MANY_REQUESTS, "the lookups of the verifier are used up");
+    }
+    let info = verifier.inquire(address).await;
+    if let Err(e) = verifier.save_cache() {
+        warn!("cannot save the cached lookups: {:?}", e);
+    }
+    let mut response = match info {
+        Ok(info) => json_response(StatusCode::OK, &info),
+        Err(e) => {
+            error!("cannot verify [{}]: {:?}", address.line1, e);
+            error(StatusCode::BAD_GATEWAY, &format!("the verifier failed: {:#}", e))
+        }
+    };
+    if let Some(remaining) = verifier.remaining_quota() {
+        response.headers_mut().insert(REMAINING_QUOTA_HEADER, HeaderValue::from(remaining));
+    }
+    response
+}
+
+/// the body of `POST /verify`, the address in one line or two
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum VerifyRequest {
+    Address { address: String },
+    Lines { line1: String, line2: String },
+}
+
+/// the query parameters of `/mailboxes`, every one is optional
+#[derive(Debug, Default, PartialEq)]
+struct MailboxFilter {
+    /// the state codes
+    states: Option<Vec<String>>,
+    rdi: RdiFilter,
+    /// in cents per month, the yearly prices are compared by their monthly amount like `--max-price`
+    max_price: Option<u64>,
+    /// whether the locations should be CMRA
+    cmra: Option<bool>,
+}
+
+impl MailboxFilter {
+    fn parse(query: &str) -> anyhow::Result<Self> {
+        let url = Url::parse(&format!("http://localhost/?{}", query))?;
+        let mut filter = Self::default();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "state" => filter.states = Some(value.split(',').map(|state| state.trim().to_uppercase()).collect()),
+                "rdi" => filter.rdi = RdiFilter::from_str(&value)?,
+                "max_price" => filter.max_price = Some(
+                    value.parse::<f64>().ok()
+                        .filter(|price| *price >= 0.0)
+                        .map(|price| (price * 100.0).round() as u64)
+                        .ok_or_else(|| anyhow!("invalid max_price: {}", value))?
+                ),
+                "cmra" => filter.cmra = Some(match value.to_lowercase().as_str() {
+                    "y" | "yes" | "true" => true,
+                    "n" | "no" | "false" => false,
+                    _ => bail!("invalid cmra: {}, expected y or n", value),
+                }),
+                _ => bail!("unknown query parameter: {}, expected one of: state, rdi, max_price, cmra", key),
+            }
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        self.states.as_ref().is_none_or(|states| states.iter().any(|state| state.eq_ignore_ascii_case(&record.state)))
+            && self.rdi.matches(&record.rdi)
+            && self.max_price.is_none_or(|max_price| Price::parse(&record.price).is_some_and(|price| price.monthly() <= max_price))
+            && self.cmra.is_none_or(|cmra| cmra == (record.cmra == YesOrNo::Y))
+    }
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> Response {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    (status, [(CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    json_response(status, &json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mailbox_filter() {
+        let filter = MailboxFilter::parse("state=tx,FL&rdi=residential&max_price=10&cmra=n").unwrap();
+        let record = Record { state: "TX".to_string(), price_amount: Some(9.99), ..Record::sample("a") };
+        assert!(filter.matches(&record));
+        assert!(!filter.matches(&Record { state: "CA".to_string(), ..record.clone() }));
+        assert!(!filter.matches(&Record { price: String::new(), ..record.clone() }));
+        // `US$99.99/year` is about 8.33 a month
+        assert!(filter.matches(&Record { price: "US$99.99/year".to_string(), ..record.clone() }));
+        assert!(!filter.matches(&Record { price: "US$149.99/year".to_string(), ..record.clone() }));
+        assert!(!filter.matches(&Record { price: "US$10.99/month".to_string(), ..record.clone() }));
+        assert!(!filter.matches(&Record { cmra: YesOrNo::Y, ..record.clone() }));
+        assert!(MailboxFilter::parse("").unwrap().matches(&Record { cmra: YesOrNo::Y, ..record }));
+
+        assert!(MailboxFilter::parse("max_price=-1").is_err());
+        assert!(MailboxFilter::parse("zip=77657").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mailboxes_from_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let (lookups, _pending) = mpsc::channel(1);
+        let state = AppState { results: Arc::new(dir.path().join("mailboxes.db")), lookups };
+        let get = |query: &str| mailboxes(State(state.clone()), RawQuery(Some(query.to_string())));
+
+        // nothing has been written with `--sink sqlite` yet
+        assert_eq!(get("").await.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let records = [
+            Record { state: "TX".to_string(), ..Record::sample("/s/a") },
+            Record { state: "CA".to_string(), ..Record::sample("/s/b") },
+        ];
+        SqliteSink::open(state.results.as_path()).unwrap().write(&records).unwrap();
+        let response = get("state=tx").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), MAX_BODY_LEN).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Vec<Record>>(&body).unwrap(), records[..1]);
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt::{Display, Formatter};
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// the stage the results are written in, the `--timeout` doesn't cut it off
+pub const SAVE_STAGE: &str = "save";
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// the stage of the run, see [`enter_stage`]
+static STAGE: Mutex<&str> = Mutex::new("startup");
+/// the stage the `--timeout` has passed in
+static TIMED_OUT: OnceLock<&str> = OnceLock::new();
+
+fn notify() -> &'static Notify {
+    static NOTIFY: OnceLock<Notify> = OnceLock::new();
+    NOTIFY.get_or_init(Notify::new)
+}
+
+/// notified on every [`enter_stage`]
+fn stage_changed() -> &'static Notify {
+    static STAGE_CHANGED: OnceLock<Notify> = OnceLock::new();
+    STAGE_CHANGED.get_or_init(Notify::new)
+}
+
+fn stage() -> &'static str {
+    *STAGE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Stop the run gracefully on the first Ctrl-C, the in-flight requests are cancelled and
+/// the completed work is saved. The second Ctrl-C exits at once.
+pub fn listen() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("interrupted, saving what has been done, press Ctrl-C again to exit now");
+        interrupt();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Stop the run gracefully like the first Ctrl-C once `timeout` has passed, see `--timeout`.
+///
+/// the results being saved are not cut off, a timeout in the [`SAVE_STAGE`] is held back until the
+/// run enters another stage, so a run ending there is not reported as timed out.
+pub fn deadline(timeout: Duration) {
+    tokio::spawn(async move {
+        tokio::time::sleep(timeout).await;
+        let stage = loop {
+            // registered before the check, so a stage entered in between is not missed
+            let changed = stage_changed().notified();
+            match stage() {
+                SAVE_STAGE => changed.await,
+                stage => break stage,
+            }
+        };
+        if is_interrupted() {
+            return;
+        }
+        warn!("the run has exceeded the timeout of {} in the [{}] stage, saving what has been done", humantime::format_duration(timeout), stage);
+        let _ = TIMED_OUT.set(stage);
+        interrupt();
+    });
+}
+
+fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Release);
+    notify().notify_waiters();
+}
+
+/// record the stage the run is in, so a timeout tells where it's stuck
+pub fn enter_stage(stage: &'static str) {
+    *STAGE.lock().unwrap_or_else(|e| e.into_inner()) = stage;
+    stage_changed().notify_waiters();
+}
+
+/// the stage the run has timed out in, `None` if it hasn't
+pub fn timed_out() -> Option<&'static str> {
+    TIMED_OUT.get().copied()
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Acquire)
+}
+
+/// resolves once the run is interrupted
+pub async fn interrupted() {
+    loop {
+        // registered before the check, so an interruption in between is not missed
+        let notified = notify().notified();
+        if is_interrupted() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// The run stopped on Ctrl-C or `--timeout`, what has been done is saved.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match timed_out() {
+            Some(stage) => write!(f, "the run has timed out in the [{}] stage", stage),
+            None => f.write_str("the run is interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::atmb::model::Mailbox;
+use crate::verify::model::AdditionalInfo;
+
+/// The verified mailboxes of the previous runs, so `--incremental` only crawls and verifies what has changed.
+///
+/// Every run adds its mailboxes, the ones of the states not crawled this time are kept.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// keyed by the link
+    verified: HashMap<String, (Mailbox, AdditionalInfo)>,
+}
+
+impl Snapshot {
+    /// load the snapshot, an empty one if no run has saved it yet
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.verified.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.verified.is_empty()
+    }
+
+    /// add the verified mailboxes of this run, replacing the previous ones of the same link
+    pub fn extend<'a>(&mut self, verified: impl IntoIterator<Item = (&'a Mailbox, &'a AdditionalInfo)>) {
+        self.verified.extend(
+            verified.into_iter().map(|(mailbox, info)| (mailbox.link.clone(), (mailbox.clone(), info.clone())))
+        );
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// the mailboxes of the previous run, to compare the listings of the crawl with
+    pub fn mailboxes(&self) -> impl Iterator<Item = &Mailbox> {
+        self.verified.values().map(|(mailbox, _)| mailbox)
+    }
+
+    /// the mailbox and its verification result of the previous runs
+    pub fn get(&self, link: &str) -> Option<&(Mailbox, AdditionalInfo)> {
+        self.verified.get(link)
+    }
+
+    /// forget the mailboxes that are not listed anymore
+    pub fn remove<'a>(&mut self, links: impl IntoIterator<Item = &'a str>) {
+        for link in links {
+            self.verified.remove(link);
+        }
+    }
+
+    /// the verification result of the mailbox, if it's the same as in the previous run
+    pub fn unchanged(&self, mailbox: &Mailbox) -> Option<&AdditionalInfo> {
+        self.verified.get(&mailbox.link)
+            .filter(|(previous, _)| previous == mailbox)
+            .map(|(_, info)| info)
+    }
+}
@@ -0,0 +1,307 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail};
+use rusqlite::{params_from_iter, Connection, OpenFlags, Transaction};
+use rusqlite::types::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+/// the table the records are written to
+const TABLE: &str = "mailboxes";
+/// the records of the run in progress, as they're verified
+const STAGING_TABLE: &str = "verifying";
+
+/// sinks the records are written to besides the result files, selectable via `--sink`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    /// a SQLite database, see [`SqliteSink`]
+    Sqlite,
+}
+
+impl FromStr for Sink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sqlite" => Ok(Sink::Sqlite),
+            _ => Err(anyhow!("unknown sink: {}, expected one of: sqlite", s)),
+        }
+    }
+}
+
+/// Keeps the records of every run in the `mailboxes` table of a SQLite database.
+///
+/// A row is keyed by `(provider, link)`, so a run updates the rows of the locations it has seen
+/// and adds the new ones. `first_seen` and `last_seen` are the unix times of the runs the location
+/// is first and last seen in, the delisted ones are kept with an older `last_seen`.
+/// The other columns are the fields of the records, the ones added by a newer version are added to
+/// the table on the next write.
+///
+/// The records of a run in progress are kept in the `verifying` table until its results are written,
+/// so the ones verified before a crash are not lost.
+pub struct SqliteSink {
+    conn: Connection,
+}
+
+impl SqliteSink {
+    /// open the database, it's created along with its directory if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow!("cannot open the database [{}]: {}", path.display(), e))?;
+        Self::with_connection(conn)
+    }
+
+    /// open the database for reading, it's not created if it doesn't exist
+    pub fn open_read_only(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| anyhow!("cannot open the database [{}]: {}", path.display(), e))?;
+        Ok(Self { conn })
+    }
+
+    fn with_connection(conn: Connection) -> anyhow::Result<Self> {
+        for table in [TABLE, STAGING_TABLE] {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {table} (
+                    provider TEXT NOT NULL,
+                    link TEXT NOT NULL,
+                    first_seen INTEGER NOT NULL,
+                    last_seen INTEGER NOT NULL,
+                    PRIMARY KEY (provider, link)
+                )"
+            ))?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// insert the records, or update the ones written before, returns the number of rows written
+    ///
+    /// the records staged by the run are dropped, as its results are written
+    pub fn write<T: Serialize>(&mut self, records: &[T]) -> anyhow::Result<usize> {
+        self.write_at(records, now()?)
+    }
+
+    /// keep the records verified so far in the `verifying` table, until the results are written
+    pub fn stage<T: Serialize>(&mut self, records: &[T]) -> anyhow::Result<usize> {
+        let tx = self.conn.transaction()?;
+        let written = upsert(&tx, STAGING_TABLE, records, now()?)?;
+        tx.commit()?;
+        Ok(written)
+    }
+
+    fn write_at<T: Serialize>(&mut self, records: &[T], seen_at: i64) -> anyhow::Result<usize> {
+        let tx = self.conn.transaction()?;
+        let written = upsert(&tx, TABLE, records, seen_at)?;
+        tx.execute(&format!("DELETE FROM {STAGING_TABLE}"), ())?;
+        tx.commit()?;
+        Ok(written)
+    }
+
+    /// the records seen in the latest run, the delisted ones are left out
+    pub fn latest<T: DeserializeOwned>(&self) -> anyhow::Result<Vec<T>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT * FROM {TABLE} WHERE last_seen = (SELECT max(last_seen) FROM {TABLE}) ORDER BY provider, link"
+        ))?;
+        let columns = stmt.column_names().into_iter().map(String::from).collect::<Vec<_>>();
+        let rows = stmt.query_map((), |row| {
+            let mut fields = serde_json::Map::new();
+            for (idx, column) in columns.iter().enumerate() {
+                let value = match row.get::<_, Value>(idx)? {
+                    // the columns added after the row was written are null, the fields take their defaults
+                    Value::Null | Value::Blob(_) => continue,
+                    Value::Integer(n) => n.into(),
+                    Value::Real(n) => n.into(),
+                    Value::Text(s) => s.into(),
+                };
+                fields.insert(column.clone(), value);
+            }
+            Ok(fields)
+        })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|fields| serde_json::from_value(JsonValue::Object(fields)).map_err(|e| anyhow!("cannot read the row: {}", e)))
+            .collect()
+    }
+}
+
+/// insert the records into the table, or update the ones written before
+fn upsert<T: Serialize>(tx: &Transaction, table: &str, records: &[T], seen_at: i64) -> anyhow::Result<usize> {
+    let rows = records.iter()
+        .map(|record| match serde_json::to_value(record)? {
+            JsonValue::Object(fields) => Ok(fields),
+            _ => bail!("the records must be structs"),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let Some(first) = rows.first() else {
+        return Ok(0);
+    };
+    let columns = first.keys().cloned().collect::<Vec<_>>();
+    if !columns.iter().any(|column| column == "provider") || !columns.iter().any(|column| column == "link") {
+        bail!("the records must have the `provider` and `link` fields");
+    }
+
+    let existing = table_columns(tx, table)?;
+    for column in columns.iter().filter(|column| !existing.contains(column)) {
+        let column_type = rows.iter()
+            .map(|row| row.get(column).map_or("", column_type))
+            .find(|column_type| !column_type.is_empty())
+            .unwrap_or("TEXT");
+        tx.execute(&format!("ALTER TABLE {table} ADD COLUMN {} {}", quote(column), column_type), ())?;
+    }
+
+    let names = columns.iter().map(|column| quote(column)).collect::<Vec<_>>();
+    let updates = names.iter()
+        .filter(|name| !matches!(name.as_str(), r#""provider""# | r#""link""#))
+        .map(|name| format!("{name} = excluded.{name}"))
+        .collect::<Vec<_>>();
+    let sql = format!(
+        "INSERT INTO {table} ({}, first_seen, last_seen) VALUES ({}?, ?) \
+         ON CONFLICT (provider, link) DO UPDATE SET {}last_seen = excluded.last_seen",
+        names.join(", "),
+        "?, ".repeat(names.len()),
+        updates.iter().map(|update| format!("{update}, ")).collect::<String>(),
+    );
+    let mut stmt = tx.prepare(&sql)?;
+    for row in &rows {
+        let values = columns.iter()
+            .map(|column| to_sql_value(row.get(column).unwrap_or(&JsonValue::Null)))
+            .chain([Value::Integer(seen_at), Value::Integer(seen_at)]);
+        stmt.execute(params_from_iter(values))?;
+    }
+    Ok(rows.len())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> anyhow::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt.query_map((), |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+/// the unix time, in seconds
+fn now() -> anyhow::Result<i64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+}
+
+/// the type of the column holding the value, empty if it cannot be told from a `null`
+fn column_type(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "",
+        JsonValue::Bool(_) => "INTEGER",
+        JsonValue::Number(number) if number.is_f64() => "REAL",
+        JsonValue::Number(_) => "INTEGER",
+        _ => "TEXT",
+    }
+}
+
+fn to_sql_value(value: &JsonValue) -> Value {
+    match value {
+        JsonValue::Null => Value::Null,
+        JsonValue::Bool(b) => Value::Integer(*b as i64),
+        JsonValue::Number(number) => match number.as_i64() {
+            Some(n) => Value::Integer(n),
+            None => Value::Real(number.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(s) => Value::Text(s.clone()),
+        // nested fields are kept as JSON
+        other => Value::Text(other.to_string()),
+    }
+}
+
+/// quote the name of a column, i.e. `CMRA`
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::Record;
+    use crate::verify::model::YesOrNo;
+
+    #[derive(Serialize)]
+    struct Row {
+        provider: &'static str,
+        link: &'static str,
+        price: &'static str,
+        #[serde(rename = "CMRA")]
+        cmra: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct NewerRow {
+        provider: &'static str,
+        link: &'static str,
+        price: &'static str,
+        #[serde(rename = "CMRA")]
+        cmra: &'static str,
+        latitude: Option<f64>,
+    }
+
+    #[test]
+    fn test_sqlite_sink() {
+        let mut sink = SqliteSink::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let rows = [
+            Row { provider: "atmb", link: "/s/a", price: "US$9.99/month", cmra: "N" },
+            Row { provider: "atmb", link: "/s/b", price: "US$19.99/month", cmra: "N" },
+            // the same link of another provider is another location
+            Row { provider: "ipostal1", link: "/s/a", price: "$9.99", cmra: "N" },
+        ];
+        assert_eq!(sink.write_at(&rows, 100).unwrap(), 3);
+
+        // the next run updates the seen ones, and adds a column for the new field
+        let rows = [NewerRow { provider: "atmb", link: "/s/a", price: "US$14.99/month", cmra: "N", latitude: Some(33.5) }];
+        sink.write_at(&rows, 200).unwrap();
+
+        let mut stmt = sink.conn.prepare(r#"SELECT provider, link, price, "CMRA", latitude, first_seen, last_seen FROM mailboxes ORDER BY provider, link"#).unwrap();
+        let rows = stmt.query_map((), |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<f64>>(4)?,
+            row.get::<_, i64>(5)?,
+            row.get::<_, i64>(6)?,
+        )))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows, [
+            ("atmb".to_string(), "/s/a".to_string(), "US$14.99/month".to_string(), "N".to_string(), Some(33.5), 100, 200),
+            ("atmb".to_string(), "/s/b".to_string(), "US$19.99/month".to_string(), "N".to_string(), None, 100, 100),
+            ("ipostal1".to_string(), "/s/a".to_string(), "$9.99".to_string(), "N".to_string(), None, 100, 100),
+        ]);
+    }
+
+    #[test]
+    fn test_latest_records() {
+        let mut sink = SqliteSink::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        sink.write_at(&[Record::sample("/s/a"), Record::sample("/s/b")], 100).unwrap();
+        let record = Record { cmra: YesOrNo::Y, latitude: None, ..Record::sample("/s/a") };
+        sink.write_at(std::slice::from_ref(&record), 200).unwrap();
+
+        // `/s/b` is not listed in the latest run
+        assert_eq!(sink.latest::<Record>().unwrap(), [record]);
+    }
+
+    #[test]
+    fn test_staged_records() {
+        let mut sink = SqliteSink::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let count = |sink: &SqliteSink, table: &str| sink.conn
+            .query_row(&format!("SELECT count(*) FROM {table}"), (), |row| row.get::<_, usize>(0))
+            .unwrap();
+        sink.stage(&[Record::sample("/s/a")]).unwrap();
+        sink.stage(&[Record::sample("/s/b")]).unwrap();
+        assert_eq!((count(&sink, TABLE), count(&sink, STAGING_TABLE)), (0, 2));
+
+        // they're dropped once the results are written
+        sink.write(&[Record::sample("/s/a")]).unwrap();
+        assert_eq!((count(&sink, TABLE), count(&sink, STAGING_TABLE)), (1, 0));
+    }
+}
@@ -0,0 +1,104 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+
+/// the states, DC and Puerto Rico, as `(code, name)`
+const US_STATES: [(&str, &str); 52] = [
+    ("AL", "Alabama"), ("AK", "Alaska"), ("AZ", "Arizona"), ("AR", "Arkansas"), ("CA", "California"),
+    ("CO", "Colorado"), ("CT", "Connecticut"), ("DE", "Delaware"), ("DC", "District of Columbia"), ("FL", "Florida"),
+    ("GA", "Georgia"), ("HI", "Hawaii"), ("ID", "Idaho"), ("IL", "Illinois"), ("IN", "Indiana"),
+    ("IA", "Iowa"), ("KS", "Kansas"), ("KY", "Kentucky"), ("LA", "Louisiana"), ("ME", "Maine"),
+    ("MD", "Maryland"), ("MA", "Massachusetts"), ("MI", "Michigan"), ("MN", "Minnesota"), ("MS", "Mississippi"),
+    ("MO", "Missouri"), ("MT", "Montana"), ("NE", "Nebraska"), ("NV", "Nevada"), ("NH", "New Hampshire"),
+    ("NJ", "New Jersey"), ("NM", "New Mexico"), ("NY", "New York"), ("NC", "North Carolina"), ("ND", "North Dakota"),
+    ("OH", "Ohio"), ("OK", "Oklahoma"), ("OR", "Oregon"), ("PA", "Pennsylvania"), ("PR", "Puerto Rico"),
+    ("RI", "Rhode Island"), ("SC", "South Carolina"), ("SD", "South Dakota"), ("TN", "Tennessee"), ("TX", "Texas"),
+    ("UT", "Utah"), ("VT", "Vermont"), ("VA", "Virginia"), ("WA", "Washington"), ("WV", "West Virginia"),
+    ("WI", "Wisconsin"), ("WY", "Wyoming"),
+];
+
+/// the Canadian provinces and territories, as `(code, name)`
+const CA_PROVINCES: [(&str, &str); 13] = [
+    ("AB", "Alberta"), ("BC", "British Columbia"), ("MB", "Manitoba"), ("NB", "New Brunswick"),
+    ("NL", "Newfoundland and Labrador"), ("NS", "Nova Scotia"), ("NT", "Northwest Territories"), ("NU", "Nunavut"),
+    ("ON", "Ontario"), ("PE", "Prince Edward Island"), ("QC", "Quebec"), ("SK", "Saskatchewan"), ("YT", "Yukon"),
+];
+
+/// code of the Canadian province or territory, given by its code or name, i.e. `ON` for `Ontario`
+pub fn province_code(province: &str) -> Option<&'static str> {
+    let province = province.trim();
+    CA_PROVINCES.iter()
+        .find(|(code, name)| province.eq_ignore_ascii_case(code) || province.eq_ignore_ascii_case(name))
+        .map(|&(code, _)| code)
+}
+
+/// the Australian states and territories, as `(code, name)`
+const AU_STATES: [(&str, &str); 8] = [
+    ("ACT", "Australian Capital Territory"), ("NSW", "New South Wales"), ("NT", "Northern Territory"), ("QLD", "Queensland"),
+    ("SA", "South Australia"), ("TAS", "Tasmania"), ("VIC", "Victoria"), ("WA", "Western Australia"),
+];
+
+/// the nations of the UK, they're sometimes written in place of a county
+const UK_NATIONS: [&str; 4] = ["England", "Scotland", "Wales", "Northern Ireland"];
+
+/// code of the Australian state or territory, given by its code or name, i.e. `NSW` for `New South Wales`
+pub fn au_state_code(state: &str) -> Option<&'static str> {
+    let state = state.trim();
+    AU_STATES.iter()
+        .find(|(code, name)| state.eq_ignore_ascii_case(code) || state.eq_ignore_ascii_case(name))
+        .map(|&(code, _)| code)
+}
+
+/// the UK nation, i.e. `England`
+pub fn uk_nation(nation: &str) -> Option<&'static str> {
+    UK_NATIONS.into_iter().find(|name| nation.trim().eq_ignore_ascii_case(name))
+}
+
+/// A US state, selectable via `--states` by its code or name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsState {
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+impl UsState {
+    /// whether `state` is the code or the name of this state, i.e. `TX` or `Texas`
+    pub fn is(&self, state: &str) -> bool {
+        let state = state.trim();
+        state.eq_ignore_ascii_case(self.code) || state.eq_ignore_ascii_case(self.name)
+    }
+}
+
+/// whether the state, given by its code or name, is one of the selected ones, all of them are if `None`
+pub fn is_selected(selected: Option<&[UsState]>, state: &str) -> bool {
+    selected.is_none_or(|selected| selected.iter().any(|selected| selected.is(state)))
+}
+
+impl FromStr for UsState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        US_STATES.iter()
+            .map(|&(code, name)| UsState { code, name })
+            .find(|state| state.is(s))
+            .ok_or_else(|| anyhow!("unknown state: {}, expected a code like TX or a name like Texas", s))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_state() {
+        let texas = "tx".parse::<UsState>().unwrap();
+        assert_eq!(texas, UsState { code: "TX", name: "Texas" });
+        assert_eq!("New York".parse::<UsState>().unwrap().code, "NY");
+        assert!(texas.is("TEXAS"));
+        assert!(!texas.is("TN"));
+        assert!("Ontario".parse::<UsState>().is_err());
+        assert_eq!(province_code("ontario"), Some("ON"));
+        assert_eq!(province_code("QC"), Some("QC"));
+        assert_eq!(province_code("Texas"), None);
+        assert_eq!(au_state_code("new south wales"), Some("NSW"));
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+use serde::Serialize;
+use crate::atmb::model::Mailbox;
+use crate::country::Country;
+use crate::progress;
+use crate::verify::model::{AdditionalInfo, Rdi};
+
+/// counts of the mailboxes in a state
+#[derive(Debug, Default, Serialize)]
+pub struct StateStats {
+    pub mailboxes: usize,
+    pub cmra: usize,
+    pub non_cmra: usize,
+}
+
+/// wall-clock time of a stage
+#[derive(Debug, Serialize)]
+pub struct StageTime {
+    pub stage: &'static str,
+    pub seconds: f64,
+}
+
+/// Statistics of a run, printed at the end and written to `result/summary.json` with `--summary`.
+#[derive(Debug, Default, Serialize)]
+pub struct RunStats {
+    pub mailboxes: usize,
+    /// detail pages that cannot be fetched
+    pub fetch_failures: usize,
+    /// mailboxes whose address is malformed, they're not verified
+    pub parse_errors: usize,
+    pub verified: usize,
+    /// listings sharing the address with a cheaper one
+    pub duplicates: usize,
+    pub cmra: usize,
+    pub non_cmra: usize,
+    /// RDI of the non-CMRA mailboxes
+    pub rdi: BTreeMap<&'static str, usize>,
+    pub states: BTreeMap<String, StateStats>,
+    /// lookups consumed in this run, keyed by the credential
+    pub lookups: BTreeMap<String, usize>,
+    pub stages: Vec<StageTime>,
+}
+
+impl RunStats {
+    /// count the crawled or loaded mailboxes
+    pub fn record_mailboxes(&mut self, mailboxes: &[Mailbox]) {
+        self.mailboxes = mailboxes.len();
+        for mailbox in mailboxes {
+            self.states.entry(mailbox.address.state.clone()).or_default().mailboxes += 1;
+        }
+    }
+
+    /// count a verified mailbox
+    pub fn record_info(&mut self, mailbox: &Mailbox, info: &AdditionalInfo) {
+        self.verified += 1;
+        // neither CMRA nor RDI is known outside the US
+        if mailbox.address.country != Country::Us {
+            return;
+        }
+        let state = self.states.entry(mailbox.address.state.clone()).or_default();
+        if info.is_cmra() {
+            self.cmra += 1;
+            state.cmra += 1;
+        } else {
+            self.non_cmra += 1;
+            state.non_cmra += 1;
+            let rdi = match info.rdi {
+                Rdi::Residential => "residential",
+                Rdi::Commercial => "commercial",
+                Rdi::Unknown => "unknown",
+            };
+            *self.rdi.entry(rdi).or_default() += 1;
+        }
+    }
+
+    /// record the time elapsed since the stage started
+    pub fn finish_stage(&mut self, stage: &'static str, start: Instant) {
+        self.stages.push(StageTime { stage, seconds: start.elapsed().as_secs_f64() });
+    }
+
+    pub fn table(&self) -> String {
+        let mut rows = vec![
+            ("mailboxes".to_string(), self.mailboxes),
+            ("failed to fetch".to_string(), self.fetch_failures),
+            ("malformed address".to_string(), self.parse_errors),
+        ];
+        if self.verified > 0 {
+            rows.extend([
+                ("verified".to_string(), self.verified),
+                ("failed to verify".to_string(), self.mailboxes.saturating_sub(self.verified)),
+                ("duplicate listings".to_string(), self.duplicates),
+                ("CMRA".to_string(), self.cmra),
+                ("non-CMRA".to_string(), self.non_cmra),
+            ]);
+            rows.extend(self.rdi.iter().map(|(rdi, count)| (format!("non-CMRA {rdi}"), *count)));
+        }
+        rows.extend(self.lookups.iter().map(|(credential, count)| (format!("lookups of [{credential}]"), *count)));
+        let rows = rows.iter().map(|(name, count)| (name.as_str(), *count)).collect::<Vec<_>>();
+
+        let mut table = progress::summary_table(&rows);
+        for stage in &self.stages {
+            table.push_str(&format!("{}: {:.1}s\n", stage.stage, stage.seconds));
+        }
+        table
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+use rand::Rng;
+use reqwest::StatusCode;
+use tracing::warn;
+use crate::error::Error;
+use crate::metrics::{self, Counter};
+
+/// attempts of a request before giving up, including the first one
+const MAX_ATTEMPTS: u32 = 4;
+/// delay before the first retry of a network error, doubled on every retry
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+/// delay before the first retry when the server is overloaded, quadrupled on every retry
+const THROTTLED_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// the longest the server can ask us to wait via `Retry-After`
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+/// up to this fraction of the delay is added at random, so the concurrent requests failed together don't retry together
+const MAX_JITTER: f64 = 0.5;
+
+/// tell the timeouts apart from the other network errors, so a hung connection is obvious in the logs
+///
+/// the context is only added on top, [`RetryPolicy`] still sees the [`reqwest::Error`] beneath.
+pub fn label_timeout(error: reqwest::Error) -> anyhow::Error {
+    if !error.is_timeout() {
+        return error.into();
+    }
+    let url = error.url().map(|url| url.to_string()).unwrap_or_default();
+    anyhow::Error::new(error).context(format!("request to [{}] timed out", url))
+}
+
+/// `Retry-After` is either the seconds to wait or an HTTP date
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate(value)?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// parse an IMF-fixdate, i.e. `Wed, 21 Oct 2015 07:28:00 GMT`
+fn httpdate(value: &str) -> Option<SystemTime> {
+    let mut parts = value.split_whitespace().skip(1);
+    let (day, month, year, time) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let month = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"]
+        .iter()
+        .position(|m| *m == month)? + 1;
+    humantime::parse_rfc3339(&format!("{year}-{month:02}-{day:0>2}T{time}Z")).ok()
+}
+
+/// how to deal with a failed attempt
+#[derive(Debug, PartialEq)]
+enum RetryPolicy {
+    /// the error won't go away by retrying, i.e. `404 Not Found`
+    FailFast,
+    /// network errors and server errors, retried with exponential backoff
+    Backoff,
+    /// the server is throttling us, wait for the time it asks or back off much longer
+    Throttled(Option<Duration>),
+}
+
+impl RetryPolicy {
+    fn of(error: &anyhow::Error) -> Self {
+        match Error::of(error) {
+            Some(Error::Blocked { .. }) => return RetryPolicy::Throttled(None),
+            Some(Error::Fetch { status: Some(status), retry_after, .. }) => return match *status {
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => RetryPolicy::Throttled(*retry_after),
+                status if status.is_server_error() => RetryPolicy::Backoff,
+                _ => RetryPolicy::FailFast,
+            },
+            _ => {}
+        }
+        match error.downcast_ref::<reqwest::Error>() {
+            // `error_for_status` drops the headers, so there's no `Retry-After` to respect
+            Some(e) if matches!(e.status(), Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE)) => RetryPolicy::Throttled(None),
+            Some(e) if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() => RetryPolicy::Backoff,
+            _ => RetryPolicy::FailFast,
+        }
+    }
+
+    /// delay before the next attempt, `None` if it shouldn't be retried
+    ///
+    /// * `attempt` - the attempts made so far, starts from 1
+    fn delay(&self, attempt: u32) -> Option<Duration> {
+        match self {
+            RetryPolicy::FailFast => None,
+            RetryPolicy::Backoff => Some(BACKOFF_BASE * 2u32.pow(attempt - 1)),
+            RetryPolicy::Throttled(Some(retry_after)) => Some((*retry_after).min(MAX_RETRY_AFTER)),
+            RetryPolicy::Throttled(None) => Some(THROTTLED_BACKOFF_BASE * 4u32.pow(attempt - 1)),
+        }
+    }
+}
+
+/// run the request until it succeeds, the delay between attempts depends on the error
+///
+/// `429`/`503` responses and anti-bot challenges wait for `Retry-After` or back off longer, other `4xx` fail fast,
+/// and network errors back off exponentially. A random jitter is added on top of every delay,
+/// `Retry-After` is only ever waited longer than asked.
+pub async fn retry_wrapper<T, F, Fut>(mut request: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let error = match request().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        let Some(delay) = RetryPolicy::of(&error).delay(attempt).filter(|_| attempt < MAX_ATTEMPTS) else {
+            return Err(error);
+        };
+        let delay = jitter(delay, rand::thread_rng().gen_range(0.0..=MAX_JITTER));
+        warn!("attempt [{}/{}] failed: {}, retry in {}s", attempt, MAX_ATTEMPTS, error, delay.as_secs_f32());
+        metrics::inc(Counter::Retries);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// lengthen the delay by the fraction
+fn jitter(delay: Duration, fraction: f64) -> Duration {
+    delay + delay.mul_f64(fraction)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn status_error(status: StatusCode, retry_after: Option<Duration>) -> anyhow::Error {
+        Error::Fetch { url: "https://example.com".to_string(), status: Some(status), retry_after }.into()
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        assert_eq!(RetryPolicy::of(&status_error(StatusCode::NOT_FOUND, None)).delay(1), None);
+        assert_eq!(RetryPolicy::of(&status_error(StatusCode::BAD_GATEWAY, None)).delay(3), Some(Duration::from_secs(4)));
+        assert_eq!(
+            RetryPolicy::of(&status_error(StatusCode::TOO_MANY_REQUESTS, Some(Duration::from_secs(30)))).delay(1),
+            Some(Duration::from_secs(30)),
+        );
+        assert_eq!(RetryPolicy::of(&status_error(StatusCode::SERVICE_UNAVAILABLE, None)).delay(2), Some(Duration::from_secs(20)));
+        assert_eq!(RetryPolicy::of(&anyhow::anyhow!("cannot parse the page")).delay(1), None);
+        assert_eq!(jitter(Duration::from_secs(30), 0.5), Duration::from_secs(45));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
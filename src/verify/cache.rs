@@ -0,0 +1,268 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use crate::atmb::model::Address;
+use crate::error::Error;
+use crate::metrics::{self, Counter};
+use crate::verify::AddressVerifier;
+use crate::verify::model::AdditionalInfo;
+
+/// Wraps a verifier, so the results of previous runs are reused if the address is unchanged
+/// and the result is not older than the TTL.
+pub struct CachedVerifier {
+    inner: Box<dyn AddressVerifier>,
+    cache: RefCell<LookupCache>,
+    ttl: Duration,
+    /// keys of the stale entries whose result changed after being queried again
+    changed: RefCell<HashSet<String>>,
+}
+
+impl CachedVerifier {
+    /// the cache of every verifier is stored separately, i.e. `cache/smarty.json`
+    pub fn new(inner: Box<dyn AddressVerifier>, cache_dir: impl AsRef<Path>, ttl: Duration) -> anyhow::Result<Self> {
+        let cache = LookupCache::load(cache_dir.as_ref().join(format!("{}.json", inner.name())))?;
+        Ok(
+            Self {
+                inner,
+                cache: RefCell::new(cache),
+                ttl,
+                changed: RefCell::new(HashSet::new()),
+            }
+        )
+    }
+
+    /// the fresh cached result of the address, or the stale one if it needs to be queried again
+    fn lookup(&self, address: &Address) -> CacheLookup {
+        match self.cache.borrow().get(address) {
+            Some(entry) if entry.age() <= self.ttl => {
+                debug!("use the cached address info for [{}]", address.line1);
+                CacheLookup::Fresh(self.stamped(entry.info.clone(), entry.info.verified_at))
+            }
+            Some(entry) => {
+                debug!("the cached address info for [{}] is stale, inquire again", address.line1);
+                CacheLookup::Miss { stale: Some(entry.info.clone()) }
+            }
+            None => CacheLookup::Miss { stale: None },
+        }
+    }
+
+    /// the result with when and by which verifier it was queried
+    fn stamped(&self, mut info: AdditionalInfo, verified_at: u64) -> AdditionalInfo {
+        info.verified_at = verified_at;
+        // the entries cached before the verifier was recorded are all of this one
+        if info.verifier.is_empty() {
+            info.verifier = self.inner.name().to_string();
+        }
+        info
+    }
+
+    /// cache the queried result stamped with the current time, and remember the address if it differs from the stale one
+    fn store(&self, address: &Address, stale: Option<AdditionalInfo>, info: AdditionalInfo) -> AdditionalInfo {
+        let info = self.stamped(info, SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+        if stale.is_some_and(|stale| !stale.same_result(&info)) {
+            self.changed.borrow_mut().insert(LookupCache::key(address));
+        }
+        self.cache.borrow_mut().insert(address, info.clone());
+        info
+    }
+
+    /// how many of the addresses need to be queried, that is, not cached or stale
+    pub fn pending<'a>(&self, addresses: impl IntoIterator<Item = &'a Address>) -> usize {
+        addresses.into_iter()
+            .filter(|address| matches!(self.lookup(address), CacheLookup::Miss { .. }))
+            .count()
+    }
+
+    /// whether the address was cached with a different result than the one just queried
+    pub fn has_changed(&self, address: &Address) -> bool {
+        self.changed.borrow().contains(&LookupCache::key(address))
+    }
+}
+
+#[async_trait(?Send)]
+impl AddressVerifier for CachedVerifier {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo> {
+        let stale = match self.lookup(address) {
+            CacheLookup::Fresh(info) => {
+                metrics::inc(Counter::CacheHits);
+                return Ok(info);
+            }
+            CacheLookup::Miss { stale } => stale,
+        };
+        let info = self.inner.inquire(address).await.map_err(|e| verify_error(e, address))?;
+        Ok(self.store(address, stale, info))
+    }
+
+    fn batch_size(&self) -> usize {
+        self.inner.batch_size()
+    }
+
+    fn remaining_quota(&self) -> Option<usize> {
+        self.inner.remaining_quota()
+    }
+
+    fn usage(&self) -> Vec<(String, usize)> {
+        self.inner.usage()
+    }
+
+    /// only the addresses that are not cached or stale are passed to the inner verifier
+    async fn inquire_batch(&self, addresses: &[Address]) -> Vec<anyhow::Result<AdditionalInfo>> {
+        let mut results = Vec::with_capacity(addresses.len());
+        let mut misses = Vec::new();
+        for (idx, address) in addresses.iter().enumerate() {
+            match self.lookup(address) {
+                CacheLookup::Fresh(info) => {
+                    metrics::inc(Counter::CacheHits);
+                    results.push(Some(Ok(info)));
+                }
+                CacheLookup::Miss { stale } => {
+                    results.push(None);
+                    misses.push((idx, stale));
+                }
+            }
+        }
+        if !misses.is_empty() {
+            let miss_addresses = misses.iter().map(|(idx, _)| addresses[*idx].clone()).collect::<Vec<_>>();
+            let infos = self.inner.inquire_batch(&miss_addresses).await;
+            for ((idx, stale), info) in misses.into_iter().zip(infos) {
+                results[idx] = Some(info
+                    .map(|info| self.store(&addresses[idx], stale, info))
+                    .map_err(|e| verify_error(e, &addresses[idx])));
+            }
+        }
+        results.into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow!("no result returned by the verifier"))))
+            .collect()
+    }
+
+    /// persist the lookup results, so the next run doesn't need to query them again
+    fn save_cache(&self) -> anyhow::Result<()> {
+        self.cache.borrow().save()?;
+        self.inner.save_cache()
+    }
+}
+
+/// the failures not told apart by the verifier become an [`Error::Verify`] of the address
+fn verify_error(error: anyhow::Error, address: &Address) -> anyhow::Error {
+    if Error::of(&error).is_some() {
+        return error;
+    }
+    error.context(Error::Verify {
+        address: format!("{}, {}, {} {}", address.line1, address.city, address.state, address.full_zip()),
+    })
+}
+
+enum CacheLookup {
+    Fresh(AdditionalInfo),
+    /// not cached, or the cached result is stale and needs to be queried again
+    Miss { stale: Option<AdditionalInfo> },
+}
+
+/// a cached lookup result, stamped with when it was queried
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// the entries cached before [`AdditionalInfo::verified_at`] was recorded are treated as stale
+    #[serde(flatten)]
+    info: AdditionalInfo,
+}
+
+impl CacheEntry {
+    fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(self.info.verified_at))
+            .unwrap_or_default()
+    }
+}
+
+/// Lookup results keyed by the normalized address
+#[derive(Default)]
+struct LookupCache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl LookupCache {
+    fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(
+            Self {
+                path,
+                entries,
+            }
+        )
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(&self.path, serde_json::to_string(&self.entries)?)?;
+        Ok(())
+    }
+
+    fn get(&self, address: &Address) -> Option<&CacheEntry> {
+        self.entries.get(&Self::key(address))
+    }
+
+    fn insert(&mut self, address: &Address, info: AdditionalInfo) {
+        self.entries.insert(Self::key(address), CacheEntry { info });
+    }
+
+    /// normalize the address, so trivial formatting differences don't cause a cache miss
+    ///
+    /// i.e. `120 19th Street North, Suite MAILBOX` -> `120 19TH STREET NORTH SUITE MAILBOX`
+    fn key(address: &Address) -> String {
+        fn normalize(s: &str) -> String {
+            s.chars()
+                .map(|c| if c.is_alphanumeric() { c.to_ascii_uppercase() } else { ' ' })
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+
+        [&address.line1, &address.city, &address.state, &address.full_zip()]
+            .map(|s| normalize(s))
+            .join("|")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let address = Address {
+            line1: "120 19th Street North,  Suite MAILBOX".to_string(),
+            city: "Birmingham".to_string(),
+            state: "AL".to_string(),
+            zip: "35203".to_string(),
+            zip4: Some("3107".to_string()),
+            country: Default::default(),
+        };
+        assert_eq!(LookupCache::key(&address), "120 19TH STREET NORTH SUITE MAILBOX|BIRMINGHAM|AL|35203 3107");
+    }
+
+    #[test]
+    fn test_entry_without_timestamp_is_stale() {
+        let entry: CacheEntry = serde_json::from_str(r#"{"cmra": "N", "rdi": "Residential"}"#).unwrap();
+        assert!(entry.age() > Duration::from_secs(365 * 24 * 3600));
+    }
+}
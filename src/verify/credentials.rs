@@ -0,0 +1,85 @@
+use std::path::Path;
+use anyhow::{anyhow, bail};
+use crate::verify::smarty::{self, SmartyCredential};
+
+/// environment variable of the comma separated credentials, used if no credentials file is given
+const CREDENTIALS_ENV: &str = "CREDENTIALS";
+
+/// load the Smarty credentials from a `.env` style file
+///
+/// every line is an `AUTH_ID=AUTH_TOKEN` pair, empty lines and lines starting with `#` are ignored.
+pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Vec<SmartyCredential>> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("cannot read credentials file [{}]: {}", path.display(), e))?;
+    let credentials = content.lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(idx, line)| parse_pair(line)
+            .map_err(|e| anyhow!("invalid credential at [{}:{}]: {}", path.display(), idx + 1, e)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if credentials.is_empty() {
+        bail!("no credentials found in [{}]", path.display());
+    }
+    Ok(credentials)
+}
+
+/// load the Smarty credentials from the `CREDENTIALS` environment variable
+///
+/// CREDENTIALS=`ID1`=`SECRET1`[:`LOOKUPS1`][,`ID2`=`SECRET2`[:`LOOKUPS2`]]*
+pub fn from_env() -> anyhow::Result<Vec<SmartyCredential>> {
+    let credentials = std::env::var(CREDENTIALS_ENV)
+        .map_err(|_| anyhow!("`{}` environment variable must be set, or use `--credentials-file`", CREDENTIALS_ENV))?;
+    parse_list(&credentials)
+        .map_err(|e| anyhow!("invalid `{}` environment variable: {}", CREDENTIALS_ENV, e))
+}
+
+fn parse_list(credentials: &str) -> anyhow::Result<Vec<SmartyCredential>> {
+    let credentials = credentials.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(parse_pair)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if credentials.is_empty() {
+        bail!("no credentials found");
+    }
+    Ok(credentials)
+}
+
+/// parse an `AUTH_ID=AUTH_TOKEN` pair, the value may be quoted and followed by the monthly lookups, i.e. `:5000`
+fn parse_pair(pair: &str) -> anyhow::Result<SmartyCredential> {
+    let (id, token) = pair.split_once('=')
+        .ok_or_else(|| anyhow!("expected `AUTH_ID=AUTH_TOKEN`, got `{}`", pair))?;
+    let (token, monthly_lookups) = smarty::split_monthly_lookups(token.trim())?;
+    let (id, token) = (id.trim(), token.trim().trim_matches(|c| c == '"' || c == '\''));
+    if id.is_empty() || token.is_empty() {
+        bail!("the auth ID and token cannot be empty: `{}`", pair);
+    }
+    if token.contains('=') {
+        bail!("expected a single `=` between the auth ID and token: `{}`", pair);
+    }
+    Ok(SmartyCredential::SecretKey { auth_id: id.to_string(), auth_token: token.to_string(), monthly_lookups })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_credentials() {
+        assert_eq!(
+            parse_list("id1=token1, id2=\"token2\"").unwrap(),
+            vec![("id1".to_string(), "token1".to_string()).into(), ("id2".to_string(), "token2".to_string()).into()],
+        );
+        assert_eq!(
+            parse_list("id1=token1:5000,id2=\"token2\":unlimited").unwrap().iter().map(SmartyCredential::monthly_lookups).collect::<Vec<_>>(),
+            vec![Some(5000), None],
+        );
+        assert!(parse_list("id1=token1:many").is_err());
+        assert!(parse_list("id1").is_err());
+        assert!(parse_list("id1=").is_err());
+        assert!(parse_list("id1=token1=id2").is_err());
+        assert!(parse_list(" , ").is_err());
+    }
+}
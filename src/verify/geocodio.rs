@@ -0,0 +1,195 @@
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use crate::atmb::model::Address;
+use crate::verify::AddressVerifier;
+use crate::verify::model::{AdditionalInfo, Rdi, VerifiedAddress, YesOrNo};
+
+const GEOCODE_URL: &str = "https://api.geocod.io/v1.7/geocode";
+/// environment variable of the API key, used if `--geocodio-key` is not given
+const API_KEY_ENV: &str = "GEOCODIO_API_KEY";
+
+/// Client of the Geocodio geocoding API, with its USPS ZIP+4 field.
+///
+/// It only supplies the RDI and the coordinates, CMRA is not reported. The RDI is told by the record type of the ZIP+4:
+/// a firm is commercial, a street is residential, and the others are unknown.
+/// So it's meant for `--enrich`, filling in what the primary verifier lacks, or for `--include-cmra` runs.
+pub struct GeocodioClient {
+    client: Client,
+    api_key: String,
+}
+
+impl GeocodioClient {
+    pub fn new(api_key: Option<&str>) -> anyhow::Result<Self> {
+        let api_key = match api_key {
+            Some(api_key) => api_key.to_string(),
+            None => std::env::var(API_KEY_ENV)
+                .map_err(|_| anyhow!("`{}` environment variable must be set, or use `--geocodio-key`", API_KEY_ENV))?,
+        };
+        Ok(
+            Self {
+                client: Client::new(),
+                api_key,
+            }
+        )
+    }
+}
+
+#[async_trait(?Send)]
+impl AddressVerifier for GeocodioClient {
+    fn name(&self) -> &'static str {
+        "geocodio"
+    }
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo> {
+        let resp = self.client.get(GEOCODE_URL)
+            .query(&[
+                ("api_key", self.api_key.as_str()),
+                ("street", &address.line1),
+                ("city", &address.city),
+                ("state", &address.state),
+                ("postal_code", &address.zip),
+                ("fields", "zip4"),
+                ("limit", "1"),
+            ])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            bail!("Geocodio responded with [{}]: {}", resp.status(), resp.text().await.unwrap_or_default());
+        }
+        resp.json::<GeocodeResponse>().await?.try_into()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeResponse {
+    #[serde(default)]
+    results: Vec<GeocodeResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeocodeResult {
+    address_components: AddressComponents,
+    location: Location,
+    /// i.e. `rooftop`, `range_interpolation` or `place`
+    #[serde(default)]
+    accuracy_type: String,
+    #[serde(default)]
+    fields: Fields,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AddressComponents {
+    formatted_street: String,
+    number: String,
+    city: String,
+    state: String,
+    zip: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Location {
+    lat: f64,
+    lng: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Fields {
+    zip4: Option<Zip4>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Zip4 {
+    record_type: Option<CodeDescription>,
+    plus4: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CodeDescription {
+    code: String,
+}
+
+impl TryFrom<GeocodeResponse> for AdditionalInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(resp: GeocodeResponse) -> Result<Self, Self::Error> {
+        let result = resp.results.into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no results found in Geocodio response"))?;
+        let zip4 = result.fields.zip4.unwrap_or_default();
+        // the place or the ZIP centroid is too coarse to tell anything about the address
+        let matched = matches!(result.accuracy_type.as_str(), "rooftop" | "point" | "range_interpolation" | "nearest_rooftop_match");
+        let components = result.address_components;
+
+        Ok(
+            Self {
+                // not reported
+                cmra: YesOrNo::N,
+                rdi: match zip4.record_type.as_ref().map(|record_type| record_type.code.as_str()) {
+                    Some("F") if matched => Rdi::Commercial,
+                    Some("S") if matched => Rdi::Residential,
+                    _ => Rdi::Unknown,
+                },
+                // Geocodio doesn't confirm the delivery point
+                dpv_match_code: String::new(),
+                dpv_footnotes: String::new(),
+                active: None,
+                vacant: None,
+                no_stat: None,
+                enhanced_match: String::new(),
+                latitude: Some(result.location.lat),
+                longitude: Some(result.location.lng),
+                verified: matched.then(|| VerifiedAddress {
+                    street: [components.number, components.formatted_street].join(" ").trim().to_string(),
+                    city: components.city,
+                    state: components.state,
+                    zip: components.zip,
+                    zip4: zip4.plus4.into_iter().next().unwrap_or_default(),
+                    ..Default::default()
+                }),
+                double_check: None,
+                international: None,
+                candidates: Vec::new(),
+                votes: Vec::new(),
+                // stamped by the cache, see `CachedVerifier`
+                verified_at: 0,
+                verifier: String::new(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_geocode_response() {
+        let json = r#"{
+            "input": {"formatted_address": "120 19th St N, Birmingham, AL 35203"},
+            "results": [{
+                "address_components": {"number": "120", "formatted_street": "19th St N", "city": "Birmingham", "state": "AL", "zip": "35203", "country": "US"},
+                "formatted_address": "120 19th St N, Birmingham, AL 35203",
+                "location": {"lat": 33.515759, "lng": -86.808541},
+                "accuracy": 1, "accuracy_type": "rooftop", "source": "Jefferson",
+                "fields": {"zip4": {"record_type": {"code": "F", "description": "Firm"}, "plus4": ["3712"], "zip9": ["35203-3712"]}}
+            }]
+        }"#;
+        let info = AdditionalInfo::try_from(serde_json::from_str::<GeocodeResponse>(json).unwrap()).unwrap();
+        assert_eq!(info.rdi, Rdi::Commercial);
+        assert_eq!((info.latitude, info.longitude), (Some(33.515759), Some(-86.808541)));
+        let verified = info.verified.as_ref().unwrap();
+        assert_eq!(verified.street, "120 19th St N");
+        assert_eq!(verified.zip4, "3712");
+
+        let mut primary: AdditionalInfo = serde_json::from_str(r#"{"cmra": "Y", "rdi": "Unknown"}"#).unwrap();
+        primary.enrich(&info);
+        assert!(primary.is_cmra());
+        assert_eq!(primary.rdi, Rdi::Commercial);
+        assert_eq!(primary.latitude, Some(33.515759));
+    }
+}
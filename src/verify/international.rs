@@ -0,0 +1,136 @@
+use std::cell::Cell;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use smarty_rust_sdk::international_street_api::candidate::Candidate;
+use smarty_rust_sdk::international_street_api::client::InternationalStreetClient;
+use smarty_rust_sdk::international_street_api::lookup::Lookup;
+use smarty_rust_sdk::sdk::options::OptionsBuilder;
+use tracing::warn;
+use crate::atmb::model::Address;
+use crate::verify::{AddressVerifier, SmartyCredential};
+use crate::verify::model::{AdditionalInfo, InternationalStatus, Rdi, VerifiedAddress, YesOrNo};
+
+/// Smarty's international street API, for the addresses outside the US.
+///
+/// It only tells whether the address is deliverable, neither CMRA nor RDI is reported,
+/// so the results are always non-CMRA with an unknown RDI, see [`AdditionalInfo::international`].
+pub struct SmartyInternationalClient {
+    /// the credentials are used in turn, the next one is tried if a lookup fails
+    clients: Vec<(String, InternationalStreetClient)>,
+    next: Cell<usize>,
+    /// successful lookups of every credential
+    consumed: Vec<Cell<usize>>,
+}
+
+impl SmartyInternationalClient {
+    pub fn new(credentials: Vec<SmartyCredential>) -> anyhow::Result<Self> {
+        let clients = credentials.iter()
+            .map(|credential| {
+                let options = OptionsBuilder::new(Some(credential.authentication()))
+                    .with_retries(3)
+                    .build();
+                Ok((credential.id().to_string(), InternationalStreetClient::new(options)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(
+            Self {
+                consumed: clients.iter().map(|_| Cell::new(0)).collect(),
+                clients,
+                next: Cell::new(0),
+            }
+        )
+    }
+}
+
+#[async_trait(?Send)]
+impl AddressVerifier for SmartyInternationalClient {
+    fn name(&self) -> &'static str {
+        "smarty_international"
+    }
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo> {
+        let mut last_error = None;
+        for _ in 0..self.clients.len() {
+            let idx = self.next.get();
+            self.next.set((idx + 1) % self.clients.len());
+            let (id, client) = &self.clients[idx];
+
+            let mut lookup = Lookup::from(address.clone());
+            match client.send(&mut lookup).await {
+                Ok(()) => {
+                    self.consumed[idx].set(self.consumed[idx].get() + 1);
+                    let candidate = lookup.results.into_iter()
+                        .next()
+                        .ok_or_else(|| anyhow!("no results found for [{}]", address.line1))?;
+                    return Ok(candidate.into());
+                }
+                Err(e) => {
+                    warn!("international lookup with [{}] failed, try the next credential: {}", id, e);
+                    last_error = Some(anyhow!("{}", e));
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => bail!("no credentials for the international lookups"),
+        }
+    }
+
+    fn usage(&self) -> Vec<(String, usize)> {
+        self.clients.iter()
+            .zip(&self.consumed)
+            .map(|((id, _), consumed)| (id.clone(), consumed.get()))
+            .collect()
+    }
+}
+
+impl From<Address> for Lookup {
+    fn from(address: Address) -> Self {
+        Self {
+            country: address.country.code().to_string(),
+            address1: address.line1,
+            locality: address.city,
+            administrative_area: address.state,
+            postal_code: address.zip,
+            geocode: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Candidate> for AdditionalInfo {
+    fn from(candidate: Candidate) -> Self {
+        let geocoded = !matches!(candidate.metadata.geocode_precision.as_str(), "" | "None" | "Unknown");
+        Self {
+            // not reported outside the US
+            cmra: YesOrNo::N,
+            rdi: Rdi::Unknown,
+            dpv_match_code: String::new(),
+            dpv_footnotes: String::new(),
+            active: None,
+            vacant: None,
+            no_stat: None,
+            enhanced_match: String::new(),
+            latitude: geocoded.then_some(candidate.metadata.latitude),
+            longitude: geocoded.then_some(candidate.metadata.longitude),
+            verified: Some(VerifiedAddress {
+                street: candidate.root_level.address1,
+                city: candidate.components.locality,
+                state: candidate.components.administrative_area,
+                zip: candidate.components.postal_code,
+                zip4: String::new(),
+                ..Default::default()
+            }),
+            double_check: None,
+            candidates: Vec::new(),
+            votes: Vec::new(),
+            // stamped by the cache, see `CachedVerifier`
+            verified_at: 0,
+            verifier: String::new(),
+            international: Some(InternationalStatus {
+                verification_status: candidate.analysis.verification_status,
+                address_precision: candidate.analysis.address_precision,
+            }),
+        }
+    }
+}
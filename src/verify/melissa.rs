@@ -0,0 +1,193 @@
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use crate::atmb::model::Address;
+use crate::verify::AddressVerifier;
+use crate::verify::model::{AdditionalInfo, Rdi, VerifiedAddress, YesOrNo};
+
+const ADDRESS_URL: &str = "https://address.melissadata.net/v3/WEB/GlobalAddress/doGlobalAddress";
+/// environment variable of the license key, used if `--melissa-license` is not given
+const LICENSE_ENV: &str = "MELISSA_LICENSE";
+
+/// Client of Melissa's Global Address Verification API.
+///
+/// The US extras of the results report the CMRA and RBDI (residential or business delivery indicator) of the address,
+/// the license key is given with `--melissa-license` or the `MELISSA_LICENSE` environment variable.
+pub struct MelissaClient {
+    client: Client,
+    license: String,
+}
+
+impl MelissaClient {
+    pub fn new(license: Option<&str>) -> anyhow::Result<Self> {
+        let license = match license {
+            Some(license) => license.to_string(),
+            None => std::env::var(LICENSE_ENV)
+                .map_err(|_| anyhow!("`{}` environment variable must be set, or use `--melissa-license`", LICENSE_ENV))?,
+        };
+        Ok(
+            Self {
+                client: Client::new(),
+                license,
+            }
+        )
+    }
+}
+
+#[async_trait(?Send)]
+impl AddressVerifier for MelissaClient {
+    fn name(&self) -> &'static str {
+        "melissa"
+    }
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo> {
+        let postal = match &address.zip4 {
+            Some(zip4) => format!("{}-{}", address.zip, zip4),
+            None => address.zip.clone(),
+        };
+        let resp = self.client.get(ADDRESS_URL)
+            .query(&[
+                ("id", self.license.as_str()),
+                ("format", "json"),
+                ("opt", "USExtras:ON"),
+                ("ctry", address.country.code()),
+                ("a1", &address.line1),
+                ("loc", &address.city),
+                ("admarea", &address.state),
+                ("postal", &postal),
+            ])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            bail!("Melissa responded with [{}]: {}", resp.status(), resp.text().await.unwrap_or_default());
+        }
+        resp.json::<AddressResponse>().await?.try_into()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AddressResponse {
+    /// error codes of the whole request, i.e. `GE05` for an invalid license
+    #[serde(default)]
+    transmission_results: String,
+    #[serde(default)]
+    records: Vec<AddressRecord>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct AddressRecord {
+    /// comma separated result codes, i.e. `AV24,GS05`
+    results: String,
+    address_line1: String,
+    locality: String,
+    administrative_area: String,
+    /// ZIP+4, i.e. `35203-3712`
+    postal_code: String,
+    latitude: String,
+    longitude: String,
+    /// the US extras, all of them are `Y`, `N` or empty
+    #[serde(rename = "CMRA")]
+    cmra: String,
+    /// `R` for residential, `B` for business, `U` if unknown
+    delivery_indicator: String,
+    vacant: String,
+    #[serde(rename = "DPVFootnotes")]
+    dpv_footnotes: String,
+}
+
+impl TryFrom<AddressResponse> for AdditionalInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(resp: AddressResponse) -> Result<Self, Self::Error> {
+        if !resp.transmission_results.is_empty() {
+            bail!("Melissa rejected the request: {}", resp.transmission_results);
+        }
+        let record = resp.records.into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no records found in Melissa response"))?;
+        if record.cmra.is_empty() {
+            bail!("no CMRA indicator found in Melissa response, result codes: {}", record.results);
+        }
+        let codes = record.results.split(',').map(str::trim).collect::<Vec<_>>();
+        let (zip, zip4) = match record.postal_code.split_once('-') {
+            Some((zip, zip4)) => (zip.to_string(), zip4.to_string()),
+            None => (record.postal_code, String::new()),
+        };
+
+        Ok(
+            Self {
+                cmra: YesOrNo::try_from(record.cmra)
+                    .map_err(|e| anyhow!("failed to parse CMRA: {}", e))?,
+                rdi: match record.delivery_indicator.as_str() {
+                    "R" => Rdi::Residential,
+                    "B" => Rdi::Commercial,
+                    _ => Rdi::Unknown,
+                },
+                // `AV24` and `AV25` are verified to the delivery point, any `AE` code is an error
+                dpv_match_code: if codes.iter().any(|code| matches!(*code, "AV24" | "AV25")) {
+                    "Y".to_string()
+                } else if codes.iter().any(|code| code.starts_with("AE")) {
+                    "N".to_string()
+                } else {
+                    String::new()
+                },
+                dpv_footnotes: record.dpv_footnotes,
+                // Melissa doesn't report the active nor the no-stat flag, and it has no enhanced matching
+                active: None,
+                vacant: YesOrNo::try_from_optional(record.vacant)
+                    .map_err(|e| anyhow!("failed to parse vacant indicator: {}", e))?,
+                no_stat: None,
+                enhanced_match: String::new(),
+                latitude: record.latitude.parse().ok(),
+                longitude: record.longitude.parse().ok(),
+                verified: Some(VerifiedAddress {
+                    street: record.address_line1,
+                    city: record.locality,
+                    state: record.administrative_area,
+                    zip,
+                    zip4,
+                    ..Default::default()
+                }),
+                double_check: None,
+                international: None,
+                candidates: Vec::new(),
+                votes: Vec::new(),
+                // stamped by the cache, see `CachedVerifier`
+                verified_at: 0,
+                verifier: String::new(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_address_response() {
+        let json = r#"{
+            "Version": "7.4.1.1012", "TransmissionReference": "", "TransmissionResults": "", "TotalRecords": "1",
+            "Records": [{
+                "RecordID": "1", "Results": "AV24,GS05", "AddressLine1": "120 19th St N Ste 1234", "Locality": "Birmingham",
+                "AdministrativeArea": "AL", "PostalCode": "35203-3712", "Latitude": "33.515759", "Longitude": "-86.808541",
+                "CMRA": "Y", "DeliveryIndicator": "B", "Vacant": "N", "DPVFootnotes": "AABB"
+            }]
+        }"#;
+        let resp: AddressResponse = serde_json::from_str(json).unwrap();
+        let info = AdditionalInfo::try_from(resp).unwrap();
+        assert!(info.is_cmra());
+        assert_eq!(info.rdi, Rdi::Commercial);
+        assert_eq!(info.dpv_match_code, "Y");
+        assert_eq!(info.vacant, Some(YesOrNo::N));
+        assert_eq!(info.latitude, Some(33.515759));
+        let verified = info.verified.unwrap();
+        assert_eq!((verified.zip.as_str(), verified.zip4.as_str()), ("35203", "3712"));
+
+        let rejected = r#"{"TransmissionResults": "GE05", "Records": []}"#;
+        assert!(AdditionalInfo::try_from(serde_json::from_str::<AddressResponse>(rejected).unwrap()).is_err());
+    }
+}
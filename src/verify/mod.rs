@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use crate::atmb::model::Address;
+use crate::cassette::{Cassette, HttpMode};
+use crate::country::Country;
+use crate::verify::cache::CachedVerifier;
+use crate::verify::international::SmartyInternationalClient;
+use crate::verify::model::AdditionalInfo;
+use crate::verify::smarty::SmartyClientProxy;
+use crate::verify::geocodio::GeocodioClient;
+use crate::verify::melissa::MelissaClient;
+use crate::verify::usps::UspsClient;
+use crate::verify::zipcode::ZipChecker;
+pub use crate::verify::smarty::{split_monthly_lookups, SmartyCredential, SmartyMatch, DEFAULT_LICENSE as DEFAULT_SMARTY_LICENSE};
+
+pub mod cache;
+mod credentials;
+mod international;
+mod smarty;
+mod usps;
+mod melissa;
+mod geocodio;
+pub mod zipcode;
+pub mod model;
+
+/// where the lookup results of the verifiers are cached between runs
+const CACHE_DIR: &str = "cache";
+
+/// A service that tells whether an address is a CMRA and whether it's residential.
+///
+/// New providers only need to implement this trait and register themselves in [`VerifierKind`].
+#[async_trait(?Send)]
+pub trait AddressVerifier {
+    /// short name of the provider, also used to name its cache file
+    fn name(&self) -> &'static str;
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo>;
+
+    /// maximum addresses to pass to [`AddressVerifier::inquire_batch`] at once
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// inquire several addresses at once, the results are in the same order as the addresses
+    ///
+    /// verifiers with a batch API should override it, by default the addresses are inquired one by one concurrently.
+    async fn inquire_batch(&self, addresses: &[Address]) -> Vec<anyhow::Result<AdditionalInfo>> {
+        futures::future::join_all(addresses.iter().map(|address| self.inquire(address))).await
+    }
+
+    /// lookups left before the verifier's quota is used up, `None` if it's unknown or unlimited
+    fn remaining_quota(&self) -> Option<usize> {
+        None
+    }
+
+    /// lookups consumed so far by every credential, empty if the verifier doesn't count them
+    fn usage(&self) -> Vec<(String, usize)> {
+        Vec::new()
+    }
+
+    /// persist the cached lookup results, if the verifier has any
+    fn save_cache(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// settings shared by the verifiers
+#[derive(Debug, Clone)]
+pub struct VerifyConfig {
+    /// cached results older than this are queried again
+    pub cache_ttl: Duration,
+    /// file of the Smarty credentials, the `CREDENTIALS` environment variable is used if not set
+    pub credentials_file: Option<PathBuf>,
+    /// record the Smarty lookups, or replay the recorded ones
+    pub http: Option<HttpMode>,
+    /// how Smarty matches the addresses
+    pub smarty_match: SmartyMatch,
+    /// the license of the Smarty plan, i.e. `us-core-cloud`
+    pub smarty_license: String,
+    /// a website key used instead of the secret keys
+    pub smarty_website_key: Option<SmartyCredential>,
+    /// the license key of Melissa, the `MELISSA_LICENSE` environment variable is used if not set
+    pub melissa_license: Option<String>,
+    /// the API key of Geocodio, the `GEOCODIO_API_KEY` environment variable is used if not set
+    pub geocodio_key: Option<String>,
+}
+
+impl VerifyConfig {
+    fn smarty_credentials(&self) -> anyhow::Result<Vec<SmartyCredential>> {
+        if let Some(website_key) = &self.smarty_website_key {
+            return Ok(vec![website_key.clone()]);
+        }
+        match &self.credentials_file {
+            Some(path) => credentials::from_file(path),
+            None => credentials::from_env(),
+        }
+    }
+
+    /// the checker of `--zip-precheck`, with the first of the Smarty credentials
+    pub fn zip_checker(&self) -> anyhow::Result<ZipChecker> {
+        if self.http.is_some() {
+            bail!("the ZIP Code API lookups cannot be recorded nor replayed");
+        }
+        let credentials = self.smarty_credentials()?;
+        ZipChecker::new(&credentials[0], &self.smarty_license, CACHE_DIR)
+    }
+
+    /// the Smarty client, no credential is needed to replay the recorded lookups
+    fn smarty(&self) -> anyhow::Result<SmartyClientProxy> {
+        let Some(mode) = &self.http else {
+            return Ok(SmartyClientProxy::new(self.smarty_credentials()?, CACHE_DIR, &self.smarty_license)?.with_match_strategy(self.smarty_match));
+        };
+        let cassette = Cassette::open(mode, "smarty", "json")?;
+        let credentials = match self.smarty_credentials() {
+            Err(_) if cassette.is_replay() => vec![("replay".to_string(), String::new()).into()],
+            credentials => credentials?,
+        };
+        Ok(
+            SmartyClientProxy::new(credentials, CACHE_DIR, &self.smarty_license)?
+                .with_match_strategy(self.smarty_match)
+                .with_cassette(cassette)
+        )
+    }
+}
+
+/// Check a single address with the verifier, the result is cached like the ones of a full run.
+///
+/// the addresses outside the US are always checked with [`VerifierKind::International`].
+pub async fn check_address(address: &Address, kind: VerifierKind, config: &VerifyConfig) -> anyhow::Result<AdditionalInfo> {
+    let kind = match address.country {
+        Country::Us => kind,
+        _ => VerifierKind::International,
+    };
+    let verifier = kind.build(config)?;
+    let info = verifier.inquire(address).await;
+    verifier.save_cache()?;
+    info
+}
+
+/// verifiers selectable via `--verifier`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VerifierKind {
+    #[default]
+    Smarty,
+    Usps,
+    Melissa,
+    /// only reports the RDI and the coordinates, see `--enrich`
+    Geocodio,
+    /// Smarty's international API, the addresses outside the US can only be verified with it
+    International,
+}
+
+impl VerifierKind {
+    /// the name of `--verifier`, the same as [`AddressVerifier::name`] of its verifier
+    pub fn name(self) -> &'static str {
+        match self {
+            VerifierKind::Smarty => "smarty",
+            VerifierKind::Usps => "usps",
+            VerifierKind::Melissa => "melissa",
+            VerifierKind::Geocodio => "geocodio",
+            VerifierKind::International => "smarty_international",
+        }
+    }
+
+    /// build the verifier, with its results cached between runs
+    pub fn build(self, config: &VerifyConfig) -> anyhow::Result<CachedVerifier> {
+        let verifier: Box<dyn AddressVerifier> = match self {
+            VerifierKind::Smarty => Box::new(config.smarty()?),
+            VerifierKind::Usps => Box::new(UspsClient::new()?),
+            VerifierKind::Melissa => Box::new(MelissaClient::new(config.melissa_license.as_deref())?),
+            VerifierKind::Geocodio => Box::new(GeocodioClient::new(config.geocodio_key.as_deref())?),
+            VerifierKind::International => Box::new(SmartyInternationalClient::new(config.smarty_credentials()?)?),
+        };
+        // the cached results would hide the lookups from the cassette
+        let ttl = match self {
+            VerifierKind::Smarty if config.http.is_some() => Duration::ZERO,
+            _ => config.cache_ttl,
+        };
+        CachedVerifier::new(verifier, CACHE_DIR, ttl)
+    }
+}
+
+impl FromStr for VerifierKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "smarty" => Ok(VerifierKind::Smarty),
+            "usps" => Ok(VerifierKind::Usps),
+            "melissa" => Ok(VerifierKind::Melissa),
+            "geocodio" => Ok(VerifierKind::Geocodio),
+            "international" => Ok(VerifierKind::International),
+            _ => Err(anyhow!("unknown verifier: {}, expected one of: smarty, usps, melissa, geocodio, international", s)),
+        }
+    }
+}
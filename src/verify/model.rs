@@ -0,0 +1,307 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// CMRA and RDI information of an address, as reported by a verifier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdditionalInfo {
+    pub cmra: YesOrNo,
+    pub rdi: Rdi,
+    /// DPV match code, `Y` confirmed, `S` secondary ignored, `D` secondary missing, `N` not confirmed
+    #[serde(default)]
+    pub dpv_match_code: String,
+    /// DPV footnotes, two-character codes concatenated, i.e. `AABB`
+    #[serde(default)]
+    pub dpv_footnotes: String,
+    /// whether the address is active, not every verifier reports it
+    #[serde(default)]
+    pub active: Option<YesOrNo>,
+    /// whether the address is vacant, not every verifier reports it
+    #[serde(default)]
+    pub vacant: Option<YesOrNo>,
+    /// whether USPS doesn't deliver to the address, i.e. it's under construction or gets its mail elsewhere,
+    /// not every verifier reports it
+    #[serde(default)]
+    pub no_stat: Option<YesOrNo>,
+    /// how Smarty has matched the address with the enhanced matching, i.e. `postal-match,missing-secondary`
+    #[serde(default)]
+    pub enhanced_match: String,
+    /// ZIP and county of the matched address, only present if the verifier has found a match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<VerifiedAddress>,
+    /// geocoordinates of the address, not every verifier reports them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    /// result of the second verifier, only present if the address has been double-checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_check: Option<Box<AdditionalInfo>>,
+    /// only present for the addresses outside the US, whose CMRA and RDI are unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub international: Option<InternationalStatus>,
+    /// every candidate if the verifier has matched more than one, the best of them is the one above
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Candidate>,
+    /// when the verifier was queried, seconds since the UNIX epoch, 0 if it's unknown
+    #[serde(default)]
+    pub verified_at: u64,
+    /// the [`AddressVerifier::name`](crate::verify::AddressVerifier::name) of the verifier that has reported it
+    #[serde(default)]
+    pub verifier: String,
+    /// what every verifier of `--verifier consensus:...` has reported, this one's included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub votes: Vec<Vote>,
+}
+
+/// The CMRA and RDI reported by one of the verifiers of `--verifier consensus:...`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    /// see [`AddressVerifier::name`](crate::verify::AddressVerifier::name)
+    pub verifier: String,
+    pub cmra: YesOrNo,
+    pub rdi: Rdi,
+}
+
+/// What most of the verifiers agree on, see [`AdditionalInfo::consensus`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Consensus {
+    /// CMRA if at least half of them say so, a mailbox is better left out than wrongly listed as non-CMRA
+    pub cmra: YesOrNo,
+    /// the RDI most of the ones that know it report, unknown if there's a tie
+    pub rdi: Rdi,
+    /// whether any of them disagree on the CMRA or on the known RDI
+    pub disagreement: bool,
+}
+
+/// A match of an address that has more than one, see `--ambiguous-report`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candidate {
+    /// standardized delivery line, i.e. `120 19th St N Ste 1234`
+    pub street: String,
+    /// ZIP+4, i.e. `35203-3712`
+    pub zip: String,
+    pub dpv_match_code: String,
+    pub cmra: YesOrNo,
+    pub rdi: Rdi,
+}
+
+/// How well an address outside the US is matched, as reported by an international verifier
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InternationalStatus {
+    /// `Verified`, `Partial`, `Ambiguous` or `None`
+    pub verification_status: String,
+    /// the most precise level the address is matched at, i.e. `Premise` or `DeliveryPoint`
+    pub address_precision: String,
+}
+
+/// Components of the address as standardized by the verifier, more authoritative than the scraped ones
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedAddress {
+    /// standardized delivery line, i.e. `120 19th St N Ste 1234`
+    #[serde(default)]
+    pub street: String,
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub state: String,
+    pub zip: String,
+    pub zip4: String,
+    /// not every verifier reports the county
+    #[serde(default)]
+    pub county_name: String,
+    #[serde(default)]
+    pub county_fips: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Ord, PartialOrd)]
+#[serde(rename_all = "PascalCase")]
+#[repr(u8)]
+pub enum Rdi {
+    Residential,
+    Commercial,
+    Unknown,
+}
+
+impl TryFrom<String> for Rdi {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "residential" => Ok(Rdi::Residential),
+            "commercial" => Ok(Rdi::Commercial),
+            "" => Ok(Rdi::Unknown),
+            _ => Err(value),
+        }
+    }
+}
+
+/// RDI of the non-CMRA mailboxes to keep, selected via `--rdi`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RdiFilter {
+    Residential,
+    Commercial,
+    /// keep all of them, including the unknown ones
+    #[default]
+    Any,
+}
+
+impl RdiFilter {
+    pub fn matches(self, rdi: &Rdi) -> bool {
+        match self {
+            RdiFilter::Residential => *rdi == Rdi::Residential,
+            RdiFilter::Commercial => *rdi == Rdi::Commercial,
+            RdiFilter::Any => true,
+        }
+    }
+}
+
+impl FromStr for RdiFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "residential" => Ok(RdiFilter::Residential),
+            "commercial" => Ok(RdiFilter::Commercial),
+            "any" => Ok(RdiFilter::Any),
+            _ => Err(anyhow!("unknown RDI: {}, expected one of: residential, commercial, any", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl AdditionalInfo {
+    /// a non-CMRA residential result with nothing else reported, for tests
+    pub fn sample() -> Self {
+        serde_json::from_str(r#"{"cmra": "N", "rdi": "Residential"}"#).unwrap()
+    }
+}
+
+impl AdditionalInfo {
+    pub fn is_cmra(&self) -> bool {
+        self.cmra == YesOrNo::Y
+    }
+
+    pub fn is_residential(&self) -> bool {
+        self.rdi == Rdi::Residential
+    }
+
+    /// whether the address is only deliverable with a unit number, i.e. the PMB of a CMRA mailbox,
+    /// given the DPV match code `D`, the DPV footnote `N1` or `C1`, or the enhanced match `missing-secondary`
+    pub fn is_missing_secondary(&self) -> bool {
+        self.dpv_match_code == "D"
+            || self.dpv_footnotes.as_bytes().chunks(2).any(|footnote| matches!(footnote, b"N1" | b"C1"))
+            || self.enhanced_match.split(',').any(|category| category.trim() == "missing-secondary")
+    }
+
+    /// [`AdditionalInfo::is_missing_secondary`] as a column, `None` if the verifier reports neither the DPV results nor the enhanced match
+    pub fn missing_secondary(&self) -> Option<YesOrNo> {
+        if self.dpv_match_code.is_empty() && self.dpv_footnotes.is_empty() && self.enhanced_match.is_empty() {
+            return None;
+        }
+        Some(if self.is_missing_secondary() { YesOrNo::Y } else { YesOrNo::N })
+    }
+
+    /// whether the RDI or the coordinates are missing, see [`AdditionalInfo::enrich`]
+    pub fn needs_enrichment(&self) -> bool {
+        self.rdi == Rdi::Unknown || self.latitude.is_none() || self.longitude.is_none()
+    }
+
+    /// fill in the RDI and the coordinates the verifier hasn't reported with the ones of `other`, see `--enrich`
+    pub fn enrich(&mut self, other: &AdditionalInfo) {
+        if self.rdi == Rdi::Unknown {
+            self.rdi = other.rdi.clone();
+        }
+        if self.latitude.is_none() || self.longitude.is_none() {
+            self.latitude = other.latitude;
+            self.longitude = other.longitude;
+        }
+    }
+
+    /// whether the results are the same, regardless of when and by which verifier they were queried
+    pub fn same_result(&self, other: &AdditionalInfo) -> bool {
+        let unstamped = |info: &AdditionalInfo| AdditionalInfo { verified_at: 0, verifier: String::new(), ..info.clone() };
+        unstamped(self) == unstamped(other)
+    }
+
+    /// the consensus of the votes, `None` if it's not verified with `--verifier consensus:...`
+    pub fn consensus(&self) -> Option<Consensus> {
+        if self.votes.is_empty() {
+            return None;
+        }
+        let cmra_votes = self.votes.iter().filter(|vote| vote.cmra == YesOrNo::Y).count();
+        let rdi_votes = |rdi: Rdi| self.votes.iter().filter(|vote| vote.rdi == rdi).count();
+        let (residential, commercial) = (rdi_votes(Rdi::Residential), rdi_votes(Rdi::Commercial));
+        Some(Consensus {
+            cmra: if cmra_votes * 2 >= self.votes.len() { YesOrNo::Y } else { YesOrNo::N },
+            rdi: match residential.cmp(&commercial) {
+                Ordering::Greater => Rdi::Residential,
+                Ordering::Less => Rdi::Commercial,
+                Ordering::Equal => Rdi::Unknown,
+            },
+            disagreement: (cmra_votes != 0 && cmra_votes != self.votes.len()) || (residential != 0 && commercial != 0),
+        })
+    }
+
+    /// the CMRA and RDI the mailbox is filtered by, the consensus if there are votes
+    pub fn decided(&self) -> (YesOrNo, Rdi) {
+        match self.consensus() {
+            Some(consensus) => (consensus.cmra, consensus.rdi),
+            None => (self.cmra.clone(), self.rdi.clone()),
+        }
+    }
+
+    /// how many candidates the verifier has matched, 1 unless it reports more
+    pub fn candidate_count(&self) -> usize {
+        self.candidates.len().max(1)
+    }
+
+    /// whether the candidates disagree on the CMRA or the RDI, so the picked one may be wrong
+    pub fn is_ambiguous(&self) -> bool {
+        self.candidates.iter().any(|candidate| candidate.cmra != self.cmra || candidate.rdi != self.rdi)
+    }
+}
+
+/// how good a DPV match code is, the lower the better
+///
+/// `Y` is confirmed, `S` has its secondary ignored, `D` is missing its secondary, and `N` is not confirmed
+pub fn dpv_match_rank(dpv_match_code: &str) -> u8 {
+    match dpv_match_code {
+        "Y" => 0,
+        "S" => 1,
+        "D" => 2,
+        "N" => 3,
+        _ => 4,
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum YesOrNo {
+    N,
+    Y,
+}
+
+impl YesOrNo {
+    /// parse the indicator, an empty value means it's not reported
+    pub fn try_from_optional(value: String) -> Result<Option<Self>, String> {
+        if value.is_empty() {
+            Ok(None)
+        } else {
+            Self::try_from(value).map(Some)
+        }
+    }
+}
+
+impl TryFrom<String> for YesOrNo {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "y" => Ok(YesOrNo::Y),
+            "n" => Ok(YesOrNo::N),
+            _ => Err(value),
+        }
+    }
+}
@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::SystemTime;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use smarty_rust_sdk::sdk::authentication::{Authenticate, SecretKeyCredential, WebsiteKeyCredential};
+use smarty_rust_sdk::sdk::batch::Batch;
+use smarty_rust_sdk::sdk::error::SmartyError;
+use smarty_rust_sdk::sdk::options::{Options, OptionsBuilder};
+use smarty_rust_sdk::us_street_api::candidate::Candidate as SmartyCandidate;
+use smarty_rust_sdk::us_street_api::client::USStreetAddressClient;
+use smarty_rust_sdk::us_street_api::lookup::{Lookup, MatchStrategy};
+use tracing::{debug, info, warn};
+use crate::atmb::model::Address;
+use crate::cassette::Cassette;
+use crate::error::Error;
+use crate::verify::AddressVerifier;
+use crate::verify::model::{self, AdditionalInfo, Candidate, Rdi, VerifiedAddress, YesOrNo};
+
+/// lookups allowed for a free trial account every month, the cap of a credential if not given
+const MONTHLY_LOOKUPS: u32 = 1000;
+/// maximum lookups of a single batch request
+const MAX_BATCH_SIZE: usize = 100;
+/// lookups used this month, stored under the cache directory
+const QUOTA_FILE: &str = "smarty_quota.json";
+/// the license of the free trial accounts
+pub const DEFAULT_LICENSE: &str = "us-core-cloud";
+
+/// How a Smarty client is authenticated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmartyCredential {
+    /// the auth ID and token of a secret key, see `CREDENTIALS`
+    SecretKey { auth_id: String, auth_token: String, monthly_lookups: Option<u32> },
+    /// a website (embedded) key, only accepted with a `Referer` of the hosts configured for it, see `--smarty-key`
+    WebsiteKey { key: String, referer: String, monthly_lookups: Option<u32> },
+}
+
+impl SmartyCredential {
+    /// the lookups of this month are counted under it
+    pub(crate) fn id(&self) -> &str {
+        match self {
+            SmartyCredential::SecretKey { auth_id, .. } => auth_id,
+            SmartyCredential::WebsiteKey { key, .. } => key,
+        }
+    }
+
+    /// lookups it can use every month, `None` if they are not capped, then only a `402 Payment Required` tells they are used up
+    pub(crate) fn monthly_lookups(&self) -> Option<u32> {
+        match self {
+            SmartyCredential::SecretKey { monthly_lookups, .. } | SmartyCredential::WebsiteKey { monthly_lookups, .. } => *monthly_lookups,
+        }
+    }
+
+    pub(crate) fn authentication(&self) -> Box<dyn Authenticate> {
+        match self {
+            SmartyCredential::SecretKey { auth_id, auth_token, .. } => SecretKeyCredential::new(auth_id.clone(), auth_token.clone()),
+            SmartyCredential::WebsiteKey { key, referer, .. } => WebsiteKeyCredential::new(key, referer),
+        }
+    }
+}
+
+/// the secret key capped at the lookups of a free trial account
+impl From<(String, String)> for SmartyCredential {
+    fn from((auth_id, auth_token): (String, String)) -> Self {
+        SmartyCredential::SecretKey { auth_id, auth_token, monthly_lookups: Some(MONTHLY_LOOKUPS) }
+    }
+}
+
+/// split the lookups a credential can use every month off it, i.e. `TOKEN:5000` or `TOKEN:unlimited`,
+/// it's capped at the lookups of a free trial account if not given
+pub fn split_monthly_lookups(credential: &str) -> anyhow::Result<(&str, Option<u32>)> {
+    let Some((credential, lookups)) = credential.rsplit_once(':') else {
+        return Ok((credential, Some(MONTHLY_LOOKUPS)));
+    };
+    let lookups = match lookups.trim() {
+        "unlimited" => None,
+        lookups => Some(lookups.parse().map_err(|_| anyhow!("expected the monthly lookups or `unlimited` after `:`, got `{}`", lookups))?),
+    };
+    Ok((credential, lookups))
+}
+
+/// How Smarty matches the addresses, selected via `--smarty-match`.
+///
+/// `Enhanced` also matches the addresses not in the USPS data, but not every plan includes it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SmartyMatch {
+    /// only the addresses USPS can deliver to
+    Strict,
+    /// the most likely candidate, even if it cannot be delivered to
+    Invalid,
+    #[default]
+    Enhanced,
+}
+
+impl From<SmartyMatch> for MatchStrategy {
+    fn from(value: SmartyMatch) -> Self {
+        match value {
+            SmartyMatch::Strict => MatchStrategy::Strict,
+            SmartyMatch::Invalid => MatchStrategy::Invalid,
+            SmartyMatch::Enhanced => MatchStrategy::Enhanced,
+        }
+    }
+}
+
+impl FromStr for SmartyMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(SmartyMatch::Strict),
+            "invalid" => Ok(SmartyMatch::Invalid),
+            "enhanced" => Ok(SmartyMatch::Enhanced),
+            _ => Err(anyhow!("unknown match strategy: {}, expected one of: strict, invalid, enhanced", s)),
+        }
+    }
+}
+
+/// A free trial account is limited to 1000 lookups per month.
+/// So we use multiple accounts to avoid the limitation.
+///
+/// As there are ~1700 atmb location currently, we need at least 2 accounts.
+///
+/// The proxy is `Send + Sync`, the lookups of every client are counted atomically.
+pub struct SmartyClientProxy {
+    clients: Vec<SmartyClient>,
+    state: Vec<ClientState>,
+    /// where the lookups used this month are persisted
+    quota_file: PathBuf,
+    month: String,
+    /// where the lookups are recorded to or replayed from, see `--record-http`
+    cassette: Option<Cassette>,
+    match_strategy: SmartyMatch,
+}
+
+impl SmartyClientProxy {
+    /// * `credentials` - at least one is required
+    /// * `cache_dir` - directory of the quota file, so the runs in the same month share the lookups
+    /// * `license` - the license of the plan of the credentials, i.e. [`DEFAULT_LICENSE`]
+    pub fn new(credentials: Vec<SmartyCredential>, cache_dir: impl AsRef<Path>, license: &str) -> anyhow::Result<Self> {
+        if credentials.is_empty() {
+            bail!("no Smarty credentials found");
+        }
+        let clients = credentials.iter()
+            .map(|credential| SmartyClient::new(credential, license))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let quota_file = cache_dir.as_ref().join(QUOTA_FILE);
+        let month = current_month();
+        let usage = QuotaUsage::load(&quota_file)?.for_month(&month);
+        let state = clients.iter().zip(&credentials)
+            .map(|(client, credential)| ClientState {
+                cap: credential.monthly_lookups(),
+                lookups: AtomicU32::new(usage.lookups.get(&client.id).copied().unwrap_or_default()),
+                exhausted: AtomicBool::new(usage.exhausted.contains(&client.id)),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let proxy = Self {
+            clients,
+            state,
+            quota_file,
+            month,
+            cassette: None,
+            match_strategy: SmartyMatch::default(),
+        };
+        match proxy.remaining() {
+            Some(remaining) => info!("[{}] Smarty lookups left this month", remaining),
+            None => info!("the Smarty lookups are not capped, the credentials are used until Smarty rejects them"),
+        }
+        Ok(proxy)
+    }
+
+    /// match the addresses with the strategy instead of [`SmartyMatch::Enhanced`]
+    pub fn with_match_strategy(mut self, match_strategy: SmartyMatch) -> Self {
+        self.match_strategy = match_strategy;
+        self
+    }
+
+    /// record the lookups to the cassette, or replay the recorded ones without sending any
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// lookups left of all the clients, `None` if any of them is not capped
+    fn remaining(&self) -> Option<usize> {
+        self.state.iter().map(|state| state.remaining().map(|remaining| remaining as usize)).sum()
+    }
+
+    /// reserve up to `lookups` lookups from the first client that is not exceeded,
+    /// returns the index of the client and the lookups it can take
+    ///
+    /// the lookups are reserved before sending, so concurrent batches cannot overrun a client,
+    /// and released if the request fails.
+    fn reserve(&self, lookups: usize) -> Option<(usize, usize)> {
+        self.state.iter().enumerate()
+            .find_map(|(idx, state)| state.reserve(lookups as u32).map(|reserved| (idx, reserved as usize)))
+    }
+
+}
+
+#[async_trait(?Send)]
+impl AddressVerifier for SmartyClientProxy {
+    fn name(&self) -> &'static str {
+        "smarty"
+    }
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo> {
+        self.inquire_batch(std::slice::from_ref(address)).await
+            .pop()
+            .unwrap_or_else(|| Err(anyhow!("no response from Smarty")))
+    }
+
+    fn batch_size(&self) -> usize {
+        MAX_BATCH_SIZE
+    }
+
+    fn remaining_quota(&self) -> Option<usize> {
+        self.remaining()
+    }
+
+    /// lookups sent successfully in this run, keyed by the auth ID
+    fn usage(&self) -> Vec<(String, usize)> {
+        self.clients.iter().zip(&self.state)
+            .map(|(client, state)| (client.id.clone(), state.consumed.load(Ordering::Acquire) as usize))
+            .collect()
+    }
+
+    /// persist the lookups used this month, the replayed ones don't count
+    fn save_cache(&self) -> anyhow::Result<()> {
+        if self.cassette.as_ref().is_some_and(|cassette| cassette.is_replay()) {
+            return Ok(());
+        }
+        let usage = QuotaUsage {
+            month: self.month.clone(),
+            lookups: self.clients.iter().zip(&self.state)
+                .map(|(client, state)| (client.id.clone(), state.lookups.load(Ordering::Acquire)))
+                .collect(),
+            exhausted: self.clients.iter().zip(&self.state)
+                .filter(|(_, state)| state.exhausted.load(Ordering::Acquire))
+                .map(|(client, _)| client.id.clone())
+                .collect(),
+        };
+        usage.save(&self.quota_file)
+    }
+
+    /// the addresses are sent in batches of up to 100, a batch is split if the client doesn't have enough lookups left
+    async fn inquire_batch(&self, addresses: &[Address]) -> Vec<anyhow::Result<AdditionalInfo>> {
+        if let Some(cassette) = self.cassette.as_ref().filter(|cassette| cassette.is_replay()) {
+            return addresses.iter().map(|address| replay(cassette, address)).collect();
+        }
+        let mut results = Vec::with_capacity(addresses.len());
+        let mut rest = addresses;
+        while !rest.is_empty() {
+            let Some((idx, reserved)) = self.reserve(rest.len().min(MAX_BATCH_SIZE)) else {
+                results.extend(rest.iter().map(|_| Err(Error::QuotaExceeded { verifier: "smarty" }.into())));
+                break;
+            };
+            let (batch, remaining) = rest.split_at(reserved);
+            match self.clients[idx].inquire_addresses(batch, self.match_strategy, self.cassette.as_ref()).await {
+                Ok(infos) => {
+                    self.state[idx].consumed.fetch_add(reserved as u32, Ordering::AcqRel);
+                    results.extend(infos);
+                }
+                Err(e) if rejected_status(&e).is_some() => {
+                    // retry the same batch through the next client
+                    warn!("Smarty client [{}] is rejected, switch to the next one: {:#}", idx + 1, e);
+                    // the rejected request hasn't used any lookups
+                    self.state[idx].release(reserved as u32);
+                    if rejected_status(&e) == Some(402) {
+                        // remembered as used up for the rest of the month, whatever it's capped at
+                        self.state[idx].exhaust();
+                    } else {
+                        self.state[idx].disable();
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    self.state[idx].release(reserved as u32);
+                    if let Some(cassette) = &self.cassette {
+                        batch.iter().for_each(|address| record(cassette, address, &Err(anyhow!("batch lookup failed: {:#}", e))));
+                    }
+                    results.extend(batch.iter().map(|_| Err(anyhow!("batch lookup failed: {:#}", e))));
+                }
+            }
+            rest = remaining;
+        }
+        results
+    }
+}
+
+/// the status if Smarty rejected the credential, `401 Unauthorized` if it's invalid
+/// and `402 Payment Required` if its lookups are used up
+fn rejected_status(error: &anyhow::Error) -> Option<u16> {
+    match error.downcast_ref::<SmartyError>() {
+        Some(SmartyError::HttpError { code, .. }) if matches!(code.as_u16(), 401 | 402) => Some(code.as_u16()),
+        _ => None,
+    }
+}
+
+/// the lookup of the address recorded by `--record-http`
+fn replay(cassette: &Cassette, address: &Address) -> anyhow::Result<AdditionalInfo> {
+    let results = serde_json::from_str(&cassette.play(&cassette_key(address))?)?;
+    Lookup { results, ..Lookup::from(address.clone()) }.try_into()
+}
+
+/// save the candidates of the address, or the error its lookup failed with
+fn record(cassette: &Cassette, address: &Address, result: &anyhow::Result<String>) {
+    if let Err(e) = cassette.record(&cassette_key(address), result) {
+        warn!("cannot record the lookup of [{}]: {:?}", address.line1, e);
+    }
+}
+
+/// the address as sent, i.e. `120 19th Street North|Birmingham|AL|35203`
+fn cassette_key(address: &Address) -> String {
+    [address.line1.as_str(), &address.city, &address.state, &address.full_zip()].join("|")
+}
+
+/// the month the quota belongs to, i.e. `2024-07`
+fn current_month() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()[..7].to_string()
+}
+
+/// Lookups used by every credential in a month, keyed by the auth ID
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuotaUsage {
+    month: String,
+    lookups: HashMap<String, u32>,
+    /// the credentials Smarty answered with `402 Payment Required`
+    #[serde(default)]
+    exhausted: Vec<String>,
+}
+
+impl QuotaUsage {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("cannot parse Smarty quota file [{}]: {}", path.display(), e))
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// the quota is reset every month
+    fn for_month(self, month: &str) -> Self {
+        if self.month == month {
+            self
+        } else {
+            Self {
+                month: month.to_string(),
+                ..Default::default()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct ClientState {
+    /// lookups the credential can use every month, `None` if not capped
+    cap: Option<u32>,
+    /// lookups that have been sent successfully or are in flight
+    lookups: AtomicU32,
+    /// lookups sent successfully since the proxy is created
+    consumed: AtomicU32,
+    /// the credential has been rejected by Smarty, it's not used anymore
+    disabled: AtomicBool,
+    /// Smarty rejected the credential as its lookups are used up, it's not used for the rest of the month
+    exhausted: AtomicBool,
+}
+
+impl ClientState {
+    /// take up to `lookups` from the remaining ones, `None` if the client is exceeded
+    fn reserve(&self, lookups: u32) -> Option<u32> {
+        if self.disabled.load(Ordering::Acquire) || self.exhausted.load(Ordering::Acquire) {
+            return None;
+        }
+        let mut reserved = 0;
+        self.lookups.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            reserved = self.cap.map_or(lookups, |cap| lookups.min(cap.saturating_sub(used)));
+            (reserved > 0).then_some(used + reserved)
+        })
+            .ok()
+            .map(|_| reserved)
+    }
+
+    fn disable(&self) {
+        self.disabled.store(true, Ordering::Release);
+    }
+
+    fn exhaust(&self) {
+        self.exhausted.store(true, Ordering::Release);
+    }
+
+    /// `None` if the lookups are not capped
+    fn remaining(&self) -> Option<u32> {
+        if self.disabled.load(Ordering::Acquire) || self.exhausted.load(Ordering::Acquire) {
+            Some(0)
+        } else {
+            self.cap.map(|cap| cap.saturating_sub(self.lookups.load(Ordering::Acquire)))
+        }
+    }
+
+    /// give back the lookups of a failed request
+    fn release(&self, lookups: u32) {
+        self.lookups.fetch_sub(lookups, Ordering::AcqRel);
+    }
+}
+
+struct SmartyClient {
+    /// auth ID or website key of the credential
+    id: String,
+    client: USStreetAddressClient,
+}
+
+impl SmartyClient {
+    fn new(credential: &SmartyCredential, license: &str) -> anyhow::Result<Self> {
+        Ok(
+            Self {
+                id: credential.id().to_string(),
+                client: USStreetAddressClient::new(Self::options(credential, license))?,
+            }
+        )
+    }
+
+    /// send the addresses in a single batch, at most 100 of them
+    ///
+    /// the outer error means the whole request failed, the inner ones are the addresses that cannot be matched.
+    /// the candidates of every address are saved to `cassette` if it's given.
+    async fn inquire_addresses(&self, addresses: &[Address], match_strategy: SmartyMatch, cassette: Option<&Cassette>) -> anyhow::Result<Vec<anyhow::Result<AdditionalInfo>>> {
+        let mut batch = Batch::default();
+        for address in addresses {
+            batch.push(Lookup { match_strategy: match_strategy.into(), ..Lookup::from(address.clone()) })?;
+        }
+        self.client.send(&mut batch).await?;
+        if let Some(cassette) = cassette {
+            for (address, lookup) in addresses.iter().zip(batch.records()) {
+                record(cassette, address, &serde_json::to_string_pretty(&lookup.results).map_err(Into::into));
+            }
+        }
+        let results = batch.records().iter()
+            .map(|lookup| lookup.clone().try_into())
+            .collect::<Vec<_>>();
+        if results.len() != addresses.len() {
+            bail!("expected [{}] results from Smarty, got [{}]", addresses.len(), results.len());
+        }
+        Ok(results)
+    }
+
+    fn options(credential: &SmartyCredential, license: &str) -> Options {
+        OptionsBuilder::new(Some(credential.authentication()))
+            .with_license(license)
+            .with_retries(3)
+            .build()
+    }
+}
+
+impl From<Address> for Lookup {
+    fn from(address: Address) -> Self {
+        Self {
+            zipcode: address.full_zip(),
+            street: address.line1,
+            city: address.city,
+            state: address.state,
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<Lookup> for AdditionalInfo {
+    type Error = anyhow::Error;
+
+    /// the best candidate by the DPV match code, or the first of the equally good ones as Smarty sorts them
+    ///
+    /// the candidates that cannot be parsed are skipped, it fails only if none of them can.
+    fn try_from(lookup: Lookup) -> Result<Self, Self::Error> {
+        if lookup.results.is_empty() {
+            bail!("no results found: {:?}", lookup);
+        }
+        let mut infos = Vec::with_capacity(lookup.results.len());
+        let mut last_error = None;
+        for candidate in lookup.results {
+            let street = candidate.delivery_line_1.clone();
+            match AdditionalInfo::try_from(candidate) {
+                Ok(info) => infos.push(info),
+                Err(e) => {
+                    debug!("skip the Smarty candidate [{}] of [{}]: {}", street, lookup.street, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if infos.is_empty() {
+            return Err(last_error.unwrap_or_else(|| anyhow!("no results found")));
+        }
+        if infos.len() == 1 {
+            return Ok(infos.remove(0));
+        }
+        let candidates = infos.iter()
+            .map(|info| {
+                let verified = info.verified.clone().unwrap_or_default();
+                Candidate {
+                    street: verified.street,
+                    zip: if verified.zip4.is_empty() { verified.zip } else { format!("{}-{}", verified.zip, verified.zip4) },
+                    dpv_match_code: info.dpv_match_code.clone(),
+                    cmra: info.cmra.clone(),
+                    rdi: info.rdi.clone(),
+                }
+            })
+            .collect();
+        let best = (0..infos.len()).min_by_key(|&idx| model::dpv_match_rank(&infos[idx].dpv_match_code)).unwrap();
+        let mut info = infos.swap_remove(best);
+        info.candidates = candidates;
+        Ok(info)
+    }
+}
+
+impl TryFrom<SmartyCandidate> for AdditionalInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(candidate: SmartyCandidate) -> Result<Self, Self::Error> {
+        // the coordinates are zero if the address cannot be geocoded
+        let geocoded = !matches!(candidate.metadata.precision.as_str(), "" | "Unknown");
+        Ok(
+            Self {
+                cmra: YesOrNo::try_from(candidate.analysis.dpv_cmra)
+                    .map_err(|e| anyhow!("failed to parse CMRA: {}", e))?,
+                rdi: Rdi::try_from(candidate.metadata.rdi)
+                    .map_err(|e| anyhow!("failed to parse RDI: {}", e))?,
+                dpv_match_code: candidate.analysis.dpv_match_code,
+                dpv_footnotes: candidate.analysis.dpv_footnotes,
+                active: YesOrNo::try_from_optional(candidate.analysis.active)
+                    .map_err(|e| anyhow!("failed to parse active flag: {}", e))?,
+                vacant: YesOrNo::try_from_optional(candidate.analysis.dpv_vacant)
+                    .map_err(|e| anyhow!("failed to parse vacant flag: {}", e))?,
+                no_stat: YesOrNo::try_from_optional(candidate.analysis.dpv_no_stat)
+                    .map_err(|e| anyhow!("failed to parse no-stat flag: {}", e))?,
+                enhanced_match: candidate.analysis.enhanced_match,
+                latitude: geocoded.then_some(candidate.metadata.latitude),
+                longitude: geocoded.then_some(candidate.metadata.longitude),
+                verified: Some(VerifiedAddress {
+                    street: candidate.delivery_line_1,
+                    city: candidate.components.city_name,
+                    state: candidate.components.state_abbreviation,
+                    zip: candidate.components.zipcode,
+                    zip4: candidate.components.plus4_code,
+                    county_name: candidate.metadata.county_name,
+                    county_fips: candidate.metadata.county_fips,
+                }),
+                double_check: None,
+                international: None,
+                candidates: Vec::new(),
+                votes: Vec::new(),
+                // stamped by the cache, see `CachedVerifier`
+                verified_at: 0,
+                verifier: String::new(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_state_reserve() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SmartyClientProxy>();
+
+        let state = ClientState { cap: Some(MONTHLY_LOOKUPS), ..Default::default() };
+        assert_eq!(state.reserve(MAX_BATCH_SIZE as u32), Some(100));
+        state.lookups.store(950, Ordering::Release);
+        assert_eq!(state.reserve(100), Some(50));
+        assert_eq!(state.reserve(1), None);
+        state.release(50);
+        assert_eq!(state.reserve(1), Some(1));
+        state.disable();
+        assert_eq!(state.reserve(1), None);
+        assert_eq!(state.remaining(), Some(0));
+
+        // only Smarty tells when the lookups are used up
+        let state = ClientState { lookups: AtomicU32::new(5000), ..Default::default() };
+        assert_eq!(state.reserve(100), Some(100));
+        assert_eq!(state.remaining(), None);
+        state.exhaust();
+        assert_eq!(state.reserve(1), None);
+        assert_eq!(state.remaining(), Some(0));
+    }
+
+    #[test]
+    fn test_split_monthly_lookups() {
+        assert_eq!(split_monthly_lookups("token").unwrap(), ("token", Some(MONTHLY_LOOKUPS)));
+        assert_eq!(split_monthly_lookups("token:5000").unwrap(), ("token", Some(5000)));
+        assert_eq!(split_monthly_lookups("token:unlimited").unwrap(), ("token", None));
+        assert!(split_monthly_lookups("token:many").is_err());
+    }
+
+    /// the proxy of the credential whose requests are all answered with the status, i.e. `401 Unauthorized`
+    fn rejected_proxy(credential: SmartyCredential, status: &'static str, cache_dir: &Path) -> SmartyClientProxy {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 4096];
+                let _ = std::io::Read::read(&mut stream, &mut request);
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                let _ = std::io::Write::write_all(&mut stream, response.as_bytes());
+            }
+        });
+        let mut proxy = SmartyClientProxy::new(vec![credential.clone()], cache_dir, DEFAULT_LICENSE).unwrap();
+        proxy.clients[0].client = USStreetAddressClient::new_custom_base_url(url.parse().unwrap(), SmartyClient::options(&credential, DEFAULT_LICENSE)).unwrap();
+        proxy
+    }
+
+    async fn assert_quota_exceeded(proxy: &SmartyClientProxy) {
+        let address = Address::from_lines("120 19th Street North", "Birmingham, AL 35203").unwrap();
+        let results = proxy.inquire_batch(&[address]).await;
+        assert!(matches!(Error::of(results[0].as_ref().unwrap_err()), Some(Error::QuotaExceeded { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_keeps_quota() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let quota_file = cache_dir.path().join(QUOTA_FILE);
+        let usage = QuotaUsage { month: current_month(), lookups: HashMap::from([("id".to_string(), 10)]), ..Default::default() };
+        usage.save(&quota_file).unwrap();
+        let credential = SmartyCredential::from(("id".to_string(), "token".to_string()));
+        let proxy = rejected_proxy(credential, "401 Unauthorized", cache_dir.path());
+
+        assert_quota_exceeded(&proxy).await;
+        proxy.save_cache().unwrap();
+        let usage = QuotaUsage::load(&quota_file).unwrap();
+        assert_eq!(usage.lookups["id"], 10);
+        assert!(usage.exhausted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_payment_required_without_cap() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let credential = SmartyCredential::SecretKey { auth_id: "id".to_string(), auth_token: "token".to_string(), monthly_lookups: None };
+        let proxy = rejected_proxy(credential.clone(), "402 Payment Required", cache_dir.path());
+        assert_eq!(proxy.remaining_quota(), None);
+
+        assert_quota_exceeded(&proxy).await;
+        assert_eq!(proxy.remaining_quota(), Some(0));
+        proxy.save_cache().unwrap();
+        // still used up in the next run of the month
+        let proxy = SmartyClientProxy::new(vec![credential], cache_dir.path(), DEFAULT_LICENSE).unwrap();
+        assert_eq!(proxy.remaining_quota(), Some(0));
+    }
+
+    #[test]
+    fn test_quota_month_rollover() {
+        let usage = QuotaUsage {
+            month: "2024-07".to_string(),
+            lookups: HashMap::from([("id".to_string(), 900)]),
+            exhausted: vec!["id".to_string()],
+        };
+        let usage = usage.for_month("2024-07");
+        assert_eq!(usage.lookups["id"], 900);
+        let usage = usage.for_month("2024-08");
+        assert_eq!(usage.month, "2024-08");
+        assert!(usage.lookups.is_empty());
+        assert!(usage.exhausted.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use crate::atmb::model::Address;
+use crate::verify::AddressVerifier;
+use crate::verify::model::{AdditionalInfo, Rdi, VerifiedAddress, YesOrNo};
+
+const TOKEN_URL: &str = "https://apis.usps.com/oauth2/v3/token";
+const ADDRESS_URL: &str = "https://apis.usps.com/addresses/v3/address";
+/// the token is requested again this long before it expires, so a lookup isn't sent with one about to expire
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Client of the USPS Addresses API, the successor of the Web Tools address validation API.
+///
+/// The credentials are the consumer key and secret of an app registered on the USPS developer portal,
+/// loaded from the `USPS_CLIENT_ID` and `USPS_CLIENT_SECRET` environment variables.
+pub struct UspsClient {
+    client: Client,
+    client_id: String,
+    client_secret: String,
+    token: TokenCache,
+}
+
+impl UspsClient {
+    pub fn new() -> anyhow::Result<Self> {
+        let env = |name: &str| std::env::var(name)
+            .map_err(|_| anyhow!("`{}` environment variable must be set", name));
+        Ok(
+            Self {
+                client: Client::new(),
+                client_id: env("USPS_CLIENT_ID")?,
+                client_secret: env("USPS_CLIENT_SECRET")?,
+                token: TokenCache::default(),
+            }
+        )
+    }
+
+    /// get the OAuth access token, it's shared by all lookups and requested again once it expires
+    async fn token(&self) -> anyhow::Result<String> {
+        self.token.get(|| async {
+            let resp = self.client.post(TOKEN_URL)
+                .form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", &self.client_id),
+                    ("client_secret", &self.client_secret),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<TokenResponse>()
+                .await?;
+            Ok(AccessToken::from(resp))
+        })
+            .await
+    }
+
+    async fn lookup(&self, token: &str, query: &[(&str, &str)]) -> anyhow::Result<Response> {
+        Ok(self.client.get(ADDRESS_URL).bearer_auth(token).query(query).send().await?)
+    }
+}
+
+#[async_trait(?Send)]
+impl AddressVerifier for UspsClient {
+    fn name(&self) -> &'static str {
+        "usps"
+    }
+
+    async fn inquire(&self, address: &Address) -> anyhow::Result<AdditionalInfo> {
+        let token = self.token().await?;
+        let mut query = vec![
+            ("streetAddress", address.line1.as_str()),
+            ("city", address.city.as_str()),
+            ("state", address.state.as_str()),
+            ("ZIPCode", address.zip.as_str()),
+        ];
+        if let Some(zip4) = &address.zip4 {
+            query.push(("ZIPPlus4", zip4));
+        }
+        let mut resp = self.lookup(&token, &query).await?;
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            // the token is revoked or expired earlier than it said, retry once with a new one
+            self.token.invalidate(&token).await;
+            resp = self.lookup(&self.token().await?, &query).await?;
+        }
+        if !resp.status().is_success() {
+            bail!("USPS responded with [{}]: {}", resp.status(), resp.text().await.unwrap_or_default());
+        }
+        resp.json::<AddressResponse>().await?.try_into()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// seconds the token is valid for
+    expires_in: Option<u64>,
+}
+
+struct AccessToken {
+    value: String,
+    /// `None` if USPS didn't tell, it's only requested again once rejected
+    expires_at: Option<Instant>,
+}
+
+impl From<TokenResponse> for AccessToken {
+    fn from(resp: TokenResponse) -> Self {
+        Self {
+            value: resp.access_token,
+            expires_at: resp.expires_in.map(|secs| Instant::now() + Duration::from_secs(secs)),
+        }
+    }
+}
+
+impl AccessToken {
+    fn is_fresh(&self) -> bool {
+        self.expires_at.is_none_or(|expires_at| Instant::now() + TOKEN_EXPIRY_MARGIN < expires_at)
+    }
+}
+
+/// The access token shared by the lookups, `serve` and `--watch` outlive a single token.
+#[derive(Default)]
+struct TokenCache {
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl TokenCache {
+    /// the cached token, or a new one from `request` if there's none or it's about to expire
+    async fn get<F, Fut>(&self, request: F) -> anyhow::Result<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<AccessToken>>,
+    {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|token| token.is_fresh()) {
+            return Ok(token.value.clone());
+        }
+        let value = token.insert(request().await?).value.clone();
+        Ok(value)
+    }
+
+    /// drop the token rejected by USPS, unless another lookup has replaced it already
+    async fn invalidate(&self, rejected: &str) {
+        let mut token = self.token.lock().await;
+        if token.as_ref().is_some_and(|token| token.value == rejected) {
+            *token = None;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddressResponse {
+    address: Option<StandardizedAddress>,
+    additional_info: Option<AddressAdditionalInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StandardizedAddress {
+    #[serde(default)]
+    street_address: String,
+    secondary_address: Option<String>,
+    #[serde(default)]
+    city: String,
+    #[serde(default)]
+    state: String,
+    #[serde(rename = "ZIPCode", default)]
+    zip: String,
+    #[serde(rename = "ZIPPlus4", default)]
+    zip4: Option<String>,
+}
+
+/// DPV indicators of the address, all of them are `Y` or `N`
+#[derive(Debug, Deserialize)]
+struct AddressAdditionalInfo {
+    #[serde(rename = "DPVConfirmation")]
+    dpv_confirmation: Option<String>,
+    #[serde(rename = "DPVCMRA")]
+    dpv_cmra: Option<String>,
+    business: Option<String>,
+    vacant: Option<String>,
+}
+
+impl TryFrom<AddressResponse> for AdditionalInfo {
+    type Error = anyhow::Error;
+
+    fn try_from(resp: AddressResponse) -> Result<Self, Self::Error> {
+        let info = resp.additional_info
+            .ok_or_else(|| anyhow!("no additional info found in USPS response"))?;
+        let cmra = info.dpv_cmra
+            .ok_or_else(|| anyhow!("no CMRA indicator found in USPS response"))?;
+
+        Ok(
+            Self {
+                cmra: YesOrNo::try_from(cmra)
+                    .map_err(|e| anyhow!("failed to parse CMRA: {}", e))?,
+                // USPS only tells whether the address is a business
+                rdi: match info.business.map(YesOrNo::try_from) {
+                    Some(Ok(YesOrNo::Y)) => Rdi::Commercial,
+                    Some(Ok(YesOrNo::N)) => Rdi::Residential,
+                    Some(Err(e)) => bail!("failed to parse business indicator: {}", e),
+                    None => Rdi::Unknown,
+                },
+                dpv_match_code: info.dpv_confirmation.unwrap_or_default(),
+                // USPS doesn't report the DPV footnotes nor the active flag
+                dpv_footnotes: String::new(),
+                active: None,
+                vacant: YesOrNo::try_from_optional(info.vacant.unwrap_or_default())
+                    .map_err(|e| anyhow!("failed to parse vacant indicator: {}", e))?,
+                // nor the no-stat flag, and it has no enhanced matching
+                no_stat: None,
+                enhanced_match: String::new(),
+                // USPS doesn't report the county nor the coordinates
+                latitude: None,
+                longitude: None,
+                verified: resp.address.map(|address| VerifiedAddress {
+                    street: match address.secondary_address {
+                        Some(secondary) if !secondary.is_empty() => format!("{} {}", address.street_address, secondary),
+                        _ => address.street_address,
+                    },
+                    city: address.city,
+                    state: address.state,
+                    zip: address.zip,
+                    zip4: address.zip4.unwrap_or_default(),
+                    ..Default::default()
+                }),
+                double_check: None,
+                international: None,
+                candidates: Vec::new(),
+                votes: Vec::new(),
+                // stamped by the cache, see `CachedVerifier`
+                verified_at: 0,
+                verifier: String::new(),
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_refresh_token() {
+        let cache = TokenCache::default();
+        let requested = Cell::new(0);
+        let request = |expires_in: Option<u64>| {
+            requested.set(requested.get() + 1);
+            let resp = TokenResponse { access_token: format!("token-{}", requested.get()), expires_in };
+            async move { Ok(AccessToken::from(resp)) }
+        };
+
+        assert_eq!(cache.get(|| request(Some(28799))).await.unwrap(), "token-1");
+        assert_eq!(cache.get(|| request(Some(28799))).await.unwrap(), "token-1");
+        // about to expire
+        *cache.token.lock().await = Some(AccessToken::from(TokenResponse { access_token: "expiring".to_string(), expires_in: Some(30) }));
+        assert_eq!(cache.get(|| request(None)).await.unwrap(), "token-2");
+        // a token without `expires_in` is kept until it's rejected
+        assert_eq!(cache.get(|| request(None)).await.unwrap(), "token-2");
+        cache.invalidate("token-1").await;
+        assert_eq!(cache.get(|| request(None)).await.unwrap(), "token-2");
+        cache.invalidate("token-2").await;
+        assert_eq!(cache.get(|| request(None)).await.unwrap(), "token-3");
+        assert_eq!(requested.get(), 3);
+    }
+
+    #[test]
+    fn test_parse_address_response() {
+        let json = r#"{
+            "address": {"streetAddress": "120 19TH ST N STE MAILBOX", "city": "BIRMINGHAM", "state": "AL", "ZIPCode": "35203", "ZIPPlus4": "3712"},
+            "additionalInfo": {"deliveryPoint": "99", "DPVConfirmation": "Y", "DPVCMRA": "Y", "business": "Y", "vacant": "N"}
+        }"#;
+        let resp: AddressResponse = serde_json::from_str(json).unwrap();
+        let info = AdditionalInfo::try_from(resp).unwrap();
+        assert!(info.is_cmra());
+        assert_eq!(info.rdi, Rdi::Commercial);
+        assert_eq!(info.dpv_match_code, "Y");
+        assert_eq!(info.vacant, Some(YesOrNo::N));
+        let verified = info.verified.unwrap();
+        assert_eq!(verified.street, "120 19TH ST N STE MAILBOX");
+        assert_eq!((verified.zip.as_str(), verified.zip4.as_str()), ("35203", "3712"));
+    }
+}
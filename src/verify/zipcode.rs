@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::bail;
+use serde::Deserialize;
+use smarty_rust_sdk::sdk::batch::Batch;
+use smarty_rust_sdk::sdk::options::OptionsBuilder;
+use smarty_rust_sdk::us_zipcode_api::candidate::ZipcodeResult;
+use smarty_rust_sdk::us_zipcode_api::client::USZipcodeClient;
+use smarty_rust_sdk::us_zipcode_api::lookup::Lookup;
+use crate::atmb::model::Address;
+use crate::verify::SmartyCredential;
+
+/// the results of the ZIP Code API, stored under the cache directory
+const CACHE_FILE: &str = "zipcode_cache.json";
+/// maximum lookups of a single batch request
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Checks whether the city, state and ZIP of the addresses agree with Smarty's US ZIP Code API, see `--zip-precheck`.
+///
+/// The addresses a scraped page is parsed wrong for are caught before a lookup of the street API is spent on them.
+/// Many locations share their city and ZIP, and the results are cached without expiry, so only the combinations
+/// never seen before are looked up.
+pub struct ZipChecker {
+    client: USZipcodeClient,
+    /// keyed by `city|state|zip`, the problem reported or empty if they agree
+    cache: HashMap<String, String>,
+    cache_file: PathBuf,
+}
+
+/// the fields of a result that tell whether the lookup is valid, the others are only needed by the street API
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ZipStatus {
+    /// empty if valid, otherwise i.e. `conflict` or `invalid_zipcode`
+    status: String,
+    reason: String,
+}
+
+impl ZipChecker {
+    /// * `cache_dir` - directory of the cached results
+    pub fn new(credential: &SmartyCredential, license: &str, cache_dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let options = OptionsBuilder::new(Some(credential.authentication()))
+            .with_license(license)
+            .with_retries(3)
+            .build();
+        let cache_file = cache_dir.as_ref().join(CACHE_FILE);
+        let cache = if cache_file.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&cache_file)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(
+            Self {
+                client: USZipcodeClient::new(options)?,
+                cache,
+                cache_file,
+            }
+        )
+    }
+
+    /// the problem of every address, `None` if its city, state and ZIP agree
+    pub async fn check(&mut self, addresses: &[&Address]) -> anyhow::Result<Vec<Option<String>>> {
+        let mut unknown = addresses.iter()
+            .map(|address| key(address))
+            .filter(|key| !self.cache.contains_key(key))
+            .collect::<Vec<_>>();
+        unknown.sort();
+        unknown.dedup();
+        for keys in unknown.chunks(MAX_BATCH_SIZE) {
+            let mut batch = Batch::default();
+            for key in keys {
+                let [city, state, zip] = key.splitn(3, '|').collect::<Vec<_>>()[..] else {
+                    unreachable!("the key is joined from three fields");
+                };
+                batch.push(Lookup {
+                    city: city.to_string(),
+                    state: state.to_string(),
+                    zipcode: zip.to_string(),
+                    ..Default::default()
+                })?;
+            }
+            self.client.send(&mut batch).await?;
+            if batch.records().len() != keys.len() {
+                bail!("expected [{}] results from the ZIP Code API, got [{}]", keys.len(), batch.records().len());
+            }
+            for (key, lookup) in keys.iter().zip(batch.records()) {
+                self.cache.insert(key.clone(), problem(&lookup.results)?);
+            }
+        }
+        Ok(
+            addresses.iter()
+                .map(|address| self.cache.get(&key(address)).filter(|problem| !problem.is_empty()).cloned())
+                .collect()
+        )
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(parent) = self.cache_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.cache_file, serde_json::to_string_pretty(&self.cache)?)?;
+        Ok(())
+    }
+}
+
+fn key(address: &Address) -> String {
+    [address.city.to_uppercase(), address.state.to_uppercase(), address.zip.clone()].join("|")
+}
+
+/// the reason the lookup is invalid, empty if it's valid
+fn problem(result: &ZipcodeResult) -> anyhow::Result<String> {
+    // the fields of the result are private, so they're read back from its JSON
+    let status = serde_json::from_value::<ZipStatus>(serde_json::to_value(result)?)?;
+    Ok(
+        match status.status.as_str() {
+            "" => String::new(),
+            code if status.reason.is_empty() => code.to_string(),
+            _ => status.reason,
+        }
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_problem() {
+        let conflict = r#"{"input_index": 0, "status": "conflict", "reason": "Conflicting ZIP Code/city/state information."}"#;
+        let result = serde_json::from_str::<ZipcodeResult>(conflict).unwrap();
+        assert_eq!(problem(&result).unwrap(), "Conflicting ZIP Code/city/state information.");
+
+        let valid = r#"{"input_index": 0, "city_states": [{"city": "Dallas", "state_abbreviation": "TX"}], "zipcodes": [{"zipcode": "75201"}]}"#;
+        assert_eq!(problem(&serde_json::from_str(valid).unwrap()).unwrap(), "");
+    }
+}
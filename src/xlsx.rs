@@ -0,0 +1,276 @@
+use std::path::Path;
+
+/// columns holding numbers, written as numeric cells so they can be sorted and filtered as numbers
+const NUMERIC_COLUMNS: [&str; 3] = ["price_amount", "latitude", "longitude"];
+/// background of the rows by RDI, as `(rdi, ARGB color)`
+const RDI_COLORS: [(&str, &str); 2] = [("Residential", "FFC6EFCE"), ("Commercial", "FFFFEB9C")];
+
+/// cell styles, the indexes of `cellXfs` in the stylesheet
+const STYLE_HEADER: u32 = 1;
+const STYLE_HYPERLINK: u32 = 2;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+/// Write the rows as a single sheet workbook.
+///
+/// The header row is bold and frozen with an auto filter, the `link` column is hyperlinked,
+/// and the rows are colored by the `rdi` column if there is one.
+pub fn write(header: &[String], rows: &[Vec<String>], path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let last_cell = format!("{}{}", column_name(header.len().saturating_sub(1)), rows.len() + 1);
+    let (sheet, links) = worksheet(header, rows, &last_cell);
+    let mut zip = ZipWriter::default();
+    zip.add("[Content_Types].xml", CONTENT_TYPES.as_bytes());
+    zip.add("_rels/.rels", ROOT_RELS.as_bytes());
+    zip.add("xl/workbook.xml", workbook(&last_cell).as_bytes());
+    zip.add("xl/_rels/workbook.xml.rels", WORKBOOK_RELS.as_bytes());
+    zip.add("xl/styles.xml", styles().as_bytes());
+    zip.add("xl/worksheets/sheet1.xml", sheet.as_bytes());
+    if !links.is_empty() {
+        zip.add("xl/worksheets/_rels/sheet1.xml.rels", sheet_rels(&links).as_bytes());
+    }
+    std::fs::write(path, zip.finish())?;
+    Ok(())
+}
+
+fn workbook(last_cell: &str) -> String {
+    // the range of the auto filter, with absolute references
+    let (column, row) = last_cell.split_at(last_cell.find(|c: char| c.is_ascii_digit()).unwrap_or(last_cell.len()));
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Mailboxes" sheetId="1" r:id="rId1"/></sheets><definedNames><definedName name="_xlnm._FilterDatabase" localSheetId="0" hidden="1">Mailboxes!$A$1:${column}${row}</definedName></definedNames></workbook>"#
+    )
+}
+
+fn styles() -> String {
+    let dxfs = RDI_COLORS.iter()
+        .map(|(_, color)| format!(r#"<dxf><fill><patternFill><bgColor rgb="{color}"/></patternFill></fill></dxf>"#))
+        .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="3"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font><font><u/><sz val="11"/><color rgb="FF0563C1"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="3"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/><xf numFmtId="0" fontId="2" fillId="0" borderId="0" xfId="0" applyFont="1"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles><dxfs count="{}">{dxfs}</dxfs></styleSheet>"#,
+        RDI_COLORS.len(),
+    )
+}
+
+/// the sheet XML and the URLs of the hyperlinks, `rId<n>` refers to the n-th URL
+fn worksheet(header: &[String], rows: &[Vec<String>], last_cell: &str) -> (String, Vec<String>) {
+    let link_column = header.iter().position(|name| name == "link");
+    let numeric = header.iter().map(|name| NUMERIC_COLUMNS.contains(&name.as_str())).collect::<Vec<_>>();
+
+    let mut data = String::from(r#"<row r="1">"#);
+    for (idx, name) in header.iter().enumerate() {
+        data.push_str(&string_cell(&format!("{}1", column_name(idx)), name, Some(STYLE_HEADER)));
+    }
+    data.push_str("</row>");
+
+    let mut hyperlinks = String::new();
+    let mut links = Vec::new();
+    for (row_idx, row) in rows.iter().enumerate() {
+        let row_num = row_idx + 2;
+        data.push_str(&format!(r#"<row r="{row_num}">"#));
+        for (idx, value) in row.iter().enumerate() {
+            let cell = format!("{}{}", column_name(idx), row_num);
+            if value.is_empty() {
+                continue;
+            }
+            if numeric.get(idx).copied().unwrap_or_default() && value.parse::<f64>().is_ok() {
+                data.push_str(&format!(r#"<c r="{cell}"><v>{value}</v></c>"#));
+            } else if Some(idx) == link_column {
+                links.push(value.clone());
+                hyperlinks.push_str(&format!(r#"<hyperlink ref="{cell}" r:id="rId{}"/>"#, links.len()));
+                data.push_str(&string_cell(&cell, value, Some(STYLE_HYPERLINK)));
+            } else {
+                data.push_str(&string_cell(&cell, value, None));
+            }
+        }
+        data.push_str("</row>");
+    }
+
+    let mut sheet = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#, "\n",
+        r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+        r#"<sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews>"#,
+    ));
+    sheet.push_str(&format!("<sheetData>{data}</sheetData>"));
+    sheet.push_str(&format!(r#"<autoFilter ref="A1:{last_cell}"/>"#));
+    if let Some(rdi_column) = header.iter().position(|name| name == "rdi").filter(|_| !rows.is_empty()) {
+        let rdi_column = column_name(rdi_column);
+        sheet.push_str(&format!(r#"<conditionalFormatting sqref="A2:{last_cell}">"#));
+        for (idx, (rdi, _)) in RDI_COLORS.iter().enumerate() {
+            sheet.push_str(&format!(
+                r#"<cfRule type="expression" dxfId="{idx}" priority="{}"><formula>${rdi_column}2="{rdi}"</formula></cfRule>"#,
+                idx + 1,
+            ));
+        }
+        sheet.push_str("</conditionalFormatting>");
+    }
+    if !hyperlinks.is_empty() {
+        sheet.push_str(&format!("<hyperlinks>{hyperlinks}</hyperlinks>"));
+    }
+    sheet.push_str("</worksheet>");
+    (sheet, links)
+}
+
+fn sheet_rels(links: &[String]) -> String {
+    let relationships = links.iter().enumerate()
+        .map(|(idx, link)| format!(
+            r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="{}" TargetMode="External"/>"#,
+            idx + 1,
+            escape(link),
+        ))
+        .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{relationships}</Relationships>"#
+    )
+}
+
+fn string_cell(cell: &str, value: &str, style: Option<u32>) -> String {
+    let style = style.map(|style| format!(r#" s="{style}""#)).unwrap_or_default();
+    format!(r#"<c r="{cell}"{style} t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#, escape(value))
+}
+
+/// i.e. `0` -> `A`, `26` -> `AA`
+fn column_name(mut idx: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (idx % 26) as u8);
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// the text as XML, without the control characters XML doesn't allow, all but tab, LF and CR
+pub(crate) fn escape(s: &str) -> String {
+    s.replace(|c: char| c < ' ' && !matches!(c, '\t' | '\n' | '\r'), "")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A zip archive of uncompressed entries, which is all a workbook needs.
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    fn add(&mut self, name: &str, content: &[u8]) {
+        let offset = self.data.len() as u32;
+        let crc = crc32(content);
+        let size = content.len() as u32;
+        // version 2.0, UTF-8 names, stored, the DOS time of 1980-01-01 00:00
+        let common = |buf: &mut Vec<u8>| {
+            buf.extend_from_slice(&20u16.to_le_bytes());
+            buf.extend_from_slice(&0x0800u16.to_le_bytes());
+            buf.extend_from_slice(&0u16.to_le_bytes());
+            buf.extend_from_slice(&0u16.to_le_bytes());
+            buf.extend_from_slice(&0x21u16.to_le_bytes());
+            buf.extend_from_slice(&crc.to_le_bytes());
+            buf.extend_from_slice(&size.to_le_bytes());
+            buf.extend_from_slice(&size.to_le_bytes());
+            buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&0u16.to_le_bytes());
+        };
+
+        self.data.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        common(&mut self.data);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(content);
+
+        let cd = &mut self.central_directory;
+        cd.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        cd.extend_from_slice(&20u16.to_le_bytes());
+        common(cd);
+        // comment length, disk number, internal and external attributes
+        cd.extend_from_slice(&[0; 10]);
+        cd.extend_from_slice(&offset.to_le_bytes());
+        cd.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.central_directory.len() as u32;
+        self.data.append(&mut self.central_directory);
+        self.data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.data.extend_from_slice(&[0; 4]);
+        self.data.extend_from_slice(&self.entries.to_le_bytes());
+        self.data.extend_from_slice(&self.entries.to_le_bytes());
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data
+    }
+}
+
+/// CRC-32 of the zip entries, i.e. IEEE 802.3
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_xlsx_parts() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(27 * 26), "AAA");
+
+        let header = ["name", "link", "rdi"].map(String::from).to_vec();
+        let rows = vec![["A & B", "https://example.com/?a=1&b=2", "Residential"].map(String::from).to_vec()];
+        let (sheet, links) = worksheet(&header, &rows, "C2");
+        assert!(sheet.contains(r#"<t xml:space="preserve">A &amp; B</t>"#));
+        assert!(sheet.contains(r#"<hyperlink ref="B2" r:id="rId1"/>"#));
+        assert!(sheet.contains(r#"<formula>$C2="Residential"</formula>"#));
+        assert_eq!(links, ["https://example.com/?a=1&b=2"]);
+        assert_eq!(escape("A\u{0}\u{1}\u{1f}\tB\r\n<C>"), "A\tB\r\n&lt;C&gt;");
+    }
+
+    #[test]
+    fn test_xlsx_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mailboxes.xlsx");
+        let header = ["name", "link", "price_amount"].map(String::from).to_vec();
+        let rows = vec![["A\u{1}B", "https://example.com/a", "9.99"].map(String::from).to_vec()];
+        write(&header, &rows, &path).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut names = archive.file_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, [
+            "[Content_Types].xml", "_rels/.rels", "xl/_rels/workbook.xml.rels", "xl/styles.xml",
+            "xl/workbook.xml", "xl/worksheets/_rels/sheet1.xml.rels", "xl/worksheets/sheet1.xml",
+        ]);
+        // the CRC is checked once the entry is read to the end
+        let mut sheet = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("xl/worksheets/sheet1.xml").unwrap(), &mut sheet).unwrap();
+        assert!(sheet.contains(r#"<t xml:space="preserve">AB</t>"#));
+        assert!(sheet.contains(r#"<c r="C2"><v>9.99</v></c>"#));
+    }
+}
@@ -0,0 +1,171 @@
+//! Crawl the saved ATMB pages through the whole crawl logic, without network access.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+use anyhow::bail;
+use async_trait::async_trait;
+use atmb_us_physical::atmb::{ATMBCrawl, FixtureFetcher, IncompleteCrawl, PageFetcher};
+use atmb_us_physical::atmb::page::StatePage;
+use atmb_us_physical::country::Country;
+use atmb_us_physical::error::Error;
+use atmb_us_physical::provider::{CrawlConfig, MailboxProvider};
+
+const COUNTRY_PAGE_HTML: &str = include_str!("../test_data/https___www.anytimemailbox.com_l_usa.html");
+const STATE_PAGE_HTML: &str = include_str!("../test_data/https___www.anytimemailbox.com_l_usa_alabama.html");
+const LOCATION_PAGE_HTML: &str = include_str!("../test_data/https___www.anytimemailbox.com_s_birmingham-120-19th-street-north.html");
+const BIRMINGHAM_LINK: &str = "https://www.anytimemailbox.com/s/birmingham-120-19th-street-north";
+
+/// the failed detail pages are retried without waiting, or not retried if `retry_delay` is `None`
+fn alabama_config(retry_delay: Option<Duration>) -> CrawlConfig {
+    CrawlConfig {
+        states: Some(vec!["AL".parse().unwrap()]),
+        retry_delay,
+        ..Default::default()
+    }
+}
+
+/// fails the first request of every detail page matching `flaky`
+struct FlakyFetcher {
+    inner: FixtureFetcher,
+    flaky: &'static str,
+    failed: RefCell<HashSet<String>>,
+}
+
+#[async_trait(?Send)]
+impl PageFetcher for FlakyFetcher {
+    async fn fetch_page(&self, url_path: &str) -> anyhow::Result<String> {
+        if url_path.contains(self.flaky) && self.failed.borrow_mut().insert(url_path.to_string()) {
+            bail!("connection reset for [{}]", url_path);
+        }
+        self.inner.fetch_page(url_path).await
+    }
+}
+
+/// the index, the US page and the Alabama page, with a detail page for every location unless `skip` says otherwise
+fn fetcher(skip: impl Fn(&str) -> bool) -> FixtureFetcher {
+    let mut fetcher = FixtureFetcher::new()
+        .with_page("/locations", COUNTRY_PAGE_HTML)
+        .with_page("/l/usa", COUNTRY_PAGE_HTML)
+        .with_page("/l/usa/alabama", STATE_PAGE_HTML)
+        .with_page(BIRMINGHAM_LINK, LOCATION_PAGE_HTML);
+    let mailboxes = StatePage::parse_html(STATE_PAGE_HTML).unwrap().to_mailboxes(Country::Us).unwrap();
+    for mailbox in mailboxes.iter().filter(|mailbox| mailbox.link != BIRMINGHAM_LINK && !skip(&mailbox.link)) {
+        let html = format!(
+            r#"<div class="t-sec1"><div class="t-text"><div>YOUR NAME</div><div>{}</div><div>Suite MAILBOX</div><div>{}, {} {}</div><div>United States</div></div></div>"#,
+            mailbox.address.line1, mailbox.address.city, mailbox.address.state, mailbox.address.full_zip(),
+        );
+        fetcher = fetcher.with_page(&mailbox.link, html);
+    }
+    fetcher
+}
+
+#[tokio::test]
+async fn test_crawl_saved_pages() {
+    let crawl = ATMBCrawl::with_fetcher(fetcher(|_| false), &alabama_config(None));
+    let mailboxes = crawl.fetch().await.unwrap();
+    assert_eq!(mailboxes.len(), 10);
+    assert!(mailboxes.iter().all(|mailbox| mailbox.address.state == "AL" && mailbox.address.line1.ends_with("Suite MAILBOX")));
+    let birmingham = mailboxes.iter().find(|mailbox| mailbox.link == BIRMINGHAM_LINK).unwrap();
+    assert_eq!(birmingham.operator.as_deref(), Some("Forge"));
+}
+
+#[tokio::test]
+async fn test_crawl_changed_layout() {
+    let fetcher = FixtureFetcher::new()
+        .with_page("/locations", COUNTRY_PAGE_HTML)
+        .with_page("/l/usa", "<html><body><ul><li>Alabama</li></ul></body></html>");
+    let err = ATMBCrawl::with_fetcher(fetcher, &alabama_config(None)).fetch().await.unwrap_err();
+    match Error::of(&err) {
+        Some(Error::Parse { url, what, .. }) => assert_eq!((url.as_str(), *what), ("/l/usa", "state link")),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_crawl_missing_detail_pages() {
+    let crawl = ATMBCrawl::with_fetcher(fetcher(|link| link.contains("montgomery")), &alabama_config(Some(Duration::ZERO)));
+    let err = crawl.fetch().await.unwrap_err();
+    let incomplete = err.downcast_ref::<IncompleteCrawl>().unwrap();
+    assert!(!incomplete.failed.is_empty());
+    assert_eq!(incomplete.fetched.len() + incomplete.failed.len(), 10);
+    assert!(incomplete.failed.iter().all(|mailbox| mailbox.link.contains("montgomery")));
+}
+
+#[tokio::test]
+async fn test_crawl_retries_failed_detail_pages() {
+    let flaky = FlakyFetcher {
+        inner: fetcher(|_| false),
+        flaky: "/s/mobile-",
+        failed: RefCell::new(HashSet::new()),
+    };
+    let crawl = ATMBCrawl::with_fetcher(flaky, &alabama_config(Some(Duration::ZERO)));
+    let mailboxes = crawl.fetch().await.unwrap();
+    assert_eq!(mailboxes.len(), 10);
+
+    let flaky = FlakyFetcher {
+        inner: fetcher(|_| false),
+        flaky: "/s/mobile-",
+        failed: RefCell::new(HashSet::new()),
+    };
+    let err = ATMBCrawl::with_fetcher(flaky, &alabama_config(None)).fetch().await.unwrap_err();
+    assert_eq!(err.downcast_ref::<IncompleteCrawl>().unwrap().failed.len(), 2);
+}
+
+#[tokio::test]
+async fn test_incremental_crawl_skips_unchanged_locations() {
+    let previous = ATMBCrawl::with_fetcher(fetcher(|_| false), &alabama_config(None)).fetch().await.unwrap();
+    let mut changed = previous.clone();
+    // the price of montgomery has changed since, so its detail page is fetched again
+    changed.iter_mut().filter(|mailbox| mailbox.link.contains("montgomery")).for_each(|mailbox| mailbox.listing.push('0'));
+
+    let crawl = ATMBCrawl::with_fetcher(fetcher(|link| !link.contains("montgomery")), &alabama_config(None))
+        .with_previous(changed);
+    let mut mailboxes = crawl.fetch().await.unwrap();
+    let mut previous = previous;
+    mailboxes.sort_by(|a, b| a.link.cmp(&b.link));
+    previous.sort_by(|a, b| a.link.cmp(&b.link));
+    assert_eq!(mailboxes, previous);
+}
+
+/// remembers the pages requested
+struct RecordingFetcher {
+    inner: FixtureFetcher,
+    fetched: Rc<RefCell<Vec<String>>>,
+}
+
+#[async_trait(?Send)]
+impl PageFetcher for RecordingFetcher {
+    async fn fetch_page(&self, url_path: &str) -> anyhow::Result<String> {
+        self.fetched.borrow_mut().push(url_path.to_string());
+        self.inner.fetch_page(url_path).await
+    }
+}
+
+#[tokio::test]
+async fn test_recover_from_sitemap() {
+    const HUNTSVILLE_LINK: &str = "https://www.anytimemailbox.com/s/huntsville-100-church-st";
+    const TORONTO_LINK: &str = "https://www.anytimemailbox.com/l/canada/s/toronto-1-yonge-st";
+    let sitemap = format!(
+        "<urlset><url><loc>{}/</loc></url><url><loc>{}</loc></url><url><loc>{}</loc></url></urlset>",
+        BIRMINGHAM_LINK, HUNTSVILLE_LINK, TORONTO_LINK,
+    );
+    let huntsville = r#"<div class="t-sec1"><div class="t-text"><div>YOUR NAME</div><div>100 Church St</div><div>Suite MAILBOX</div><div>Huntsville, AL 35801</div><div>United States</div></div></div>"#;
+    let fetched = Rc::new(RefCell::new(Vec::new()));
+    let fetcher = RecordingFetcher {
+        inner: fetcher(|_| false)
+            .with_page("/sitemap.xml", sitemap)
+            .with_page(HUNTSVILLE_LINK, huntsville),
+        fetched: fetched.clone(),
+    };
+    let config = CrawlConfig { sitemap: true, ..alabama_config(None) };
+    let crawl = ATMBCrawl::with_fetcher(fetcher, &config);
+    let mailboxes = crawl.fetch().await.unwrap();
+    assert_eq!(mailboxes.len(), 11);
+    assert!(mailboxes.iter().any(|mailbox| mailbox.link == HUNTSVILLE_LINK && mailbox.address.city == "Huntsville"));
+    // the location under the path of Canada is not fetched, and Birmingham is only fetched by the crawl
+    let fetched = fetched.borrow();
+    assert!(!fetched.iter().any(|url| url.contains("toronto")));
+    assert_eq!(fetched.iter().filter(|url| url.as_str() == BIRMINGHAM_LINK).count(), 1);
+}
@@ -0,0 +1,114 @@
+//! Parse the saved ATMB pages end to end, so the parsers can be checked against the markup offline.
+
+use atmb_us_physical::atmb::model::PricePeriod;
+use atmb_us_physical::atmb::page::{CountryPage, LocationDetailPage, LocationIndexPage, StatePage};
+use atmb_us_physical::country::Country;
+use atmb_us_physical::normalize;
+
+const COUNTRY_PAGE_HTML: &str = include_str!("../test_data/https___www.anytimemailbox.com_l_usa.html");
+const STATE_PAGE_HTML: &str = include_str!("../test_data/https___www.anytimemailbox.com_l_usa_alabama.html");
+const LOCATION_PAGE_HTML: &str = include_str!("../test_data/https___www.anytimemailbox.com_s_birmingham-120-19th-street-north.html");
+const LOCATION_INDEX_HTML: &str = include_str!("../test_data/location_index.html");
+const CANADA_PAGE_HTML: &str = include_str!("../test_data/country_page_canada.html");
+const ONTARIO_PAGE_HTML: &str = include_str!("../test_data/state_page_ontario.html");
+const TORONTO_PAGE_HTML: &str = include_str!("../test_data/detail_page_toronto.html");
+
+/// a detail page with the address lines, the first one is the name placeholder
+fn detail_page(lines: &[&str]) -> String {
+    let divs = lines.iter().map(|line| format!("<div>{}</div>", line)).collect::<String>();
+    format!(r#"<div class="t-sec1"><div class="t-text">{}</div></div>"#, divs)
+}
+
+#[test]
+fn test_crawl_pages() {
+    let country_page = CountryPage::parse_html(COUNTRY_PAGE_HTML).unwrap();
+    let alabama = country_page.states.iter().find(|state| state.name() == "Alabama").unwrap();
+    assert_eq!(alabama.url(), "/l/usa/alabama");
+
+    let state_page = StatePage::parse_html(STATE_PAGE_HTML).unwrap().with_state(alabama.name());
+    let mut mailboxes = state_page.to_mailboxes(Country::Us).unwrap();
+    assert_eq!(mailboxes.len(), 10);
+
+    let birmingham = mailboxes.iter_mut().find(|mailbox| mailbox.link.ends_with("/s/birmingham-120-19th-street-north")).unwrap();
+    LocationDetailPage::parse_html(LOCATION_PAGE_HTML).unwrap().update_mailbox(birmingham);
+    assert_eq!(birmingham.address.line1, "120 19th Street North Suite MAILBOX");
+    assert_eq!(birmingham.operator.as_deref(), Some("Forge"));
+
+    let (valid, errors) = normalize::check_mailboxes(mailboxes);
+    assert_eq!(valid.len(), 10);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn test_detail_page_line_counts() {
+    let street = |lines: &[&str]| LocationDetailPage::parse_html(&detail_page(lines)).map(|page| page.street());
+    assert_eq!(street(&["YOUR NAME", "1 Main St", "Dallas, TX 75201", "United States"]).unwrap(), "1 Main St");
+    assert_eq!(street(&["YOUR NAME", "1 Main St", "Suite MAILBOX", "Dallas, TX 75201", "United States"]).unwrap(), "1 Main St Suite MAILBOX");
+    assert_eq!(
+        street(&["YOUR NAME", "1 Main St", "Building B", "Suite MAILBOX", "Dallas, TX 75201", "United States"]).unwrap(),
+        "1 Main St Building B Suite MAILBOX",
+    );
+    // more lines than ever seen is warned about, not an error
+    assert_eq!(
+        street(&["YOUR NAME", "1 Main St", "Building B", "Floor 2", "Suite MAILBOX", "Dallas, TX 75201", "United States"]).unwrap(),
+        "1 Main St Building B Floor 2 Suite MAILBOX",
+    );
+    assert!(street(&["YOUR NAME", "United States"]).is_err());
+}
+
+#[test]
+fn test_location_index() {
+    let index = LocationIndexPage::parse_html(LOCATION_INDEX_HTML).unwrap();
+    // every country is listed once, in the order of the menu
+    assert_eq!(index.countries, [
+        ("/l/usa", "United States"),
+        ("/l/canada", "Canada"),
+        ("/l/germany", "Germany"),
+        ("/l/united-kingdom", "United Kingdom"),
+        ("/l/australia", "Australia"),
+    ]);
+    let supported = index.countries.iter().filter_map(|(_, name)| name.parse::<Country>().ok()).collect::<Vec<_>>();
+    assert_eq!(supported, [Country::Us, Country::Ca, Country::Uk, Country::Au]);
+}
+
+#[test]
+fn test_international_pages() {
+    let country_page = CountryPage::parse_html(CANADA_PAGE_HTML).unwrap();
+    assert_eq!(country_page.states.len(), 4);
+    let ontario = country_page.states.iter().find(|state| state.name() == "Ontario").unwrap();
+    assert_eq!(ontario.url(), "/l/canada/ontario");
+
+    let state_page = StatePage::parse_html(ONTARIO_PAGE_HTML).unwrap().with_state(ontario.name());
+    let mut mailboxes = state_page.to_mailboxes(Country::Ca).unwrap();
+    assert_eq!(mailboxes.len(), 2);
+    let ottawa = &mailboxes[1];
+    assert_eq!((ottawa.address.city.as_str(), ottawa.address.zip.as_str()), ("Ottawa", "K2P 1W8"));
+    assert_eq!(ottawa.address.country, Country::Ca);
+    assert!(ottawa.address.zip4.is_none());
+    let price = ottawa.price.as_ref().unwrap();
+    assert_eq!((price.amount, price.currency.as_str(), price.period), (1499, "CAD", PricePeriod::Month));
+    // the US addresses can't be parsed from a Canadian page
+    assert!(state_page.to_mailboxes(Country::Us).is_err());
+
+    let toronto_page = LocationDetailPage::parse_html(TORONTO_PAGE_HTML).unwrap();
+    assert_eq!(toronto_page.country(), Some(Country::Ca));
+    let toronto = &mut mailboxes[0];
+    toronto_page.update_mailbox(toronto);
+    assert_eq!(toronto.address.line1, "1 Yonge St Suite MAILBOX");
+    assert_eq!(toronto.operator.as_deref(), Some("Northern Mail Co."));
+    assert_eq!(toronto.phone.as_deref(), Some("+1 416-555-0100"));
+    assert_eq!(toronto.plans.len(), 2);
+    assert_eq!(toronto.plans[0].price_of(PricePeriod::Year), Some("CA$199.99/year"));
+
+    // the locations missing from the state pages are built from the detail page alone
+    let recovered = toronto_page.to_mailbox("https://www.anytimemailbox.com/s/toronto-1-yonge-st", Country::Ca).unwrap();
+    assert_eq!(recovered.address, toronto.address);
+    assert_eq!(recovered.raw_price, "CA$19.99/month");
+
+    let london = LocationDetailPage::parse_html(&detail_page(&["YOUR NAME", "1 Example St", "London, SW1A 2AA", "United Kingdom"])).unwrap();
+    assert_eq!(london.country(), Some(Country::Uk));
+    let mailbox = london.to_mailbox("/s/london-1-example-st", Country::Uk).unwrap();
+    assert_eq!((mailbox.address.state.as_str(), mailbox.address.zip.as_str()), ("", "SW1A 2AA"));
+    let unsupported = LocationDetailPage::parse_html(&detail_page(&["YOUR NAME", "1 Hauptstr", "10115 Berlin", "Germany"])).unwrap();
+    assert_eq!(unsupported.country(), None);
+}
@@ -0,0 +1,19 @@
+//! The `--timeout` of a run, in its own process as the interruption is global.
+
+use std::time::Duration;
+use atmb_us_physical::shutdown;
+
+#[tokio::test]
+async fn test_deadline() {
+    // a timeout passing while the results are saved waits for the save
+    shutdown::enter_stage(shutdown::SAVE_STAGE);
+    shutdown::deadline(Duration::from_millis(10));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!shutdown::is_interrupted());
+    assert_eq!(shutdown::timed_out(), None);
+
+    shutdown::enter_stage("crawl");
+    tokio::time::timeout(Duration::from_secs(5), shutdown::interrupted()).await.unwrap();
+    assert_eq!(shutdown::timed_out(), Some("crawl"));
+    assert_eq!(shutdown::Interrupted.to_string(), "the run has timed out in the [crawl] stage");
+}
@@ -0,0 +1,102 @@
+//! Turn a saved Smarty response into the records, so the mapping of its fields can be checked offline.
+
+use smarty_rust_sdk::us_street_api::candidate::Candidate;
+use smarty_rust_sdk::us_street_api::lookup::Lookup;
+use atmb_us_physical::atmb::model::{Address, Mailbox, Price};
+use atmb_us_physical::provider::ProviderKind;
+use atmb_us_physical::record::Record;
+use atmb_us_physical::verify::model::{AdditionalInfo, Rdi, YesOrNo};
+
+const RESPONSE_JSON: &str = include_str!("../test_data/smarty_us_street.json");
+
+fn lookup(results: Vec<Candidate>) -> Lookup {
+    Lookup {
+        results,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_smarty_response() {
+    let candidates = serde_json::from_str::<Vec<Candidate>>(RESPONSE_JSON).unwrap();
+    let info = AdditionalInfo::try_from(lookup(candidates)).unwrap();
+    assert_eq!(info.cmra, YesOrNo::Y);
+    assert_eq!(info.rdi, Rdi::Commercial);
+    assert_eq!(info.dpv_match_code, "Y");
+    assert_eq!(info.vacant, Some(YesOrNo::N));
+    assert_eq!(info.latitude, Some(33.5186));
+    let verified = info.verified.as_ref().unwrap();
+    assert_eq!(verified.street, "120 19th St N Ste 1234");
+    assert_eq!(verified.county_fips, "01073");
+
+    let mailbox = Mailbox {
+        provider: ProviderKind::Atmb,
+        name: "Birmingham - 19th St".to_string(),
+        address: Address::from_lines("120 19th Street North Suite MAILBOX", "Birmingham, AL 35203").unwrap(),
+        link: "https://www.anytimemailbox.com/s/birmingham-120-19th-street-north".to_string(),
+        price: Price::parse("US$39.00/month"),
+        raw_price: "US$39.00/month".to_string(),
+        phone: None,
+        operator: Some("Forge".to_string()),
+        plans: Vec::new(),
+        geo: None,
+        listing: String::new(),
+    };
+    let record = Record::from_mailbox_and_info(mailbox, info, true);
+    assert_eq!(record.street, "120 19th St N Ste 1234");
+    assert_eq!((record.zip.as_str(), record.zip4.as_str()), ("35203", "3712"));
+    assert_eq!(record.county, "Jefferson");
+    assert_eq!(record.operator, "Forge");
+}
+
+#[test]
+fn test_smarty_no_match() {
+    assert!(AdditionalInfo::try_from(lookup(Vec::new())).is_err());
+}
+
+#[test]
+fn test_smarty_multiple_candidates() {
+    let confirmed = serde_json::from_str::<Vec<Candidate>>(RESPONSE_JSON).unwrap().remove(0);
+    let mut missing_secondary = confirmed.clone();
+    missing_secondary.analysis.dpv_match_code = "D".to_string();
+    missing_secondary.metadata.rdi = "Residential".to_string();
+    // the confirmed candidate is picked even if Smarty lists it second
+    let info = AdditionalInfo::try_from(lookup(vec![missing_secondary, confirmed])).unwrap();
+    assert_eq!(info.dpv_match_code, "Y");
+    assert_eq!(info.rdi, Rdi::Commercial);
+    assert_eq!(info.candidate_count(), 2);
+    assert!(info.is_ambiguous());
+    assert_eq!(info.candidates[0].rdi, Rdi::Residential);
+}
+
+#[test]
+fn test_smarty_missing_secondary() {
+    let mut candidate = serde_json::from_str::<Vec<Candidate>>(RESPONSE_JSON).unwrap().remove(0);
+    let info = AdditionalInfo::try_from(lookup(vec![candidate.clone()])).unwrap();
+    assert_eq!(info.no_stat, Some(YesOrNo::N));
+    assert!(!info.is_missing_secondary());
+    assert_eq!(info.missing_secondary(), Some(YesOrNo::N));
+
+    candidate.analysis.enhanced_match = "postal-match,missing-secondary".to_string();
+    let info = AdditionalInfo::try_from(lookup(vec![candidate])).unwrap();
+    assert!(info.is_missing_secondary());
+    assert_eq!(info.missing_secondary(), Some(YesOrNo::Y));
+
+    // not reported at all, i.e. by geocodio
+    let info = AdditionalInfo { dpv_match_code: String::new(), dpv_footnotes: String::new(), enhanced_match: String::new(), ..info };
+    assert_eq!(info.missing_secondary(), None);
+}
+
+#[test]
+fn test_smarty_unparsable_candidate() {
+    let confirmed = serde_json::from_str::<Vec<Candidate>>(RESPONSE_JSON).unwrap().remove(0);
+    let mut unparsable = confirmed.clone();
+    unparsable.analysis.dpv_cmra = String::new();
+    // the candidate without the CMRA flag is skipped, not the whole lookup
+    let info = AdditionalInfo::try_from(lookup(vec![unparsable.clone(), confirmed])).unwrap();
+    assert_eq!(info.cmra, YesOrNo::Y);
+    assert_eq!(info.candidate_count(), 1);
+    assert!(!info.is_ambiguous());
+
+    assert!(AdditionalInfo::try_from(lookup(vec![unparsable])).is_err());
+}